@@ -0,0 +1,16 @@
+# Überschriften und Beschriftungen der Lokalisierungsberichte.
+
+unavailable = Nicht erreichbar ({ $count })
+shipped-locales = Mitgelieferte Sprachen, nach Anzahl der Instanzen, die sie aktivieren:
+shipped-locale-column = Mitgelieferte Sprache
+instances-column = Instanzen
+unused-locales = Auf keiner Instanz aktiviert ({ $count })
+unofficial-locales = Inoffizielle Sprachen ({ $count })
+invalid-locales = Unbekannte Sprachen ({ $count })
+missing-locale = Nicht aktiviert: { $locale }
+translated = { $percent } % übersetzt
+not-on-weblate = nicht auf Weblate
+enabled-on = auf { $count ->
+    [one] einer Instanz
+   *[other] { $count } Instanzen
+} aktiviert
@@ -0,0 +1,16 @@
+# Headings and labels of the localization reports.
+
+unavailable = Unavailable ({ $count })
+shipped-locales = Shipped locales, by the number of instances enabling them:
+shipped-locale-column = Shipped locale
+instances-column = Instances
+unused-locales = Not enabled on any instance ({ $count })
+unofficial-locales = Unofficial locales ({ $count })
+invalid-locales = Unrecognized locales ({ $count })
+missing-locale = Not enabled: { $locale }
+translated = { $percent }% translated
+not-on-weblate = not on Weblate
+enabled-on = enabled on { $count ->
+    [one] { $count } instance
+   *[other] { $count } instances
+}
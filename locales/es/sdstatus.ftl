@@ -0,0 +1,16 @@
+# Encabezados y etiquetas de los informes de localización.
+
+unavailable = No disponibles ({ $count })
+shipped-locales = Idiomas incluidos, por número de instancias que los activan:
+shipped-locale-column = Idioma incluido
+instances-column = Instancias
+unused-locales = No activados en ninguna instancia ({ $count })
+unofficial-locales = Idiomas no oficiales ({ $count })
+invalid-locales = Idiomas no reconocidos ({ $count })
+missing-locale = No activado: { $locale }
+translated = { $percent } % traducido
+not-on-weblate = no está en Weblate
+enabled-on = activado en { $count ->
+    [one] { $count } instancia
+   *[other] { $count } instancias
+}
@@ -0,0 +1,16 @@
+# Titres et libellés des rapports de localisation.
+
+unavailable = Indisponibles ({ $count })
+shipped-locales = Langues fournies, par nombre d’instances qui les activent :
+shipped-locale-column = Langue fournie
+instances-column = Instances
+unused-locales = Activées sur aucune instance ({ $count })
+unofficial-locales = Langues non officielles ({ $count })
+invalid-locales = Langues non reconnues ({ $count })
+missing-locale = Non activée : { $locale }
+translated = traduite à { $percent } %
+not-on-weblate = absente de Weblate
+enabled-on = activée sur { $count ->
+    [one] { $count } instance
+   *[other] { $count } instances
+}
@@ -0,0 +1,16 @@
+# Títulos e rótulos dos relatórios de localização.
+
+unavailable = Indisponíveis ({ $count })
+shipped-locales = Idiomas incluídos, pelo número de instâncias que os ativam:
+shipped-locale-column = Idioma incluído
+instances-column = Instâncias
+unused-locales = Não ativados em nenhuma instância ({ $count })
+unofficial-locales = Idiomas não oficiais ({ $count })
+invalid-locales = Idiomas não reconhecidos ({ $count })
+missing-locale = Não ativado: { $locale }
+translated = { $percent }% traduzido
+not-on-weblate = não está no Weblate
+enabled-on = ativado em { $count ->
+    [one] { $count } instância
+   *[other] { $count } instâncias
+}
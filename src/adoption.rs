@@ -0,0 +1,183 @@
+use crate::db::VersionOutcome;
+use crate::releases::parse_version;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// How many weeks after a release is first seen its adoption is followed.
+pub const ADOPTION_WEEKS: i64 = 8;
+
+/// The versions the fleet ran over one interval of the history.
+#[derive(Serialize, Debug)]
+pub struct AdoptionPoint {
+    /// When the interval began.
+    pub start: DateTime<Utc>,
+    /// How many sites were available in the interval.
+    pub sites: usize,
+    /// How many of those sites ran each version, as of their latest scan
+    /// in the interval.
+    pub versions: BTreeMap<String, usize>,
+}
+
+/// How a release spread through the fleet after it was first seen.
+#[derive(Serialize, Debug)]
+pub struct ReleaseAdoption {
+    pub version: String,
+    /// When a site was first seen running the release. This is when the
+    /// release shipped at the earliest, or before the history begins.
+    pub first_seen: DateTime<Utc>,
+    /// The percentage of available sites running the release or a newer
+    /// one, one week after it was first seen, then two, and so on.
+    pub weekly: Vec<f64>,
+}
+
+/// The fleet's version distribution over time, and each release's
+/// adoption curve.
+#[derive(Serialize, Debug)]
+pub struct AdoptionReport {
+    pub series: Vec<AdoptionPoint>,
+    /// Releases, newest first.
+    pub releases: Vec<ReleaseAdoption>,
+}
+
+/// Whether `version` is `release` or newer. Versions that can't be parsed
+/// only count as the release they name.
+fn at_least(version: &str, release: &str) -> bool {
+    match (parse_version(version), parse_version(release)) {
+        (Some(v), Some(r)) => v >= r,
+        _ => version == release,
+    }
+}
+
+/// The share of a point's sites running `release` or newer.
+fn share(point: &AdoptionPoint, release: &str) -> f64 {
+    let on = point
+        .versions
+        .iter()
+        .filter(|(v, _)| at_least(v, release))
+        .map(|(_, n)| n)
+        .sum::<usize>();
+    100.0 * on as f64 / point.sites.max(1) as f64
+}
+
+/// Buckets the versions recorded since `since` into intervals of
+/// `interval`, and follows each release's adoption for `ADOPTION_WEEKS`
+/// weeks, or until `now`.
+pub fn generate_adoption_report(
+    outcomes: &[VersionOutcome],
+    since: DateTime<Utc>,
+    interval: Duration,
+    now: DateTime<Utc>,
+) -> AdoptionReport {
+    let interval_secs = interval.num_seconds().max(1);
+    // The latest version of each site in each interval.
+    let mut buckets: BTreeMap<i64, BTreeMap<&str, &str>> = BTreeMap::new();
+    let mut first_seen: BTreeMap<&str, DateTime<Utc>> = BTreeMap::new();
+    for o in outcomes {
+        let bucket = (o.scanned_at - since)
+            .num_seconds()
+            .div_euclid(interval_secs);
+        buckets
+            .entry(bucket)
+            .or_default()
+            .insert(&o.onion_address, &o.sd_version);
+        first_seen
+            .entry(&o.sd_version)
+            .and_modify(|t| *t = (*t).min(o.scanned_at))
+            .or_insert(o.scanned_at);
+    }
+    let series: Vec<AdoptionPoint> = buckets
+        .into_iter()
+        .map(|(bucket, sites)| {
+            let mut versions = BTreeMap::new();
+            for version in sites.values() {
+                *versions.entry(version.to_string()).or_default() += 1;
+            }
+            AdoptionPoint {
+                start: since + Duration::seconds(bucket * interval_secs),
+                sites: sites.len(),
+                versions,
+            }
+        })
+        .collect();
+
+    let mut releases: Vec<ReleaseAdoption> = first_seen
+        .into_iter()
+        .map(|(version, first_seen)| ReleaseAdoption {
+            version: version.to_owned(),
+            first_seen,
+            weekly: (1..=ADOPTION_WEEKS)
+                .map(|w| first_seen + Duration::weeks(w))
+                .take_while(|t| *t <= now)
+                .filter_map(|t| series.iter().rev().find(|p| p.start <= t))
+                .map(|p| share(p, version))
+                .collect(),
+        })
+        .collect();
+    releases.sort_by_key(|r| std::cmp::Reverse((parse_version(&r.version), r.first_seen)));
+    AdoptionReport { series, releases }
+}
+
+/// Renders the adoption report as human-readable text: each release's
+/// adoption curve, then the version distribution over time.
+pub fn format_adoption_report(report: &AdoptionReport) -> String {
+    let mut text = String::from("Adoption after first seen (sites on the release or newer):\n");
+    for r in &report.releases {
+        let weeks: Vec<String> = r
+            .weekly
+            .iter()
+            .enumerate()
+            .map(|(w, share)| format!("week {} {:.0}%", w + 1, share))
+            .collect();
+        text += &format!(
+            "  {} (first seen {}): {}\n",
+            r.version,
+            r.first_seen.format("%Y-%m-%d"),
+            if weeks.is_empty() {
+                "too recent".to_owned()
+            } else {
+                weeks.join(", ")
+            }
+        );
+    }
+    text += "\nVersions over time:\n";
+    for p in &report.series {
+        let mut versions: Vec<_> = p.versions.iter().collect();
+        versions.sort_by_key(|(v, _)| std::cmp::Reverse(parse_version(v)));
+        let versions: Vec<String> = versions
+            .into_iter()
+            .map(|(v, n)| format!("{} {}", v, n))
+            .collect();
+        text += &format!(
+            "  {}  {} sites: {}\n",
+            p.start.format("%Y-%m-%d %H:%M"),
+            p.sites,
+            versions.join(", ")
+        );
+    }
+    text
+}
+
+/// Writes the version distribution over time as CSV, one row per interval
+/// and version: its start, the version, how many sites ran it, and their
+/// percentage of the sites available.
+pub fn write_adoption_csv<W: std::io::Write>(
+    report: &AdoptionReport,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record(["start", "version", "sites", "percentage"])?;
+    for p in &report.series {
+        for (version, n) in &p.versions {
+            wtr.write_record(&[
+                p.start.to_rfc3339(),
+                version.to_owned(),
+                n.to_string(),
+                format!("{:.1}", 100.0 * *n as f64 / p.sites.max(1) as f64),
+            ])?;
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
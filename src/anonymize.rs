@@ -0,0 +1,280 @@
+use crate::db::Outcome;
+use crate::findings::Finding;
+use crate::{normalize_onion, SDDirectoryInstance, ScanResults};
+use regex::{Captures, Regex};
+use sha3::{Digest, Sha3_256};
+use std::collections::BTreeMap;
+
+/// Onion addresses and OpenPGP fingerprints, as they turn up in free text
+/// such as errors and the messages of findings. Fingerprints may be
+/// written in groups of four, as GnuPG prints them.
+const IDENTIFIERS: &str = r"(?i)\b(?:[a-z0-9-]+\.)+onion\b|\b[0-9a-f]{4}(?: {0,2}[0-9a-f]{4}){9}\b";
+
+/// Replaces what identifies SecureDrop sites in scan results, their
+/// titles, addresses and fingerprints, with salted hashes, so the results
+/// can be shared without giving away a list of sites. A salt gives each
+/// site the same pseudonyms every time, so anonymized results can still
+/// be compared with each other.
+#[derive(Debug, Clone)]
+pub struct Anonymizer {
+    salt: String,
+    identifiers: Regex,
+}
+
+impl Anonymizer {
+    pub fn new(salt: &str) -> Anonymizer {
+        Anonymizer {
+            salt: salt.to_owned(),
+            identifiers: Regex::new(IDENTIFIERS).unwrap(),
+        }
+    }
+
+    /// The pseudonym of `value`: the start of its salted SHA3-256 hash,
+    /// in hex. Empty values stay empty, so their absence still shows.
+    pub fn pseudonym(&self, value: &str) -> String {
+        if value.trim().is_empty() {
+            return String::new();
+        }
+        let mut hasher = Sha3_256::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update(value.as_bytes());
+        hasher.finalize()[..8]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// The pseudonym of an Onion address however written, so `v3_source_url`
+    /// and the address listed in the directory still match.
+    pub fn onion(&self, onion_url: &str) -> String {
+        self.pseudonym(&normalize_onion(onion_url).to_lowercase())
+    }
+
+    fn fingerprint(&self, fingerprint: &str) -> String {
+        let fingerprint: String = fingerprint.split_whitespace().collect();
+        self.pseudonym(&fingerprint.to_uppercase())
+    }
+
+    /// Replaces the Onion addresses and fingerprints in `text`.
+    fn scrub(&self, text: &str) -> String {
+        self.identifiers
+            .replace_all(text, |c: &Captures| {
+                let found = &c[0];
+                if found.to_lowercase().ends_with(".onion") {
+                    self.onion(found)
+                } else {
+                    self.fingerprint(found)
+                }
+            })
+            .into_owned()
+    }
+
+    /// Anonymizes a scanned instance. The metadata fields sdstatus doesn't
+    /// model, such as `organization_name`, and the metadata as served are
+    /// dropped, as they may name the site.
+    pub fn anonymize_instance(&self, instance: &mut SDDirectoryInstance) {
+        instance.title = self.pseudonym(&instance.title);
+        instance.landing_page_url = self.pseudonym(&instance.landing_page_url);
+        instance.onion_address = self.onion(&instance.onion_address);
+        instance.onion_name = instance.onion_name.as_deref().map(|n| self.onion(n));
+        if let Some(m) = &mut instance.metadata {
+            m.gpg_fpr = self.fingerprint(&m.gpg_fpr);
+            m.v2_source_url = m.v2_source_url.as_deref().map(|u| self.onion(u));
+            m.v3_source_url = self.onion(&m.v3_source_url);
+            m.extra.clear();
+        }
+        instance.raw_metadata = None;
+        instance.error = instance.error.as_deref().map(|e| self.scrub(e));
+        instance.key_fingerprint = instance
+            .key_fingerprint
+            .as_deref()
+            .map(|f| self.fingerprint(f));
+        if let Some(key) = &mut instance.journalist_key {
+            for k in std::iter::once(&mut key.primary).chain(key.subkeys.iter_mut()) {
+                k.fingerprint = self.fingerprint(&k.fingerprint);
+            }
+        }
+    }
+
+    pub fn anonymize_instances(&self, instances: &mut [SDDirectoryInstance]) {
+        for i in instances {
+            self.anonymize_instance(i);
+        }
+    }
+
+    pub fn anonymize_findings(&self, findings: &mut [Finding]) {
+        for f in findings {
+            f.title = self.pseudonym(&f.title);
+            f.onion_address = self.onion(&f.onion_address);
+            f.message = self.scrub(&f.message);
+        }
+    }
+
+    /// Anonymizes a scan's instances and findings.
+    pub fn anonymize_results(&self, results: &mut ScanResults) {
+        self.anonymize_instances(&mut results.instances);
+        self.anonymize_findings(&mut results.findings);
+    }
+
+    /// Anonymizes the outcomes of past scans read from the history.
+    pub fn anonymize_outcomes(&self, outcomes: &mut [Outcome]) {
+        for o in outcomes {
+            o.title = self.pseudonym(&o.title);
+            o.onion_address = self.onion(&o.onion_address);
+        }
+    }
+
+    /// Keys the tags given each Onion address by its pseudonym instead,
+    /// to look anonymized instances up in.
+    pub fn anonymize_tags(
+        &self,
+        tags: &BTreeMap<String, Vec<String>>,
+    ) -> BTreeMap<String, Vec<String>> {
+        tags.iter()
+            .map(|(onion, tags)| (self.onion(onion), tags.to_vec()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::findings::FindingKind;
+    use crate::landing::Severity;
+    use crate::SDMetadata;
+
+    const ONION: &str = "currentaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaal37qd.onion";
+    const FINGERPRINT: &str = "65A1B5FF195B56353CC63DFFCC40EF1228271441";
+    const SPACED: &str = "65A1 B5FF 195B 5635 3CC6  3DFF CC40 EF12 2827 1441";
+
+    fn instance() -> SDDirectoryInstance {
+        SDDirectoryInstance {
+            title: "Current Times".to_owned(),
+            landing_page_url: "https://current.example/securedrop".to_owned(),
+            onion_name: Some("current.securedrop.tor.onion".to_owned()),
+            metadata: Some(SDMetadata {
+                gpg_fpr: FINGERPRINT.to_owned(),
+                v3_source_url: format!("http://{}/", ONION.to_uppercase()),
+                extra: BTreeMap::from([("organization_name".to_owned(), "Current Times".into())]),
+                ..SDMetadata::default()
+            }),
+            raw_metadata: Some(serde_json::json!({ "organization_name": "Current Times" })),
+            error: Some(format!("Key {} not found", SPACED)),
+            ..SDDirectoryInstance::from_onion(ONION)
+        }
+    }
+
+    #[test]
+    fn pseudonyms_depend_on_the_salt() {
+        let a = Anonymizer::new("salt");
+        // Another run, with the same salt, gives the same pseudonyms.
+        assert_eq!(
+            a.pseudonym("Current Times"),
+            Anonymizer::new("salt").pseudonym("Current Times")
+        );
+        assert_eq!(a.pseudonym("Current Times").len(), 16);
+        assert_ne!(
+            a.pseudonym("Current Times"),
+            a.pseudonym("Outdated Gazette")
+        );
+        assert_ne!(
+            a.pseudonym("Current Times"),
+            Anonymizer::new("pepper").pseudonym("Current Times")
+        );
+        assert_eq!(a.pseudonym(" "), "");
+    }
+
+    #[test]
+    fn identifiers_however_written_share_a_pseudonym() {
+        let a = Anonymizer::new("salt");
+        assert_eq!(
+            a.onion(ONION),
+            a.onion(&format!("http://{}/metadata", ONION))
+        );
+        assert_eq!(a.onion(ONION), a.onion(&ONION.to_uppercase()));
+        assert_eq!(a.fingerprint(FINGERPRINT), a.fingerprint(SPACED));
+        assert_eq!(
+            a.fingerprint(FINGERPRINT),
+            a.fingerprint(&FINGERPRINT.to_lowercase())
+        );
+    }
+
+    #[test]
+    fn scrubs_identifiers_from_text() {
+        let a = Anonymizer::new("salt");
+        let onion = a.onion(ONION);
+        let fingerprint = a.fingerprint(FINGERPRINT);
+        assert_eq!(
+            a.scrub(&format!("Failed to connect to http://{}/metadata", ONION)),
+            format!("Failed to connect to http://{}/metadata", onion)
+        );
+        assert_eq!(
+            a.scrub(&format!("fingerprint {} != {}", FINGERPRINT, SPACED)),
+            format!("fingerprint {} != {}", fingerprint, fingerprint)
+        );
+        assert_eq!(
+            a.scrub(&format!("single-spaced {}", SPACED.replace("  ", " "))),
+            format!("single-spaced {}", fingerprint)
+        );
+        // Neither part of a longer hash, nor a short key ID.
+        let sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        assert_eq!(a.scrub(sha256), sha256);
+        assert_eq!(a.scrub("key CC40EF1228271441"), "key CC40EF1228271441");
+    }
+
+    #[test]
+    fn anonymizes_instances() {
+        let a = Anonymizer::new("salt");
+        let mut i = instance();
+        a.anonymize_instance(&mut i);
+        assert_eq!(i.title, a.pseudonym("Current Times"));
+        assert_eq!(
+            i.landing_page_url,
+            a.pseudonym("https://current.example/securedrop")
+        );
+        assert_eq!(i.onion_address, a.onion(ONION));
+        assert_eq!(
+            i.onion_name.as_deref(),
+            Some(a.onion("current.securedrop.tor.onion").as_str())
+        );
+        let m = i.metadata.as_ref().unwrap();
+        assert_eq!(m.gpg_fpr, a.fingerprint(FINGERPRINT));
+        assert_eq!(m.v3_source_url, i.onion_address);
+        assert!(m.extra.is_empty());
+        assert!(i.raw_metadata.is_none());
+        assert_eq!(
+            i.error.as_deref(),
+            Some(format!("Key {} not found", m.gpg_fpr).as_str())
+        );
+    }
+
+    #[test]
+    fn anonymizes_findings_outcomes_and_tags() {
+        let a = Anonymizer::new("salt");
+        let mut findings = [Finding {
+            title: "Current Times".to_owned(),
+            onion_address: ONION.to_owned(),
+            severity: Severity::High,
+            kind: FindingKind::KeyMismatch,
+            message: format!("Published key is not {}", SPACED),
+        }];
+        a.anonymize_findings(&mut findings);
+        assert_eq!(findings[0].title, a.pseudonym("Current Times"));
+        assert_eq!(findings[0].onion_address, a.onion(ONION));
+        assert!(!findings[0].message.contains("65A1"));
+
+        let mut outcomes = [Outcome {
+            onion_address: ONION.to_owned(),
+            title: "Current Times".to_owned(),
+            scanned_at: chrono::Utc::now(),
+            available: true,
+        }];
+        a.anonymize_outcomes(&mut outcomes);
+        assert_eq!(outcomes[0].onion_address, a.onion(ONION));
+        assert_eq!(outcomes[0].title, a.pseudonym("Current Times"));
+
+        let tags = BTreeMap::from([(ONION.to_owned(), vec!["newsroom".to_owned()])]);
+        let tags = a.anonymize_tags(&tags);
+        assert_eq!(tags[&a.onion(ONION)], vec!["newsroom"]);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::locale::{instance_locales, normalize_locale};
+use crate::releases::parse_version;
+use crate::SDDirectoryInstance;
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// What an assertion measures of a scan.
+#[derive(Debug, Clone, PartialEq)]
+enum Metric {
+    /// The number of available sites enabling a locale.
+    Locale(String),
+    /// The oldest SecureDrop release running on an available site.
+    Version,
+    /// The number of sites that couldn't be scanned.
+    Down,
+    /// The number of sites that could.
+    Up,
+}
+
+/// What a metric is compared to.
+#[derive(Debug, Clone, PartialEq)]
+enum Threshold {
+    Count(usize),
+    Version(Vec<u64>),
+}
+
+/// The comparison operators, longest first so `>=` isn't taken for `>`.
+const OPERATORS: &[(&str, &[Ordering])] = &[
+    (">=", &[Ordering::Greater, Ordering::Equal]),
+    ("<=", &[Ordering::Less, Ordering::Equal]),
+    ("==", &[Ordering::Equal]),
+    (">", &[Ordering::Greater]),
+    ("<", &[Ordering::Less]),
+    ("=", &[Ordering::Equal]),
+];
+
+/// Compares versions as if the shorter were padded with zeros, so `2.6`
+/// and `2.6.0` are the same release.
+fn compare_versions(a: &[u64], b: &[u64]) -> Ordering {
+    let part = |v: &[u64], i: usize| v.get(i).copied().unwrap_or(0);
+    (0..a.len().max(b.len()))
+        .map(|i| part(a, i).cmp(&part(b, i)))
+        .find(|o| o.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// A policy a scan must meet, such as `locale:de_DE>=10` (at least 10
+/// sites enable `de-DE`), `version>=2.6.0` (no site runs an older
+/// release) or `down<=3` (at most 3 sites are down). `up` counts the
+/// sites that were scanned. The operators are `>=`, `<=`, `>`, `<` and
+/// `=`.
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    expr: String,
+    metric: Metric,
+    /// The orderings of the metric against the threshold that satisfy it.
+    holds: &'static [Ordering],
+    threshold: Threshold,
+}
+
+impl FromStr for Assertion {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| format!("Invalid assertion {}, {}", s, reason);
+        let start = s
+            .find(['<', '>', '='])
+            .ok_or_else(|| invalid("expected a comparison such as >="))?;
+        let (metric, rest) = s.split_at(start);
+        let (operator, holds) = OPERATORS
+            .iter()
+            .find(|(op, _)| rest.starts_with(op))
+            .unwrap();
+        let value = rest[operator.len()..].trim();
+        if value.is_empty() {
+            return Err(invalid(&format!("expected a value after {}", operator)).into());
+        }
+        let metric = match metric.trim() {
+            "version" => Metric::Version,
+            "down" => Metric::Down,
+            "up" => Metric::Up,
+            m => match m.strip_prefix("locale:") {
+                Some(locale) => {
+                    Metric::Locale(normalize_locale(locale).ok_or_else(|| invalid("not a locale"))?)
+                }
+                None => {
+                    return Err(
+                        invalid("expected 'locale:<locale>', 'version', 'down' or 'up'").into(),
+                    )
+                }
+            },
+        };
+        let threshold = if metric == Metric::Version {
+            Threshold::Version(parse_version(value).ok_or_else(|| invalid("not a version"))?)
+        } else {
+            Threshold::Count(value.parse().map_err(|_| invalid("not a number"))?)
+        };
+        Ok(Assertion {
+            expr: s.to_owned(),
+            metric,
+            holds,
+            threshold,
+        })
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+impl Assertion {
+    /// Checks the assertion against a scan's instances, returning what was
+    /// found instead if it doesn't hold. Without any version known, a
+    /// `version` assertion doesn't hold.
+    pub fn check(&self, instances: &[SDDirectoryInstance]) -> Result<(), String> {
+        let available = instances.iter().filter(|i| i.available);
+        let (ordering, found) = match (&self.metric, &self.threshold) {
+            (Metric::Locale(locale), Threshold::Count(n)) => {
+                let count = available
+                    .filter(|i| instance_locales(i).contains(locale))
+                    .count();
+                (
+                    count.cmp(n),
+                    format!("sites enabling {}: {}", locale, count),
+                )
+            }
+            (Metric::Down, Threshold::Count(n)) => {
+                let count = instances.iter().filter(|i| !i.available).count();
+                (count.cmp(n), format!("sites down: {}", count))
+            }
+            (Metric::Up, Threshold::Count(n)) => {
+                let count = available.count();
+                (count.cmp(n), format!("sites up: {}", count))
+            }
+            (Metric::Version, Threshold::Version(v)) => {
+                let oldest = available
+                    .filter_map(|i| {
+                        let version = &i.metadata.as_ref()?.sd_version;
+                        Some((parse_version(version)?, version))
+                    })
+                    .min_by(|(a, _), (b, _)| compare_versions(a, b));
+                match oldest {
+                    Some((oldest, version)) => (
+                        compare_versions(&oldest, v),
+                        format!("oldest release running: {}", version),
+                    ),
+                    None => return Err("no site's release is known".to_owned()),
+                }
+            }
+            _ => unreachable!("thresholds are parsed to suit their metric"),
+        };
+        if self.holds.contains(&ordering) {
+            Ok(())
+        } else {
+            Err(found)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SDMetadata;
+
+    fn instance(version: Option<&str>, languages: &[&str]) -> SDDirectoryInstance {
+        SDDirectoryInstance {
+            available: version.is_some(),
+            metadata: version.map(|v| SDMetadata {
+                sd_version: v.to_owned(),
+                supported_languages: languages.iter().map(|l| l.to_string()).collect(),
+                ..SDMetadata::default()
+            }),
+            ..SDDirectoryInstance::from_onion("example.onion")
+        }
+    }
+
+    fn check(expr: &str, instances: &[SDDirectoryInstance]) -> Result<(), String> {
+        expr.parse::<Assertion>().unwrap().check(instances)
+    }
+
+    #[test]
+    fn parses_assertions() {
+        let a: Assertion = "locale:de_DE>=10".parse().unwrap();
+        assert_eq!(a.metric, Metric::Locale("de-DE".to_owned()));
+        assert_eq!(a.holds, &[Ordering::Greater, Ordering::Equal]);
+        assert_eq!(a.threshold, Threshold::Count(10));
+        assert_eq!(a.to_string(), "locale:de_DE>=10");
+
+        let a: Assertion = " down <= 3 ".parse().unwrap();
+        assert_eq!(a.metric, Metric::Down);
+        assert_eq!(a.holds, &[Ordering::Less, Ordering::Equal]);
+        assert_eq!(a.threshold, Threshold::Count(3));
+
+        let a: Assertion = "version>2.6".parse().unwrap();
+        assert_eq!(a.metric, Metric::Version);
+        assert_eq!(a.holds, &[Ordering::Greater]);
+        assert_eq!(a.threshold, Threshold::Version(vec![2, 6]));
+
+        let a: Assertion = "up==5".parse().unwrap();
+        assert_eq!(a.holds, &[Ordering::Equal]);
+    }
+
+    #[test]
+    fn rejects_invalid_assertions() {
+        let error = |expr: &str| expr.parse::<Assertion>().unwrap_err().to_string();
+        assert!(error("up").contains("expected a comparison"));
+        assert!(error("up>=").contains("expected a value after >="));
+        assert!(error("speed>=").contains("expected a value after >="));
+        assert!(error("speed>1").contains("expected 'locale:<locale>'"));
+        assert!(error("locale:!!>=1").contains("not a locale"));
+        assert!(error("up>=x").contains("not a number"));
+        assert!(error("up>=-1").contains("not a number"));
+        assert!(error("version>=two").contains("not a version"));
+    }
+
+    #[test]
+    fn compares_versions_padded() {
+        assert_eq!(compare_versions(&[2, 6, 0], &[2, 6]), Ordering::Equal);
+        assert_eq!(compare_versions(&[2, 6], &[2, 6, 1]), Ordering::Less);
+        assert_eq!(compare_versions(&[2, 10], &[2, 9, 9]), Ordering::Greater);
+    }
+
+    #[test]
+    fn checks_thresholds() {
+        let instances = [
+            instance(Some("2.6.0"), &["en_US", "de_DE"]),
+            instance(Some("2.7.1"), &["en_US"]),
+            instance(None, &[]),
+        ];
+        assert_eq!(check("up=2", &instances), Ok(()));
+        assert_eq!(check("down<1", &instances), Err("sites down: 1".to_owned()));
+        assert_eq!(check("locale:en-US>=2", &instances), Ok(()));
+        assert_eq!(
+            check("locale:de_DE>1", &instances),
+            Err("sites enabling de-DE: 1".to_owned())
+        );
+        assert_eq!(check("version>=2.6", &instances), Ok(()));
+        assert_eq!(check("version<=2.6", &instances), Ok(()));
+        assert_eq!(check("version=2.6", &instances), Ok(()));
+        assert_eq!(
+            check("version>=2.6.1", &instances),
+            Err("oldest release running: 2.6.0".to_owned())
+        );
+        assert_eq!(
+            check("version>=2.6", &instances[2..]),
+            Err("no site's release is known".to_owned())
+        );
+    }
+}
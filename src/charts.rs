@@ -0,0 +1,169 @@
+use crate::adoption::AdoptionReport;
+use crate::db::{LocaleOutcome, Outcome};
+use crate::locale::normalize_locale;
+use crate::releases::parse_version;
+use chrono::{DateTime, Duration, Utc};
+use plotters::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+/// The size of the charts, in pixels.
+const CHART_SIZE: (u32, u32) = (900, 450);
+
+/// At most this many lines are drawn on a chart, those ending highest.
+const MAX_SERIES: usize = 10;
+
+/// One line on a chart: a percentage over time.
+#[derive(Debug)]
+pub struct Series {
+    pub name: String,
+    pub points: Vec<(DateTime<Utc>, f64)>,
+}
+
+/// The start of the interval of length `interval`, counted from `since`,
+/// that `at` falls in.
+fn interval_start(at: DateTime<Utc>, since: DateTime<Utc>, interval: Duration) -> DateTime<Utc> {
+    let secs = interval.num_seconds().max(1);
+    since + Duration::seconds((at - since).num_seconds().div_euclid(secs) * secs)
+}
+
+/// The share of available sites running each version, newest version
+/// first.
+pub fn adoption_series(report: &AdoptionReport) -> Vec<Series> {
+    let mut by_version: BTreeMap<&str, Vec<(DateTime<Utc>, f64)>> = BTreeMap::new();
+    for p in &report.series {
+        for (version, n) in &p.versions {
+            by_version
+                .entry(version)
+                .or_default()
+                .push((p.start, 100.0 * *n as f64 / p.sites.max(1) as f64));
+        }
+    }
+    let mut series: Vec<Series> = by_version
+        .into_iter()
+        .map(|(name, points)| Series {
+            name: name.to_owned(),
+            points,
+        })
+        .collect();
+    series.sort_by_key(|s| std::cmp::Reverse(parse_version(&s.name)));
+    series
+}
+
+/// The percentage of scans, over all sites, in which a site was
+/// available, per interval.
+pub fn uptime_series(outcomes: &[Outcome], since: DateTime<Utc>, interval: Duration) -> Series {
+    let mut buckets: BTreeMap<DateTime<Utc>, (usize, usize)> = BTreeMap::new();
+    for o in outcomes {
+        let (up, scans) = buckets
+            .entry(interval_start(o.scanned_at, since, interval))
+            .or_default();
+        *up += o.available as usize;
+        *scans += 1;
+    }
+    Series {
+        name: "All sites".to_owned(),
+        points: buckets
+            .into_iter()
+            .map(|(start, (up, scans))| (start, 100.0 * up as f64 / scans as f64))
+            .collect(),
+    }
+}
+
+/// The share of available sites supporting each locale, per interval,
+/// as of each site's latest scan in the interval.
+pub fn locale_series(
+    outcomes: &[LocaleOutcome],
+    since: DateTime<Utc>,
+    interval: Duration,
+) -> Vec<Series> {
+    let mut buckets: BTreeMap<DateTime<Utc>, BTreeMap<&str, BTreeSet<String>>> = BTreeMap::new();
+    for o in outcomes {
+        // As in the localization report, locales are counted by their
+        // BCP 47 tags.
+        buckets
+            .entry(interval_start(o.scanned_at, since, interval))
+            .or_default()
+            .insert(
+                &o.onion_address,
+                o.locales
+                    .iter()
+                    .filter_map(|l| normalize_locale(l))
+                    .collect(),
+            );
+    }
+    let mut by_locale: BTreeMap<&str, Vec<(DateTime<Utc>, f64)>> = BTreeMap::new();
+    for (start, sites) in &buckets {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for locales in sites.values() {
+            for l in locales.iter() {
+                *counts.entry(l).or_default() += 1;
+            }
+        }
+        for (locale, n) in counts {
+            by_locale
+                .entry(locale)
+                .or_default()
+                .push((*start, 100.0 * n as f64 / sites.len() as f64));
+        }
+    }
+    by_locale
+        .into_iter()
+        .map(|(name, points)| Series {
+            name: name.to_owned(),
+            points,
+        })
+        .collect()
+}
+
+/// Draws the series as a line chart of percentages over time, returning
+/// the SVG. Only the `MAX_SERIES` series ending highest are drawn, so
+/// e.g. the many locales enabled by a single site don't crowd the legend.
+pub fn render_chart(title: &str, series: &[Series]) -> Result<String, Box<dyn Error>> {
+    let mut series: Vec<&Series> = series.iter().filter(|s| !s.points.is_empty()).collect();
+    let last = |s: &Series| s.points.last().map_or(0.0, |(_, p)| *p);
+    // A stable sort, so series ending level keep their order.
+    series.sort_by(|a, b| last(b).total_cmp(&last(a)));
+    series.truncate(MAX_SERIES);
+    let times = series.iter().flat_map(|s| s.points.iter().map(|(t, _)| *t));
+    let (start, end) = match (times.clone().min(), times.max()) {
+        (Some(start), Some(end)) => (start, end.max(start + Duration::hours(1))),
+        _ => return Err("Nothing to chart, the history has no scans in the window".into()),
+    };
+
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, CHART_SIZE).into_drawing_area();
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(&root)
+            .caption(title, ("sans-serif", 20))
+            .margin(15)
+            .x_label_area_size(30)
+            .y_label_area_size(50)
+            .build_cartesian_2d(RangedDateTime::from(start..end), 0.0..100.0)?;
+        chart
+            .configure_mesh()
+            .x_labels(8)
+            .x_label_formatter(&|t| t.format("%Y-%m-%d").to_string())
+            .y_label_formatter(&|p| format!("{:.0}%", p))
+            .draw()?;
+        for (n, s) in series.iter().enumerate() {
+            let color = Palette99::pick(n).to_rgba();
+            chart
+                // Points are marked, so a series of one point still shows.
+                .draw_series(
+                    LineSeries::new(s.points.iter().copied(), color.stroke_width(2)).point_size(3),
+                )?
+                .label(&s.name)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        }
+        chart
+            .configure_series_labels()
+            .position(SeriesLabelPosition::LowerLeft)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        root.present()?;
+    }
+    Ok(svg)
+}
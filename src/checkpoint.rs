@@ -0,0 +1,84 @@
+use crate::SDDirectoryInstance;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The progress of a scan, kept on disk so an interrupted one can be
+/// resumed: each instance is appended as a line of JSON as soon as its
+/// scan completes, and the file is removed once the whole scan has.
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl Checkpoint {
+    /// Starts a checkpoint at `path` for a new scan, replacing any left by
+    /// an earlier one.
+    pub fn create(path: &Path) -> Result<Checkpoint, Box<dyn Error>> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create checkpoint {}, {}", path.display(), e))?;
+        Ok(Checkpoint {
+            path: path.to_owned(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Reopens the checkpoint at `path` to resume its scan, returning the
+    /// instances it had completed. Without a checkpoint, the scan starts
+    /// afresh. A line cut short, by a crash mid-write, is skipped, so that
+    /// instance is scanned again.
+    pub fn resume(path: &Path) -> Result<(Checkpoint, Vec<SDDirectoryInstance>), Box<dyn Error>> {
+        let mut completed = vec![];
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    match serde_json::from_str(&line?) {
+                        Ok(instance) => completed.push(instance),
+                        Err(e) => warn!("Skipping a malformed checkpoint entry, {}", e),
+                    }
+                }
+                info!(
+                    "Resuming the scan in {}, {} sites already scanned",
+                    path.display(),
+                    completed.len()
+                );
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("No checkpoint at {}, starting a new scan", path.display());
+            }
+            Err(e) => {
+                return Err(format!("Failed to read checkpoint {}, {}", path.display(), e).into())
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open checkpoint {}, {}", path.display(), e))?;
+        let checkpoint = Checkpoint {
+            path: path.to_owned(),
+            file: Mutex::new(file),
+        };
+        Ok((checkpoint, completed))
+    }
+
+    /// Records that `instance`'s scan completed.
+    pub fn record(&self, instance: &SDDirectoryInstance) -> Result<(), Box<dyn Error>> {
+        let mut line = serde_json::to_string(instance)?;
+        line.push('\n');
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Removes the checkpoint, once its scan is complete.
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        std::fs::remove_file(&self.path)
+            .map_err(|e| format!("Failed to remove checkpoint {}, {}", self.path.display(), e))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use std::io::IsTerminal;
+
+/// A color for highlighting text in terminal reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+}
+
+/// Whether to color text output, given `--color`: always, never, or
+/// (for `auto`) when standard output is a terminal and `NO_COLOR` isn't
+/// set, as per https://no-color.org.
+pub fn color_enabled(choice: &str) -> bool {
+    match choice {
+        "always" => true,
+        "never" => false,
+        _ => {
+            std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                && std::io::stdout().is_terminal()
+        }
+    }
+}
+
+/// Wraps `text` in the ANSI escape codes for `color`, if `enabled`. Pad
+/// text before painting it, as the escape codes count towards widths.
+pub fn paint(text: &str, color: Color, enabled: bool) -> String {
+    if !enabled {
+        return text.to_owned();
+    }
+    let code = match color {
+        Color::Red => 31,
+        Color::Green => 32,
+        Color::Yellow => 33,
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
@@ -0,0 +1,58 @@
+use crate::failure::FailureKind;
+use crate::{SDDirectoryInstance, ScanOptions, MAX_CONCURRENCY};
+
+/// How many sites a scan fetches at once. With adaptive concurrency, the
+/// limit follows AIMD (additive increase, multiplicative decrease) as in
+/// TCP congestion control: it halves when a site times out or Tor fails
+/// to build a circuit to it, and otherwise grows by one for each limit's
+/// worth of sites scanned. Failures of sites that were being scanned
+/// alongside each other are one spike, so the limit halves at most once
+/// per limit's worth of results. Without, it stays where it started.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimit {
+    limit: f64,
+    adaptive: bool,
+    /// Results recorded since the limit was last lowered, if it has been.
+    since_decrease: Option<usize>,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(options: &ScanOptions) -> ConcurrencyLimit {
+        ConcurrencyLimit {
+            limit: options.concurrency.clamp(1, MAX_CONCURRENCY) as f64,
+            adaptive: options.adaptive_concurrency,
+            since_decrease: None,
+        }
+    }
+
+    /// How many sites may currently be scanned at once.
+    pub fn current(&self) -> usize {
+        self.limit as usize
+    }
+
+    /// Adjusts the limit to the outcome of scanning a site.
+    pub fn record(&mut self, instance: &SDDirectoryInstance) {
+        if !self.adaptive {
+            return;
+        }
+        let before = self.current();
+        let congested = matches!(
+            instance.failure_kind(),
+            Some(FailureKind::Timeout) | Some(FailureKind::TorCircuit)
+        );
+        if congested && self.since_decrease.is_none_or(|n| n >= before) {
+            self.limit = (self.limit / 2.0).max(1.0);
+            self.since_decrease = Some(0);
+        } else {
+            if !congested {
+                self.limit = (self.limit + 1.0 / self.limit).min(MAX_CONCURRENCY as f64);
+            }
+            if let Some(n) = &mut self.since_decrease {
+                *n += 1;
+            }
+        }
+        if self.current() != before {
+            debug!("Concurrency changed from {} to {}", before, self.current());
+        }
+    }
+}
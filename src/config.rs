@@ -0,0 +1,259 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Settings read from the TOML config file. Every field is optional, and
+/// command-line flags take precedence over any value set here.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The directory API(s) to read sites to scan from.
+    pub directory_url: Option<OneOrMany>,
+    /// The output format, for commands that don't get one via `--format`.
+    pub format: Option<String>,
+    /// A history database to record scans in: the path of a SQLite file,
+    /// or a PostgreSQL connection string.
+    pub db: Option<String>,
+    /// The Tor SOCKS proxy (or proxies, to share sites between) to send
+    /// requests through, as `host:port`.
+    pub socks_proxy: Option<OneOrMany>,
+    /// Whether to fetch each site over circuits of its own.
+    pub isolate_circuits: Option<bool>,
+    /// Whether to check Tor can connect to each site before fetching it.
+    pub preflight: Option<bool>,
+    /// Seconds to wait for each request to a site.
+    pub timeout: Option<u64>,
+    /// Seconds to allow for a whole scan.
+    pub deadline: Option<u64>,
+    /// How many times to retry fetching metadata from a site.
+    pub retries: Option<u32>,
+    /// Seconds to wait before the first retry.
+    pub retry_backoff: Option<u64>,
+    /// Whether to check each site's journalist key against its metadata.
+    pub verify_key: Option<bool>,
+    /// Seconds to leave between consecutive requests to the same site.
+    pub request_delay: Option<f64>,
+    /// The most requests to make to each site in a scan.
+    pub request_budget: Option<u32>,
+    /// How many sites to scan at once.
+    pub concurrency: Option<usize>,
+    /// Whether to adjust the concurrency to how Tor is coping.
+    pub adaptive_concurrency: Option<bool>,
+    /// The SecureDrop release the outdated report compares against,
+    /// instead of fetching the latest one.
+    pub latest_version: Option<String>,
+    /// Days before a landing page's certificate expires to warn about it.
+    pub cert_expiry_days: Option<i64>,
+    /// Where to send notifications of changes between scans.
+    pub notifications: Option<NotificationsConfig>,
+    /// When `daemon` runs scans.
+    pub daemon: Option<DaemonConfig>,
+    /// A file to append log messages to, instead of standard error.
+    pub log_file: Option<String>,
+    /// How to write log messages: `text` or `json`.
+    pub log_format: Option<String>,
+    /// The language to write reports in, as a BCP 47 tag.
+    pub lang: Option<String>,
+    /// A directory to archive each scan's raw responses in.
+    pub snapshot_dir: Option<String>,
+    /// Whether to gzip the archived responses.
+    pub snapshot_compress: Option<bool>,
+    /// A file to record each scan's progress in, so it can be resumed.
+    pub checkpoint: Option<String>,
+    /// The Weblate project to read translation completeness from.
+    pub weblate: Option<WeblateConfig>,
+    /// The repository to file issues about persistent outages in.
+    pub github: Option<GitHubConfig>,
+    /// Where to publish each scan's results for the public directory.
+    pub publish: Option<PublishConfig>,
+    /// Where to export traces and metrics over OTLP.
+    pub telemetry: Option<TelemetryConfig>,
+    /// How many times to fetch each site, to summarize its latency.
+    pub samples: Option<u32>,
+    /// What to check on each site: `metadata` or `ping`.
+    pub mode: Option<String>,
+    /// The User-Agent to send to sites, instead of none.
+    pub user_agent: Option<String>,
+    /// Further headers to send with every request to a site, by name.
+    pub headers: Option<BTreeMap<String, String>>,
+    /// Tags to group and filter sites by, e.g. their region, for each
+    /// Onion address.
+    pub tags: Option<BTreeMap<String, Vec<String>>>,
+    /// The salt `--anonymize` hashes with, to keep pseudonyms stable
+    /// across runs.
+    pub anonymize_salt: Option<String>,
+    /// A minisign secret key to sign the files `scan --output` writes.
+    pub sign_key: Option<String>,
+    /// The password the signing key is encrypted with, if it is.
+    pub sign_key_password: Option<String>,
+}
+
+/// The `[telemetry]` table of the config file, used when sdstatus is
+/// built with the `otel` feature.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// The base URL of the OTLP (HTTP) collector, e.g.
+    /// `http://localhost:4318`.
+    pub otlp_endpoint: Option<String>,
+    /// The service name to report, if not `sdstatus`.
+    pub service_name: Option<String>,
+}
+
+/// The `[github]` table of the config file.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GitHubConfig {
+    /// The repository to file issues in, as `owner/name`.
+    pub repo: String,
+    /// A token allowed to open and close issues in the repository.
+    pub token: String,
+    /// How many consecutive failed scans open an issue.
+    pub consecutive_scans: Option<usize>,
+    /// The label to file issues under.
+    pub label: Option<String>,
+    /// The base URL of the API, if not GitHub's own.
+    pub api_url: Option<String>,
+}
+
+/// The `[publish]` table of the config file.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PublishConfig {
+    /// The endpoint of the securedrop.org API to POST results to.
+    pub url: String,
+    /// The API token to authenticate with.
+    pub token: String,
+}
+
+/// The `[weblate]` table of the config file.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct WeblateConfig {
+    /// An API token for the Weblate instance.
+    pub token: String,
+    /// The base URL of the Weblate instance, if not SecureDrop's own.
+    pub url: Option<String>,
+    /// The project and component translating SecureDrop, if not
+    /// `securedrop` for both.
+    pub project: Option<String>,
+    pub component: Option<String>,
+}
+
+/// The `[daemon]` table of the config file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Seconds between the start of one scan and the next.
+    pub interval: Option<u64>,
+    /// A cron expression to scan on, instead of a fixed interval.
+    pub cron: Option<String>,
+    /// Up to how many seconds to randomly delay each scan by.
+    pub jitter: Option<u64>,
+    /// The address to serve health checks on.
+    pub listen: Option<String>,
+}
+
+/// The `[notifications]` table of the config file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationsConfig {
+    /// URLs to POST a JSON payload to for each event.
+    #[serde(default)]
+    pub webhooks: Vec<String>,
+    /// A Tera template for the messages sent to chat channels, rendered
+    /// with the fields of each event.
+    pub message: Option<String>,
+    pub slack: Option<SlackConfig>,
+    pub matrix: Option<MatrixConfig>,
+    pub email: Option<EmailConfig>,
+    pub mastodon: Option<MastodonConfig>,
+    /// Hours a site must stay unavailable before an `outage` event fires.
+    pub outage_hours: Option<i64>,
+}
+
+/// An SMTP relay to mail critical alerts and scan summaries through.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    pub smtp_server: String,
+    /// Defaults to the usual port for the `tls` mode.
+    pub smtp_port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// How to secure the connection: `starttls` (the default), `tls`, or
+    /// `none` for a trusted local relay.
+    pub tls: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+/// A Slack incoming webhook to post messages to.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SlackConfig {
+    pub webhook_url: String,
+}
+
+/// A Matrix room to post messages to, via the client-server API.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MatrixConfig {
+    /// The base URL of the homeserver, e.g. `https://matrix.org`.
+    pub homeserver: String,
+    /// The room's id (not an alias), e.g. `!abcdef:matrix.org`.
+    pub room_id: String,
+    /// The access token of the account messages are sent as, which must
+    /// have joined the room.
+    pub access_token: String,
+}
+
+/// A Mastodon account to post a weekly summary of the directory from.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MastodonConfig {
+    /// The base URL of the account's server, e.g. `https://mastodon.social`.
+    pub instance: String,
+    /// An access token of the account with the `write:statuses` scope.
+    pub access_token: String,
+    /// The visibility of the posts, `public` unless set (e.g. `unlisted`).
+    pub visibility: Option<String>,
+}
+
+/// A setting that may be given as a single string or a list of them.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    pub fn to_vec(&self) -> Vec<String> {
+        match self {
+            OneOrMany::One(s) => vec![s.to_owned()],
+            OneOrMany::Many(v) => v.to_owned(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file at `path`.
+    pub fn load(path: &Path) -> Result<Config, Box<dyn Error>> {
+        let contents = std::fs::read_to_string(path)?;
+        let config: Config = toml::from_str(&contents)?;
+        Ok(config)
+    }
+
+    /// The location of the config file when none is given explicitly:
+    /// `$XDG_CONFIG_HOME/sdstatus/config.toml`, falling back to
+    /// `~/.config/sdstatus/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(config_home.join("sdstatus").join("config.toml"))
+    }
+}
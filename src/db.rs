@@ -0,0 +1,589 @@
+use crate::{SDDirectoryInstance, SDMetadata};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use std::collections::BTreeMap;
+use std::error::Error;
+
+// Schema migrations, applied in order. The number of migrations already
+// applied to a database is tracked in its `user_version` pragma, so new
+// migrations must only ever be appended to this list, and mirrored in
+// `postgres::MIGRATIONS`.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE scans (
+        id INTEGER PRIMARY KEY,
+        started_at TEXT NOT NULL,
+        finished_at TEXT NOT NULL
+    );
+    CREATE TABLE results (
+        id INTEGER PRIMARY KEY,
+        scan_id INTEGER NOT NULL REFERENCES scans(id),
+        onion_address TEXT NOT NULL,
+        title TEXT NOT NULL,
+        landing_page_url TEXT NOT NULL,
+        available INTEGER NOT NULL,
+        http_status INTEGER,
+        latency_ms INTEGER,
+        error TEXT,
+        metadata TEXT
+    );
+    CREATE INDEX results_onion_address ON results(onion_address);",
+    "ALTER TABLE results ADD COLUMN duration_ms INTEGER;",
+];
+
+/// An instance's result, and when the scan it was part of began.
+pub type TimedResult = (DateTime<Utc>, SDDirectoryInstance);
+
+/// The latest value of a metadata field, and when it was seen, by onion
+/// address.
+pub type LatestValues = BTreeMap<String, (String, DateTime<Utc>)>;
+
+/// Where the history of scans is kept: in a SQLite file (`SqliteHistory`),
+/// or with the `postgres` feature, in a PostgreSQL database
+/// (`postgres::PostgresHistory`). Open one with `open`.
+pub trait Storage: Send {
+    /// Persists a completed scan and the outcome for each instance in it,
+    /// returning the id of the new scan.
+    fn record_scan(
+        &mut self,
+        started_at: DateTime<Utc>,
+        finished_at: DateTime<Utc>,
+        instances: &[SDDirectoryInstance],
+    ) -> Result<i64, Box<dyn Error>>;
+
+    /// Returns the ids of the most recent scans, newest first.
+    fn latest_scan_ids(&self, limit: usize) -> Result<Vec<i64>, Box<dyn Error>>;
+
+    /// Returns when a scan was started.
+    fn scan_started_at(&self, scan_id: i64) -> Result<DateTime<Utc>, Box<dyn Error>>;
+
+    /// Returns when the instance at `onion_address` was first found
+    /// unavailable since it was last available, or None if it was
+    /// available in its latest scan.
+    fn down_since(&self, onion_address: &str) -> Result<Option<DateTime<Utc>>, Box<dyn Error>>;
+
+    /// Returns the outcome for every instance in every scan started since
+    /// `since`, oldest first.
+    fn outcomes_since(&self, since: DateTime<Utc>) -> Result<Vec<Outcome>, Box<dyn Error>>;
+
+    /// Returns the SecureDrop version each available instance reported in
+    /// every scan started since `since`, oldest first.
+    fn versions_since(&self, since: DateTime<Utc>) -> Result<Vec<VersionOutcome>, Box<dyn Error>>;
+
+    /// Returns the locales each available instance supported in every scan
+    /// started since `since`, oldest first.
+    fn locales_since(&self, since: DateTime<Utc>) -> Result<Vec<LocaleOutcome>, Box<dyn Error>>;
+
+    /// Returns the value of a metadata field each instance last reported,
+    /// by onion address, with when the scan that saw it began. Empty
+    /// values are ignored.
+    fn latest_metadata_values(&self, field: &str) -> Result<LatestValues, Box<dyn Error>>;
+
+    /// Returns the GPG fingerprint each instance last advertised, by onion
+    /// address, however long ago that was.
+    fn known_fingerprints(&self) -> Result<BTreeMap<String, KnownFingerprint>, Box<dyn Error>> {
+        Ok(self
+            .latest_metadata_values("gpg_fpr")?
+            .into_iter()
+            .map(|(onion, (fingerprint, seen_at))| {
+                (
+                    onion,
+                    KnownFingerprint {
+                        fingerprint,
+                        seen_at,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Returns the SecureDrop version each instance last reported, by
+    /// onion address, however long ago that was.
+    fn known_versions(&self) -> Result<BTreeMap<String, KnownVersion>, Box<dyn Error>> {
+        Ok(self
+            .latest_metadata_values("sd_version")?
+            .into_iter()
+            .map(|(onion, (sd_version, seen_at))| {
+                (
+                    onion,
+                    KnownVersion {
+                        sd_version,
+                        seen_at,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Returns the title and Onion address of every result with a title,
+    /// oldest first.
+    fn listings(&self) -> Result<Vec<Listing>, Box<dyn Error>>;
+
+    /// Returns the Onion address each title was last listed with, by title.
+    /// Titles listed with several addresses in their latest scan, which
+    /// are likely distinct sites, are left out.
+    fn known_addresses(&self) -> Result<BTreeMap<String, KnownAddress>, Box<dyn Error>> {
+        // The addresses of each title in its latest scan.
+        let mut latest: BTreeMap<String, (i64, DateTime<Utc>, Vec<String>)> = BTreeMap::new();
+        for l in self.listings()? {
+            let (scan_id, scanned_at) = (l.scan_id, l.scanned_at);
+            let entry = latest
+                .entry(l.title)
+                .or_insert_with(|| (scan_id, scanned_at, vec![]));
+            if entry.0 != l.scan_id {
+                *entry = (l.scan_id, l.scanned_at, vec![]);
+            }
+            if !entry.2.contains(&l.onion_address) {
+                entry.2.push(l.onion_address);
+            }
+        }
+        Ok(latest
+            .into_iter()
+            .filter(|(_, (_, _, addresses))| addresses.len() == 1)
+            .map(|(title, (_, seen_at, mut addresses))| {
+                (
+                    title,
+                    KnownAddress {
+                        onion_address: addresses.remove(0),
+                        seen_at,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Loads the instances recorded in a scan, in the same shape as the
+    /// JSON output of `scan`.
+    fn load_scan(&self, scan_id: i64) -> Result<Vec<SDDirectoryInstance>, Box<dyn Error>>;
+
+    /// Loads the most recent result recorded for the instance at
+    /// `onion_address`, and when the scan it was part of began.
+    fn latest_result(&self, onion_address: &str) -> Result<Option<TimedResult>, Box<dyn Error>>;
+
+    /// Returns when the instance at `onion_address` was last found
+    /// available, or None if it never was.
+    fn last_available(&self, onion_address: &str) -> Result<Option<DateTime<Utc>>, Box<dyn Error>>;
+}
+
+/// Opens (or creates) the history database at `location`, bringing its
+/// schema up to date: a PostgreSQL connection string such as
+/// `postgres://user@host/sdstatus`, or the path of a SQLite file.
+pub fn open(location: &str) -> Result<Box<dyn Storage>, Box<dyn Error>> {
+    if location.starts_with("postgres://") || location.starts_with("postgresql://") {
+        #[cfg(feature = "postgres")]
+        return Ok(Box::new(crate::postgres::PostgresHistory::connect(
+            location,
+        )?));
+        #[cfg(not(feature = "postgres"))]
+        return Err(
+            "sdstatus was built without PostgreSQL support, rebuild it with --features postgres"
+                .into(),
+        );
+    }
+    Ok(Box::new(SqliteHistory::open(location)?))
+}
+
+/// SqliteHistory stores the results of each scan in a SQLite database, so
+/// that later runs can compare against them.
+pub struct SqliteHistory {
+    conn: Connection,
+}
+
+impl SqliteHistory {
+    /// Opens (or creates) the database at `path`, bringing its schema up
+    /// to date.
+    pub fn open(path: &str) -> Result<SqliteHistory, Box<dyn Error>> {
+        let mut conn = Connection::open(path)?;
+        migrate(&mut conn)?;
+        Ok(SqliteHistory { conn })
+    }
+}
+
+impl Storage for SqliteHistory {
+    fn record_scan(
+        &mut self,
+        started_at: DateTime<Utc>,
+        finished_at: DateTime<Utc>,
+        instances: &[SDDirectoryInstance],
+    ) -> Result<i64, Box<dyn Error>> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO scans (started_at, finished_at) VALUES (?1, ?2)",
+            params![started_at.to_rfc3339(), finished_at.to_rfc3339()],
+        )?;
+        let scan_id = tx.last_insert_rowid();
+        for i in instances {
+            let metadata = match &i.metadata {
+                Some(m) => Some(serde_json::to_string(m)?),
+                None => None,
+            };
+            tx.execute(
+                "INSERT INTO results (scan_id, onion_address, title, landing_page_url,
+                     available, http_status, latency_ms, duration_ms, error, metadata)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    scan_id,
+                    i.onion_address,
+                    i.title,
+                    i.landing_page_url,
+                    i.available,
+                    i.http_status,
+                    i.latency_ms,
+                    i.duration_ms,
+                    i.error,
+                    metadata,
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(scan_id)
+    }
+
+    fn latest_scan_ids(&self, limit: usize) -> Result<Vec<i64>, Box<dyn Error>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id FROM scans ORDER BY started_at DESC, id DESC LIMIT ?1")?;
+        let ids = stmt
+            .query_map(params![limit as i64], |row| row.get(0))?
+            .collect::<Result<Vec<i64>, _>>()?;
+        Ok(ids)
+    }
+
+    fn scan_started_at(&self, scan_id: i64) -> Result<DateTime<Utc>, Box<dyn Error>> {
+        let started_at: String = self.conn.query_row(
+            "SELECT started_at FROM scans WHERE id = ?1",
+            params![scan_id],
+            |row| row.get(0),
+        )?;
+        Ok(DateTime::parse_from_rfc3339(&started_at)?.with_timezone(&Utc))
+    }
+
+    fn down_since(&self, onion_address: &str) -> Result<Option<DateTime<Utc>>, Box<dyn Error>> {
+        let started_at: Option<String> = self.conn.query_row(
+            "SELECT MIN(s.started_at) FROM results r JOIN scans s ON s.id = r.scan_id
+             WHERE r.onion_address = ?1 AND NOT r.available AND s.started_at > COALESCE(
+                 (SELECT MAX(s2.started_at) FROM results r2 JOIN scans s2 ON s2.id = r2.scan_id
+                  WHERE r2.onion_address = ?1 AND r2.available), '')",
+            params![onion_address],
+            |row| row.get(0),
+        )?;
+        match started_at {
+            Some(s) => Ok(Some(DateTime::parse_from_rfc3339(&s)?.with_timezone(&Utc))),
+            None => Ok(None),
+        }
+    }
+
+    fn outcomes_since(&self, since: DateTime<Utc>) -> Result<Vec<Outcome>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT r.onion_address, r.title, s.started_at, r.available
+             FROM results r JOIN scans s ON s.id = r.scan_id
+             WHERE s.started_at >= ?1 ORDER BY s.started_at, r.id",
+        )?;
+        let rows = stmt.query_map(params![since.to_rfc3339()], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, bool>(3)?,
+            ))
+        })?;
+        let mut outcomes = vec![];
+        for row in rows {
+            let (onion_address, title, started_at, available) = row?;
+            outcomes.push(Outcome {
+                onion_address,
+                title,
+                scanned_at: DateTime::parse_from_rfc3339(&started_at)?.with_timezone(&Utc),
+                available,
+            });
+        }
+        Ok(outcomes)
+    }
+
+    fn versions_since(&self, since: DateTime<Utc>) -> Result<Vec<VersionOutcome>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT r.onion_address, r.title, s.started_at, json_extract(r.metadata, '$.sd_version')
+             FROM results r JOIN scans s ON s.id = r.scan_id
+             WHERE s.started_at >= ?1 AND r.available AND r.metadata IS NOT NULL
+             ORDER BY s.started_at, r.id",
+        )?;
+        let rows = stmt.query_map(params![since.to_rfc3339()], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?;
+        let mut outcomes = vec![];
+        for row in rows {
+            let (onion_address, title, started_at, sd_version) = row?;
+            if let Some(sd_version) = sd_version.filter(|v| !v.is_empty()) {
+                outcomes.push(VersionOutcome {
+                    onion_address,
+                    title,
+                    scanned_at: DateTime::parse_from_rfc3339(&started_at)?.with_timezone(&Utc),
+                    sd_version,
+                });
+            }
+        }
+        Ok(outcomes)
+    }
+
+    fn locales_since(&self, since: DateTime<Utc>) -> Result<Vec<LocaleOutcome>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT r.onion_address, r.title, s.started_at,
+                    json_extract(r.metadata, '$.supported_languages')
+             FROM results r JOIN scans s ON s.id = r.scan_id
+             WHERE s.started_at >= ?1 AND r.available AND r.metadata IS NOT NULL
+             ORDER BY s.started_at, r.id",
+        )?;
+        let rows = stmt.query_map(params![since.to_rfc3339()], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?;
+        let mut outcomes = vec![];
+        for row in rows {
+            let (onion_address, title, started_at, locales) = row?;
+            outcomes.push(LocaleOutcome {
+                onion_address,
+                title,
+                scanned_at: DateTime::parse_from_rfc3339(&started_at)?.with_timezone(&Utc),
+                locales: match locales {
+                    Some(locales) => serde_json::from_str(&locales)?,
+                    None => vec![],
+                },
+            });
+        }
+        Ok(outcomes)
+    }
+
+    fn latest_metadata_values(&self, field: &str) -> Result<LatestValues, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT r.onion_address, s.started_at, json_extract(r.metadata, ?1) AS value
+             FROM results r JOIN scans s ON s.id = r.scan_id
+             WHERE value IS NOT NULL AND value != ''
+             ORDER BY s.started_at, r.id",
+        )?;
+        let rows = stmt.query_map(params![format!("$.{}", field)], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        let mut latest = BTreeMap::new();
+        for row in rows {
+            let (onion_address, started_at, value) = row?;
+            // Later scans overwrite earlier ones.
+            latest.insert(
+                onion_address,
+                (
+                    value,
+                    DateTime::parse_from_rfc3339(&started_at)?.with_timezone(&Utc),
+                ),
+            );
+        }
+        Ok(latest)
+    }
+
+    fn listings(&self) -> Result<Vec<Listing>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT r.title, r.onion_address, s.id, s.started_at
+             FROM results r JOIN scans s ON s.id = r.scan_id
+             WHERE r.title != ''
+             ORDER BY s.started_at, r.id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+        let mut listings = vec![];
+        for row in rows {
+            let (title, onion_address, scan_id, started_at) = row?;
+            listings.push(Listing {
+                title,
+                onion_address,
+                scan_id,
+                scanned_at: DateTime::parse_from_rfc3339(&started_at)?.with_timezone(&Utc),
+            });
+        }
+        Ok(listings)
+    }
+
+    fn load_scan(&self, scan_id: i64) -> Result<Vec<SDDirectoryInstance>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT onion_address, title, landing_page_url, available, http_status,
+                    latency_ms, duration_ms, error, metadata
+             FROM results WHERE scan_id = ?1 ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![scan_id], |row| {
+            Ok((instance_from_row(row)?, row.get::<_, Option<String>>(8)?))
+        })?;
+        let mut instances = vec![];
+        for row in rows {
+            let (mut i, metadata) = row?;
+            if let Some(m) = metadata {
+                i.metadata = Some(serde_json::from_str::<SDMetadata>(&m)?);
+            }
+            instances.push(i);
+        }
+        Ok(instances)
+    }
+
+    fn latest_result(&self, onion_address: &str) -> Result<Option<TimedResult>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT r.onion_address, r.title, r.landing_page_url, r.available, r.http_status,
+                    r.latency_ms, r.duration_ms, r.error, r.metadata, s.started_at
+             FROM results r JOIN scans s ON s.id = r.scan_id
+             WHERE r.onion_address = ?1 ORDER BY s.started_at DESC, r.id DESC LIMIT 1",
+        )?;
+        let mut rows = stmt.query_map(params![onion_address], |row| {
+            Ok((
+                instance_from_row(row)?,
+                row.get::<_, Option<String>>(8)?,
+                row.get::<_, String>(9)?,
+            ))
+        })?;
+        match rows.next() {
+            Some(row) => {
+                let (mut i, metadata, started_at) = row?;
+                if let Some(m) = metadata {
+                    i.metadata = Some(serde_json::from_str::<SDMetadata>(&m)?);
+                }
+                let started_at = DateTime::parse_from_rfc3339(&started_at)?.with_timezone(&Utc);
+                Ok(Some((started_at, i)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn last_available(&self, onion_address: &str) -> Result<Option<DateTime<Utc>>, Box<dyn Error>> {
+        let started_at: Option<String> = self.conn.query_row(
+            "SELECT MAX(s.started_at) FROM results r JOIN scans s ON s.id = r.scan_id
+             WHERE r.onion_address = ?1 AND r.available",
+            params![onion_address],
+            |row| row.get(0),
+        )?;
+        match started_at {
+            Some(s) => Ok(Some(DateTime::parse_from_rfc3339(&s)?.with_timezone(&Utc))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Reads the columns of a result row, in the order `load_scan` selects
+/// them, into an instance without metadata.
+fn instance_from_row(row: &rusqlite::Row) -> rusqlite::Result<SDDirectoryInstance> {
+    Ok(SDDirectoryInstance {
+        metadata: None,
+        onion_name: None,
+        onion_address: row.get(0)?,
+        title: row.get(1)?,
+        landing_page_url: row.get(2)?,
+        available: row.get(3)?,
+        http_status: row.get(4)?,
+        latency_ms: row.get(5)?,
+        duration_ms: row.get(6)?,
+        error: row.get(7)?,
+        failure: None,
+        attempts: 0,
+        key_fingerprint: None,
+        key_matches: None,
+        journalist_key: None,
+        schema_drift: vec![],
+        raw_metadata: None,
+        samples: None,
+    })
+}
+
+/// The outcome of scanning one instance, and when the scan began.
+#[derive(Debug)]
+pub struct Outcome {
+    pub onion_address: String,
+    pub title: String,
+    pub scanned_at: DateTime<Utc>,
+    pub available: bool,
+}
+
+/// The version an available instance reported in one scan, and when the
+/// scan began.
+#[derive(Debug)]
+pub struct VersionOutcome {
+    pub onion_address: String,
+    pub title: String,
+    pub scanned_at: DateTime<Utc>,
+    pub sd_version: String,
+}
+
+/// The locales an available instance supported in one scan, and when the
+/// scan began.
+#[derive(Debug)]
+pub struct LocaleOutcome {
+    pub onion_address: String,
+    pub title: String,
+    pub scanned_at: DateTime<Utc>,
+    pub locales: Vec<String>,
+}
+
+/// An instance's title and Onion address in one scan.
+#[derive(Debug)]
+pub struct Listing {
+    pub title: String,
+    pub onion_address: String,
+    pub scan_id: i64,
+    pub scanned_at: DateTime<Utc>,
+}
+
+/// The GPG fingerprint an instance last advertised, and when the scan
+/// that saw it began.
+#[derive(Debug, Clone)]
+pub struct KnownFingerprint {
+    pub fingerprint: String,
+    pub seen_at: DateTime<Utc>,
+}
+
+impl KnownFingerprint {
+    /// Whether `fingerprint` is another key than this one. Fingerprints
+    /// are compared ignoring case and spaces.
+    pub fn rotated_to(&self, fingerprint: &str) -> bool {
+        let normalize = |f: &str| f.replace(' ', "").to_uppercase();
+        !fingerprint.trim().is_empty() && normalize(&self.fingerprint) != normalize(fingerprint)
+    }
+}
+
+/// The SecureDrop version an instance last reported, and when the scan
+/// that saw it began.
+#[derive(Debug, Clone)]
+pub struct KnownVersion {
+    pub sd_version: String,
+    pub seen_at: DateTime<Utc>,
+}
+
+/// The Onion address a title was last listed with, and when the scan that
+/// saw it began.
+#[derive(Debug, Clone)]
+pub struct KnownAddress {
+    pub onion_address: String,
+    pub seen_at: DateTime<Utc>,
+}
+
+/// Applies any migrations the database has not seen yet.
+fn migrate(conn: &mut Connection) -> Result<(), Box<dyn Error>> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (n, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        debug!("Applying database migration {}", n + 1);
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", n + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use crate::SDDirectoryInstance;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// An instance present in only one of the two compared scans.
+#[derive(Serialize, Debug)]
+pub struct Entry {
+    pub title: String,
+    pub onion_address: String,
+}
+
+/// A metadata field that differs between the two compared scans.
+#[derive(Serialize, Debug)]
+pub struct Change {
+    pub title: String,
+    pub onion_address: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// The differences between two scans, matching instances by onion address.
+#[derive(Serialize, Debug, Default)]
+pub struct ScanDiff {
+    pub appeared: Vec<Entry>,
+    pub disappeared: Vec<Entry>,
+    pub version_changes: Vec<Change>,
+    pub os_changes: Vec<Change>,
+    pub fingerprint_changes: Vec<Change>,
+}
+
+impl ScanDiff {
+    /// Compares an older scan against a newer one. Metadata changes are
+    /// only reported for instances that were available in both scans.
+    pub fn between(old: &[SDDirectoryInstance], new: &[SDDirectoryInstance]) -> ScanDiff {
+        let old_by_onion: BTreeMap<&str, &SDDirectoryInstance> =
+            old.iter().map(|i| (i.onion_address.as_str(), i)).collect();
+        let new_by_onion: BTreeMap<&str, &SDDirectoryInstance> =
+            new.iter().map(|i| (i.onion_address.as_str(), i)).collect();
+        let mut diff = ScanDiff::default();
+        for (onion, i) in &new_by_onion {
+            let o = match old_by_onion.get(onion) {
+                Some(o) => o,
+                None => {
+                    diff.appeared.push(entry(i));
+                    continue;
+                }
+            };
+            if let (Some(om), Some(nm)) = (&o.metadata, &i.metadata) {
+                if om.sd_version != nm.sd_version {
+                    diff.version_changes
+                        .push(change(i, &om.sd_version, &nm.sd_version));
+                }
+                if om.server_os != nm.server_os {
+                    diff.os_changes
+                        .push(change(i, &om.server_os, &nm.server_os));
+                }
+                if om.gpg_fpr != nm.gpg_fpr {
+                    diff.fingerprint_changes
+                        .push(change(i, &om.gpg_fpr, &nm.gpg_fpr));
+                }
+            }
+        }
+        for (onion, o) in &old_by_onion {
+            if !new_by_onion.contains_key(onion) {
+                diff.disappeared.push(entry(o));
+            }
+        }
+        diff
+    }
+}
+
+fn entry(i: &SDDirectoryInstance) -> Entry {
+    Entry {
+        title: i.title.to_owned(),
+        onion_address: i.onion_address.to_owned(),
+    }
+}
+
+fn change(i: &SDDirectoryInstance, old: &str, new: &str) -> Change {
+    Change {
+        title: i.title.to_owned(),
+        onion_address: i.onion_address.to_owned(),
+        old: old.to_owned(),
+        new: new.to_owned(),
+    }
+}
+
+/// Renders the differences between two scans as human-readable text.
+pub fn format_diff(diff: &ScanDiff) -> String {
+    let mut report = String::from("");
+    for (heading, entries) in &[
+        ("Appeared", &diff.appeared),
+        ("Disappeared", &diff.disappeared),
+    ] {
+        report += &format!("{} ({}):\n", heading, entries.len());
+        for e in entries.iter() {
+            report += &format!("  {} ({})\n", e.title, e.onion_address);
+        }
+        report += "\n";
+    }
+    for (heading, changes) in &[
+        ("Version changed", &diff.version_changes),
+        ("Server OS changed", &diff.os_changes),
+        ("GPG fingerprint rotated", &diff.fingerprint_changes),
+    ] {
+        report += &format!("{} ({}):\n", heading, changes.len());
+        for c in changes.iter() {
+            report += &format!(
+                "  {} ({}): {} -> {}\n",
+                c.title, c.onion_address, c.old, c.new
+            );
+        }
+        report += "\n";
+    }
+    report
+}
@@ -0,0 +1,217 @@
+use crate::http::HttpError;
+use crate::preflight::PreflightError;
+use serde::{Deserialize, Serialize};
+use serde_json::error::Category;
+
+/// Why an instance's metadata couldn't be retrieved, for triaging failed
+/// instances without reading through error messages.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// The Tor SOCKS proxy itself couldn't be reached.
+    TorProxyUnreachable,
+    /// Tor couldn't find a valid descriptor for the Onion service, so the
+    /// service is offline (as opposed to its web server being broken).
+    DescriptorNotFound,
+    /// Tor couldn't build a circuit to the Onion service.
+    TorCircuit,
+    /// The circuit was built, but the service refused the connection.
+    ConnectionRefused,
+    Timeout,
+    /// The service responded with a 4xx status.
+    HttpClientError,
+    /// The service responded with a 5xx status.
+    HttpServerError,
+    /// The response wasn't valid JSON.
+    JsonParse,
+    /// The response was JSON, but not metadata as SecureDrop serves it.
+    SchemaMismatch,
+    /// The directory lists an address that isn't a valid v3 Onion address.
+    MalformedAddress,
+    /// The scan deadline passed before the instance was scanned.
+    DeadlineExceeded,
+    Other,
+}
+
+impl std::fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            FailureKind::TorProxyUnreachable => "Tor proxy unreachable",
+            FailureKind::DescriptorNotFound => "descriptor not found",
+            FailureKind::TorCircuit => "Tor circuit failure",
+            FailureKind::ConnectionRefused => "connection refused",
+            FailureKind::Timeout => "timeout",
+            FailureKind::HttpClientError => "HTTP 4xx",
+            FailureKind::HttpServerError => "HTTP 5xx",
+            FailureKind::JsonParse => "JSON parse error",
+            FailureKind::SchemaMismatch => "schema mismatch",
+            FailureKind::MalformedAddress => "malformed address",
+            FailureKind::DeadlineExceeded => "deadline exceeded",
+            FailureKind::Other => "other",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FailureKind {
+    /// Classifies a failed request.
+    pub fn from_http_error(error: &HttpError) -> FailureKind {
+        match error {
+            HttpError::Request { source } if source.is_timeout() => FailureKind::Timeout,
+            _ => FailureKind::classify(&error.to_string()),
+        }
+    }
+
+    /// Classifies a response that couldn't be read as metadata.
+    pub fn from_parse_error(error: &serde_json::Error) -> FailureKind {
+        match error.classify() {
+            Category::Data => FailureKind::SchemaMismatch,
+            _ => FailureKind::JsonParse,
+        }
+    }
+
+    /// Classifies a response by its HTTP status, if it is an error.
+    pub fn from_status(status: u16) -> Option<FailureKind> {
+        match status {
+            400..=499 => Some(FailureKind::HttpClientError),
+            500..=599 => Some(FailureKind::HttpServerError),
+            _ => None,
+        }
+    }
+
+    /// Classifies a failure from its error message alone, for results
+    /// recorded without a classification. The messages matched are those
+    /// of reqwest, tokio-socks and serde_json. Errors from the proxy's own
+    /// socket, as opposed to replies from Tor, carry an OS error code.
+    pub fn classify(error: &str) -> FailureKind {
+        let error = error.to_lowercase();
+        if error.starts_with("malformed onion address") {
+            FailureKind::MalformedAddress
+        } else if error.starts_with("scan deadline exceeded") {
+            FailureKind::DeadlineExceeded
+        } else if error.starts_with("http status 4") {
+            FailureKind::HttpClientError
+        } else if error.starts_with("http status 5") {
+            FailureKind::HttpServerError
+        } else if error.starts_with("error decoding response body") {
+            let data = [
+                "missing field",
+                "invalid type",
+                "invalid value",
+                "invalid length",
+                "unknown variant",
+            ];
+            if data.iter().any(|d| error.contains(d)) {
+                FailureKind::SchemaMismatch
+            } else {
+                FailureKind::JsonParse
+            }
+        } else if let Some(code) = error
+            .split_once("(socks reply 0x")
+            .and_then(|(_, code)| u8::from_str_radix(code.get(..2)?, 16).ok())
+        {
+            PreflightError::Rejected { code }.failure_kind()
+        } else if error.starts_with("pre-flight check failed") {
+            FailureKind::TorProxyUnreachable
+        } else if error.contains("timed out") || error.contains("ttl expired") {
+            FailureKind::Timeout
+        } else if error.contains("socks connect error: proxy server unreachable")
+            || (error.contains("socks connect error") && error.contains("os error"))
+        {
+            FailureKind::TorProxyUnreachable
+        } else if error.contains("socks connect error: connection refused") {
+            FailureKind::ConnectionRefused
+        } else if error.contains("socks connect error") {
+            FailureKind::TorCircuit
+        } else {
+            FailureKind::Other
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_statuses() {
+        assert_eq!(FailureKind::from_status(200), None);
+        assert_eq!(FailureKind::from_status(302), None);
+        assert_eq!(
+            FailureKind::from_status(404),
+            Some(FailureKind::HttpClientError)
+        );
+        assert_eq!(
+            FailureKind::from_status(503),
+            Some(FailureKind::HttpServerError)
+        );
+    }
+
+    #[test]
+    fn classifies_parse_errors() {
+        let syntax = serde_json::from_str::<serde_json::Value>("<html>").unwrap_err();
+        assert_eq!(
+            FailureKind::from_parse_error(&syntax),
+            FailureKind::JsonParse
+        );
+        let data = serde_json::from_str::<Vec<u8>>(r#"{"a": 1}"#).unwrap_err();
+        assert_eq!(
+            FailureKind::from_parse_error(&data),
+            FailureKind::SchemaMismatch
+        );
+    }
+
+    #[test]
+    fn classifies_error_messages() {
+        let cases = [
+            (
+                "Malformed onion address, checksum does not match",
+                FailureKind::MalformedAddress,
+            ),
+            ("Scan deadline exceeded", FailureKind::DeadlineExceeded),
+            ("HTTP status 403", FailureKind::HttpClientError),
+            ("HTTP status 502", FailureKind::HttpServerError),
+            (
+                "error decoding response body: expected value at line 1 column 1",
+                FailureKind::JsonParse,
+            ),
+            (
+                "error decoding response body: missing field `sd_version`",
+                FailureKind::SchemaMismatch,
+            ),
+            (
+                "socks connect error: onion service descriptor not found (SOCKS reply 0xf0)",
+                FailureKind::DescriptorNotFound,
+            ),
+            (
+                "Pre-flight check failed, Connection refused (os error 111)",
+                FailureKind::TorProxyUnreachable,
+            ),
+            ("operation timed out", FailureKind::Timeout),
+            (
+                "error trying to connect: socks connect error: Connection refused (os error 111)",
+                FailureKind::TorProxyUnreachable,
+            ),
+            (
+                "socks connect error: connection refused",
+                FailureKind::ConnectionRefused,
+            ),
+            (
+                "socks connect error: general SOCKS server failure",
+                FailureKind::TorCircuit,
+            ),
+            ("something else entirely", FailureKind::Other),
+        ];
+        for (error, kind) in cases {
+            assert_eq!(FailureKind::classify(error), kind, "{}", error);
+        }
+    }
+
+    #[test]
+    fn classifies_recorded_errors() {
+        let error = HttpError::Recorded {
+            message: "socks connect error: TTL expired (SOCKS reply 0x06)".to_owned(),
+        };
+        assert_eq!(FailureKind::from_http_error(&error), FailureKind::Timeout);
+    }
+}
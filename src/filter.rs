@@ -0,0 +1,138 @@
+use crate::locale::normalize_locale;
+use crate::SDDirectoryInstance;
+use regex::{Regex, RegexBuilder};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::str::FromStr;
+
+/// The instance field a pattern is matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Title,
+    Onion,
+    Locale,
+    Tag,
+}
+
+/// A pattern such as `Guardian`, `title:^The`, `onion:^abc`,
+/// `locale:de_DE` or `tag:^europe$`. Patterns without a field prefix match the title.
+/// Matching is a case-insensitive regular expression search.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    field: Field,
+    regex: Regex,
+}
+
+impl FromStr for Pattern {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, expr) = match s.split_once(':') {
+            Some(("title", expr)) => (Field::Title, expr),
+            Some(("onion", expr)) => (Field::Onion, expr),
+            Some(("locale", expr)) => (Field::Locale, expr),
+            Some(("tag", expr)) => (Field::Tag, expr),
+            _ => (Field::Title, s),
+        };
+        let regex = RegexBuilder::new(expr)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| format!("Invalid filter {}, {}", s, e))?;
+        Ok(Pattern { field, regex })
+    }
+}
+
+impl Pattern {
+    /// Whether the pattern matches a locale as given, or as normalized to
+    /// BCP 47, so `locale:pt-BR` also selects sites listing `pt_BR`.
+    fn matches_locale(&self, locale: &str) -> bool {
+        self.regex.is_match(locale)
+            || normalize_locale(locale).is_some_and(|l| self.regex.is_match(&l))
+    }
+
+    /// Whether the pattern matches, or None if it matches on locales and
+    /// they aren't known.
+    fn matches(
+        &self,
+        title: &str,
+        onion_address: &str,
+        locales: Option<&[String]>,
+        tags: &[String],
+    ) -> Option<bool> {
+        match self.field {
+            Field::Title => Some(self.regex.is_match(title)),
+            Field::Onion => Some(self.regex.is_match(onion_address)),
+            Field::Locale => locales.map(|l| l.iter().any(|l| self.matches_locale(l))),
+            Field::Tag => Some(tags.iter().any(|t| self.regex.is_match(t))),
+        }
+    }
+}
+
+/// Selects instances with `--include` and `--exclude` patterns. An
+/// instance is kept if it matches any include pattern (or there are none),
+/// and no exclude pattern.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub include: Vec<Pattern>,
+    pub exclude: Vec<Pattern>,
+    /// The tags of each Onion address, which `tag:` patterns match.
+    pub tags: BTreeMap<String, Vec<String>>,
+}
+
+impl Filter {
+    /// Whether the filter has no patterns, and so keeps everything.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether an instance with these fields is kept. When its locales are
+    /// unknown (None), locale patterns are given the benefit of the doubt.
+    pub fn admits(&self, title: &str, onion_address: &str, locales: Option<&[String]>) -> bool {
+        let tags = self
+            .tags
+            .get(onion_address)
+            .map(|t| t.as_slice())
+            .unwrap_or(&[]);
+        let included = self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|p| p.matches(title, onion_address, locales, tags) != Some(false));
+        let excluded = self
+            .exclude
+            .iter()
+            .any(|p| p.matches(title, onion_address, locales, tags) == Some(true));
+        included && !excluded
+    }
+
+    /// Whether a directory listing could be kept once it has been scanned,
+    /// judging by its title and onion address alone.
+    pub fn admits_listing(&self, instance: &SDDirectoryInstance) -> bool {
+        self.admits(&instance.title, &instance.onion_address, None)
+    }
+
+    /// Whether a scanned instance is kept. Instances without metadata
+    /// support no locales.
+    pub fn admits_instance(&self, instance: &SDDirectoryInstance) -> bool {
+        let locales = instance
+            .metadata
+            .as_ref()
+            .map(|m| m.supported_languages.as_slice())
+            .unwrap_or(&[]);
+        self.admits(&instance.title, &instance.onion_address, Some(locales))
+    }
+
+    /// Keeps only the scanned instances the filter admits.
+    pub fn apply(&self, instances: Vec<SDDirectoryInstance>) -> Vec<SDDirectoryInstance> {
+        if self.is_empty() {
+            return instances;
+        }
+        let before = instances.len();
+        let kept: Vec<_> = instances
+            .into_iter()
+            .filter(|i| self.admits_instance(i))
+            .collect();
+        debug!("Filtered {} of {} instances", before - kept.len(), before);
+        kept
+    }
+}
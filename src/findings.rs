@@ -0,0 +1,245 @@
+use crate::color::{paint, Color};
+use crate::db::{KnownFingerprint, KnownVersion};
+use crate::keys::{key_status, KeyStatus, EXPIRY_WARNING_DAYS};
+use crate::landing::Severity;
+use crate::releases::parse_version;
+use crate::{normalize_onion, SDDirectoryInstance};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// What a finding about an instance is about.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    /// The published journalist key doesn't match the metadata.
+    KeyMismatch,
+    /// The metadata advertises another GPG fingerprint than the instance
+    /// last did in the history.
+    KeyRotated,
+    /// The metadata reports an older SecureDrop version than the instance
+    /// last did in the history.
+    VersionDowngrade,
+    /// The published journalist key has expired.
+    KeyExpired,
+    /// The published journalist key expires soon.
+    KeyExpiring,
+    /// The metadata still advertises a v2 Onion address, which Tor no
+    /// longer supports.
+    V2Onion,
+    /// The metadata advertises another Onion address than the directory
+    /// lists.
+    DirectoryMismatch,
+    /// The metadata differs from the schema sdstatus expects.
+    SchemaDrift,
+}
+
+/// Something about a scanned instance for the directory team to follow
+/// up on.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Finding {
+    pub title: String,
+    pub onion_address: String,
+    pub severity: Severity,
+    pub kind: FindingKind,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(
+        instance: &SDDirectoryInstance,
+        severity: Severity,
+        kind: FindingKind,
+        message: String,
+    ) -> Finding {
+        Finding {
+            title: instance.title.to_owned(),
+            onion_address: instance.onion_address.to_owned(),
+            severity,
+            kind,
+            message,
+        }
+    }
+}
+
+/// Lists the findings about scanned instances, most severe first.
+pub fn detect_findings(instances: &[SDDirectoryInstance]) -> Vec<Finding> {
+    let mut findings = vec![];
+    for i in instances {
+        if i.key_matches == Some(false) {
+            findings.push(Finding::new(
+                i,
+                Severity::High,
+                FindingKind::KeyMismatch,
+                "published journalist key does not match the metadata".to_owned(),
+            ));
+        }
+        if let Some(key) = &i.journalist_key {
+            let expires = key.expires();
+            let warning = Duration::days(EXPIRY_WARNING_DAYS);
+            match (key_status(expires, warning, Utc::now()), expires) {
+                (KeyStatus::Expired, Some(expires)) => findings.push(Finding::new(
+                    i,
+                    Severity::High,
+                    FindingKind::KeyExpired,
+                    format!(
+                        "published journalist key expired on {}",
+                        expires.format("%Y-%m-%d")
+                    ),
+                )),
+                (KeyStatus::Expiring, Some(expires)) => findings.push(Finding::new(
+                    i,
+                    Severity::Medium,
+                    FindingKind::KeyExpiring,
+                    format!(
+                        "published journalist key expires on {}",
+                        expires.format("%Y-%m-%d")
+                    ),
+                )),
+                _ => {}
+            }
+        }
+        let v2 = i.metadata.as_ref().and_then(|m| m.v2_source_url.as_deref());
+        if let Some(url) = v2.filter(|url| !url.trim().is_empty()) {
+            findings.push(Finding::new(
+                i,
+                Severity::Medium,
+                FindingKind::V2Onion,
+                format!("metadata still advertises the v2 Onion address {}", url),
+            ));
+        }
+        if let Some(m) = &i.metadata {
+            let advertised = normalize_onion(&m.v3_source_url).to_lowercase();
+            let listed = normalize_onion(&i.onion_address).to_lowercase();
+            if !advertised.is_empty() && advertised != listed {
+                findings.push(Finding::new(
+                    i,
+                    Severity::Medium,
+                    FindingKind::DirectoryMismatch,
+                    format!(
+                        "metadata advertises the Onion address {}, but the directory lists {}",
+                        m.v3_source_url.trim(),
+                        i.onion_address
+                    ),
+                ));
+            }
+        }
+        if !i.schema_drift.is_empty() {
+            findings.push(Finding::new(
+                i,
+                Severity::Info,
+                FindingKind::SchemaDrift,
+                format!(
+                    "metadata differs from the expected schema: {}",
+                    i.schema_drift.join("; ")
+                ),
+            ));
+        }
+    }
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    findings
+}
+
+/// Lists a finding for each instance advertising another GPG fingerprint
+/// than it last did, per `known` (see `Storage::known_fingerprints`). It
+/// may be a planned key rotation, or the site may be compromised, so the
+/// rotation should be confirmed with the organization either way.
+pub fn detect_key_rotations(
+    instances: &[SDDirectoryInstance],
+    known: &BTreeMap<String, KnownFingerprint>,
+) -> Vec<Finding> {
+    let mut findings = vec![];
+    for i in instances {
+        let (m, k) = match (&i.metadata, known.get(&i.onion_address)) {
+            (Some(m), Some(k)) => (m, k),
+            _ => continue,
+        };
+        if k.rotated_to(&m.gpg_fpr) {
+            findings.push(Finding::new(
+                i,
+                Severity::High,
+                FindingKind::KeyRotated,
+                format!(
+                    "GPG fingerprint changed from {} (last seen {}) to {}, confirm the rotation with the organization",
+                    k.fingerprint,
+                    k.seen_at.format("%Y-%m-%d"),
+                    m.gpg_fpr
+                ),
+            ));
+        }
+    }
+    findings
+}
+
+/// Lists a finding for each instance reporting an older SecureDrop version
+/// than it last did, per `known` (see `Storage::known_versions`). SecureDrop
+/// doesn't support downgrades, so this usually means the server was
+/// restored from an old backup, or the metadata endpoint is spoofed.
+pub fn detect_downgrades(
+    instances: &[SDDirectoryInstance],
+    known: &BTreeMap<String, KnownVersion>,
+) -> Vec<Finding> {
+    let mut findings = vec![];
+    for i in instances {
+        let (m, k) = match (&i.metadata, known.get(&i.onion_address)) {
+            (Some(m), Some(k)) => (m, k),
+            _ => continue,
+        };
+        if let (Some(new), Some(old)) = (parse_version(&m.sd_version), parse_version(&k.sd_version))
+        {
+            if new < old {
+                findings.push(Finding::new(
+                    i,
+                    Severity::High,
+                    FindingKind::VersionDowngrade,
+                    format!(
+                        "SecureDrop version went back from {} (last seen {}) to {}, the server may have been restored from an old backup or its metadata spoofed",
+                        k.sd_version,
+                        k.seen_at.format("%Y-%m-%d"),
+                        m.sd_version
+                    ),
+                ));
+            }
+        }
+    }
+    findings
+}
+
+/// Renders the findings as human-readable text, followed by a count of
+/// instances still advertising v2 addresses. With `color`, high severity
+/// findings are red and medium ones yellow.
+pub fn format_findings(findings: &[Finding], color: bool) -> String {
+    let mut report = String::from("");
+    if !findings.is_empty() {
+        report += &format!("Findings ({}):\n", findings.len());
+        for f in findings {
+            let severity = format!("[{}]", f.severity);
+            let severity = match f.severity {
+                Severity::High => paint(&severity, Color::Red, color),
+                Severity::Medium => paint(&severity, Color::Yellow, color),
+                _ => severity,
+            };
+            report += &format!(
+                "  {} {} ({}): {}\n",
+                severity, f.title, f.onion_address, f.message
+            );
+        }
+        report += "\n";
+    }
+    report += &format!("{}\n", v2_summary(findings));
+    report
+}
+
+/// Counts the instances still advertising a v2 Onion address.
+pub fn v2_summary(findings: &[Finding]) -> String {
+    let v2 = findings
+        .iter()
+        .filter(|f| f.kind == FindingKind::V2Onion)
+        .count();
+    format!(
+        "{} instance{} still advertise{} a v2 Onion address",
+        v2,
+        if v2 == 1 { "" } else { "s" },
+        if v2 == 1 { "s" } else { "" }
+    )
+}
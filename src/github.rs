@@ -0,0 +1,224 @@
+use crate::config::GitHubConfig;
+use crate::db::Storage;
+use crate::{normalize_onion, SDDirectoryInstance};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// The GitHub API, unless the config names another (e.g. GitHub Enterprise).
+pub const GITHUB_API_URL: &str = "https://api.github.com";
+
+/// How many consecutive failed scans open an issue, unless configured.
+pub const DEFAULT_CONSECUTIVE_SCANS: usize = 3;
+
+/// The label tracking issues are filed under, unless configured.
+pub const DEFAULT_LABEL: &str = "sdstatus-outage";
+
+/// How long an instance has been down: since when, and for how many
+/// consecutive scans.
+#[derive(Debug, Clone, Copy)]
+pub struct Streak {
+    pub down_since: DateTime<Utc>,
+    pub scans: usize,
+}
+
+/// Works out how long each unavailable instance has been down, by onion
+/// address, from the history (which must already include this scan).
+pub fn outage_streaks(
+    history: &dyn Storage,
+    instances: &[SDDirectoryInstance],
+) -> Result<BTreeMap<String, Streak>, Box<dyn Error>> {
+    let mut streaks = BTreeMap::new();
+    for i in instances.iter().filter(|i| !i.available) {
+        if let Some(down_since) = history.down_since(&i.onion_address)? {
+            let scans = history
+                .outcomes_since(down_since)?
+                .iter()
+                .filter(|o| o.onion_address == i.onion_address)
+                .count();
+            streaks.insert(i.onion_address.to_owned(), Streak { down_since, scans });
+        }
+    }
+    Ok(streaks)
+}
+
+#[derive(Deserialize, Debug)]
+struct Issue {
+    number: u64,
+    body: Option<String>,
+}
+
+/// Marks the body of an instance's tracking issue, so it can be found
+/// again to be closed.
+fn marker(onion_address: &str) -> String {
+    format!(
+        "<!-- sdstatus: {} -->",
+        normalize_onion(onion_address).to_lowercase()
+    )
+}
+
+/// The body of the tracking issue for an instance that has been down for
+/// `streak`.
+fn issue_body(instance: &SDDirectoryInstance, streak: &Streak) -> String {
+    let mut body = format!(
+        "{} has been unavailable for {} consecutive scans, since {}.\n\n",
+        instance.title,
+        streak.scans,
+        streak.down_since.format("%Y-%m-%d %H:%M UTC")
+    );
+    body += &format!("- Onion address: `{}`\n", instance.onion_address);
+    if !instance.landing_page_url.is_empty() {
+        body += &format!("- Landing page: {}\n", instance.landing_page_url);
+    }
+    if let Some(kind) = instance.failure_kind() {
+        body += &format!("- Failure: {}\n", kind);
+    }
+    if let Some(status) = instance.http_status {
+        body += &format!("- HTTP status: {}\n", status);
+    }
+    if let Some(error) = &instance.error {
+        body += &format!("- Error: `{}`\n", error);
+    }
+    body += &format!(
+        "\nThis issue is closed automatically once the site is back.\n\n{}\n",
+        marker(&instance.onion_address)
+    );
+    body
+}
+
+/// Talks to the issues API of the configured repository.
+struct Repository<'a> {
+    client: reqwest::Client,
+    config: &'a GitHubConfig,
+}
+
+impl Repository<'_> {
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let api = self
+            .config
+            .api_url
+            .as_deref()
+            .unwrap_or(GITHUB_API_URL)
+            .trim_end_matches('/');
+        self.client
+            .request(
+                method,
+                &format!("{}/repos/{}{}", api, self.config.repo, path),
+            )
+            .bearer_auth(&self.config.token)
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            // GitHub rejects requests without a user agent.
+            .header(reqwest::header::USER_AGENT, "sdstatus")
+    }
+
+    /// Lists the open issues under the label, by the onion address their
+    /// body marks.
+    async fn open_issues(&self, label: &str) -> Result<BTreeMap<String, u64>, Box<dyn Error>> {
+        let mut issues = BTreeMap::new();
+        for page in 1.. {
+            let batch: Vec<Issue> = self
+                .request(reqwest::Method::GET, "/issues")
+                .query(&[("state", "open"), ("labels", label), ("per_page", "100")])
+                .query(&[("page", page)])
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            for issue in &batch {
+                let body = issue.body.as_deref().unwrap_or("");
+                if let Some(onion) = body
+                    .split("<!-- sdstatus: ")
+                    .nth(1)
+                    .and_then(|rest| rest.split(" -->").next())
+                {
+                    issues.insert(onion.to_owned(), issue.number);
+                }
+            }
+            if batch.len() < 100 {
+                break;
+            }
+        }
+        Ok(issues)
+    }
+
+    async fn open(&self, title: &str, body: &str, label: &str) -> Result<u64, Box<dyn Error>> {
+        let issue: Issue = self
+            .request(reqwest::Method::POST, "/issues")
+            .json(&json!({ "title": title, "body": body, "labels": [label] }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(issue.number)
+    }
+
+    async fn close(&self, number: u64, comment: &str) -> Result<(), Box<dyn Error>> {
+        self.request(
+            reqwest::Method::POST,
+            &format!("/issues/{}/comments", number),
+        )
+        .json(&json!({ "body": comment }))
+        .send()
+        .await?
+        .error_for_status()?;
+        self.request(reqwest::Method::PATCH, &format!("/issues/{}", number))
+            .json(&json!({ "state": "closed", "state_reason": "completed" }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Opens a tracking issue for each instance down for at least the
+/// configured number of consecutive scans, per `streaks` (see
+/// `outage_streaks`), and closes those of instances that are back. Each
+/// instance has at most one open issue, found again by a marker in its
+/// body, so nothing needs to be remembered between scans.
+pub async fn sync_issues(
+    config: &GitHubConfig,
+    instances: &[SDDirectoryInstance],
+    streaks: &BTreeMap<String, Streak>,
+    scanned_at: DateTime<Utc>,
+) -> Result<(), Box<dyn Error>> {
+    let repo = Repository {
+        client: reqwest::Client::new(),
+        config,
+    };
+    let label = config.label.as_deref().unwrap_or(DEFAULT_LABEL);
+    let threshold = config
+        .consecutive_scans
+        .unwrap_or(DEFAULT_CONSECUTIVE_SCANS);
+    let open = repo.open_issues(label).await?;
+    for i in instances {
+        let onion = normalize_onion(&i.onion_address).to_lowercase();
+        match (i.available, open.get(&onion), streaks.get(&i.onion_address)) {
+            (true, Some(number), _) => {
+                let comment = format!(
+                    "{} is available again as of {}.",
+                    i.title,
+                    scanned_at.format("%Y-%m-%d %H:%M UTC")
+                );
+                repo.close(*number, &comment).await?;
+                info!(
+                    "Closed issue #{} for {} in {}",
+                    number, i.title, config.repo
+                );
+            }
+            (false, None, Some(streak)) if streak.scans >= threshold => {
+                let title = format!("{} is down", i.title);
+                let number = repo.open(&title, &issue_body(i, streak), label).await?;
+                info!(
+                    "Opened issue #{} for {} in {}",
+                    number, i.title, config.repo
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
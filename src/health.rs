@@ -0,0 +1,71 @@
+use chrono::{DateTime, Utc};
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// What the daemon reports about its scans to a supervisor.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ScanHealth {
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Whether the last scan completed, or None before the first one
+    /// finishes.
+    pub succeeded: Option<bool>,
+    /// Why the last scan failed, if it did.
+    pub error: Option<String>,
+    /// How many instances the last successful scan covered, and how many
+    /// of those were available.
+    pub instances: Option<usize>,
+    pub available: Option<usize>,
+    pub next_scan_at: Option<DateTime<Utc>>,
+}
+
+/// Serves the daemon's health until the server fails: `/healthz` answers
+/// 200 unless the last scan failed, and `/last-scan` describes that scan
+/// in JSON.
+pub async fn serve_health(
+    addr: SocketAddr,
+    health: Arc<RwLock<ScanHealth>>,
+) -> Result<(), hyper::Error> {
+    let make_svc = make_service_fn(move |_conn| {
+        let health = health.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let health = health.clone();
+                async move {
+                    let health = health.read().await.clone();
+                    let response = match req.uri().path() {
+                        "/healthz" => {
+                            if health.succeeded == Some(false) {
+                                Response::builder()
+                                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                                    .body(Body::from(format!(
+                                        "last scan failed: {}\n",
+                                        health.error.unwrap_or_default()
+                                    )))
+                            } else {
+                                Response::builder().body(Body::from("ok\n"))
+                            }
+                        }
+                        "/last-scan" => Response::builder()
+                            .header(CONTENT_TYPE, "application/json")
+                            .body(Body::from(
+                                serde_json::to_string_pretty(&health).unwrap() + "\n",
+                            )),
+                        _ => Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Body::empty()),
+                    };
+                    Ok::<_, Infallible>(response.unwrap())
+                }
+            }))
+        }
+    });
+    info!("Serving health checks at http://{}/healthz", addr);
+    Server::bind(&addr).serve(make_svc).await
+}
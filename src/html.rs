@@ -0,0 +1,132 @@
+use crate::findings::{detect_findings, v2_summary};
+use crate::locale::instance_locales;
+use crate::report::{generate_l10n_report, list_failures};
+use crate::SDDirectoryInstance;
+use chrono::{DateTime, Utc};
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }
+th { background: #f0f0f0; cursor: pointer; }
+td.up { color: #1a7f37; font-weight: bold; }
+td.down { color: #cf222e; font-weight: bold; }
+td.center { text-align: center; }
+footer { color: #666; font-size: 0.9em; }";
+
+// Sorts a table by the clicked column, toggling the direction on each
+// click. Numeric-looking cells (versions, latencies) compare naturally.
+const SORT_SCRIPT: &str =
+    "document.querySelectorAll('table.sortable thead th').forEach(function (th, col) {
+  th.addEventListener('click', function () {
+    var table = th.closest('table');
+    var tbody = table.tBodies[0];
+    var asc = th.dataset.sort !== 'asc';
+    table.querySelectorAll('th').forEach(function (h) { delete h.dataset.sort; });
+    th.dataset.sort = asc ? 'asc' : 'desc';
+    Array.from(tbody.rows)
+      .sort(function (a, b) {
+        var x = a.cells[col].textContent, y = b.cells[col].textContent;
+        return (asc ? 1 : -1) * x.localeCompare(y, undefined, { numeric: true });
+      })
+      .forEach(function (row) { tbody.appendChild(row); });
+  });
+});";
+
+/// Escapes text for inclusion in HTML element content or attributes.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Renders the results of a scan as a self-contained HTML page, with a
+/// sortable table of instances, a matrix of the locales each supports,
+/// and the list of failures.
+pub fn render_html(instances: &[SDDirectoryInstance], generated_at: DateTime<Utc>) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>SecureDrop status</title>\n<style>\n{}\n</style>\n</head>\n<body>\n\
+         <h1>SecureDrop status</h1>\n",
+        STYLE
+    );
+
+    let up = instances.iter().filter(|i| i.available).count();
+    html += &format!(
+        "<p>{} of {} instances available.</p>\n",
+        up,
+        instances.len()
+    );
+
+    html += "<h2>Instances</h2>\n<table class=\"sortable\">\n<thead><tr>\
+             <th>Title</th><th>Onion address</th><th>Status</th><th>SecureDrop version</th>\
+             <th>Server OS</th><th>Latency (ms)</th></tr></thead>\n<tbody>\n";
+    for i in instances {
+        let (version, os) = match &i.metadata {
+            Some(m) => (m.sd_version.as_str(), m.server_os.as_str()),
+            None => ("", ""),
+        };
+        html += &format!(
+            "<tr><td>{}</td><td><code>{}</code></td><td class=\"{}\">{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape(&i.title),
+            escape(&i.onion_address),
+            if i.available { "up" } else { "down" },
+            if i.available { "UP" } else { "DOWN" },
+            escape(version),
+            escape(os),
+            i.latency_ms.map(|l| l.to_string()).unwrap_or_default()
+        );
+    }
+    html += "</tbody>\n</table>\n";
+
+    let locales = generate_l10n_report(instances);
+    html += "<h2>Localization</h2>\n<table class=\"sortable\">\n<thead><tr><th>Title</th>";
+    for locale in locales.keys() {
+        html += &format!("<th>{}</th>", escape(locale));
+    }
+    html += "</tr></thead>\n<tbody>\n";
+    for i in instances.iter().filter(|i| i.metadata.is_some()) {
+        let enabled = instance_locales(i);
+        html += &format!("<tr><td>{}</td>", escape(&i.title));
+        for locale in locales.keys() {
+            let enabled = enabled.contains(locale);
+            html += &format!(
+                "<td class=\"center\">{}</td>",
+                if enabled { "&#10003;" } else { "" }
+            );
+        }
+        html += "</tr>\n";
+    }
+    html += "</tbody>\n<tfoot><tr><th>Total</th>";
+    for sites in locales.values() {
+        html += &format!("<th class=\"center\">{}</th>", sites.len());
+    }
+    html += "</tr></tfoot>\n</table>\n";
+
+    let failures = list_failures(instances);
+    html += &format!("<h2>Failures ({})</h2>\n", failures.len());
+    if !failures.is_empty() {
+        html += "<table class=\"sortable\">\n<thead><tr><th>Title</th><th>Onion address</th>\
+                 <th>Failure</th><th>Error</th></tr></thead>\n<tbody>\n";
+        for f in &failures {
+            html += &format!(
+                "<tr><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td></tr>\n",
+                escape(&f.title),
+                escape(&f.onion_address),
+                escape(&f.kind.to_string()),
+                escape(&f.error)
+            );
+        }
+        html += "</tbody>\n</table>\n";
+    }
+
+    html += &format!(
+        "<footer>{}. Generated by sdstatus {} at {}.</footer>\n<script>\n{}\n</script>\n</body>\n</html>\n",
+        escape(&v2_summary(&detect_findings(instances))),
+        env!("CARGO_PKG_VERSION"),
+        generated_at.to_rfc2822(),
+        SORT_SCRIPT
+    );
+    html
+}
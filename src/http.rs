@@ -0,0 +1,295 @@
+use crate::snapshot::{load_manifest, read_body, ResponseKind, Snapshot};
+use custom_error::custom_error;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Debug;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+custom_error! {pub HttpError
+    Request{source: reqwest::Error} = "{source}",
+    Recorded{message: String} = "{message}",
+    NotRecorded{url: String} = "no recorded response for {url}",
+}
+
+/// A response to an HTTP GET, read in full.
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+    /// How long the response took to start arriving, and to arrive in
+    /// full, if it came over the network.
+    pub latency: Option<Duration>,
+    pub duration: Option<Duration>,
+}
+
+pub type ResponseFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<HttpResponse, HttpError>> + Send + 'a>>;
+
+/// Makes the GET requests of a scan. Abstracting over it lets the scan be
+/// run against recorded or canned responses, without Tor.
+pub trait HttpClient: Send + Sync + Debug {
+    fn get<'a>(&'a self, url: &'a str) -> ResponseFuture<'a>;
+
+    /// Makes a HEAD request, for when only the status matters. Clients
+    /// that can't make one send a GET instead.
+    fn head<'a>(&'a self, url: &'a str) -> ResponseFuture<'a> {
+        self.get(url)
+    }
+}
+
+impl<C: HttpClient + ?Sized> HttpClient for Arc<C> {
+    fn get<'a>(&'a self, url: &'a str) -> ResponseFuture<'a> {
+        (**self).get(url)
+    }
+
+    fn head<'a>(&'a self, url: &'a str) -> ResponseFuture<'a> {
+        (**self).head(url)
+    }
+}
+
+/// Sends requests over the network with reqwest.
+#[derive(Debug, Clone)]
+pub struct ReqwestClient {
+    client: reqwest::Client,
+}
+
+impl ReqwestClient {
+    pub fn new(client: reqwest::Client) -> ReqwestClient {
+        ReqwestClient { client }
+    }
+}
+
+impl HttpClient for ReqwestClient {
+    fn get<'a>(&'a self, url: &'a str) -> ResponseFuture<'a> {
+        Box::pin(async move {
+            let start = Instant::now();
+            let response = self.client.get(url).send().await?;
+            let status = response.status().as_u16();
+            let latency = start.elapsed();
+            let body = response.bytes().await?.to_vec();
+            Ok(HttpResponse {
+                status,
+                body,
+                latency: Some(latency),
+                duration: Some(start.elapsed()),
+            })
+        })
+    }
+
+    fn head<'a>(&'a self, url: &'a str) -> ResponseFuture<'a> {
+        Box::pin(async move {
+            let start = Instant::now();
+            let response = self.client.head(url).send().await?;
+            let latency = start.elapsed();
+            Ok(HttpResponse {
+                status: response.status().as_u16(),
+                body: vec![],
+                latency: Some(latency),
+                duration: Some(latency),
+            })
+        })
+    }
+}
+
+/// Passes requests on to another client, saving the metadata responses
+/// (and failures) in a snapshot.
+#[derive(Debug)]
+pub struct RecordingClient<C> {
+    inner: C,
+    snapshot: Arc<Snapshot>,
+}
+
+impl<C: HttpClient> RecordingClient<C> {
+    pub fn new(inner: C, snapshot: Arc<Snapshot>) -> RecordingClient<C> {
+        RecordingClient { inner, snapshot }
+    }
+}
+
+/// The host a URL is for, which for instances is their onion address.
+fn host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split('/').next().unwrap_or(rest)
+}
+
+impl<C: HttpClient> HttpClient for RecordingClient<C> {
+    fn get<'a>(&'a self, url: &'a str) -> ResponseFuture<'a> {
+        Box::pin(async move {
+            let result = self.inner.get(url).await;
+            // Only metadata is archived, not e.g. journalist keys.
+            if url.ends_with("/metadata") {
+                match &result {
+                    Ok(r) => self
+                        .snapshot
+                        .save_metadata(host(url), url, r.status, &r.body),
+                    Err(e) => self.snapshot.save_failure(host(url), url, &e.to_string()),
+                }
+            }
+            result
+        })
+    }
+
+    fn head<'a>(&'a self, url: &'a str) -> ResponseFuture<'a> {
+        self.inner.head(url)
+    }
+}
+
+/// Serves canned responses, keyed by URL, without touching the network.
+/// Requests for any other URL fail.
+#[derive(Debug, Default, Clone)]
+pub struct ReplayClient {
+    responses: HashMap<String, Result<HttpResponse, String>>,
+}
+
+impl ReplayClient {
+    pub fn new() -> ReplayClient {
+        ReplayClient::default()
+    }
+
+    /// Adds a response to serve for `url`.
+    pub fn respond(&mut self, url: &str, status: u16, body: &[u8]) -> &mut ReplayClient {
+        self.responses.insert(
+            url.to_owned(),
+            Ok(HttpResponse {
+                status,
+                body: body.to_vec(),
+                latency: None,
+                duration: None,
+            }),
+        );
+        self
+    }
+
+    /// Makes requests for `url` fail with `message`.
+    pub fn fail(&mut self, url: &str, message: &str) -> &mut ReplayClient {
+        self.responses
+            .insert(url.to_owned(), Err(message.to_owned()));
+        self
+    }
+
+    /// Serves the metadata responses recorded in a snapshot (see
+    /// `--snapshot-dir`).
+    pub fn from_snapshot(path: &Path) -> Result<ReplayClient, Box<dyn Error>> {
+        let (dir, manifest) = load_manifest(path)?;
+        let mut client = ReplayClient::new();
+        for entry in manifest
+            .responses
+            .iter()
+            .filter(|r| r.kind == ResponseKind::Metadata)
+        {
+            match (&entry.file, &entry.error) {
+                (Some(file), _) => {
+                    let body = read_body(&dir, file)?;
+                    client.respond(&entry.url, entry.http_status.unwrap_or(200), &body)
+                }
+                (None, error) => client.fail(&entry.url, error.as_deref().unwrap_or("")),
+            };
+        }
+        Ok(client)
+    }
+}
+
+impl HttpClient for ReplayClient {
+    fn get<'a>(&'a self, url: &'a str) -> ResponseFuture<'a> {
+        Box::pin(async move {
+            match self.responses.get(url) {
+                Some(Ok(response)) => Ok(response.clone()),
+                Some(Err(message)) => Err(HttpError::Recorded {
+                    message: message.to_owned(),
+                }),
+                None => Err(HttpError::NotRecorded {
+                    url: url.to_owned(),
+                }),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::path::PathBuf;
+
+    const METADATA_URL: &str = "http://example.onion/metadata";
+    const OFFLINE_URL: &str = "http://offline.onion/metadata";
+    const KEY_URL: &str = "http://example.onion/public-key";
+
+    fn canned() -> ReplayClient {
+        let mut client = ReplayClient::new();
+        client
+            .respond(METADATA_URL, 200, br#"{"sd_version": "2.6.0"}"#)
+            .respond(KEY_URL, 200, b"-----BEGIN PGP PUBLIC KEY BLOCK-----")
+            .fail(OFFLINE_URL, "onion service descriptor not found");
+        client
+    }
+
+    /// A directory of its own for each test, as they run in parallel.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sdstatus-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn host_of_url() {
+        assert_eq!(host(METADATA_URL), "example.onion");
+        assert_eq!(host("http://example.onion"), "example.onion");
+        assert_eq!(host("example.onion/metadata"), "example.onion");
+    }
+
+    #[tokio::test]
+    async fn replay_serves_canned_responses() {
+        let client = canned();
+        let response = client.get(METADATA_URL).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, br#"{"sd_version": "2.6.0"}"#);
+        assert!(response.latency.is_none());
+
+        let error = client.get(OFFLINE_URL).await.unwrap_err();
+        assert!(matches!(error, HttpError::Recorded { .. }));
+        assert_eq!(error.to_string(), "onion service descriptor not found");
+
+        let error = client.head("http://elsewhere.onion/").await.unwrap_err();
+        assert!(matches!(error, HttpError::NotRecorded { .. }));
+    }
+
+    async fn record_and_replay(compress: bool) {
+        let root = scratch_dir(if compress { "replay-gz" } else { "replay" });
+        let snapshot = Arc::new(Snapshot::create(&root, Utc::now(), compress).unwrap());
+        let recording = RecordingClient::new(canned(), snapshot.clone());
+        assert_eq!(recording.get(METADATA_URL).await.unwrap().status, 200);
+        assert!(recording.get(OFFLINE_URL).await.is_err());
+        assert!(recording.get(KEY_URL).await.is_ok());
+        snapshot.finish().unwrap();
+
+        let dir = std::fs::read_dir(&root).unwrap().next().unwrap().unwrap();
+        let replay = ReplayClient::from_snapshot(&dir.path()).unwrap();
+        let response = replay.get(METADATA_URL).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, br#"{"sd_version": "2.6.0"}"#);
+        assert_eq!(
+            replay.get(OFFLINE_URL).await.unwrap_err().to_string(),
+            "onion service descriptor not found"
+        );
+        // Only metadata is archived.
+        assert!(matches!(
+            replay.get(KEY_URL).await,
+            Err(HttpError::NotRecorded { .. })
+        ));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn recorded_responses_replay() {
+        record_and_replay(false).await;
+    }
+
+    #[tokio::test]
+    async fn compressed_recorded_responses_replay() {
+        record_and_replay(true).await;
+    }
+}
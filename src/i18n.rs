@@ -0,0 +1,100 @@
+use fluent_bundle::{FluentArgs, FluentBundle, FluentResource, FluentValue};
+use std::error::Error;
+use unic_langid::LanguageIdentifier;
+
+/// The language reports are written in unless `--lang` says otherwise.
+pub const DEFAULT_LANG: &str = "en-US";
+
+/// The Fluent catalogs bundled with sdstatus, by language. Messages a
+/// catalog lacks are taken from the `DEFAULT_LANG` one.
+const CATALOGS: &[(&str, &str)] = &[
+    ("en-US", include_str!("../locales/en-US/sdstatus.ftl")),
+    ("de", include_str!("../locales/de/sdstatus.ftl")),
+    ("es", include_str!("../locales/es/sdstatus.ftl")),
+    ("fr", include_str!("../locales/fr/sdstatus.ftl")),
+    ("pt-BR", include_str!("../locales/pt-BR/sdstatus.ftl")),
+];
+
+/// The headings and labels of reports, in one language.
+pub struct Messages {
+    /// The bundle for the language asked for, then the default one.
+    bundles: Vec<FluentBundle<FluentResource>>,
+}
+
+impl Default for Messages {
+    fn default() -> Messages {
+        Messages::new(DEFAULT_LANG).expect("the default language is bundled")
+    }
+}
+
+fn bundle(lang: &str, catalog: &str) -> FluentBundle<FluentResource> {
+    let langid: LanguageIdentifier = lang.parse().expect("bundled languages are valid");
+    let mut bundle = FluentBundle::new(vec![langid]);
+    // Unicode isolation marks around arguments only clutter a terminal.
+    bundle.set_use_isolating(false);
+    let resource =
+        FluentResource::try_new(catalog.to_owned()).expect("bundled catalogs are valid Fluent");
+    bundle
+        .add_resource(resource)
+        .expect("bundled catalogs have no duplicate messages");
+    bundle
+}
+
+impl Messages {
+    /// Loads the messages for a language given as a BCP 47 tag (or a
+    /// locale such as `pt_BR`). A language without a catalog of its own
+    /// takes that of its region-less language, e.g. `de-AT` is reported in
+    /// `de`; failing that, reports are in `DEFAULT_LANG`.
+    pub fn new(lang: &str) -> Result<Messages, Box<dyn Error>> {
+        let requested: LanguageIdentifier = lang
+            .replace('_', "-")
+            .parse()
+            .map_err(|e| format!("Invalid language {}, {}", lang, e))?;
+        let langid = |l: &str| l.parse::<LanguageIdentifier>().unwrap();
+        let catalog = CATALOGS
+            .iter()
+            .find(|(l, _)| langid(l) == requested)
+            .or_else(|| {
+                CATALOGS
+                    .iter()
+                    .find(|(l, _)| langid(l).language == requested.language)
+            });
+        let mut bundles = vec![];
+        match catalog {
+            Some((l, catalog)) => bundles.push(bundle(l, catalog)),
+            None => warn!("No translation for {}, reporting in {}", lang, DEFAULT_LANG),
+        }
+        if catalog.is_none_or(|(l, _)| *l != DEFAULT_LANG) {
+            bundles.push(bundle(DEFAULT_LANG, CATALOGS[0].1));
+        }
+        Ok(Messages { bundles })
+    }
+
+    /// Formats a message with its arguments. An unknown message formats as
+    /// its id.
+    pub fn format(&self, id: &str, args: &FluentArgs) -> String {
+        for bundle in &self.bundles {
+            if let Some(pattern) = bundle.get_message(id).and_then(|m| m.value()) {
+                let mut errors = vec![];
+                let text = bundle.format_pattern(pattern, Some(args), &mut errors);
+                for e in errors {
+                    warn!("Failed to format message {}, {}", id, e);
+                }
+                return text.into_owned();
+            }
+        }
+        id.to_owned()
+    }
+
+    /// Formats a message without arguments.
+    pub fn get(&self, id: &str) -> String {
+        self.format(id, &FluentArgs::new())
+    }
+
+    /// Formats a message whose only argument is `$count`.
+    pub fn count(&self, id: &str, count: usize) -> String {
+        let mut args = FluentArgs::new();
+        args.set("count", FluentValue::from(count));
+        self.format(id, &args)
+    }
+}
@@ -0,0 +1,64 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+
+/// How long the fetches in flight when a scan is interrupted are given to
+/// finish.
+pub const INTERRUPT_GRACE: Duration = Duration::from_secs(10);
+
+static INTERRUPT: OnceLock<Interrupt> = OnceLock::new();
+
+/// Whether sdstatus has been asked to stop, by SIGINT (Ctrl-C) or SIGTERM.
+#[derive(Debug, Clone)]
+pub struct Interrupt {
+    rx: watch::Receiver<bool>,
+}
+
+impl Interrupt {
+    /// Starts listening for SIGINT and SIGTERM, once: until then, they
+    /// kill sdstatus as usual. After the first signal, a second one exits
+    /// at once. Must be called from within the runtime.
+    pub fn listen() -> Interrupt {
+        INTERRUPT
+            .get_or_init(|| {
+                let (tx, rx) = watch::channel(false);
+                tokio::spawn(async move {
+                    let mut term = match signal(SignalKind::terminate()) {
+                        Ok(term) => term,
+                        Err(e) => {
+                            warn!("Failed to listen for SIGTERM, {}", e);
+                            return;
+                        }
+                    };
+                    tokio::select! {
+                        _ = tokio::signal::ctrl_c() => {}
+                        _ = term.recv() => {}
+                    }
+                    warn!("Interrupted, waiting for the sites being scanned (interrupt again to exit now)");
+                    tx.broadcast(true).ok();
+                    tokio::select! {
+                        _ = tokio::signal::ctrl_c() => {}
+                        _ = term.recv() => {}
+                    }
+                    std::process::exit(130);
+                });
+                Interrupt { rx }
+            })
+            .clone()
+    }
+
+    pub fn is_set(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Resolves once sdstatus is interrupted.
+    pub async fn wait(&mut self) {
+        while !self.is_set() {
+            if self.rx.recv().await.is_none() {
+                // The listener failed, so no interrupt will come.
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use crate::color::{paint, Color};
+use crate::SDDirectoryInstance;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+/// How many days before a journalist key expires it is reported as
+/// expiring soon, unless told otherwise.
+pub const EXPIRY_WARNING_DAYS: i64 = 30;
+
+/// Whether a journalist key is still usable.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStatus {
+    Valid,
+    /// The key expires within the warning window.
+    Expiring,
+    Expired,
+}
+
+impl std::fmt::Display for KeyStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            KeyStatus::Valid => "valid",
+            KeyStatus::Expiring => "expiring",
+            KeyStatus::Expired => "expired",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Whether a key expiring at `expires` is usable at `now`, warning when
+/// it expires within `warning`.
+pub fn key_status(
+    expires: Option<DateTime<Utc>>,
+    warning: Duration,
+    now: DateTime<Utc>,
+) -> KeyStatus {
+    match expires {
+        Some(expires) if expires <= now => KeyStatus::Expired,
+        Some(expires) if expires - now <= warning => KeyStatus::Expiring,
+        _ => KeyStatus::Valid,
+    }
+}
+
+/// The health of one instance's journalist key.
+#[derive(Serialize, Debug)]
+pub struct KeyReportEntry {
+    pub title: String,
+    pub onion_address: String,
+    pub fingerprint: String,
+    /// The primary key's algorithm and size, e.g. `rsa4096`.
+    pub algorithm: String,
+    /// The algorithms and sizes of the subkeys.
+    pub subkeys: Vec<String>,
+    pub created: DateTime<Utc>,
+    /// When the key stops being usable, if it expires at all.
+    pub expires: Option<DateTime<Utc>>,
+    pub status: KeyStatus,
+}
+
+/// Which instances' journalist keys are expired or expiring.
+#[derive(Serialize, Debug)]
+pub struct KeyReport {
+    /// The instances whose keys were read, those expiring soonest first.
+    pub keys: Vec<KeyReportEntry>,
+    /// Sites, as `title (onion address)`, that were available but whose
+    /// key wasn't read, e.g. as the scan didn't verify keys.
+    pub unchecked: Vec<String>,
+}
+
+/// Reports on the journalist keys read by a scan with key verification,
+/// as of `now`.
+pub fn generate_key_report(
+    instances: &[SDDirectoryInstance],
+    warning: Duration,
+    now: DateTime<Utc>,
+) -> KeyReport {
+    let mut keys = vec![];
+    let mut unchecked = vec![];
+    for i in instances {
+        let key = match &i.journalist_key {
+            Some(key) => key,
+            None => {
+                if i.available {
+                    unchecked.push(format!("{} ({})", i.title, i.onion_address));
+                }
+                continue;
+            }
+        };
+        let expires = key.expires();
+        keys.push(KeyReportEntry {
+            title: i.title.to_owned(),
+            onion_address: i.onion_address.to_owned(),
+            fingerprint: key.primary.fingerprint.to_owned(),
+            algorithm: key.primary.describe(),
+            subkeys: key.subkeys.iter().map(|k| k.describe()).collect(),
+            created: key.primary.created,
+            expires,
+            status: key_status(expires, warning, now),
+        });
+    }
+    // Keys that never expire go last.
+    keys.sort_by_key(|k| (k.expires.is_none(), k.expires));
+    KeyReport { keys, unchecked }
+}
+
+/// Renders the key report as human-readable text. With `color`, expired
+/// keys are red and expiring ones yellow.
+pub fn format_key_report(report: &KeyReport, now: DateTime<Utc>, color: bool) -> String {
+    let mut text = String::from("");
+    for k in &report.keys {
+        let algorithms = std::iter::once(k.algorithm.as_str())
+            .chain(k.subkeys.iter().map(|s| s.as_str()))
+            .collect::<Vec<_>>()
+            .join("/");
+        let status = match (k.status, k.expires) {
+            (KeyStatus::Expired, Some(expires)) => paint(
+                &format!("expired {}", expires.format("%Y-%m-%d")),
+                Color::Red,
+                color,
+            ),
+            (KeyStatus::Expiring, Some(expires)) => paint(
+                &format!(
+                    "expires {}, in {} days",
+                    expires.format("%Y-%m-%d"),
+                    (expires - now).num_days()
+                ),
+                Color::Yellow,
+                color,
+            ),
+            (_, Some(expires)) => format!("expires {}", expires.format("%Y-%m-%d")),
+            (_, None) => "never expires".to_owned(),
+        };
+        text += &format!(
+            "{} ({})\n  {} {}, created {}, {}\n",
+            k.title,
+            k.onion_address,
+            k.fingerprint,
+            algorithms,
+            k.created.format("%Y-%m-%d"),
+            status
+        );
+    }
+    if !report.unchecked.is_empty() {
+        text += &format!(
+            "\nKeys not checked ({}), scan with --verify-key:\n",
+            report.unchecked.len()
+        );
+        for u in &report.unchecked {
+            text += &format!("  {}\n", u);
+        }
+    }
+    let expired = report
+        .keys
+        .iter()
+        .filter(|k| k.status == KeyStatus::Expired)
+        .count();
+    let expiring = report
+        .keys
+        .iter()
+        .filter(|k| k.status == KeyStatus::Expiring)
+        .count();
+    text += &format!(
+        "\n{} of {} journalist keys expired, {} expiring soon\n",
+        expired,
+        report.keys.len(),
+        expiring
+    );
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgp::{KeyDetails, KeyHealth};
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2026, 10, 14).and_hms(0, 0, 0)
+    }
+
+    fn key(expires: Option<i64>) -> KeyDetails {
+        KeyDetails {
+            fingerprint: "A5F6A198EDAA9AD012E3DB1AC3F129E5DC3AC454".to_owned(),
+            algorithm: "rsa".to_owned(),
+            bits: Some(4096),
+            created: Utc.ymd(2020, 1, 1).and_hms(0, 0, 0),
+            expires: expires.map(|days| now() + Duration::days(days)),
+        }
+    }
+
+    fn health(primary: Option<i64>, subkeys: &[Option<i64>]) -> KeyHealth {
+        KeyHealth {
+            primary: key(primary),
+            subkeys: subkeys.iter().map(|&s| key(s)).collect(),
+        }
+    }
+
+    fn instance(title: &str, journalist_key: Option<KeyHealth>) -> SDDirectoryInstance {
+        SDDirectoryInstance {
+            title: title.to_owned(),
+            available: true,
+            journalist_key,
+            ..SDDirectoryInstance::from_onion(&format!("{}.onion", title))
+        }
+    }
+
+    #[test]
+    fn statuses_of_keys() {
+        let warning = Duration::days(EXPIRY_WARNING_DAYS);
+        let status =
+            |days: Option<i64>| key_status(days.map(|d| now() + Duration::days(d)), warning, now());
+        assert_eq!(status(Some(-1)), KeyStatus::Expired);
+        // A key expiring this instant is no longer usable.
+        assert_eq!(status(Some(0)), KeyStatus::Expired);
+        assert_eq!(status(Some(1)), KeyStatus::Expiring);
+        assert_eq!(status(Some(EXPIRY_WARNING_DAYS)), KeyStatus::Expiring);
+        assert_eq!(status(Some(EXPIRY_WARNING_DAYS + 1)), KeyStatus::Valid);
+        assert_eq!(status(None), KeyStatus::Valid);
+    }
+
+    #[test]
+    fn certificates_expire_with_their_keys() {
+        // The primary key outlives the subkey submissions are encrypted to.
+        assert_eq!(
+            health(Some(365), &[Some(10)]).expires(),
+            Some(now() + Duration::days(10))
+        );
+        assert_eq!(
+            health(Some(10), &[Some(365)]).expires(),
+            Some(now() + Duration::days(10))
+        );
+        // Only once the last subkey expires does the certificate.
+        assert_eq!(
+            health(None, &[Some(10), Some(20)]).expires(),
+            Some(now() + Duration::days(20))
+        );
+        assert_eq!(
+            health(Some(10), &[None]).expires(),
+            Some(now() + Duration::days(10))
+        );
+        assert_eq!(health(None, &[Some(10), None]).expires(), None);
+        assert_eq!(health(None, &[]).expires(), None);
+    }
+
+    #[test]
+    fn reports_keys_soonest_expiring_first() {
+        let instances = vec![
+            instance("Forever", Some(health(None, &[None]))),
+            instance("Valid", Some(health(Some(365), &[Some(100)]))),
+            instance("Expiring", Some(health(Some(365), &[Some(10)]))),
+            instance("Expired", Some(health(Some(-5), &[Some(100)]))),
+            instance("Unchecked", None),
+            SDDirectoryInstance {
+                available: false,
+                ..instance("Down", None)
+            },
+        ];
+        let report = generate_key_report(&instances, Duration::days(EXPIRY_WARNING_DAYS), now());
+        let statuses: Vec<_> = report
+            .keys
+            .iter()
+            .map(|k| (k.title.as_str(), k.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("Expired", KeyStatus::Expired),
+                ("Expiring", KeyStatus::Expiring),
+                ("Valid", KeyStatus::Valid),
+                ("Forever", KeyStatus::Valid),
+            ]
+        );
+        assert_eq!(report.keys[0].algorithm, "rsa4096");
+        assert_eq!(report.unchecked, vec!["Unchecked (Unchecked.onion)"]);
+
+        let text = format_key_report(&report, now(), false);
+        assert!(text.contains("expired 2026-10-09"), "{}", text);
+        assert!(text.contains("expires 2026-10-24, in 10 days"), "{}", text);
+        assert!(text.contains("never expires"), "{}", text);
+        assert!(text.contains("1 of 4 journalist keys expired, 1 expiring soon"));
+    }
+}
@@ -0,0 +1,1335 @@
+use crate::preflight::open_stream;
+use crate::{
+    client_builder, isolation_credentials, normalize_onion, RequestBudget, SDDirectoryInstance,
+    ScanOptions,
+};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+
+/// How landing pages are checked, beyond the options of the scan.
+#[derive(Debug, Clone)]
+pub struct LandingOptions {
+    /// Whether to fetch each page without Tor too, to compare what Tor
+    /// users get.
+    pub compare_clearnet: bool,
+    /// The most redirects to follow from the landing page URL.
+    pub max_redirects: usize,
+    /// How long before a page's certificate expires to warn about it.
+    pub expiry_warning: Duration,
+}
+
+impl Default for LandingOptions {
+    fn default() -> LandingOptions {
+        LandingOptions {
+            compare_clearnet: false,
+            max_redirects: 10,
+            expiry_warning: Duration::days(30),
+        }
+    }
+}
+
+/// The result of checking an instance's clearnet landing page against the
+/// directory's inclusion criteria.
+#[derive(Serialize, Debug)]
+pub struct LandingCheck {
+    pub title: String,
+    pub landing_page_url: String,
+    /// Whether the directory lists the landing page with an `https://` URL.
+    pub https: bool,
+    /// Whether the page's TLS certificate is valid, if it could be fetched
+    /// over HTTPS at all.
+    pub valid_certificate: Option<bool>,
+    /// When the page's TLS certificate expires (its `notAfter`), if known.
+    pub certificate_expires: Option<DateTime<Utc>>,
+    /// Whether plain HTTP requests are redirected to HTTPS, if the site
+    /// serves plain HTTP at all.
+    pub redirects_to_https: Option<bool>,
+    /// The HTTP status of the page, after following redirects.
+    pub http_status: Option<u16>,
+    /// The URLs the landing page URL redirected to over HTTPS, in order.
+    pub redirects: Vec<String>,
+    /// Where the page's `Onion-Location` header or meta tag points, if
+    /// it has one.
+    pub onion_location: Option<String>,
+    /// Whether `onion_location` is the instance's onion address from the
+    /// directory, if both are known.
+    pub onion_location_matches: Option<bool>,
+    /// Whether the page mentions the instance's onion address or onion
+    /// name from the directory, if the page and the address are known.
+    pub mentions_onion: Option<bool>,
+    /// The site's `security.txt`, if it has one.
+    pub security_txt: Option<SecurityTxt>,
+    /// The HTTP status of the page fetched over the clearnet, if it was
+    /// compared with the page fetched over Tor.
+    pub clearnet_status: Option<u16>,
+    /// Weaknesses in the page's security headers and content, and how it
+    /// treats Tor users, most severe first.
+    pub findings: Vec<Finding>,
+    pub error: Option<String>,
+}
+
+/// What a site's RFC 9116 `security.txt` says, as far as the directory
+/// cares.
+#[derive(Serialize, Debug, Clone)]
+pub struct SecurityTxt {
+    pub url: String,
+    /// The file's `Expires` field, if it has a valid one.
+    pub expires: Option<DateTime<Utc>>,
+    pub contacts: Vec<String>,
+    /// Whether the file points to the site's SecureDrop: mentions
+    /// SecureDrop, the landing page, or the onion address.
+    pub mentions_securedrop: bool,
+}
+
+/// How much a finding matters, from merely informational to high.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Severity::Info => "info",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "info" => Ok(Severity::Info),
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            _ => Err(format!("Unknown severity {}", s)),
+        }
+    }
+}
+
+/// A single weakness found when auditing a landing page.
+#[derive(Serialize, Debug, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+    /// The header the finding is about, or for the page's content, what
+    /// kind of content.
+    pub header: String,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, header: &str, message: &str) -> Finding {
+        Finding {
+            severity,
+            header: header.to_owned(),
+            message: message.to_owned(),
+        }
+    }
+}
+
+impl LandingCheck {
+    /// Describes each way in which the landing page falls short.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if let Some(error) = &self.error {
+            problems.push(error.to_owned());
+        }
+        if !self.https {
+            problems.push("not listed with an https:// URL".to_owned());
+        }
+        if self.valid_certificate == Some(false) {
+            problems.push("invalid TLS certificate".to_owned());
+        }
+        if self.redirects_to_https == Some(false) {
+            problems.push("HTTP is not redirected to HTTPS".to_owned());
+        }
+        if self.http_status.is_some() && self.onion_location.is_none() {
+            problems.push("no Onion-Location".to_owned());
+        }
+        if self.onion_location_matches == Some(false) {
+            problems.push(format!(
+                "Onion-Location points elsewhere ({})",
+                self.onion_location.as_deref().unwrap_or("")
+            ));
+        }
+        match self.http_status {
+            Some(status) if !(200..300).contains(&status) => {
+                problems.push(format!("HTTP status {}", status))
+            }
+            _ => {}
+        }
+        problems
+    }
+
+    pub fn passed(&self) -> bool {
+        self.problems().is_empty()
+    }
+}
+
+/// Fetches an instance's landing page over HTTPS, and over plain HTTP to
+/// see whether it redirects. Requests go through Tor like the rest of the
+/// scan, so checks don't reveal where they are made from.
+pub async fn check_landing_page(
+    instance: &SDDirectoryInstance,
+    options: &ScanOptions,
+    landing: &LandingOptions,
+) -> LandingCheck {
+    let url = instance.landing_page_url.trim();
+    let mut check = LandingCheck {
+        title: instance.title.to_owned(),
+        landing_page_url: url.to_owned(),
+        https: url.starts_with("https://"),
+        valid_certificate: None,
+        certificate_expires: None,
+        redirects_to_https: None,
+        http_status: None,
+        redirects: vec![],
+        onion_location: None,
+        onion_location_matches: None,
+        mentions_onion: None,
+        security_txt: None,
+        clearnet_status: None,
+        findings: vec![],
+        error: None,
+    };
+    let rest = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    let host = rest.split('/').next().unwrap_or(rest);
+    let https_url = format!("https://{}", rest);
+    let http_url = format!("http://{}", rest);
+
+    debug!("Checking landing page: {}", https_url);
+    let chain = Arc::new(Mutex::new(vec![]));
+    let client = client_builder(options, host)
+        .and_then(|b| b.redirect(recording_policy(landing, &chain)).build());
+    let client = match client {
+        Ok(client) => client,
+        Err(e) => {
+            check.error = Some(e.to_string());
+            return check;
+        }
+    };
+    let mut budget = RequestBudget::new(options);
+    budget.acquire().await;
+    let mut tor_body = None;
+    match client.get(&https_url).send().await {
+        Ok(r) => {
+            check.valid_certificate = Some(true);
+            tor_body = inspect_page(&mut check, r, instance).await;
+        }
+        Err(e) => {
+            // Tell certificate problems apart from the site being down by
+            // trying again without verifying the certificate.
+            chain.lock().unwrap().clear();
+            let insecure = client_builder(options, host).and_then(|b| {
+                b.danger_accept_invalid_certs(true)
+                    .redirect(recording_policy(landing, &chain))
+                    .build()
+            });
+            let retried = match insecure {
+                Ok(client) if budget.acquire().await => Some(client.get(&https_url).send().await),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            };
+            match retried {
+                Some(Ok(r)) => {
+                    check.valid_certificate = Some(false);
+                    tor_body = inspect_page(&mut check, r, instance).await;
+                }
+                // Too many redirects says more than the certificate did.
+                Some(Err(retry)) if retry.is_redirect() => check.error = Some(retry.to_string()),
+                _ => check.error = Some(e.to_string()),
+            }
+        }
+    }
+    if check.valid_certificate.is_some() && budget.acquire().await {
+        match certificate_expiry(host, options).await {
+            Ok(expires) => {
+                check.certificate_expires = Some(expires);
+                check
+                    .findings
+                    .extend(audit_expiry(expires, landing.expiry_warning, Utc::now()));
+            }
+            Err(e) => debug!("Failed to read the certificate of {}, {}", host, e),
+        }
+    }
+    if check.http_status.is_some() {
+        check_security_txt(
+            &mut check,
+            host,
+            &instance.onion_address,
+            options,
+            &mut budget,
+        )
+        .await;
+    }
+    check.redirects = chain.lock().unwrap().clone();
+    check
+        .findings
+        .extend(audit_redirects(host, &check.redirects));
+    if landing.compare_clearnet {
+        compare_clearnet(
+            &mut check,
+            &https_url,
+            tor_body.as_deref(),
+            options,
+            landing,
+        )
+        .await;
+    }
+    check
+        .findings
+        .sort_by_key(|f| std::cmp::Reverse(f.severity));
+
+    let no_redirects = client_builder(options, host)
+        .and_then(|b| b.redirect(reqwest::redirect::Policy::none()).build());
+    if !budget.acquire().await {
+        debug!("Request budget for {} spent, not checking HTTP", url);
+        return check;
+    }
+    let plain = match no_redirects {
+        Ok(client) => client.get(&http_url).send().await,
+        Err(e) => Err(e),
+    };
+    // A site that doesn't serve plain HTTP at all is fine.
+    if let Ok(r) = plain {
+        let location = r
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|l| l.to_str().ok())
+            .unwrap_or("");
+        check.redirects_to_https =
+            Some(r.status().is_redirection() && location.starts_with("https://"));
+    }
+    check
+}
+
+/// Fetches the certificate the landing page on `host` (optionally with a
+/// `:port`) presents, through Tor, returning when it expires. reqwest
+/// doesn't expose the certificates of its connections, so this makes a
+/// TLS handshake of its own.
+async fn certificate_expiry(
+    host: &str,
+    options: &ScanOptions,
+) -> Result<DateTime<Utc>, Box<dyn Error>> {
+    let (name, port) = match host.rsplit_once(':') {
+        Some((name, port)) => (name, port.parse()?),
+        None => (host, 443),
+    };
+    let credentials = isolation_credentials(options, host);
+    let stream = open_stream(
+        &options.socks_proxy,
+        name,
+        port,
+        credentials.as_ref().map(|(u, p)| (*u, p.as_str())),
+        options.timeout,
+    )
+    .await?;
+    // Whether the certificate is valid is checked by the page's fetch.
+    let connector = native_tls::TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .build()?;
+    let connector = tokio_native_tls::TlsConnector::from(connector);
+    let tls = tokio::time::timeout(options.timeout, connector.connect(name, stream)).await??;
+    let der = tls
+        .get_ref()
+        .peer_certificate()?
+        .ok_or("no certificate presented")?
+        .to_der()?;
+    let certificate = openssl::x509::X509::from_der(&der)?;
+    let since_epoch = openssl::asn1::Asn1Time::from_unix(0)?.diff(certificate.not_after())?;
+    Ok(Utc.timestamp(since_epoch.days as i64 * 86400 + since_epoch.secs as i64, 0))
+}
+
+/// Flags a certificate that has expired by `now`, or expires within
+/// `warning` of it, so the site can be told before its page breaks.
+pub fn audit_expiry(expires: DateTime<Utc>, warning: Duration, now: DateTime<Utc>) -> Vec<Finding> {
+    let date = expires.format("%Y-%m-%d");
+    if expires <= now {
+        vec![Finding::new(
+            Severity::High,
+            "Certificate",
+            &format!("expired on {}", date),
+        )]
+    } else if expires - now <= warning {
+        vec![Finding::new(
+            Severity::Medium,
+            "Certificate",
+            &format!(
+                "expires on {}, in {} days",
+                date,
+                (expires - now).num_days()
+            ),
+        )]
+    } else {
+        vec![]
+    }
+}
+
+/// Reads a `security.txt` file: its `Expires` and `Contact` fields, and
+/// whether it mentions the site's SecureDrop. Returns `None` for anything
+/// without a `Contact`, so error pages served in its place aren't taken
+/// for one.
+pub fn parse_security_txt(
+    url: &str,
+    text: &str,
+    landing_page_url: &str,
+    onion_address: &str,
+) -> Option<SecurityTxt> {
+    let mut expires = None;
+    let mut contacts = vec![];
+    for line in text.lines() {
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) if !line.starts_with('#') => (field.trim(), value.trim()),
+            _ => continue,
+        };
+        if field.eq_ignore_ascii_case("expires") {
+            expires = DateTime::parse_from_rfc3339(value)
+                .ok()
+                .map(|e| e.with_timezone(&Utc));
+        } else if field.eq_ignore_ascii_case("contact") {
+            contacts.push(value.to_owned());
+        }
+    }
+    if contacts.is_empty() {
+        return None;
+    }
+    let lower = text.to_lowercase();
+    let landing = landing_page_url.trim().trim_end_matches('/').to_lowercase();
+    let onion = normalize_onion(onion_address).to_lowercase();
+    let mentions_securedrop = lower.contains("securedrop")
+        || (!landing.is_empty() && lower.contains(&landing))
+        || (!onion.is_empty() && lower.contains(&onion));
+    Some(SecurityTxt {
+        url: url.to_owned(),
+        expires,
+        contacts,
+        mentions_securedrop,
+    })
+}
+
+/// Looks for a `security.txt` on the landing page's `host`, then on its
+/// apex domain, adding informational findings for a missing, expired, or
+/// unrelated one. Sites are encouraged, not required, to publish one.
+async fn check_security_txt(
+    check: &mut LandingCheck,
+    host: &str,
+    onion_address: &str,
+    options: &ScanOptions,
+    budget: &mut RequestBudget,
+) {
+    let (name, port) = host.split_at(host.find(':').unwrap_or(host.len()));
+    let labels: Vec<_> = name.split('.').collect();
+    let mut hosts = vec![host.to_owned()];
+    if labels.len() > 2 {
+        hosts.push(format!("{}{}", labels[labels.len() - 2..].join("."), port));
+    }
+    // An invalid certificate is reported already, and needn't hide the file.
+    let client = client_builder(options, host).and_then(|b| {
+        b.danger_accept_invalid_certs(check.valid_certificate == Some(false))
+            .build()
+    });
+    let client = match client {
+        Ok(client) => client,
+        Err(_) => return,
+    };
+    for h in hosts {
+        if !budget.acquire().await {
+            return;
+        }
+        let url = format!("https://{}/.well-known/security.txt", h);
+        debug!("Fetching {}", url);
+        let text = match client.get(&url).send().await {
+            Ok(r) if r.status().is_success() => r.text().await.unwrap_or_default(),
+            _ => continue,
+        };
+        check.security_txt =
+            parse_security_txt(&url, &text, &check.landing_page_url, onion_address);
+        if check.security_txt.is_some() {
+            break;
+        }
+    }
+    let mut messages = vec![];
+    match &check.security_txt {
+        None => messages.push("none at /.well-known/security.txt".to_owned()),
+        Some(txt) => {
+            match txt.expires {
+                None => messages.push(format!("{} has no valid Expires field", txt.url)),
+                Some(expires) if expires <= Utc::now() => messages.push(format!(
+                    "{} expired on {}",
+                    txt.url,
+                    expires.format("%Y-%m-%d")
+                )),
+                Some(_) => {}
+            }
+            if !txt.mentions_securedrop {
+                messages.push(format!("{} doesn't mention SecureDrop", txt.url));
+            }
+        }
+    }
+    for message in messages {
+        check
+            .findings
+            .push(Finding::new(Severity::Info, "security.txt", &message));
+    }
+}
+
+/// A redirect policy following up to `max_redirects` redirects, recording
+/// each URL redirected to in `chain`.
+fn recording_policy(
+    landing: &LandingOptions,
+    chain: &Arc<Mutex<Vec<String>>>,
+) -> reqwest::redirect::Policy {
+    let max = landing.max_redirects;
+    let chain = chain.clone();
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > max {
+            return attempt.error(format!("more than {} redirects", max));
+        }
+        chain.lock().unwrap().push(attempt.url().to_string());
+        attempt.follow()
+    })
+}
+
+/// Flags redirects from the landing page on `host` that downgrade to plain
+/// HTTP, which an attacker on the network could tamper with, or that leave
+/// its site, which the directory's listing doesn't vouch for.
+pub fn audit_redirects(host: &str, redirects: &[String]) -> Vec<Finding> {
+    let mut findings = vec![];
+    if let Some(url) = redirects.iter().find(|u| u.starts_with("http://")) {
+        findings.push(Finding::new(
+            Severity::High,
+            "Redirects",
+            &format!("downgrades to plain HTTP at {}", url),
+        ));
+    }
+    let host = host.split(':').next().unwrap_or(host).to_ascii_lowercase();
+    let offsite: Vec<String> = redirects
+        .iter()
+        .filter_map(|u| url_host(u))
+        .filter(|h| !same_site(h, &host))
+        .fold(vec![], |mut hosts, h| {
+            if !hosts.contains(&h) {
+                hosts.push(h);
+            }
+            hosts
+        });
+    if !offsite.is_empty() {
+        findings.push(Finding::new(
+            Severity::Medium,
+            "Redirects",
+            &format!("leaves {} for {}", host, offsite.join(", ")),
+        ));
+    }
+    findings
+}
+
+/// Records the status of a fetched landing page, and where it advertises
+/// its onion service: the `Onion-Location` header takes precedence over
+/// the equivalent `<meta http-equiv>` tag, as in Tor Browser. Returns the
+/// page's body, if it could be read.
+async fn inspect_page(
+    check: &mut LandingCheck,
+    response: reqwest::Response,
+    instance: &SDDirectoryInstance,
+) -> Option<String> {
+    let onion_address = instance.onion_address.as_str();
+    check.http_status = Some(response.status().as_u16());
+    check.findings = audit_headers(response.headers());
+    let header = response
+        .headers()
+        .get("onion-location")
+        .and_then(|l| l.to_str().ok())
+        .map(|l| l.trim().to_owned());
+    let host = response.url().host_str().unwrap_or("").to_owned();
+    let body = response.text().await.ok();
+    if let Some(body) = &body {
+        check.findings.extend(audit_content(body, &host));
+        if !onion_address.is_empty() {
+            let (mentioned, findings) = audit_mentions(body, instance);
+            check.mentions_onion = Some(mentioned);
+            check.findings.extend(findings);
+        }
+    }
+    check.onion_location = header.or_else(|| body.as_deref().and_then(meta_onion_location));
+    if let Some(location) = &check.onion_location {
+        if !onion_address.is_empty() {
+            check.onion_location_matches = Some(
+                normalize_onion(location).to_lowercase()
+                    == normalize_onion(onion_address).to_lowercase(),
+            );
+        }
+    }
+    body
+}
+
+/// Checks that a landing page tells sources the instance's onion address
+/// or onion name from the directory. A page that only gives another one,
+/// say from before the site moved, sends sources to an address the
+/// directory doesn't vouch for. Returns whether the listed one is
+/// mentioned.
+pub fn audit_mentions(html: &str, instance: &SDDirectoryInstance) -> (bool, Vec<Finding>) {
+    let onions =
+        Regex::new(r"(?i)\b(?:[a-z2-7]{56}|[a-z2-7]{16}|[a-z0-9-]+\.securedrop\.tor)\.onion\b")
+            .expect("the onion address pattern is valid");
+    let listed = normalize_onion(&instance.onion_address).to_lowercase();
+    let name = instance
+        .onion_name
+        .as_deref()
+        .map(|n| n.trim().to_lowercase())
+        .filter(|n| !n.is_empty());
+    let mut mentioned = false;
+    let mut others: Vec<String> = vec![];
+    for m in onions.find_iter(html) {
+        let onion = m.as_str().to_lowercase();
+        if onion == listed || Some(&onion) == name.as_ref() {
+            mentioned = true;
+        } else if !others.contains(&onion) {
+            others.push(onion);
+        }
+    }
+    let findings = if mentioned {
+        vec![]
+    } else if !others.is_empty() {
+        vec![Finding::new(
+            Severity::High,
+            "Onion address",
+            &format!(
+                "the page gives {}, not the listed {}",
+                others.join(", "),
+                listed
+            ),
+        )]
+    } else {
+        vec![Finding::new(
+            Severity::Medium,
+            "Onion address",
+            &format!("the page doesn't mention {}", listed),
+        )]
+    };
+    (mentioned, findings)
+}
+
+/// Markers of the bot challenges CDNs and firewalls show in place of a
+/// page, by the service showing them. Tor exits often share addresses
+/// with abusive traffic, so they get these far more than other visitors.
+const CHALLENGES: &[(&str, &str)] = &[
+    ("cf-challenge", "Cloudflare"),
+    ("cf_chl_", "Cloudflare"),
+    ("challenge-platform", "Cloudflare"),
+    ("attention required! | cloudflare", "Cloudflare"),
+    ("captcha-delivery.com", "DataDome"),
+    ("_incapsula_resource", "Imperva"),
+    ("px-captcha", "PerimeterX"),
+    ("sucuri website firewall", "Sucuri"),
+    ("g-recaptcha", "reCAPTCHA"),
+    ("h-captcha", "hCaptcha"),
+    ("hcaptcha.com", "hCaptcha"),
+];
+
+/// The service challenging visitors to a page with a CAPTCHA or similar,
+/// if any.
+fn challenge(html: &str) -> Option<&'static str> {
+    let lower = html.to_ascii_lowercase();
+    CHALLENGES
+        .iter()
+        .find(|(marker, _)| lower.contains(marker))
+        .map(|(_, service)| *service)
+}
+
+/// The trimmed text of a page's `<title>`, if it has one.
+fn page_title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let start = lower.find("<title")?;
+    let start = start + lower[start..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    Some(html[start..end].trim().to_owned())
+}
+
+/// Fetches the landing page directly, without Tor, and adds findings if
+/// Tor users are blocked, challenged, or served a materially different
+/// page: one with another title, or under half (or over twice) the size.
+/// This reveals the address the check is made from to the site.
+async fn compare_clearnet(
+    check: &mut LandingCheck,
+    url: &str,
+    tor_body: Option<&str>,
+    options: &ScanOptions,
+    landing: &LandingOptions,
+) {
+    debug!("Fetching landing page over the clearnet: {}", url);
+    // The certificate is checked over Tor, so isn't checked again here.
+    let client = reqwest::Client::builder()
+        .timeout(options.timeout)
+        .default_headers(options.headers.clone())
+        .danger_accept_invalid_certs(true)
+        .redirect(reqwest::redirect::Policy::limited(landing.max_redirects))
+        .build();
+    let response = match client {
+        Ok(client) => client.get(url).send().await,
+        Err(e) => Err(e),
+    };
+    let (status, body) = match response {
+        Ok(r) => (r.status().as_u16(), r.text().await.unwrap_or_default()),
+        Err(e) => {
+            debug!("Failed to fetch {} over the clearnet, {}", url, e);
+            return;
+        }
+    };
+    check.clearnet_status = Some(status);
+    if !(200..300).contains(&status) {
+        // Nothing to compare Tor users' experience to.
+        return;
+    }
+    let mut findings = vec![];
+    match (check.http_status, tor_body) {
+        (None, _) => findings.push(Finding::new(
+            Severity::High,
+            "Tor access",
+            "unreachable over Tor, but served over the clearnet",
+        )),
+        (Some(tor_status), _) if !(200..300).contains(&tor_status) => {
+            let by = tor_body
+                .and_then(challenge)
+                .map(|service| format!(" by {}", service))
+                .unwrap_or_default();
+            findings.push(Finding::new(
+                Severity::High,
+                "Tor access",
+                &format!(
+                    "blocked over Tor{} (HTTP {}), but served over the clearnet (HTTP {})",
+                    by, tor_status, status
+                ),
+            ))
+        }
+        (Some(_), Some(tor_body)) => {
+            if let Some(service) = challenge(tor_body).filter(|_| challenge(&body).is_none()) {
+                findings.push(Finding::new(
+                    Severity::High,
+                    "Tor access",
+                    &format!("Tor users are challenged by {}", service),
+                ));
+            } else {
+                let (tor_title, title) = (page_title(tor_body), page_title(&body));
+                let (tor_len, len) = (tor_body.len(), body.len());
+                if tor_title != title {
+                    findings.push(Finding::new(
+                        Severity::Medium,
+                        "Tor access",
+                        &format!(
+                            "the page over Tor is titled \"{}\", over the clearnet \"{}\"",
+                            tor_title.unwrap_or_default(),
+                            title.unwrap_or_default()
+                        ),
+                    ));
+                } else if tor_len * 2 < len || len * 2 < tor_len {
+                    findings.push(Finding::new(
+                        Severity::Medium,
+                        "Tor access",
+                        &format!(
+                            "the page over Tor is {} bytes, over the clearnet {} bytes",
+                            tor_len, len
+                        ),
+                    ));
+                }
+            }
+        }
+        (Some(_), None) => {}
+    }
+    check.findings.extend(findings);
+}
+
+/// Grades the security headers of a landing page, following the
+/// SecureDrop landing page guidelines: HSTS, a Content Security Policy,
+/// protection against framing, and a Referrer-Policy that keeps visits
+/// from leaking to other sites.
+pub fn audit_headers(headers: &reqwest::header::HeaderMap) -> Vec<Finding> {
+    let get = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_ascii_lowercase())
+    };
+    let mut findings = vec![];
+
+    const HSTS: &str = "Strict-Transport-Security";
+    match get(HSTS) {
+        None => findings.push(Finding::new(Severity::High, HSTS, "missing")),
+        Some(hsts) => {
+            let max_age = hsts
+                .split(';')
+                .filter_map(|d| d.trim().strip_prefix("max-age="))
+                .find_map(|v| v.trim_matches('"').parse::<u64>().ok())
+                .unwrap_or(0);
+            // Six months, the minimum most HSTS guidance recommends.
+            if max_age < 15_552_000 {
+                findings.push(Finding::new(
+                    Severity::Medium,
+                    HSTS,
+                    &format!("max-age of {} seconds is under 6 months", max_age),
+                ));
+            }
+            if !hsts.contains("includesubdomains") {
+                findings.push(Finding::new(Severity::Low, HSTS, "no includeSubDomains"));
+            }
+            if !hsts.contains("preload") {
+                findings.push(Finding::new(Severity::Info, HSTS, "no preload"));
+            }
+        }
+    }
+
+    const CSP: &str = "Content-Security-Policy";
+    let csp = get(CSP);
+    match &csp {
+        None => findings.push(Finding::new(Severity::Medium, CSP, "missing")),
+        Some(csp) => {
+            for keyword in &["'unsafe-inline'", "'unsafe-eval'"] {
+                if csp.contains(keyword) {
+                    findings.push(Finding::new(
+                        Severity::Low,
+                        CSP,
+                        &format!("allows {}", keyword),
+                    ));
+                }
+            }
+            if !csp.contains("default-src") {
+                findings.push(Finding::new(Severity::Low, CSP, "no default-src"));
+            }
+        }
+    }
+
+    // CSP's frame-ancestors supersedes X-Frame-Options where supported.
+    const XFO: &str = "X-Frame-Options";
+    let frame_ancestors = csp.is_some_and(|c| c.contains("frame-ancestors"));
+    match get(XFO).as_deref() {
+        Some("deny") | Some("sameorigin") => {}
+        Some(other) => findings.push(Finding::new(
+            Severity::Low,
+            XFO,
+            &format!("unrecognized value {}", other),
+        )),
+        None if frame_ancestors => {}
+        None => findings.push(Finding::new(Severity::Medium, XFO, "missing")),
+    }
+
+    const REFERRER: &str = "Referrer-Policy";
+    // Browsers use the last policy they recognize in a list.
+    let policy = get(REFERRER).and_then(|p| p.rsplit(',').next().map(|p| p.trim().to_owned()));
+    match policy.as_deref() {
+        Some("no-referrer")
+        | Some("same-origin")
+        | Some("strict-origin")
+        | Some("strict-origin-when-cross-origin") => {}
+        Some("origin") | Some("origin-when-cross-origin") => findings.push(Finding::new(
+            Severity::Low,
+            REFERRER,
+            "sends the origin to other sites",
+        )),
+        Some(other) => findings.push(Finding::new(
+            Severity::Medium,
+            REFERRER,
+            &format!("{} sends full URLs to other sites", other),
+        )),
+        None => findings.push(Finding::new(Severity::Low, REFERRER, "missing")),
+    }
+
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    findings
+}
+
+/// Analytics and advertising services, by a domain or path their
+/// snippets load from. SecureDrop's landing page guidelines rule them out,
+/// as they tell third parties who visits the page.
+const TRACKERS: &[(&str, &str)] = &[
+    ("google-analytics.com", "Google Analytics"),
+    ("googletagmanager.com", "Google Tag Manager"),
+    ("doubleclick.net", "DoubleClick"),
+    ("googlesyndication.com", "Google AdSense"),
+    ("connect.facebook.net", "Facebook pixel"),
+    ("facebook.com/tr", "Facebook pixel"),
+    ("static.ads-twitter.com", "Twitter pixel"),
+    ("analytics.twitter.com", "Twitter pixel"),
+    ("snap.licdn.com", "LinkedIn Insight"),
+    ("hotjar.com", "Hotjar"),
+    ("clarity.ms", "Microsoft Clarity"),
+    ("bat.bing.com", "Bing Ads"),
+    ("chartbeat.com", "Chartbeat"),
+    ("parsely.com", "Parse.ly"),
+    ("scorecardresearch.com", "comScore"),
+    ("quantserve.com", "Quantcast"),
+    ("omtrdc.net", "Adobe Analytics"),
+    ("adobedtm.com", "Adobe Analytics"),
+    ("nr-data.net", "New Relic"),
+    ("cdn.segment.com", "Segment"),
+    ("mixpanel.com", "Mixpanel"),
+    ("plausible.io", "Plausible"),
+    ("taboola.com", "Taboola"),
+    ("outbrain.com", "Outbrain"),
+    ("matomo.js", "Matomo"),
+    ("piwik.js", "Matomo"),
+];
+
+/// The host of an absolute (or scheme-relative) URL in a page, if it is
+/// one.
+fn url_host(url: &str) -> Option<String> {
+    let url = url.trim();
+    let rest = url
+        .strip_prefix("//")
+        .or_else(|| url.split_once("://").map(|(_, rest)| rest))?;
+    let host = rest.split(['/', '?', '#']).next()?;
+    let host = host.rsplit('@').next()?.split(':').next()?;
+    Some(host.to_ascii_lowercase()).filter(|h| !h.is_empty())
+}
+
+/// Whether a resource on `host` belongs to the landing page's own site on
+/// `page_host`, approximating the site by its last two labels.
+fn same_site(host: &str, page_host: &str) -> bool {
+    let labels: Vec<_> = page_host.rsplit('.').take(2).collect();
+    let site = labels.into_iter().rev().collect::<Vec<_>>().join(".");
+    host == site || host.ends_with(&format!(".{}", site))
+}
+
+/// Audits a landing page's HTML on `host`: analytics and trackers it
+/// includes, by the services' known domains anywhere in the page (which
+/// also covers inline loader snippets), and scripts and other resources
+/// it loads from third parties such as CDNs, which can see every visit.
+pub fn audit_content(html: &str, host: &str) -> Vec<Finding> {
+    let lower = html.to_ascii_lowercase();
+    let host = host.to_ascii_lowercase();
+    let mut findings = vec![];
+
+    let mut trackers: Vec<&str> = TRACKERS
+        .iter()
+        .filter(|(pattern, _)| lower.contains(pattern))
+        .map(|(_, name)| *name)
+        .collect();
+    trackers.sort_unstable();
+    trackers.dedup();
+    if !trackers.is_empty() {
+        findings.push(Finding::new(
+            Severity::High,
+            "Trackers",
+            &format!("includes {}", trackers.join(", ")),
+        ));
+    }
+
+    let mut scripts = std::collections::BTreeSet::new();
+    let mut resources = std::collections::BTreeSet::new();
+    for (tag, name) in [
+        ("<script", "src"),
+        ("<iframe", "src"),
+        ("<img", "src"),
+        ("<link", "href"),
+    ] {
+        let mut rest = lower.as_str();
+        while let Some(start) = rest.find(tag) {
+            let element = &rest[start..];
+            let element = &element[..element.find('>').unwrap_or(element.len())];
+            rest = &rest[start + element.len()..];
+            // Links to other pages, as opposed to stylesheets, icons and
+            // the like, aren't loaded by visiting the page.
+            if tag == "<link" && attribute(element, "rel").as_deref() == Some("canonical") {
+                continue;
+            }
+            let resource_host = match attribute(element, name).as_deref().and_then(url_host) {
+                Some(h) if !same_site(&h, &host) => h,
+                _ => continue,
+            };
+            let tracker = TRACKERS.iter().any(|(pattern, _)| {
+                let domain = pattern.split('/').next().unwrap_or(pattern);
+                resource_host == domain || resource_host.ends_with(&format!(".{}", domain))
+            });
+            if tracker {
+                continue;
+            }
+            if tag == "<script" || tag == "<iframe" {
+                scripts.insert(resource_host);
+            } else {
+                resources.insert(resource_host);
+            }
+        }
+    }
+    if !scripts.is_empty() {
+        findings.push(Finding::new(
+            Severity::Medium,
+            "Third-party content",
+            &format!(
+                "loads scripts or frames from {}",
+                scripts.into_iter().collect::<Vec<_>>().join(", ")
+            ),
+        ));
+    }
+    if !resources.is_empty() {
+        findings.push(Finding::new(
+            Severity::Low,
+            "Third-party content",
+            &format!(
+                "loads resources from {}",
+                resources.into_iter().collect::<Vec<_>>().join(", ")
+            ),
+        ));
+    }
+    findings
+}
+
+/// Finds the `content` of a `<meta http-equiv="onion-location">` tag.
+fn meta_onion_location(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let mut rest = lower.as_str();
+    while let Some(start) = rest.find("<meta") {
+        let tag = &rest[start..];
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+        if attribute(tag, "http-equiv").as_deref() == Some("onion-location") {
+            // Take the value from the original text, preserving its case.
+            let offset = lower.len() - rest.len() + start;
+            return attribute(&html[offset..offset + tag.len()], "content");
+        }
+        rest = &rest[start + tag.len()..];
+    }
+    None
+}
+
+/// Reads an attribute's value from an HTML tag, quoted or not. The name is
+/// matched case-insensitively.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut search = 0;
+    while let Some(found) = lower[search..].find(name) {
+        let start = search + found;
+        search = start + name.len();
+        // Skip matches inside another attribute's name, e.g. `data-content`.
+        let preceded_by_space = lower[..start]
+            .chars()
+            .last()
+            .is_some_and(|c| c.is_whitespace());
+        let value = lower[search..].trim_start();
+        if !preceded_by_space || !value.starts_with('=') {
+            continue;
+        }
+        let offset = tag.len() - value.len() + 1;
+        let value = tag[offset..].trim_start();
+        return Some(match value.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                let value = &value[1..];
+                value[..value.find(quote).unwrap_or(value.len())].to_owned()
+            }
+            _ => value
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .next()
+                .unwrap_or("")
+                .to_owned(),
+        });
+    }
+    None
+}
+
+/// Checks the landing pages of all instances that list one, concurrently,
+/// returning the results in the order given.
+pub async fn check_landing_pages(
+    instances: &[SDDirectoryInstance],
+    options: &ScanOptions,
+    landing: &LandingOptions,
+) -> Vec<LandingCheck> {
+    let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let handles: Vec<_> = instances
+        .iter()
+        .filter(|i| !i.landing_page_url.trim().is_empty())
+        .enumerate()
+        .map(|(n, i)| {
+            let instance = i.clone();
+            let options = options.shard(n);
+            let semaphore = semaphore.clone();
+            let landing = landing.clone();
+            tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                check_landing_page(&instance, &options, &landing).await
+            })
+        })
+        .collect();
+    let mut checks = vec![];
+    for handle in handles {
+        if let Ok(check) = handle.await {
+            checks.push(check);
+        }
+    }
+    checks
+}
+
+/// Renders the landing page checks as human-readable text, one line per
+/// site followed by a count of those that passed.
+pub fn format_landing_report(checks: &[LandingCheck]) -> String {
+    let mut report = String::from("");
+    for c in checks {
+        let problems = c.problems();
+        report += &format!(
+            "{:<4}  {} ({})",
+            if problems.is_empty() { "PASS" } else { "FAIL" },
+            c.title,
+            c.landing_page_url
+        );
+        if !problems.is_empty() {
+            report += &format!(": {}", problems.join("; "));
+        }
+        report += "\n";
+        if !c.redirects.is_empty() {
+            report += &format!(
+                "      redirects: {} -> {}\n",
+                c.landing_page_url,
+                c.redirects.join(" -> ")
+            );
+        }
+        for f in &c.findings {
+            report += &format!("      [{}] {}: {}\n", f.severity, f.header, f.message);
+        }
+    }
+    let passed = checks.iter().filter(|c| c.passed()).count();
+    report += &format!("\n{} of {} landing pages passed\n", passed, checks.len());
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+    type Header = (&'static str, &'static str);
+    type Grade<'a> = (&'a str, Severity);
+
+    fn headers(pairs: &[Header]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| {
+                (
+                    HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                    HeaderValue::from_str(value).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn graded(findings: &[Finding]) -> Vec<Grade<'_>> {
+        findings
+            .iter()
+            .map(|f| (f.header.as_str(), f.severity))
+            .collect()
+    }
+
+    const HSTS: &str = "Strict-Transport-Security";
+    const CSP: &str = "Content-Security-Policy";
+    const XFO: &str = "X-Frame-Options";
+    const REFERRER: &str = "Referrer-Policy";
+
+    #[test]
+    fn grades_security_headers() {
+        let strong_hsts = (HSTS, "max-age=31536000; includeSubDomains; preload");
+        let cases: Vec<(Vec<Header>, Vec<Grade>)> = vec![
+            (
+                vec![],
+                vec![
+                    (HSTS, Severity::High),
+                    (CSP, Severity::Medium),
+                    (XFO, Severity::Medium),
+                    (REFERRER, Severity::Low),
+                ],
+            ),
+            (
+                vec![
+                    strong_hsts,
+                    (CSP, "default-src 'self'; frame-ancestors 'none'"),
+                    (REFERRER, "no-referrer"),
+                ],
+                vec![],
+            ),
+            (
+                vec![
+                    (HSTS, "max-age=86400"),
+                    (CSP, "script-src 'self' 'unsafe-inline' 'unsafe-eval'"),
+                    (XFO, "ALLOW-FROM https://example.org"),
+                    // Browsers go by the last policy in the list.
+                    (REFERRER, "no-referrer, unsafe-url"),
+                ],
+                vec![
+                    (HSTS, Severity::Medium),
+                    (REFERRER, Severity::Medium),
+                    (HSTS, Severity::Low),
+                    (CSP, Severity::Low),
+                    (CSP, Severity::Low),
+                    (CSP, Severity::Low),
+                    (XFO, Severity::Low),
+                    (HSTS, Severity::Info),
+                ],
+            ),
+            (
+                vec![
+                    strong_hsts,
+                    (CSP, "default-src 'self'"),
+                    (XFO, "DENY"),
+                    (REFERRER, "origin-when-cross-origin"),
+                ],
+                vec![(REFERRER, Severity::Low)],
+            ),
+            (
+                vec![
+                    (HSTS, "max-age=\"31536000\"; includeSubDomains"),
+                    (CSP, "default-src 'self'"),
+                    (XFO, "sameorigin"),
+                    (REFERRER, "strict-origin-when-cross-origin"),
+                ],
+                vec![(HSTS, Severity::Info)],
+            ),
+        ];
+        for (pairs, expected) in cases {
+            let findings = audit_headers(&headers(&pairs));
+            assert_eq!(graded(&findings), expected, "{:?}", pairs);
+        }
+    }
+
+    #[test]
+    fn audits_trackers_once_each() {
+        let html = r#"<script src="https://www.google-analytics.com/analytics.js"></script>
+            <script src="https://connect.facebook.net/en_US/fbevents.js"></script>
+            <img src="https://www.facebook.com/tr?id=1">"#;
+        let findings = audit_content(html, "www.example.org");
+        assert_eq!(graded(&findings), vec![("Trackers", Severity::High)]);
+        assert_eq!(
+            findings[0].message,
+            "includes Facebook pixel, Google Analytics"
+        );
+    }
+
+    #[test]
+    fn audits_third_party_content() {
+        let html = r#"<link rel="canonical" href="https://elsewhere.example.com/">
+            <link rel="stylesheet" href="https://fonts.example.net/font.css">
+            <script src="https://cdn.example.net/jquery.js"></script>
+            <script src="https://static.example.org/site.js"></script>
+            <script src="/local.js"></script>
+            <img src="//images.example.com/logo.png">"#;
+        let findings = audit_content(html, "www.example.org");
+        assert_eq!(
+            graded(&findings),
+            vec![
+                ("Third-party content", Severity::Medium),
+                ("Third-party content", Severity::Low),
+            ]
+        );
+        assert_eq!(
+            findings[0].message,
+            "loads scripts or frames from cdn.example.net"
+        );
+        assert_eq!(
+            findings[1].message,
+            "loads resources from fonts.example.net, images.example.com"
+        );
+        assert!(audit_content("<p>Hello</p>", "www.example.org").is_empty());
+    }
+
+    #[test]
+    fn reads_urls_and_attributes() {
+        assert_eq!(
+            url_host("https://user@Example.org:8443/path?q").as_deref(),
+            Some("example.org")
+        );
+        assert_eq!(
+            url_host("//cdn.example.net/x.js").as_deref(),
+            Some("cdn.example.net")
+        );
+        assert_eq!(url_host("/local.js"), None);
+        assert!(same_site("static.example.org", "www.example.org"));
+        assert!(!same_site("example.org.evil.net", "www.example.org"));
+
+        assert_eq!(
+            attribute(r#"<meta data-content="x" content='Hello'>"#, "content").as_deref(),
+            Some("Hello")
+        );
+        assert_eq!(
+            attribute("<img src=logo.png/>", "src").as_deref(),
+            Some("logo.png")
+        );
+        assert_eq!(attribute("<img alt=x>", "src"), None);
+        assert_eq!(
+            page_title("<html><TITLE lang=en> Tips </title>").as_deref(),
+            Some("Tips")
+        );
+        assert_eq!(page_title("<html>"), None);
+        assert_eq!(challenge("<div class=\"g-recaptcha\">"), Some("reCAPTCHA"));
+        assert_eq!(challenge("<p>Welcome</p>"), None);
+        assert_eq!(
+            meta_onion_location(
+                r#"<META http-equiv="onion-location" content="http://AbC.onion/">"#
+            )
+            .as_deref(),
+            Some("http://AbC.onion/")
+        );
+    }
+
+    #[test]
+    fn audits_redirects() {
+        let redirects = [
+            "http://www.example.org/".to_owned(),
+            "https://tips.example.com/".to_owned(),
+        ];
+        let findings = audit_redirects("www.example.org:443", &redirects);
+        assert_eq!(
+            graded(&findings),
+            vec![
+                ("Redirects", Severity::High),
+                ("Redirects", Severity::Medium)
+            ]
+        );
+        assert_eq!(
+            findings[1].message,
+            "leaves www.example.org for tips.example.com"
+        );
+        let same = ["https://example.org/securedrop".to_owned()];
+        assert!(audit_redirects("www.example.org", &same).is_empty());
+    }
+
+    #[test]
+    fn audits_certificate_expiry() {
+        let now = Utc.ymd(2026, 10, 14).and_hms(0, 0, 0);
+        let warning = Duration::days(30);
+        let severities = |expires| {
+            audit_expiry(expires, warning, now)
+                .iter()
+                .map(|f| f.severity)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(severities(now - Duration::days(1)), vec![Severity::High]);
+        assert_eq!(severities(now + Duration::days(10)), vec![Severity::Medium]);
+        assert!(severities(now + Duration::days(90)).is_empty());
+    }
+
+    #[test]
+    fn parses_security_txt() {
+        let text = "# Our policy\nContact: mailto:security@example.org\n\
+                    Expires: 2027-01-01T00:00:00Z\nPolicy: https://example.org/securedrop\n";
+        let txt = parse_security_txt(
+            "https://example.org/.well-known/security.txt",
+            text,
+            "https://example.org/securedrop/",
+            "",
+        )
+        .unwrap();
+        assert_eq!(txt.contacts, vec!["mailto:security@example.org"]);
+        assert_eq!(txt.expires, Some(Utc.ymd(2027, 1, 1).and_hms(0, 0, 0)));
+        assert!(txt.mentions_securedrop);
+        // An error page in its place has no Contact.
+        assert!(parse_security_txt("", "<html>Not found</html>", "", "").is_none());
+    }
+}
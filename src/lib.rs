@@ -0,0 +1,1201 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error::Error;
+use std::sync::Arc;
+use tokio::sync::mpsc::channel;
+use tracing::Instrument;
+
+use std::time::Duration;
+
+use concurrency::ConcurrencyLimit;
+use custom_error::custom_error;
+use failure::FailureKind;
+use http::{HttpClient, RecordingClient, ReqwestClient};
+use interrupt::{Interrupt, INTERRUPT_GRACE};
+use snapshot::Snapshot;
+
+#[macro_use]
+extern crate log;
+
+pub mod adoption;
+pub mod anonymize;
+pub mod assertion;
+#[cfg(feature = "charts")]
+pub mod charts;
+pub mod checkpoint;
+pub mod color;
+pub mod concurrency;
+pub mod config;
+pub mod db;
+pub mod diff;
+pub mod failure;
+pub mod filter;
+pub mod findings;
+pub mod github;
+pub mod health;
+pub mod html;
+pub mod http;
+pub mod i18n;
+pub mod interrupt;
+pub mod keys;
+pub mod landing;
+pub mod locale;
+pub mod logging;
+pub mod markdown;
+pub mod mastodon;
+pub mod metrics;
+pub mod notify;
+pub mod onion;
+pub mod onion_names;
+pub mod ooni;
+pub mod pgp;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod preflight;
+pub mod publish;
+pub mod releases;
+pub mod report;
+pub mod schedule;
+pub mod show;
+pub mod signing;
+pub mod snapshot;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod template;
+pub mod tui;
+pub mod uptime;
+pub mod weblate;
+
+pub const DIRECTORY_URL: &str = "https://securedrop.org/api/v1/directory/";
+pub const TOR_PROXY: &str = "socks5h://127.0.0.1:9050";
+pub const TOR_TIMEOUT: u64 = 30;
+
+// SDMetadata stores the information obtained from a given SecureDrop
+// instance's /metadata endpoint, a JSON API with platform info. Fields
+// sdstatus doesn't know are kept in `extra`.
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct SDMetadata {
+    pub sd_version: String,
+    pub server_os: String,
+    pub gpg_fpr: String,
+    pub v2_source_url: Option<String>,
+    pub v3_source_url: String,
+    pub supported_languages: Vec<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// Fields SecureDrop serves in its metadata that sdstatus has no use for,
+/// kept in `extra` without counting as schema drift.
+const UNMODELLED_METADATA_FIELDS: &[&str] = &["allow_document_uploads", "organization_name"];
+
+/// Takes a metadata field out of `fields`, noting in `drift` if it is
+/// missing (unless `optional`) or of the wrong type, in which case it
+/// takes its default value.
+fn take_field<T: serde::de::DeserializeOwned + Default>(
+    fields: &mut serde_json::Map<String, serde_json::Value>,
+    name: &str,
+    optional: bool,
+    drift: &mut Vec<String>,
+) -> T {
+    match fields.remove(name) {
+        Some(value) => serde_json::from_value(value).unwrap_or_else(|e| {
+            drift.push(format!("{} is invalid, {}", name, e));
+            T::default()
+        }),
+        None => {
+            if !optional {
+                drift.push(format!("{} is missing", name));
+            }
+            T::default()
+        }
+    }
+}
+
+impl SDMetadata {
+    /// Reads a `/metadata` response leniently, so a SecureDrop release
+    /// that adds, drops or changes a field doesn't fail every site:
+    /// missing fields and those of the wrong type take default values,
+    /// and unknown ones are kept in `extra`. Returns how the response
+    /// differs from the expected schema alongside the metadata.
+    ///
+    /// Fails if the body isn't JSON, or isn't SecureDrop metadata at all:
+    /// not an object, or with neither `sd_version` nor `v3_source_url`.
+    pub fn parse(body: &[u8]) -> Result<(SDMetadata, Vec<String>), serde_json::Error> {
+        let mismatch = <serde_json::Error as serde::de::Error>::custom;
+        let mut fields = match serde_json::from_slice(body)? {
+            serde_json::Value::Object(fields) => fields,
+            _ => return Err(mismatch("expected a JSON object")),
+        };
+        if !fields.contains_key("sd_version") && !fields.contains_key("v3_source_url") {
+            return Err(mismatch("neither sd_version nor v3_source_url is present"));
+        }
+        let mut drift = vec![];
+        let metadata = SDMetadata {
+            sd_version: take_field(&mut fields, "sd_version", false, &mut drift),
+            server_os: take_field(&mut fields, "server_os", false, &mut drift),
+            gpg_fpr: take_field(&mut fields, "gpg_fpr", false, &mut drift),
+            // Dropped from releases after Tor removed v2 Onion services.
+            v2_source_url: take_field(&mut fields, "v2_source_url", true, &mut drift),
+            v3_source_url: take_field(&mut fields, "v3_source_url", false, &mut drift),
+            supported_languages: take_field(&mut fields, "supported_languages", false, &mut drift),
+            extra: BTreeMap::new(),
+        };
+        for name in fields.keys() {
+            if !UNMODELLED_METADATA_FIELDS.contains(&name.as_str()) {
+                drift.push(format!("{} is unknown", name));
+            }
+        }
+        Ok((
+            SDMetadata {
+                extra: fields.into_iter().collect(),
+                ..metadata
+            },
+            drift,
+        ))
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct SDDirectoryInstance {
+    pub metadata: Option<SDMetadata>,
+    pub onion_name: Option<String>,
+    pub title: String,
+    pub landing_page_url: String,
+    pub onion_address: String,
+    // Populated by a scan: whether metadata could be retrieved, the HTTP
+    // status of the response, how long its first byte took to arrive
+    // (`latency_ms`) and how long fetching all of it took (`duration_ms`).
+    // If the scan failed, the reason is kept in `error`, and classified in
+    // `failure`.
+    #[serde(default)]
+    pub available: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub failure: Option<FailureKind>,
+    #[serde(default)]
+    pub http_status: Option<u16>,
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    #[serde(default)]
+    pub attempts: u32,
+    // Populated when the journalist key is verified: the fingerprint of
+    // the key served by the Source Interface, whether it matches the
+    // `gpg_fpr` metadata field, and its algorithms and expiry dates.
+    #[serde(default)]
+    pub key_fingerprint: Option<String>,
+    #[serde(default)]
+    pub key_matches: Option<bool>,
+    #[serde(default)]
+    pub journalist_key: Option<pgp::KeyHealth>,
+    /// How the instance's metadata differs from the schema sdstatus
+    /// expects, e.g. `gpg_fpr is missing`.
+    #[serde(default)]
+    pub schema_drift: Vec<String>,
+    /// The metadata response as served, if it was JSON, including
+    /// fields sdstatus doesn't model or couldn't parse.
+    #[serde(default)]
+    pub raw_metadata: Option<serde_json::Value>,
+    /// How the instance fared over repeated fetches, if it was sampled
+    /// (see `ScanOptions::samples`).
+    #[serde(default)]
+    pub samples: Option<LatencySamples>,
+}
+
+/// The outcome of fetching an instance several times in a scan, each over
+/// circuits of its own, since a single fetch over Tor says little about
+/// its latency. The scan's own fetch counts as the first.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct LatencySamples {
+    pub fetches: u32,
+    pub succeeded: u32,
+    pub success_ratio: f64,
+    /// The median and 95th percentile time to first byte of the fetches
+    /// that succeeded, in milliseconds.
+    pub median_ms: Option<u64>,
+    pub p95_ms: Option<u64>,
+}
+
+/// Figures about a scan as a whole, included in machine-readable output
+/// so the scanner itself can be monitored.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct ScanSummary {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub instances: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// How many instances failed for each reason.
+    #[serde(default)]
+    pub failures: BTreeMap<FailureKind, usize>,
+    /// Metadata fetches made beyond the first for each instance.
+    pub retries: u32,
+    /// How long after the scan started the first instance responded. The
+    /// Tor daemon is external, so this is the closest sdstatus can see to
+    /// how long it took to be ready to build circuits. None if no instance
+    /// responded, or if the scan was replayed.
+    pub tor_bootstrap_ms: Option<u64>,
+    /// Whether the scan was interrupted, in which case `unscanned` sites
+    /// weren't scanned and are left out.
+    #[serde(default)]
+    pub partial: bool,
+    #[serde(default)]
+    pub unscanned: usize,
+}
+
+impl ScanSummary {
+    /// Summarizes the results of a scan that ran from `started_at` to
+    /// `finished_at`.
+    pub fn new(
+        started_at: DateTime<Utc>,
+        finished_at: DateTime<Utc>,
+        instances: &[SDDirectoryInstance],
+    ) -> ScanSummary {
+        let succeeded = instances.iter().filter(|i| i.available).count();
+        let mut failures = BTreeMap::new();
+        for kind in instances.iter().filter_map(|i| i.failure_kind()) {
+            *failures.entry(kind).or_default() += 1;
+        }
+        ScanSummary {
+            started_at,
+            finished_at,
+            instances: instances.len(),
+            succeeded,
+            failed: instances.len() - succeeded,
+            failures,
+            retries: instances.iter().map(|i| i.attempts.saturating_sub(1)).sum(),
+            tor_bootstrap_ms: None,
+            partial: false,
+            unscanned: 0,
+        }
+    }
+}
+
+/// The results of a scan, as output in JSON: the summary of the scan,
+/// each instance, and the findings about them.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct ScanResults {
+    pub scan: ScanSummary,
+    pub instances: Vec<SDDirectoryInstance>,
+    #[serde(default)]
+    pub findings: Vec<findings::Finding>,
+}
+
+impl ScanResults {
+    pub fn new(scan: ScanSummary, instances: Vec<SDDirectoryInstance>) -> ScanResults {
+        ScanResults {
+            scan,
+            findings: findings::detect_findings(&instances),
+            instances,
+        }
+    }
+}
+
+// We must implement a custom error type, because `Box<dyn Error>`
+// cannot be safely shared via channels.
+custom_error! {pub SdStatusError
+    NetworkError{source: reqwest::Error} = "Onion not available",
+    RequestError{source: http::HttpError} = "Onion not available",
+    PreflightError{source: preflight::PreflightError} = "Onion not available",
+    StatusError{status: u16} = "HTTP status {status}",
+    ParseError{source: serde_json::Error} = "Invalid metadata",
+    BudgetSpent = "Request budget spent",
+}
+
+/// Settings controlling how instances are scanned.
+#[derive(Clone, Debug)]
+pub struct ScanOptions {
+    /// How many times to retry a failed metadata fetch.
+    pub retries: u32,
+    /// The delay before the first retry, doubled for each one after it.
+    pub retry_backoff: Duration,
+    /// The limit on each HTTP request made to an instance.
+    pub timeout: Duration,
+    /// The Tor SOCKS proxy requests are sent through.
+    pub socks_proxy: String,
+    /// Further Tor SOCKS proxies, of separate Tor daemons, to share the
+    /// sites of a scan between, since a single Tor client's circuits
+    /// become the bottleneck at higher concurrency. See `shard`.
+    pub socks_proxies: Vec<String>,
+    /// Whether to have Tor fetch each site over circuits of its own, so a
+    /// bad circuit can't fail several sites at once, and sites can't be
+    /// linked through a shared circuit.
+    pub isolate_circuits: bool,
+    /// Whether to check that Tor can connect to each site before fetching
+    /// its metadata, to tell sites that are offline from broken ones.
+    pub preflight: bool,
+    /// The limit on the scan as a whole, after which any instances still
+    /// being scanned are reported as failed.
+    pub deadline: Option<Duration>,
+    /// Whether to fetch each instance's journalist key and check it
+    /// against the fingerprint in its metadata.
+    pub verify_key: bool,
+    /// The least time to leave between consecutive requests to the same
+    /// instance, so small Onion services aren't hammered.
+    pub request_delay: Duration,
+    /// The most requests to make to each instance in a scan, counting
+    /// retries and extra checks, if limited.
+    pub request_budget: Option<u32>,
+    /// How many instances to scan at once, or to start with if adaptive.
+    pub concurrency: usize,
+    /// Whether to adjust the concurrency during the scan, lowering it when
+    /// timeouts and circuit failures spike and raising it while sites
+    /// respond. See `concurrency::ConcurrencyLimit`.
+    pub adaptive_concurrency: bool,
+    /// Where to archive the raw responses, if anywhere.
+    pub snapshot: Option<Arc<Snapshot>>,
+    /// The client to fetch metadata with, instead of one sending requests
+    /// through `socks_proxy`, e.g. to replay canned responses.
+    pub client: Option<Arc<dyn HttpClient>>,
+    /// Stops the scan when set: no more instances are started, and those
+    /// in flight get `INTERRUPT_GRACE` to finish.
+    pub interrupt: Option<Interrupt>,
+    /// What to check on each instance.
+    pub mode: ScanMode,
+    /// How many times to fetch each instance, to summarize its latency
+    /// and how often it responds (see `LatencySamples`). With more than
+    /// one, the fetches beyond the scan's own are made with no retries.
+    pub samples: u32,
+    /// Sets apart the circuits of an instance's repeated fetches, which
+    /// are otherwise the same for every request to it.
+    pub circuit: u32,
+    /// Headers sent with every request to an instance, for metadata and
+    /// landing pages alike, e.g. a `User-Agent`. None are set by default.
+    pub headers: reqwest::header::HeaderMap,
+}
+
+/// How thoroughly instances are scanned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanMode {
+    /// Fetch and parse each instance's metadata.
+    Metadata,
+    /// Only check that each Source Interface responds, with a HEAD request
+    /// for its front page, for frequent availability checks that go easy
+    /// on the instances. Instances are then reported without metadata.
+    Ping,
+}
+
+impl std::str::FromStr for ScanMode {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "metadata" => Ok(ScanMode::Metadata),
+            "ping" => Ok(ScanMode::Ping),
+            _ => Err(format!("Unknown scan mode {}", s).into()),
+        }
+    }
+}
+
+/// The highest `ScanOptions::concurrency` accepted.
+pub const MAX_CONCURRENCY: usize = 64;
+
+impl Default for ScanOptions {
+    fn default() -> ScanOptions {
+        ScanOptions {
+            retries: 2,
+            retry_backoff: Duration::from_secs(5),
+            timeout: Duration::from_secs(TOR_TIMEOUT),
+            socks_proxy: TOR_PROXY.to_owned(),
+            socks_proxies: vec![],
+            isolate_circuits: true,
+            preflight: true,
+            deadline: None,
+            verify_key: false,
+            request_delay: Duration::from_secs(1),
+            request_budget: None,
+            concurrency: 8,
+            adaptive_concurrency: false,
+            snapshot: None,
+            client: None,
+            interrupt: None,
+            headers: reqwest::header::HeaderMap::new(),
+            mode: ScanMode::Metadata,
+            samples: 1,
+            circuit: 0,
+        }
+    }
+}
+
+impl ScanOptions {
+    /// The options for scanning the `n`th site, which goes through
+    /// `socks_proxy` and then each of `socks_proxies` in turn.
+    pub fn shard(&self, n: usize) -> ScanOptions {
+        let mut options = self.clone();
+        if let Some(i) = (n % (self.socks_proxies.len() + 1)).checked_sub(1) {
+            options.socks_proxy = self.socks_proxies[i].to_owned();
+        }
+        options
+    }
+}
+
+/// Paces the requests made to a single host during a scan, according to
+/// the politeness settings in `ScanOptions`.
+pub struct RequestBudget {
+    delay: Duration,
+    remaining: Option<u32>,
+    last: Option<tokio::time::Instant>,
+}
+
+impl RequestBudget {
+    pub fn new(options: &ScanOptions) -> RequestBudget {
+        RequestBudget {
+            delay: options.request_delay,
+            remaining: options.request_budget,
+            last: None,
+        }
+    }
+
+    /// Waits until another request may be made to the host, returning
+    /// false instead if the budget for it is spent.
+    pub async fn acquire(&mut self) -> bool {
+        match &mut self.remaining {
+            Some(0) => return false,
+            Some(n) => *n -= 1,
+            None => {}
+        }
+        if let Some(last) = self.last {
+            tokio::time::delay_until(last + self.delay).await;
+        }
+        self.last = Some(tokio::time::Instant::now());
+        true
+    }
+}
+
+impl SDDirectoryInstance {
+    /// Fetches the instance's metadata, retrying with exponential backoff
+    /// as configured, since Onion services are prone to transient circuit
+    /// failures.
+    pub async fn get_metadata(&mut self, options: &ScanOptions) -> Result<(), SdStatusError> {
+        let client: Arc<dyn HttpClient> = match &options.client {
+            Some(client) => client.clone(),
+            None => Arc::new(ReqwestClient::new(
+                client_builder(options, &self.onion_address)?.build()?,
+            )),
+        };
+        let client: Arc<dyn HttpClient> = match &options.snapshot {
+            Some(snapshot) => Arc::new(RecordingClient::new(client, snapshot.clone())),
+            None => client,
+        };
+        let mut budget = RequestBudget::new(options);
+        let mut backoff = options.retry_backoff;
+        self.attempts = 0;
+        tracing::debug!(event = "start", "Scanning {}", self.title);
+        let result = self
+            .fetch_with_retries(options, client, &mut budget, &mut backoff)
+            .await;
+        if options.samples > 1 {
+            self.sample_latency(options, &mut budget).await;
+        }
+        match &result {
+            Ok(()) => tracing::debug!(
+                event = "success",
+                attempts = self.attempts,
+                latency_ms = self.latency_ms,
+                "Scanned {}",
+                self.title
+            ),
+            Err(e) => tracing::info!(
+                event = "failure",
+                attempts = self.attempts,
+                failure = %self.failure_kind().unwrap_or(FailureKind::Other),
+                error = %e,
+                "Failed to scan {}",
+                self.title
+            ),
+        }
+        result
+    }
+
+    /// The fetches of `get_metadata`, retried until one succeeds or the
+    /// retries or the request budget run out.
+    async fn fetch_with_retries(
+        &mut self,
+        options: &ScanOptions,
+        client: Arc<dyn HttpClient>,
+        budget: &mut RequestBudget,
+        backoff: &mut Duration,
+    ) -> Result<(), SdStatusError> {
+        loop {
+            // The budget is checked again below before backing off, so this
+            // only fails for a budget that allows no requests at all.
+            if !budget.acquire().await {
+                debug!(
+                    "Request budget for {} spent, not fetching",
+                    self.onion_address
+                );
+                self.error = Some(SdStatusError::BudgetSpent.to_string());
+                return Err(SdStatusError::BudgetSpent);
+            }
+            self.attempts += 1;
+            let result = match self.preflight(options).await {
+                Ok(()) if options.mode == ScanMode::Ping => self.ping(client.as_ref()).await,
+                Ok(()) => self.fetch_metadata(client.as_ref()).await,
+                Err(e) => Err(e),
+            };
+            if result.is_ok() && options.verify_key {
+                if budget.acquire().await {
+                    self.verify_key(client.as_ref()).await;
+                } else {
+                    debug!(
+                        "Request budget for {} spent, not verifying key",
+                        self.onion_address
+                    );
+                }
+            }
+            if result.is_ok() || self.attempts > options.retries {
+                return result;
+            }
+            if budget.remaining == Some(0) {
+                debug!(
+                    "Request budget for {} spent, not retrying",
+                    self.onion_address
+                );
+                return result;
+            }
+            tracing::debug!(
+                event = "retry",
+                attempt = self.attempts + 1,
+                backoff_secs = backoff.as_secs(),
+                "Retrying {} in {}s (attempt {} of {})",
+                self.onion_address,
+                backoff.as_secs(),
+                self.attempts + 1,
+                options.retries + 1
+            );
+            tokio::time::delay_for(*backoff).await;
+            *backoff *= 2;
+        }
+    }
+
+    /// Fetches the instance `options.samples - 1` more times, each over
+    /// circuits of its own, and summarizes those fetches along with the
+    /// scan's last one. The instance's results are otherwise left as the
+    /// scan's fetch found them.
+    async fn sample_latency(&mut self, options: &ScanOptions, budget: &mut RequestBudget) {
+        let url = match options.mode {
+            ScanMode::Metadata => format!("http://{}/metadata", self.onion_address),
+            ScanMode::Ping => format!("http://{}/", self.onion_address),
+        };
+        let mut fetches = 1;
+        let mut succeeded = self.available as u32;
+        let mut latencies: Vec<u64> = self
+            .latency_ms
+            .filter(|_| self.available)
+            .into_iter()
+            .collect();
+        for circuit in 1..options.samples {
+            if !budget.acquire().await {
+                debug!(
+                    "Request budget for {} spent, not sampling further",
+                    self.onion_address
+                );
+                break;
+            }
+            let client: Arc<dyn HttpClient> = match &options.client {
+                Some(client) => client.clone(),
+                None => {
+                    let options = ScanOptions {
+                        circuit,
+                        ..options.clone()
+                    };
+                    match client_builder(&options, &self.onion_address).and_then(|b| b.build()) {
+                        Ok(client) => Arc::new(ReqwestClient::new(client)),
+                        Err(e) => {
+                            warn!("Failed to build a client for sampling, {}", e);
+                            break;
+                        }
+                    }
+                }
+            };
+            fetches += 1;
+            let response = match options.mode {
+                ScanMode::Metadata => client.get(&url).await,
+                ScanMode::Ping => client.head(&url).await,
+            };
+            match response {
+                Ok(r) if FailureKind::from_status(r.status).is_none() => {
+                    succeeded += 1;
+                    latencies.extend(r.latency.map(|l| l.as_millis() as u64));
+                }
+                Ok(r) => debug!(
+                    "Sample {} of {} failed: HTTP status {}",
+                    circuit + 1,
+                    self.onion_address,
+                    r.status
+                ),
+                Err(e) => debug!(
+                    "Sample {} of {} failed: {}",
+                    circuit + 1,
+                    self.onion_address,
+                    e
+                ),
+            }
+        }
+        let spread = report::summarize_latency(latencies);
+        self.samples = Some(LatencySamples {
+            fetches,
+            succeeded,
+            success_ratio: succeeded as f64 / fetches as f64,
+            median_ms: spread.as_ref().map(|s| s.median),
+            p95_ms: spread.as_ref().map(|s| s.p95),
+        });
+    }
+
+    /// Checks that Tor can connect to the instance, see
+    /// `preflight::check_onion`. Skipped when metadata is fetched with a
+    /// client of the caller's, which may not use Tor at all.
+    async fn preflight(&mut self, options: &ScanOptions) -> Result<(), SdStatusError> {
+        if !options.preflight || options.client.is_some() {
+            return Ok(());
+        }
+        let credentials = isolation_credentials(options, &self.onion_address);
+        let result = preflight::check_onion(
+            &options.socks_proxy,
+            &self.onion_address,
+            credentials.as_ref().map(|(u, p)| (*u, p.as_str())),
+            options.timeout,
+        )
+        .await;
+        if let Err(e) = result {
+            warn!(
+                "Failed to connect to {} ({}): {}",
+                self.title, self.onion_address, e
+            );
+            self.metadata = None;
+            self.http_status = None;
+            self.error = Some(e.to_string());
+            self.failure = Some(e.failure_kind());
+            if let Some(snapshot) = &options.snapshot {
+                let metadata_url = format!("http://{}/metadata", self.onion_address);
+                snapshot.save_failure(&self.onion_address, &metadata_url, &e.to_string());
+            }
+            return Err(SdStatusError::PreflightError { source: e });
+        }
+        Ok(())
+    }
+
+    /// Checks that the Source Interface responds, as in `ScanMode::Ping`.
+    async fn ping(&mut self, client: &dyn HttpClient) -> Result<(), SdStatusError> {
+        debug!("Pinging: {}", self.onion_address);
+        let url = format!("http://{}/", self.onion_address);
+        match client.head(&url).await {
+            Ok(r) => {
+                self.http_status = Some(r.status);
+                self.latency_ms = r.latency.map(|l| l.as_millis() as u64);
+                self.duration_ms = r.duration.map(|d| d.as_millis() as u64);
+                if let Some(failure) = FailureKind::from_status(r.status) {
+                    warn!(
+                        "Failed to reach {} ({}): HTTP status {}",
+                        self.title, self.onion_address, r.status
+                    );
+                    self.error = Some(format!("HTTP status {}", r.status));
+                    self.failure = Some(failure);
+                    return Err(SdStatusError::StatusError { status: r.status });
+                }
+                self.available = true;
+                self.error = None;
+                self.failure = None;
+                Ok(())
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to connect to {} ({})",
+                    self.title, self.onion_address
+                );
+                self.error = Some(e.to_string());
+                self.failure = Some(FailureKind::from_http_error(&e));
+                Err(SdStatusError::RequestError { source: e })
+            }
+        }
+    }
+
+    async fn fetch_metadata(&mut self, client: &dyn HttpClient) -> Result<(), SdStatusError> {
+        debug!("Fetching metadata: {}", self.onion_address);
+        let metadata_url = format!("http://{}/metadata", self.onion_address);
+        match client.get(&metadata_url).await {
+            Ok(r) => {
+                self.http_status = Some(r.status);
+                self.latency_ms = r.latency.map(|l| l.as_millis() as u64);
+                self.duration_ms = r.duration.map(|d| d.as_millis() as u64);
+                if let Some(failure) = FailureKind::from_status(r.status) {
+                    warn!(
+                        "Failed to fetch metadata from {} ({}): HTTP status {}",
+                        self.title, self.onion_address, r.status
+                    );
+                    self.metadata = None;
+                    self.error = Some(format!("HTTP status {}", r.status));
+                    self.failure = Some(failure);
+                    return Err(SdStatusError::StatusError { status: r.status });
+                }
+                self.raw_metadata = serde_json::from_slice(&r.body).ok();
+                match SDMetadata::parse(&r.body) {
+                    Ok((m, drift)) => {
+                        if !drift.is_empty() {
+                            warn!(
+                                "Metadata from {} ({}) differs from the expected schema: {}",
+                                self.title,
+                                self.onion_address,
+                                drift.join("; ")
+                            );
+                        }
+                        self.schema_drift = drift;
+                        if let Err(e) = onion::validate_v3(&m.v3_source_url) {
+                            warn!(
+                                "Malformed v3_source_url in metadata from {} ({}): {}",
+                                self.title, self.onion_address, e
+                            );
+                        }
+                        self.metadata = Some(m);
+                        self.available = true;
+                        self.error = None;
+                        self.failure = None;
+                        Ok(())
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to read metadata from {} ({})",
+                            self.title, self.onion_address
+                        );
+                        self.metadata = None;
+                        self.error = Some(format!("error decoding response body: {}", e));
+                        self.failure = Some(FailureKind::from_parse_error(&e));
+                        Err(SdStatusError::ParseError { source: e })
+                    }
+                }
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to connect to {} ({})",
+                    self.title, self.onion_address
+                );
+                self.metadata = None;
+                self.error = Some(e.to_string());
+                self.failure = Some(FailureKind::from_http_error(&e));
+                Err(SdStatusError::RequestError { source: e })
+            }
+        }
+    }
+
+    /// Fetches the journalist key the Source Interface publishes, and
+    /// compares its fingerprint with the one the metadata advertises. A
+    /// mismatch may mean the metadata is stale, or that sources are being
+    /// given the wrong key.
+    async fn verify_key(&mut self, client: &dyn HttpClient) {
+        let expected = match &self.metadata {
+            Some(m) => pgp::normalize_fingerprint(&m.gpg_fpr),
+            None => return,
+        };
+        let key_url = format!("http://{}/public-key", self.onion_address);
+        let key = match client.get(&key_url).await {
+            Ok(r) => pgp::key_health(&String::from_utf8_lossy(&r.body)).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match key {
+            Ok(key) => {
+                let fingerprint = key.primary.fingerprint.to_owned();
+                let matches = fingerprint == expected;
+                if !matches {
+                    warn!(
+                        "Journalist key of {} ({}) does not match its metadata: {} != {}",
+                        self.title, self.onion_address, fingerprint, expected
+                    );
+                }
+                self.key_fingerprint = Some(fingerprint);
+                self.key_matches = Some(matches);
+                self.journalist_key = Some(key);
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to read journalist key from {} ({}): {}",
+                    self.title, self.onion_address, e
+                );
+                self.key_fingerprint = None;
+                self.key_matches = None;
+                self.journalist_key = None;
+            }
+        }
+    }
+
+    /// Why the instance failed, if it did. Results recorded before
+    /// failures were classified are classified by their error message.
+    pub fn failure_kind(&self) -> Option<FailureKind> {
+        if self.available {
+            return None;
+        }
+        self.failure
+            .or_else(|| self.error.as_deref().map(FailureKind::classify))
+    }
+
+    pub fn from_onion(onion_url: &str) -> SDDirectoryInstance {
+        SDDirectoryInstance {
+            metadata: None,
+            onion_name: None,
+            title: "".to_owned(),
+            landing_page_url: "".to_owned(),
+            onion_address: normalize_onion(onion_url),
+            available: false,
+            error: None,
+            failure: None,
+            http_status: None,
+            latency_ms: None,
+            duration_ms: None,
+            attempts: 0,
+            key_fingerprint: None,
+            key_matches: None,
+            journalist_key: None,
+            schema_drift: vec![],
+            raw_metadata: None,
+            samples: None,
+        }
+    }
+}
+
+/// Starts building an HTTP client for requests to `host`, sent through the
+/// Tor SOCKS proxy with the configured timeout and headers.
+///
+/// With `isolate_circuits`, the proxy is given credentials naming the
+/// host. Tor keeps streams with different SOCKS credentials on different
+/// circuits (its `IsolateSOCKSAuth` flag, on by default), so each host is
+/// reached over circuits of its own.
+pub fn client_builder(
+    options: &ScanOptions,
+    host: &str,
+) -> Result<reqwest::ClientBuilder, reqwest::Error> {
+    let mut http = reqwest::Proxy::http(&options.socks_proxy)?;
+    let mut https = reqwest::Proxy::https(&options.socks_proxy)?;
+    if let Some((username, password)) = isolation_credentials(options, host) {
+        http = http.basic_auth(username, &password);
+        https = https.basic_auth(username, &password);
+    }
+    Ok(reqwest::Client::builder()
+        .proxy(http)
+        .proxy(https)
+        .timeout(options.timeout)
+        .default_headers(options.headers.clone()))
+}
+
+/// The SOCKS credentials isolating the circuits to `host`, if circuits
+/// are isolated. They name `options.circuit` too, unless it is the first.
+pub(crate) fn isolation_credentials(
+    options: &ScanOptions,
+    host: &str,
+) -> Option<(&'static str, String)> {
+    options.isolate_circuits.then(|| match options.circuit {
+        0 => ("sdstatus", host.to_owned()),
+        n => ("sdstatus", format!("{}#{}", host, n)),
+    })
+}
+
+/// Turns a SOCKS proxy given as `host:port` into the URL reqwest expects,
+/// resolving hostnames through the proxy so Onion addresses work.
+pub fn socks_proxy_url(proxy: &str) -> String {
+    if proxy.contains("://") {
+        proxy.to_owned()
+    } else {
+        format!("socks5h://{}", proxy)
+    }
+}
+
+/// Reduces a user-supplied Onion URL such as `http://example.onion/` to
+/// the bare address used for requests.
+pub fn normalize_onion(onion_url: &str) -> String {
+    let address = onion_url
+        .trim()
+        .trim_start_matches("http://")
+        .trim_start_matches("https://");
+    match address.find('/') {
+        Some(idx) => address[..idx].to_owned(),
+        None => address.to_owned(),
+    }
+}
+
+/// Fetches the directory API route (by default at securedrop.org, see
+/// `DIRECTORY_URL`) for info about all SecureDrops.
+pub async fn get_securedrop_directory(
+    directory_url: &str,
+    snapshot: Option<&Snapshot>,
+) -> Result<Vec<SDDirectoryInstance>, Box<dyn Error>> {
+    let response = reqwest::get(directory_url).await?;
+    let status = response.status().as_u16();
+    let body = response.bytes().await?;
+    if let Some(snapshot) = snapshot {
+        snapshot.save_directory(directory_url, status, &body);
+    }
+    let instances: Vec<SDDirectoryInstance> = serde_json::from_slice(&body)?;
+    Ok(instances)
+}
+
+/// Fetches and merges several directories, keeping only the first entry for
+/// each onion address.
+pub async fn get_securedrop_directories(
+    directory_urls: &[String],
+    snapshot: Option<&Snapshot>,
+) -> Result<Vec<SDDirectoryInstance>, Box<dyn Error>> {
+    let mut instances = vec![];
+    for url in directory_urls {
+        info!("Fetching directory API at {}", url);
+        instances.extend(
+            get_securedrop_directory(url, snapshot)
+                .instrument(tracing::info_span!("directory_fetch", url = %url))
+                .await?,
+        );
+    }
+    Ok(dedupe_instances(instances))
+}
+
+/// Drops instances whose onion address or landing page duplicates an
+/// earlier one, so each site is scanned and counted once. Duplicates in
+/// the directory are a data-quality problem, so each is logged.
+pub fn dedupe_instances(instances: Vec<SDDirectoryInstance>) -> Vec<SDDirectoryInstance> {
+    let mut onions = HashMap::new();
+    let mut landing_pages = HashMap::new();
+    let mut deduped = vec![];
+    for i in instances {
+        let onion = normalize_onion(&i.onion_address).to_lowercase();
+        let landing_page = i
+            .landing_page_url
+            .trim()
+            .trim_end_matches('/')
+            .to_lowercase();
+        let duplicate = onions
+            .get(&onion)
+            .map(|title| ("onion address", title))
+            .or_else(|| {
+                landing_pages
+                    .get(&landing_page)
+                    .map(|title| ("landing page", title))
+            });
+        if let Some((field, title)) = duplicate {
+            warn!(
+                "Directory entry {} ({}) has the same {} as {}, skipping it",
+                i.title, i.onion_address, field, title
+            );
+            continue;
+        }
+        onions.insert(onion, i.title.to_owned());
+        if !landing_page.is_empty() {
+            landing_pages.insert(landing_page, i.title.to_owned());
+        }
+        deduped.push(i);
+    }
+    deduped
+}
+
+/// Scans each SecureDrop Directory instance in order to populate the metadata
+/// field. A failing instance does not abort the scan: its metadata is None,
+/// and the reason is recorded in its error field. `on_result` is called with
+/// each instance as soon as its scan completes, e.g. to report progress.
+/// If the scan is interrupted (see `ScanOptions::interrupt`), instances it
+/// didn't finish are left out.
+pub async fn populate_metadata<F: FnMut(&SDDirectoryInstance)>(
+    instances: Vec<SDDirectoryInstance>,
+    options: &ScanOptions,
+    mut on_result: F,
+) -> Result<Vec<SDDirectoryInstance>, Box<dyn Error>> {
+    let mut results = vec![];
+    let (tx, mut rx) = channel(1024);
+    let mut limit = ConcurrencyLimit::new(options);
+    // Copies of the instances not yet scanned, so those that miss the
+    // scan deadline can still be reported.
+    let mut pending: Vec<Option<SDDirectoryInstance>> = Vec::with_capacity(instances.len());
+    let mut queue = VecDeque::new();
+    for (idx, mut i) in instances.into_iter().enumerate() {
+        // A malformed address can only produce a confusing connection
+        // error, so report the directory entry instead of scanning it.
+        if let Err(e) = onion::validate_v3(&i.onion_address) {
+            warn!(
+                "Malformed onion address for {} ({}): {}",
+                i.title, i.onion_address, e
+            );
+            i.error = Some(format!("Malformed onion address: {}", e));
+            i.failure = Some(FailureKind::MalformedAddress);
+            pending.push(None);
+            on_result(&i);
+            results.push(i);
+            continue;
+        }
+        pending.push(Some(i.clone()));
+        queue.push_back((idx, i));
+    }
+    let deadline = options.deadline.map(|d| tokio::time::Instant::now() + d);
+    let mut interrupt = options.interrupt.clone();
+    // Once interrupted, until when the instances in flight may finish.
+    let mut grace = None;
+    let mut in_flight = 0;
+    loop {
+        // Instances are only started while there is room under the
+        // limit, which may have changed with the last result.
+        while grace.is_none() && in_flight < limit.current() {
+            let (idx, mut i) = match queue.pop_front() {
+                Some(next) => next,
+                None => break,
+            };
+            let mut tx = tx.clone();
+            let options = options.shard(idx);
+            // Everything logged while scanning the instance is tagged
+            // with it, as instances are scanned concurrently.
+            let span = tracing::info_span!(
+                "instance",
+                onion_address = %i.onion_address,
+                title = %i.title
+            );
+            tokio::spawn(
+                async move {
+                    // Errors will be logged, send results to channel regardless.
+                    match i.get_metadata(&options).await {
+                        Ok(_) => tx.send((idx, i)).await,
+                        Err(_) => tx.send((idx, i)).await,
+                    }
+                }
+                .instrument(span),
+            );
+            in_flight += 1;
+        }
+        // The channel never closes while we hold `tx`, so stop reading
+        // once every instance has reported back.
+        if in_flight == 0 {
+            break;
+        }
+        let until = match (deadline, grace) {
+            (Some(deadline), Some(grace)) => Some(deadline.min(grace)),
+            (deadline, grace) => deadline.or(grace),
+        };
+        let next = async {
+            match until {
+                Some(until) => tokio::time::timeout_at(until, rx.recv()).await.ok(),
+                None => Some(rx.recv().await),
+            }
+        };
+        let received = tokio::select! {
+            received = next => received,
+            _ = interrupted(&mut interrupt), if grace.is_none() => {
+                grace = Some(tokio::time::Instant::now() + INTERRUPT_GRACE);
+                continue;
+            }
+        };
+        let received = match received {
+            Some(received) => received,
+            // Out of grace, the instances still in flight are left out.
+            None if grace.is_some() => break,
+            None => {
+                warn!(
+                    "Scan deadline exceeded, {} sites were not scanned",
+                    in_flight + queue.len()
+                );
+                break;
+            }
+        };
+        match received {
+            Some((idx, i)) => {
+                in_flight -= 1;
+                pending[idx] = None;
+                limit.record(&i);
+                on_result(&i);
+                results.push(i);
+            }
+            None => break,
+        }
+    }
+    debug!("Finished reading from channel, reporting results");
+    if grace.is_some() {
+        let unscanned = pending.iter().flatten().count();
+        if unscanned > 0 {
+            warn!("Scan interrupted, {} sites were not scanned", unscanned);
+        }
+        return Ok(results);
+    }
+    for mut i in pending.into_iter().flatten() {
+        i.error = Some("Scan deadline exceeded".to_owned());
+        i.failure = Some(FailureKind::DeadlineExceeded);
+        on_result(&i);
+        results.push(i);
+    }
+    Ok(results)
+}
+
+/// Resolves once `interrupt` is set, or never without one.
+async fn interrupted(interrupt: &mut Option<Interrupt>) {
+    match interrupt {
+        Some(interrupt) => interrupt.wait().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// The JSON output of a scan: an object with the scan summary, or the
+/// bare list of instances output by earlier versions.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScanFile {
+    Results { instances: Vec<SDDirectoryInstance> },
+    List(Vec<SDDirectoryInstance>),
+}
+
+/// Reads in a file containing JSON results from a previous scan.
+pub fn read_scan_results(input_file: &str) -> Result<Vec<SDDirectoryInstance>, Box<dyn Error>> {
+    let j = std::fs::read_to_string(input_file)?;
+    match serde_json::from_str(&j)? {
+        ScanFile::Results { instances } => Ok(instances),
+        // Earlier versions didn't record `available`, but only kept the
+        // metadata of the instances it was retrieved from.
+        ScanFile::List(mut instances) => {
+            for i in &mut instances {
+                i.available |= i.metadata.is_some();
+            }
+            Ok(instances)
+        }
+    }
+}
+
+/// An entry in an instances file: an onion address, or an object with an
+/// onion address and optionally a title and landing page.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ListedInstance {
+    Onion(String),
+    Instance {
+        onion_address: String,
+        #[serde(default)]
+        title: String,
+        #[serde(default)]
+        landing_page_url: String,
+    },
+}
+
+/// Reads the instances to scan from a file, for sites not listed in the
+/// directory. The file is either a JSON array (of onion addresses, or of
+/// objects as in the directory), or plain text with an onion address per
+/// line; blank lines and those starting with `#` are skipped.
+pub fn read_instances_file(path: &str) -> Result<Vec<SDDirectoryInstance>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+    if text.trim_start().starts_with('[') {
+        let listed: Vec<ListedInstance> = serde_json::from_str(&text)?;
+        Ok(listed
+            .into_iter()
+            .map(|l| match l {
+                ListedInstance::Onion(onion) => SDDirectoryInstance::from_onion(&onion),
+                ListedInstance::Instance {
+                    onion_address,
+                    title,
+                    landing_page_url,
+                } => SDDirectoryInstance {
+                    title,
+                    landing_page_url,
+                    ..SDDirectoryInstance::from_onion(&onion_address)
+                },
+            })
+            .collect())
+    } else {
+        Ok(text
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(SDDirectoryInstance::from_onion)
+            .collect())
+    }
+}
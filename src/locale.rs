@@ -0,0 +1,184 @@
+use crate::i18n::Messages;
+use crate::SDDirectoryInstance;
+use icu_experimental::displaynames::{
+    DisplayNamesOptions, LanguageDisplayNames, LocaleDisplayNamesFormatter,
+};
+use icu_locale_core::{locale, Locale};
+use serde::Serialize;
+use unic_langid::subtags::Language;
+use unic_langid::LanguageIdentifier;
+
+/// Withdrawn ISO 639 language codes still found in locale names, and the
+/// codes that replaced them.
+const LEGACY_LANGUAGES: &[(&str, &str)] = &[
+    ("in", "id"),
+    ("iw", "he"),
+    ("ji", "yi"),
+    ("jw", "jv"),
+    ("mo", "ro"),
+];
+
+/// Normalizes a locale as given in `supported_languages`, e.g. `pt_BR`,
+/// `PT-br` or `iw`, to its BCP 47 language tag: `pt-BR`, `pt-BR` and
+/// `he`. None if it isn't a valid tag however written.
+pub fn normalize_locale(locale: &str) -> Option<String> {
+    let mut langid: LanguageIdentifier = locale.trim().replace('_', "-").parse().ok()?;
+    if langid.language.is_empty() {
+        return None;
+    }
+    if let Some((_, current)) = LEGACY_LANGUAGES
+        .iter()
+        .find(|(legacy, _)| langid.language.as_str() == *legacy)
+    {
+        langid.language = current.parse::<Language>().ok()?;
+    }
+    Some(langid.to_string())
+}
+
+/// The normalized locales an instance supports, leaving out those that
+/// failed normalization. Instances without metadata support none.
+pub fn instance_locales(instance: &SDDirectoryInstance) -> Vec<String> {
+    let mut locales: Vec<String> = instance
+        .metadata
+        .iter()
+        .flat_map(|m| m.supported_languages.iter())
+        .filter_map(|l| normalize_locale(l))
+        .collect();
+    locales.sort();
+    locales.dedup();
+    locales
+}
+
+/// What a locale is called in English and in its own language, e.g.
+/// `German (Germany)` and `Deutsch (Deutschland)` for `de-DE`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LocaleName {
+    pub english: String,
+    /// None where CLDR has no names in the locale's own language.
+    pub native: Option<String>,
+}
+
+/// Names a normalized locale from the CLDR data built into sdstatus, or
+/// None if CLDR doesn't know its language.
+pub fn locale_name(locale: &str) -> Option<LocaleName> {
+    let locale: Locale = locale.parse().ok()?;
+    let options = DisplayNamesOptions::default();
+    let english = locale!("en");
+    LanguageDisplayNames::try_new((&english).into(), options)
+        .ok()?
+        .of(locale.id.language)?;
+    let english = LocaleDisplayNamesFormatter::try_new((&english).into(), options)
+        .ok()?
+        .of(&locale)
+        .into_owned();
+    let native = LocaleDisplayNamesFormatter::try_new((&locale).into(), options)
+        .ok()
+        .map(|f| f.of(&locale).into_owned());
+    Some(LocaleName { english, native })
+}
+
+/// Labels a normalized locale for readers who don't know its code, e.g.
+/// `de-DE — German (Germany) / Deutsch (Deutschland)`. Locales CLDR
+/// doesn't name are left as codes.
+pub fn display_locale(locale: &str) -> String {
+    match locale_name(locale) {
+        Some(LocaleName {
+            english,
+            native: Some(native),
+        }) if native != english => format!("{} — {} / {}", locale, english, native),
+        Some(LocaleName { english, .. }) => format!("{} — {}", locale, english),
+        None => locale.to_owned(),
+    }
+}
+
+/// A `supported_languages` value that isn't a language tag.
+#[derive(Serialize, Debug)]
+pub struct InvalidLocale {
+    pub title: String,
+    pub onion_address: String,
+    pub value: String,
+}
+
+/// Lists the `supported_languages` values of scanned instances that
+/// failed normalization, and so are left out of the locale reports.
+pub fn list_invalid_locales(instances: &[SDDirectoryInstance]) -> Vec<InvalidLocale> {
+    let mut invalid = vec![];
+    for i in instances {
+        for l in i.metadata.iter().flat_map(|m| m.supported_languages.iter()) {
+            if normalize_locale(l).is_none() {
+                invalid.push(InvalidLocale {
+                    title: i.title.to_owned(),
+                    onion_address: i.onion_address.to_owned(),
+                    value: l.to_owned(),
+                });
+            }
+        }
+    }
+    invalid
+}
+
+/// Renders the invalid locales as human-readable text, or nothing if
+/// there are none.
+pub fn format_invalid_locales(invalid: &[InvalidLocale], messages: &Messages) -> String {
+    if invalid.is_empty() {
+        return String::new();
+    }
+    let mut report = format!("{}:\n", messages.count("invalid-locales", invalid.len()));
+    for l in invalid {
+        report += &format!("  {:?}: {} ({})\n", l.value, l.title, l.onion_address);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SDMetadata;
+
+    fn instance(title: &str, languages: &[&str]) -> SDDirectoryInstance {
+        SDDirectoryInstance {
+            title: title.to_owned(),
+            metadata: Some(SDMetadata {
+                supported_languages: languages.iter().map(|l| l.to_string()).collect(),
+                ..SDMetadata::default()
+            }),
+            ..SDDirectoryInstance::from_onion(&format!("{}.onion", title))
+        }
+    }
+
+    #[test]
+    fn normalizes_locales() {
+        assert_eq!(normalize_locale("pt_BR").unwrap(), "pt-BR");
+        assert_eq!(normalize_locale("PT-br").unwrap(), "pt-BR");
+        assert_eq!(normalize_locale(" de ").unwrap(), "de");
+        assert_eq!(normalize_locale("zh_Hant").unwrap(), "zh-Hant");
+        assert_eq!(normalize_locale("iw").unwrap(), "he");
+        assert_eq!(normalize_locale("in_ID").unwrap(), "id-ID");
+        assert_eq!(normalize_locale(""), None);
+        assert_eq!(normalize_locale("not a locale"), None);
+        assert_eq!(normalize_locale("en_US.UTF-8"), None);
+    }
+
+    #[test]
+    fn lists_instance_locales() {
+        let i = instance("a", &["en_US", "en-US", "iw", "??", "de"]);
+        assert_eq!(instance_locales(&i), vec!["de", "en-US", "he"]);
+        let mut down = instance("b", &[]);
+        down.metadata = None;
+        assert!(instance_locales(&down).is_empty());
+
+        let invalid = list_invalid_locales(&[i, down]);
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].value, "??");
+        assert_eq!(invalid[0].title, "a");
+    }
+
+    #[test]
+    fn names_locales() {
+        let name = locale_name("de-DE").unwrap();
+        assert_eq!(name.english, "German (Germany)");
+        assert_eq!(name.native.as_deref(), Some("Deutsch (Deutschland)"));
+        assert_eq!(display_locale("en-IN"), "en-IN — English (India)");
+        assert_eq!(display_locale("qaa"), "qaa");
+    }
+}
@@ -0,0 +1,73 @@
+use crate::config::TelemetryConfig;
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::IsTerminal;
+use std::sync::Mutex;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+/// The log level used when `RUST_LOG` isn't set.
+const DEFAULT_FILTER: &str =
+    "info,reqwest=info,hyper=info,opentelemetry_sdk=warn,icu_provider=error";
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Sets up logging to standard error, or appending to `log_file` if given.
+/// With `json`, each record is written as a JSON object on its own line,
+/// for shipping to a log aggregator. Records logged while scanning an
+/// instance carry its span, with its onion address and title, so those
+/// of concurrent fetches can be told apart. With `telemetry`, spans are
+/// also exported over OTLP (see `telemetry::init_telemetry`).
+pub fn init_logging(
+    log_file: Option<&str>,
+    json: bool,
+    telemetry: Option<&TelemetryConfig>,
+) -> Result<(), Box<dyn Error>> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let (writer, ansi) = match log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Failed to open log file {}, {}", path, e))?;
+            (BoxMakeWriter::new(Mutex::new(file)), false)
+        }
+        None => (
+            BoxMakeWriter::new(std::io::stderr),
+            std::io::stderr().is_terminal(),
+        ),
+    };
+    let fmt = tracing_subscriber::fmt::layer().with_writer(writer);
+    let mut layers: Vec<BoxedLayer> = vec![if json {
+        fmt.json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed()
+    } else {
+        fmt.with_ansi(ansi).boxed()
+    }];
+    if let Some(telemetry) = telemetry {
+        layers.push(telemetry_layer(telemetry)?);
+    }
+    // Messages logged with the `log` macros are forwarded too.
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(filter)
+        .try_init()
+        .map_err(|e| e.into())
+}
+
+#[cfg(feature = "otel")]
+fn telemetry_layer(config: &TelemetryConfig) -> Result<BoxedLayer, Box<dyn Error>> {
+    crate::telemetry::init_telemetry(config)
+}
+
+#[cfg(not(feature = "otel"))]
+fn telemetry_layer(_config: &TelemetryConfig) -> Result<BoxedLayer, Box<dyn Error>> {
+    Err("sdstatus was built without OpenTelemetry support, rebuild it with --features otel".into())
+}
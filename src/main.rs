@@ -1,239 +1,2648 @@
-use serde::{Deserialize, Serialize};
+use chrono::Utc;
+use clap::{crate_version, App, Arg, ArgMatches};
+use fluent_bundle::FluentArgs;
+use serde::Serialize;
 use serde_json::json;
-// use reqwest::Error;
-use clap::{crate_version, App, Arg};
-use std::error::Error;
-//use std::sync::mpsc::channel;
-use tokio::sync::mpsc::channel;
-
 use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{IsTerminal, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
-
-use custom_error::custom_error;
+use tokio::sync::{Notify, RwLock};
 
 #[macro_use]
 extern crate log;
-use env_logger::Env;
-
-const DIRECTORY_URL: &str = "https://securedrop.org/api/v1/directory/";
-const TOR_PROXY: &str = "socks5h://127.0.0.1:9050";
-const TOR_TIMEOUT: u64 = 30;
-
-// SDMetadata stores the information obtained from a given SecureDrop
-// instance's /metadata endpoint, a JSON API with platform info.
-#[derive(Deserialize, Serialize, Debug)]
-struct SDMetadata {
-    sd_version: String,
-    server_os: String,
-    gpg_fpr: String,
-    v2_source_url: Option<String>,
-    v3_source_url: String,
-    supported_languages: Vec<String>,
-}
-
-#[derive(Deserialize, Serialize, Debug)]
-struct SDDirectoryInstance {
-    metadata: Option<SDMetadata>,
-    onion_name: Option<String>,
-    title: String,
-    landing_page_url: String,
-    onion_address: String,
-}
-
-// We must implement a custom error type, because `Box<dyn Error>`
-// cannot be safely shared via channels.
-custom_error! {pub SdStatusError
-    NetworkError{source: reqwest::Error} = "Onion not available",
-}
-
-impl SDDirectoryInstance {
-    pub async fn get_metadata(&mut self) -> Result<(), SdStatusError> {
-        let client = reqwest::Client::builder()
-            .proxy(reqwest::Proxy::http(TOR_PROXY)?)
-            .proxy(reqwest::Proxy::https(TOR_PROXY)?)
-            .timeout(Duration::from_secs(TOR_TIMEOUT))
-            .build()?;
-        debug!("Fetching metadata: {}", self.onion_address);
-        let metadata_url = format!("http://{}/metadata", self.onion_address);
-        match client.get(&metadata_url).send().await {
-            Ok(r) => {
-                let m: SDMetadata = r.json().await?;
-                self.metadata = Some(m);
-                Ok(())
-            }
-            Err(e) => {
-                warn!(
-                    "Failed to connect to {} ({})",
-                    self.title, self.onion_address
-                );
-                self.metadata = None;
-                Err(SdStatusError::NetworkError { source: e })
+use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use sdstatus::adoption::{format_adoption_report, generate_adoption_report, write_adoption_csv};
+use sdstatus::anonymize::Anonymizer;
+use sdstatus::assertion::Assertion;
+use sdstatus::checkpoint::Checkpoint;
+use sdstatus::color::color_enabled;
+use sdstatus::config::Config;
+use sdstatus::db;
+use sdstatus::diff::{format_diff, ScanDiff};
+use sdstatus::failure::FailureKind;
+use sdstatus::filter::{Filter, Pattern};
+use sdstatus::findings::{detect_downgrades, detect_key_rotations, format_findings, Finding};
+use sdstatus::github::{outage_streaks, sync_issues};
+use sdstatus::health::{serve_health, ScanHealth};
+use sdstatus::html::render_html;
+use sdstatus::i18n::{Messages, DEFAULT_LANG};
+use sdstatus::interrupt::Interrupt;
+use sdstatus::keys::{format_key_report, generate_key_report, EXPIRY_WARNING_DAYS};
+use sdstatus::landing::{check_landing_pages, format_landing_report, LandingOptions, Severity};
+use sdstatus::locale::{
+    display_locale, format_invalid_locales, list_invalid_locales, locale_name, normalize_locale,
+};
+use sdstatus::logging::init_logging;
+use sdstatus::markdown;
+use sdstatus::mastodon::{post_status, summary_due, week_start, WeeklySummary};
+use sdstatus::metrics::{render_metrics, serve_metrics};
+use sdstatus::notify::{
+    detect_events, detect_onion_changes, detect_outages, send_email, send_notifications,
+};
+use sdstatus::onion::validate_v3;
+use sdstatus::onion_names::{
+    fetch_onion_names, format_onion_name_report, verify_onion_names, RULESETS_URL,
+};
+use sdstatus::ooni::write_ooni;
+use sdstatus::publish::publish_results;
+use sdstatus::releases::{
+    fetch_releases, format_outdated_report, generate_outdated_report, releases_up_to,
+    KNOWN_RELEASES, RELEASES_URL,
+};
+use sdstatus::report::{
+    format_failures, format_grouped_report, format_locale_coverage, format_status_report,
+    generate_l10n_report, generate_locale_coverage, generate_locale_matrix,
+    generate_missing_locale_report, generate_os_report, generate_status_report,
+    generate_tags_report, generate_versions_report, group_by_locale, list_failures,
+    sort_grouped_report, write_csv, write_locale_matrix_csv, Failure, GroupOrder, LocaleCoverage,
+    SiteOrder, StatusEntry, DEPRECATED_SERVER_OS,
+};
+use sdstatus::schedule::{add_jitter, Schedule};
+use sdstatus::show::{format_instance_detail, instance_history, InstanceDetail};
+use sdstatus::signing::{sign_file, signature_path, verify_file};
+use sdstatus::snapshot::{load_snapshot, Snapshot, MANIFEST};
+use sdstatus::template::render_template;
+use sdstatus::tui::{run_dashboard, Dashboard};
+use sdstatus::uptime::{
+    format_uptime_report, generate_uptime_report, parse_window, DEFAULT_WINDOWS,
+};
+use sdstatus::weblate::{fetch_translations, format_translations, join_translations};
+use sdstatus::{
+    get_securedrop_directories, normalize_onion, populate_metadata, read_instances_file,
+    read_scan_results, socks_proxy_url, SDDirectoryInstance, SDMetadata, ScanMode, ScanOptions,
+    ScanResults, ScanSummary, DIRECTORY_URL, MAX_CONCURRENCY,
+};
+
+/// Writes a grouped report in the requested output format, listing the
+/// instances that failed during the scan separately. In JSON, the groups
+/// are keyed by `name`.
+fn write_grouped_report(
+    out: &mut Vec<u8>,
+    name: &str,
+    groups: &[(String, Vec<String>)],
+    failures: &[Failure],
+    format: &str,
+    messages: &Messages,
+) -> Result<(), Box<dyn Error>> {
+    let groups = || groups.iter().map(|(key, sites)| (key, sites));
+    if format == "text" {
+        writeln!(
+            out,
+            "{}{}",
+            format_grouped_report(groups()),
+            format_failures(failures, messages)
+        )?;
+    } else if format == "markdown" {
+        writeln!(
+            out,
+            "{}{}",
+            markdown::format_grouped_report(groups()),
+            markdown::format_failures(failures, messages)
+        )?;
+    } else if format == "json" {
+        // JSON objects are unordered, so only the order of sites is kept.
+        let groups: BTreeMap<_, _> = groups().collect();
+        let j = json!({ name: groups, "unavailable": failures });
+        writeln!(out, "{}", serde_json::to_string_pretty(&j).unwrap())?;
+    } else {
+        return Err(format!("Output format {} is unimplemented", format).into());
+    }
+    Ok(())
+}
+
+/// Writes the server OS report, followed by the instances still running
+/// a deprecated Ubuntu release and a summary of counts.
+fn write_os_report(
+    out: &mut Vec<u8>,
+    releases: &BTreeMap<String, Vec<String>>,
+    failures: &[Failure],
+    format: &str,
+    messages: &Messages,
+) -> Result<(), Box<dyn Error>> {
+    let deprecated: BTreeMap<&String, &Vec<String>> = releases
+        .iter()
+        .filter(|(os, _)| DEPRECATED_SERVER_OS.contains(&os.as_str()))
+        .collect();
+    let total: usize = releases.values().map(|sites| sites.len()).sum();
+    let total_deprecated: usize = deprecated.values().map(|sites| sites.len()).sum();
+    if format == "text" {
+        let mut report = format_grouped_report(releases);
+        report += &format!("Deprecated releases ({}):\n", total_deprecated);
+        for (os, sites) in &deprecated {
+            for site in sites.iter() {
+                report += &format!("  {} ({})\n", site, os);
             }
         }
+        report += &format!(
+            "\n{} of {} available instances run a deprecated release\n\n",
+            total_deprecated, total
+        );
+        report += &format_failures(failures, messages);
+        writeln!(out, "{}", report)?;
+    } else if format == "markdown" {
+        let mut report = markdown::format_grouped_report(releases);
+        report += &format!("### Deprecated releases ({})\n\n", total_deprecated);
+        for (os, sites) in &deprecated {
+            for site in sites.iter() {
+                report += &format!("- {} ({})\n", site, os);
+            }
+        }
+        report += &format!(
+            "\n{} of {} available instances run a deprecated release\n\n",
+            total_deprecated, total
+        );
+        report += &markdown::format_failures(failures, messages);
+        writeln!(out, "{}", report)?;
+    } else if format == "json" {
+        let j = json!({
+            "server_os": releases,
+            "deprecated": deprecated,
+            "total": total,
+            "total_deprecated": total_deprecated,
+            "unavailable": failures,
+        });
+        writeln!(out, "{}", serde_json::to_string_pretty(&j).unwrap())?;
+    } else {
+        return Err(format!("Output format {} is unimplemented", format).into());
+    }
+    Ok(())
+}
+
+/// The JSON output of `report`: every report on a single scan.
+#[derive(Serialize)]
+struct FullReport<'a> {
+    scan: &'a ScanSummary,
+    instances: Vec<StatusEntry>,
+    versions: BTreeMap<String, Vec<String>>,
+    server_os: BTreeMap<String, Vec<String>>,
+    locales: BTreeMap<String, Vec<String>>,
+    findings: &'a [Finding],
+    unavailable: Vec<Failure>,
+}
+
+/// A line heading text reports on an interrupted scan, or nothing.
+fn interrupted_note(scan: &ScanSummary) -> String {
+    if scan.partial {
+        format!(
+            "Scan interrupted, {} sites were not scanned\n\n",
+            scan.unscanned
+        )
+    } else {
+        String::new()
+    }
+}
+
+/// Writes the availability, versions, server OS and locales reports and
+/// the findings on a single scan, as one document.
+fn write_full_report(
+    out: &mut Vec<u8>,
+    results: &ScanResults,
+    format: &str,
+    color: bool,
+    messages: &Messages,
+) -> Result<(), Box<dyn Error>> {
+    let report = FullReport {
+        scan: &results.scan,
+        instances: generate_status_report(&results.instances),
+        versions: generate_versions_report(&results.instances),
+        server_os: generate_os_report(&results.instances),
+        locales: generate_l10n_report(&results.instances),
+        findings: &results.findings,
+        unavailable: list_failures(&results.instances),
+    };
+    let deprecated: usize = report
+        .server_os
+        .iter()
+        .filter(|(os, _)| DEPRECATED_SERVER_OS.contains(&os.as_str()))
+        .map(|(_, sites)| sites.len())
+        .sum();
+    let summary = format!(
+        "{} of {} available instances run a deprecated release\n\n",
+        deprecated, results.scan.succeeded
+    );
+    if format == "text" {
+        write!(out, "{}", interrupted_note(&results.scan))?;
+        writeln!(
+            out,
+            "== Availability ==\n\n{}",
+            format_status_report(&report.instances, color)
+        )?;
+        write!(
+            out,
+            "== SecureDrop versions ==\n\n{}",
+            format_grouped_report(&report.versions)
+        )?;
+        write!(
+            out,
+            "== Server OS ==\n\n{}{}",
+            format_grouped_report(&report.server_os),
+            summary
+        )?;
+        write!(
+            out,
+            "== Locales ==\n\n{}",
+            format_grouped_report(&report.locales)
+        )?;
+        writeln!(
+            out,
+            "== Findings ==\n\n{}",
+            format_findings(report.findings, color)
+        )?;
+        write!(out, "{}", format_failures(&report.unavailable, messages))?;
+    } else if format == "markdown" {
+        writeln!(
+            out,
+            "## Availability\n\n{}",
+            markdown::format_status_report(&report.instances)
+        )?;
+        write!(
+            out,
+            "## SecureDrop versions\n\n{}",
+            markdown::format_grouped_report(&report.versions)
+        )?;
+        write!(
+            out,
+            "## Server OS\n\n{}{}",
+            markdown::format_grouped_report(&report.server_os),
+            summary
+        )?;
+        write!(
+            out,
+            "## Locales\n\n{}",
+            markdown::format_grouped_report(&report.locales)
+        )?;
+        writeln!(
+            out,
+            "## Findings\n\n{}",
+            markdown::format_findings(report.findings)
+        )?;
+        write!(
+            out,
+            "{}",
+            markdown::format_failures(&report.unavailable, messages)
+        )?;
+    } else if format == "json" {
+        writeln!(out, "{}", serde_json::to_string_pretty(&report).unwrap())?;
+    } else {
+        return Err(format!("Output format {} is unimplemented", format).into());
+    }
+    Ok(())
+}
+
+/// Draws the chart asked for by the `chart` subcommand from the history
+/// database at `path`, returning the SVG.
+#[cfg(feature = "charts")]
+fn render_chart(
+    matches: &ArgMatches,
+    config: &Config,
+    path: &str,
+) -> Result<String, Box<dyn Error>> {
+    use sdstatus::charts;
+    let now = Utc::now();
+    let since = now - parse_window(matches.value_of("window").unwrap())?;
+    let interval = parse_window(matches.value_of("interval").unwrap())?;
+    let filter = instance_filter(matches, config)?;
+    let history = db::open(path)?;
+    let (title, series) = match matches.value_of("chart").unwrap() {
+        "adoption" => {
+            let mut outcomes = history.versions_since(since)?;
+            outcomes.retain(|o| filter.admits(&o.title, &o.onion_address, None));
+            let report = generate_adoption_report(&outcomes, since, interval, now);
+            ("SecureDrop versions", charts::adoption_series(&report))
+        }
+        "uptime" => {
+            let mut outcomes = history.outcomes_since(since)?;
+            outcomes.retain(|o| filter.admits(&o.title, &o.onion_address, None));
+            (
+                "Uptime",
+                vec![charts::uptime_series(&outcomes, since, interval)],
+            )
+        }
+        _ => {
+            let mut outcomes = history.locales_since(since)?;
+            outcomes.retain(|o| filter.admits(&o.title, &o.onion_address, Some(&o.locales)));
+            (
+                "Supported locales",
+                charts::locale_series(&outcomes, since, interval),
+            )
+        }
+    };
+    charts::render_chart(title, &series)
+}
+
+#[cfg(not(feature = "charts"))]
+fn render_chart(_: &ArgMatches, _: &Config, _: &str) -> Result<String, Box<dyn Error>> {
+    Err("sdstatus was built without chart support, rebuild it with --features charts".into())
+}
+
+/// Reads the uptime windows given via `--window`.
+fn uptime_windows(matches: &ArgMatches) -> Result<Vec<(String, chrono::Duration)>, Box<dyn Error>> {
+    match matches.values_of("window") {
+        Some(windows) => windows.collect(),
+        None => DEFAULT_WINDOWS.to_vec(),
     }
-    pub fn from_onion(onion_url: &str) -> SDDirectoryInstance {
-        SDDirectoryInstance {
-            metadata: None,
-            onion_name: None,
-            title: "".to_owned(),
-            landing_page_url: "".to_owned(),
-            onion_address: onion_url.to_owned(),
+    .into_iter()
+    .map(|w| Ok((w.to_owned(), parse_window(w)?)))
+    .collect()
+}
+
+/// Reads the grouped report ordering given via `--sort`.
+fn report_order(matches: &ArgMatches) -> Result<(GroupOrder, Option<SiteOrder>), Box<dyn Error>> {
+    let mut group_order = GroupOrder::Name;
+    let mut site_order = None;
+    for key in matches.values_of("sort").into_iter().flatten() {
+        match key {
+            "count" => group_order = GroupOrder::Count,
+            "locale" => group_order = GroupOrder::Name,
+            "title" => site_order = Some(SiteOrder::Title),
+            "onion" => site_order = Some(SiteOrder::Onion),
+            other => return Err(format!("Unknown sort order {}", other).into()),
         }
     }
+    Ok((group_order, site_order))
 }
 
-/// Fetches the securedrop.org API route for info about all SecureDrops.
-async fn get_securedrop_directory() -> Result<Vec<SDDirectoryInstance>, Box<dyn Error>> {
-    let response = reqwest::get(DIRECTORY_URL).await?;
-    let instances: Vec<SDDirectoryInstance> = response.json().await?;
-    Ok(instances)
+/// Reads the results a report is built from: the JSON output of a scan,
+/// or a snapshot to replay.
+fn read_results(input: &str) -> Result<Vec<SDDirectoryInstance>, Box<dyn Error>> {
+    let path = Path::new(input);
+    if path.is_dir() || path.file_name().is_some_and(|f| f == MANIFEST) {
+        Ok(load_snapshot(path)?.instances)
+    } else {
+        read_scan_results(input)
+    }
+}
+
+/// Writes the locale coverage report in the requested output format.
+fn write_locale_coverage(
+    out: &mut Vec<u8>,
+    coverage: &LocaleCoverage,
+    format: &str,
+    messages: &Messages,
+) -> Result<(), Box<dyn Error>> {
+    if format == "text" {
+        writeln!(out, "{}", format_locale_coverage(coverage, messages))?;
+    } else if format == "markdown" {
+        writeln!(
+            out,
+            "{}",
+            markdown::format_locale_coverage(coverage, messages)
+        )?;
+    } else if format == "json" {
+        writeln!(out, "{}", serde_json::to_string_pretty(coverage).unwrap())?;
+    } else {
+        return Err(format!("Output format {} is unimplemented", format).into());
+    }
+    Ok(())
+}
+
+/// Prints the metadata of a single instance in the requested output format.
+fn print_metadata(metadata: &SDMetadata, format: &str) -> Result<(), Box<dyn Error>> {
+    if format == "json" {
+        println!("{}", serde_json::to_string_pretty(metadata).unwrap());
+    } else if format == "pp" {
+        println!("{:#?}", metadata);
+    } else if format == "text" {
+        println!("sd_version: {}", metadata.sd_version);
+        println!("server_os: {}", metadata.server_os);
+        println!("gpg_fpr: {}", metadata.gpg_fpr);
+        println!(
+            "v2_source_url: {}",
+            metadata.v2_source_url.as_deref().unwrap_or("")
+        );
+        println!("v3_source_url: {}", metadata.v3_source_url);
+        println!(
+            "supported_languages: {}",
+            metadata.supported_languages.join(" ")
+        );
+        for (field, value) in &metadata.extra {
+            println!("{}: {}", field, value);
+        }
+    } else {
+        return Err(format!("Output format {} is unimplemented", format).into());
+    }
+    Ok(())
+}
+
+// Exit codes beyond 0 for success and 1 for errors, so CI can gate on a
+// scan's results. See `--fail-on` and `--assert`.
+const EXIT_UNREACHABLE: i32 = 2;
+const EXIT_FINDINGS: i32 = 3;
+const EXIT_ASSERTION: i32 = 4;
+/// As a shell reports a process killed by SIGINT.
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// The conditions given via `--fail-on` and `--assert` that make a
+/// command exit non-zero.
+#[derive(Debug, Default)]
+struct FailOn {
+    unreachable: bool,
+    /// The lowest severity of finding to fail on.
+    severity: Option<Severity>,
+    assertions: Vec<Assertion>,
+}
+
+impl FailOn {
+    fn from_matches(matches: &ArgMatches) -> Result<FailOn, Box<dyn Error>> {
+        let mut fail_on = FailOn::default();
+        for condition in matches.values_of("fail_on").into_iter().flatten() {
+            if condition == "unreachable" {
+                fail_on.unreachable = true;
+            } else {
+                let severity = condition.parse()?;
+                fail_on.severity = Some(fail_on.severity.map_or(severity, |s| s.min(severity)));
+            }
+        }
+        for assertion in matches.values_of("assert").into_iter().flatten() {
+            fail_on.assertions.push(assertion.parse()?);
+        }
+        Ok(fail_on)
+    }
+
+    /// Checks the assertions against a scan's instances, logging and
+    /// counting those that don't hold.
+    fn violated_assertions(&self, instances: &[SDDirectoryInstance]) -> usize {
+        let mut violated = 0;
+        for assertion in &self.assertions {
+            if let Err(found) = assertion.check(instances) {
+                error!("Assertion {} failed: {}", assertion, found);
+                violated += 1;
+            }
+        }
+        violated
+    }
+
+    /// The exit code for a run with `unreachable` sites, findings of the
+    /// given severities and `violated` assertions. Findings take
+    /// precedence over assertions, and those over unreachable sites.
+    fn exit_code(
+        &self,
+        unreachable: usize,
+        findings: impl IntoIterator<Item = Severity>,
+        violated: usize,
+    ) -> i32 {
+        if let Some(threshold) = self.severity {
+            if findings.into_iter().any(|s| s >= threshold) {
+                return EXIT_FINDINGS;
+            }
+        }
+        if violated > 0 {
+            return EXIT_ASSERTION;
+        }
+        if self.unreachable && unreachable > 0 {
+            return EXIT_UNREACHABLE;
+        }
+        0
+    }
+}
+
+/// The `--fail-on` argument, for commands whose results CI can gate on.
+fn fail_on_arg() -> Arg<'static> {
+    Arg::new("fail_on")
+        .about("Exit with 2 if any site is 'unreachable', or 3 on a finding of at least this severity ('info', 'low', 'medium' or 'high'); may be repeated")
+        .takes_value(true)
+        .multiple_occurrences(true)
+        .possible_values(&["unreachable", "info", "low", "medium", "high"])
+        .long("fail-on")
+}
+
+/// The `--assert` argument, for commands that scan.
+fn assert_arg() -> Arg<'static> {
+    Arg::new("assert")
+        .about("Exit with 4 unless the scan meets this policy, e.g. 'locale:de_DE>=10', 'version>=2.6.0' or 'down<=3'; may be repeated")
+        .takes_value(true)
+        .multiple_occurrences(true)
+        .long("assert")
+}
+
+// The output formats of each command, its default first.
+const SCAN_FORMATS: &[&str] = &["json", "csv", "html", "jsonl", "markdown", "ooni", "pp"];
+const REPORT_FORMATS: &[&str] = &["text", "markdown", "json"];
+const L10N_FORMATS: &[&str] = &["text", "markdown", "json"];
+const MATRIX_FORMATS: &[&str] = &["csv", "json"];
+const METADATA_FORMATS: &[&str] = &["json", "pp", "text"];
+const ADOPTION_FORMATS: &[&str] = &["text", "csv", "json"];
+const TEXT_FORMATS: &[&str] = &["text", "json"];
+
+fn format_arg(formats: &'static [&'static str]) -> Arg<'static> {
+    Arg::new("format")
+        .about("Specify output format (default: json for scan and metadata, csv for l10n --matrix, text otherwise)")
+        .takes_value(true)
+        .possible_values(formats)
+        .long("format")
+        .short('f')
+}
+
+fn output_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("output")
+            .about("Write the report to this file instead of standard output")
+            .takes_value(true)
+            .long("output"),
+        Arg::new("sign_key")
+            .about("Sign the --output file with this minisign secret key, writing the signature next to it as <file>.minisig")
+            .takes_value(true)
+            .long("sign-key"),
+    ]
+}
+
+/// Where a report goes, per `--output` and `--sign-key`: to standard
+/// output, or to a file, signed if there is a key.
+struct ReportOutput<'a> {
+    path: Option<&'a str>,
+    sign_key: Option<&'a str>,
+    password: Option<&'a str>,
+}
+
+impl<'a> ReportOutput<'a> {
+    /// Only a file can be signed, so a key without `--output`, even one
+    /// from the config file, is an error rather than silently unused.
+    fn from_matches(
+        matches: &'a ArgMatches,
+        config: &'a Config,
+    ) -> Result<ReportOutput<'a>, Box<dyn Error>> {
+        let path = matches.value_of("output");
+        let sign_key = matches.value_of("sign_key").or(config.sign_key.as_deref());
+        if path.is_none() && sign_key.is_some() {
+            return Err("Signing needs the report written to a file, see --output".into());
+        }
+        Ok(ReportOutput {
+            path,
+            sign_key,
+            password: config.sign_key_password.as_deref(),
+        })
+    }
+
+    fn write(&self, report: &[u8]) -> Result<(), Box<dyn Error>> {
+        match self.path {
+            Some(path) => {
+                std::fs::write(path, report)
+                    .map_err(|e| format!("Failed to write {}, {}", path, e))?;
+                info!("Wrote the report to {}", path);
+                if let Some(key) = self.sign_key {
+                    let signature = sign_file(Path::new(path), Path::new(key), self.password)?;
+                    info!("Signed the report, in {}", signature.display());
+                }
+            }
+            None => std::io::stdout().write_all(report)?,
+        }
+        Ok(())
+    }
+}
+
+/// The shortest `--watch` interval, so watching doesn't hammer the sites.
+const MIN_WATCH_SECS: u64 = 60;
+
+fn watch_arg() -> Arg<'static> {
+    Arg::new("watch")
+        .about("Rerun the scan every this many seconds (at least 60), reprinting the output")
+        .takes_value(true)
+        .validator(|interval| match interval.parse::<u64>() {
+            Ok(secs) if secs >= MIN_WATCH_SECS => Ok(()),
+            Ok(_) => Err(format!("must be at least {} seconds", MIN_WATCH_SECS)),
+            Err(e) => Err(e.to_string()),
+        })
+        .long("watch")
+}
+
+/// Waits for `duration`, returning false instead if sdstatus is, or gets,
+/// interrupted.
+async fn sleep_unless_interrupted(duration: Duration) -> bool {
+    let mut interrupt = Interrupt::listen();
+    tokio::select! {
+        _ = tokio::time::delay_for(duration) => !interrupt.is_set(),
+        _ = interrupt.wait() => false,
+    }
+}
+
+/// Runs a command once, exiting with its exit code, or with `--watch`,
+/// reruns it periodically until interrupted. When the output goes to a
+/// terminal, it is cleared first so each run replaces the last. A failed
+/// run, or the exit code a run would have exited with, is logged, and the
+/// next one goes ahead.
+async fn watch<F, Fut>(matches: &ArgMatches, mut run: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<i32, Box<dyn Error>>>,
+{
+    let interval = match matches.value_of("watch") {
+        Some(interval) => Duration::from_secs(interval.parse()?),
+        None => {
+            exit_with(run().await?);
+            return Ok(());
+        }
+    };
+    let terminal = std::io::stdout().is_terminal();
+    loop {
+        if terminal {
+            print!("\x1b[2J\x1b[H");
+        }
+        match run().await {
+            Ok(0) => {}
+            Ok(code) => info!("Scan finished with exit code {}", code),
+            Err(e) => error!("Scan failed, {}", e),
+        }
+        std::io::stdout().flush().ok();
+        if !sleep_unless_interrupted(interval).await {
+            exit_with(EXIT_INTERRUPTED);
+        }
+    }
+}
+
+/// The messages to write reports with, in the language given by `--lang`.
+fn report_messages(matches: &ArgMatches, config: &Config) -> Result<Messages, Box<dyn Error>> {
+    Messages::new(
+        matches
+            .value_of("lang")
+            .or(config.lang.as_deref())
+            .unwrap_or(DEFAULT_LANG),
+    )
+}
+
+/// Whether to color text output, as given by `--color`.
+fn use_color(matches: &ArgMatches) -> bool {
+    color_enabled(matches.value_of("color").unwrap_or("auto"))
+}
+
+/// Exports any telemetry not yet sent, before exiting.
+fn flush_telemetry() {
+    #[cfg(feature = "otel")]
+    sdstatus::telemetry::shutdown_telemetry();
+}
+
+/// Exits with `code` unless it is 0, flushing any output first.
+fn exit_with(code: i32) {
+    if code != 0 {
+        std::io::stdout().flush().ok();
+        flush_telemetry();
+        std::process::exit(code);
+    }
 }
 
-/// Scans each SecureDrop Directory instance in order to populate the metadata
-/// field. If the instance is down, metadata is None.
-async fn populate_metadata(
+/// The exit code for a scan, from its unreachable sites, its findings
+/// (see `findings::detect_findings`) and the assertions it violates,
+/// unless it was interrupted.
+fn scan_exit_code(fail_on: &FailOn, results: &ScanResults) -> i32 {
+    if results.scan.partial {
+        return EXIT_INTERRUPTED;
+    }
+    let findings = results.findings.iter().map(|f| f.severity);
+    let violated = fail_on.violated_assertions(&results.instances);
+    fail_on.exit_code(results.scan.failed, findings, violated)
+}
+
+/// Arguments narrowing down which sites are scanned or reported on.
+fn filter_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("include")
+            .about("Only include sites matching this regex, against the title or a 'title:', 'onion:', 'locale:' or 'tag:' field; may be repeated")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .long("include"),
+        Arg::new("exclude")
+            .about("Leave out sites matching this regex, as for --include; may be repeated")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .long("exclude"),
+    ]
+}
+
+/// The tags the config file gives each Onion address.
+fn instance_tags(config: &Config) -> BTreeMap<String, Vec<String>> {
+    config
+        .tags
+        .iter()
+        .flatten()
+        .map(|(onion, tags)| (normalize_onion(onion), tags.to_vec()))
+        .collect()
+}
+
+/// Builds the filter given by `--include` and `--exclude`, with the tags
+/// from the config file.
+fn instance_filter(matches: &ArgMatches, config: &Config) -> Result<Filter, Box<dyn Error>> {
+    let patterns = |name| -> Result<Vec<Pattern>, Box<dyn Error>> {
+        match matches.values_of(name) {
+            Some(values) => values.map(|v| v.parse()).collect(),
+            None => Ok(vec![]),
+        }
+    };
+    Ok(Filter {
+        include: patterns("include")?,
+        exclude: patterns("exclude")?,
+        tags: instance_tags(config),
+    })
+}
+
+/// The commands `--anonymize` is an error with: each is about the sites
+/// as they are known by name (their landing pages, metadata as served or
+/// onion names), or shows them live.
+const UNANONYMIZED_COMMANDS: &[&str] = &["landing", "metadata", "onion-names", "tui"];
+
+/// The anonymizer asked for with `--anonymize`, salted per the config
+/// file. A salt made up for the run would give sites new pseudonyms every
+/// time, so without one in the config file `--anonymize` is an error.
+fn anonymizer(matches: &ArgMatches, config: &Config) -> Result<Option<Anonymizer>, Box<dyn Error>> {
+    if !matches.is_present("anonymize") {
+        return Ok(None);
+    }
+    match &config.anonymize_salt {
+        Some(salt) => Ok(Some(Anonymizer::new(salt))),
+        None => Err("--anonymize needs an anonymize_salt in the config file, to keep pseudonyms stable across runs".into()),
+    }
+}
+
+/// Selects the sites to report on with `--include` and `--exclude`, then
+/// anonymizes them with `--anonymize`.
+fn select_instances(
+    matches: &ArgMatches,
+    config: &Config,
     instances: Vec<SDDirectoryInstance>,
 ) -> Result<Vec<SDDirectoryInstance>, Box<dyn Error>> {
-    let mut results = vec![];
-    let (tx, mut rx) = channel(1024);
-    let l = &instances.len();
-    for mut i in instances {
-        let mut tx = tx.clone();
-        tokio::spawn(async move {
-            // Errors will be logged, send results to channel regardless.
-            match i.get_metadata().await {
-                Ok(_) => tx.send(i).await,
-                Err(_) => tx.send(i).await,
-            }
-        });
+    let mut instances = instance_filter(matches, config)?.apply(instances);
+    if let Some(anonymizer) = anonymizer(matches, config)? {
+        anonymizer.anonymize_instances(&mut instances);
+    }
+    Ok(instances)
+}
+
+/// Arguments selecting which sites to scan, shared by subcommands that
+/// perform a scan.
+fn scan_args() -> Vec<Arg<'static>> {
+    let mut args = vec![
+        Arg::new("directory")
+            .about("Read sites to scan from the securedrop.org directory")
+            .default_value("true")
+            .takes_value(false)
+            .long("directory")
+            .short('d'),
+        Arg::new("directory_url")
+            .about("Read sites to scan from this directory API; may be repeated to merge several [default: securedrop.org]")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .long("directory-url"),
+        Arg::new("onion_url")
+            .about("Scan custom Onion URLs (skips directory)")
+            .multiple(true),
+        Arg::new("instances_file")
+            .about("Scan the Onion URLs in this file, one per line or as JSON (skips directory)")
+            .takes_value(true)
+            .conflicts_with("onion_url")
+            .long("instances-file"),
+        Arg::new("quiet")
+            .about("Don't show a progress bar while scanning")
+            .long("quiet")
+            .short('q'),
+        Arg::new("db")
+            .about("Record the scan in a history database: a SQLite file at this path, or a postgres:// connection string")
+            .takes_value(true)
+            .long("db"),
+        Arg::new("timeout")
+            .about("Seconds to wait for each request to a site [default: 30]")
+            .takes_value(true)
+            .long("timeout"),
+        Arg::new("deadline")
+            .about("Seconds to allow for the whole scan, after which unfinished sites are reported as failed")
+            .takes_value(true)
+            .long("deadline"),
+        Arg::new("retries")
+            .about("How many times to retry fetching metadata from a site [default: 2]")
+            .takes_value(true)
+            .long("retries"),
+        Arg::new("retry_backoff")
+            .about("Seconds to wait before the first retry, doubled after each one [default: 5]")
+            .takes_value(true)
+            .long("retry-backoff"),
+        Arg::new("email_summary")
+            .about("Mail a summary of the scan to the recipients in the config file")
+            .long("email-summary"),
+        Arg::new("concurrency")
+            .about("How many sites to scan at once, from 1 to 64 [default: 8]")
+            .takes_value(true)
+            .long("concurrency")
+            .short('j'),
+        Arg::new("adaptive_concurrency")
+            .about("Lower the concurrency when timeouts and circuit failures spike, and raise it while sites respond, up to 64")
+            .long("adaptive-concurrency"),
+        Arg::new("request_delay")
+            .about("Seconds to leave between consecutive requests to the same site [default: 1]")
+            .takes_value(true)
+            .long("request-delay"),
+        Arg::new("request_budget")
+            .about("Make at most this many requests to each site per scan, including retries")
+            .takes_value(true)
+            .long("request-budget"),
+        Arg::new("from_snapshot")
+            .about("Replay the responses archived in this snapshot (a directory, or its manifest.json) instead of scanning")
+            .takes_value(true)
+            .conflicts_with_all(&["onion_url", "instances_file", "directory_url", "snapshot_dir"])
+            .long("from-snapshot"),
+        Arg::new("snapshot_dir")
+            .about("Archive the raw directory and metadata responses of each scan under this directory")
+            .takes_value(true)
+            .long("snapshot-dir"),
+        Arg::new("snapshot_compress")
+            .about("Gzip the responses archived with --snapshot-dir")
+            .long("snapshot-compress"),
+        Arg::new("checkpoint")
+            .about("Record each site in this file as soon as it is scanned, so an interrupted scan can be resumed")
+            .takes_value(true)
+            .long("checkpoint"),
+        Arg::new("resume")
+            .about("Resume the scan recorded with --checkpoint, only scanning the sites it hadn't finished")
+            .conflicts_with("from_snapshot")
+            .long("resume"),
+        Arg::new("mode")
+            .about("What to check on each site: its 'metadata', or only whether it responds ('ping') [default: metadata]")
+            .takes_value(true)
+            .possible_values(&["metadata", "ping"])
+            .long("mode"),
+        Arg::new("samples")
+            .about("Fetch each site this many times, over separate circuits, and report its median and p95 latency and how many fetches succeeded [default: 1]")
+            .takes_value(true)
+            .long("samples"),
+        Arg::new("verify_key")
+            .about("Check each site's published journalist key against the fingerprint in its metadata")
+            .long("verify-key"),
+        Arg::new("no_isolate_circuits")
+            .about("Let sites share Tor circuits, instead of fetching each over circuits of its own")
+            .long("no-isolate-circuits"),
+        Arg::new("no_preflight")
+            .about("Don't check that Tor can connect to each site before fetching its metadata")
+            .long("no-preflight"),
+    ];
+    args.extend(filter_args());
+    args
+}
+
+/// Reads the scan settings from the command line, falling back to the
+/// config file and then to the defaults.
+fn scan_options(matches: &ArgMatches, config: &Config) -> Result<ScanOptions, Box<dyn Error>> {
+    let mut options = ScanOptions::default();
+    if let Some(retries) = matches.value_of("retries") {
+        options.retries = retries.parse()?;
+    } else if let Some(retries) = config.retries {
+        options.retries = retries;
+    }
+    if let Some(backoff) = matches.value_of("retry_backoff") {
+        options.retry_backoff = Duration::from_secs(backoff.parse()?);
+    } else if let Some(backoff) = config.retry_backoff {
+        options.retry_backoff = Duration::from_secs(backoff);
+    }
+    if let Some(timeout) = matches.value_of("timeout") {
+        options.timeout = Duration::from_secs(timeout.parse()?);
+    } else if let Some(timeout) = config.timeout {
+        options.timeout = Duration::from_secs(timeout);
+    }
+    let proxies: Vec<String> = match matches.values_of("socks_proxy") {
+        Some(proxies) => proxies.map(socks_proxy_url).collect(),
+        None => config
+            .socks_proxy
+            .iter()
+            .flat_map(|p| p.to_vec())
+            .map(|p| socks_proxy_url(&p))
+            .collect(),
+    };
+    if let Some((first, rest)) = proxies.split_first() {
+        options.socks_proxy = first.to_owned();
+        options.socks_proxies = rest.to_vec();
+    }
+    options.isolate_circuits =
+        !matches.is_present("no_isolate_circuits") && config.isolate_circuits.unwrap_or(true);
+    options.preflight = !matches.is_present("no_preflight") && config.preflight.unwrap_or(true);
+    if let Some(deadline) = matches.value_of("deadline") {
+        options.deadline = Some(Duration::from_secs(deadline.parse()?));
+    } else if let Some(deadline) = config.deadline {
+        options.deadline = Some(Duration::from_secs(deadline));
+    }
+    options.verify_key = matches.is_present("verify_key") || config.verify_key.unwrap_or(false);
+    if let Some(delay) = matches.value_of("request_delay") {
+        options.request_delay = Duration::from_secs_f64(delay.parse()?);
+    } else if let Some(delay) = config.request_delay {
+        options.request_delay = Duration::from_secs_f64(delay);
+    }
+    if let Some(budget) = matches.value_of("request_budget") {
+        options.request_budget = Some(budget.parse()?);
+    } else if let Some(budget) = config.request_budget {
+        options.request_budget = Some(budget);
     }
-    let mut counter: usize = 1;
-    while let Some(i) = rx.recv().await {
-        results.push(i);
-        // TODO: The while loop on recv blocks forever, why?
-        // We'll just break out manually if we've received all results, but
-        // this is a hack.
-        if &counter == l {
-            debug!("Finished reading from channel, reporting results");
-            break;
+    if let Some(concurrency) = matches.value_of("concurrency") {
+        options.concurrency = concurrency.parse()?;
+    } else if let Some(concurrency) = config.concurrency {
+        options.concurrency = concurrency;
+    }
+    options.adaptive_concurrency =
+        matches.is_present("adaptive_concurrency") || config.adaptive_concurrency.unwrap_or(false);
+    if !(1..=MAX_CONCURRENCY).contains(&options.concurrency) {
+        return Err(format!("Concurrency must be between 1 and {}", MAX_CONCURRENCY).into());
+    }
+    if options.request_budget == Some(0) {
+        return Err("The request budget must allow at least one request".into());
+    }
+    if let Some(samples) = matches.value_of("samples") {
+        options.samples = samples.parse()?;
+    } else if let Some(samples) = config.samples {
+        options.samples = samples;
+    }
+    if options.samples == 0 {
+        return Err("Each site must be fetched at least once".into());
+    }
+    if let Some(mode) = matches.value_of("mode").or(config.mode.as_deref()) {
+        options.mode = mode.parse()?;
+    }
+    if options.mode == ScanMode::Ping && options.verify_key {
+        warn!("Journalist keys can only be verified against metadata, not with --mode ping");
+        options.verify_key = false;
+    }
+    options.headers = request_headers(config)?;
+    Ok(options)
+}
+
+/// The headers the config file asks to send to sites, its `user_agent`
+/// included.
+fn request_headers(config: &Config) -> Result<HeaderMap, Box<dyn Error>> {
+    let mut headers = HeaderMap::new();
+    let user_agent = config.user_agent.as_ref().map(|ua| ("User-Agent", ua));
+    let extra = config.headers.iter().flatten();
+    for (name, value) in extra.map(|(n, v)| (n.as_str(), v)).chain(user_agent) {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| format!("Invalid header name {}, {}", name, e))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| format!("Invalid value for header {}, {}", name, e))?;
+        headers.insert(name, value);
+    }
+    Ok(headers)
+}
+
+/// The output format requested via `--format`, falling back to the config
+/// file and then to the command's default, the first of the `formats` it
+/// supports.
+fn output_format<'a>(
+    matches: &'a ArgMatches,
+    config: &'a Config,
+    formats: &[&'a str],
+) -> Result<&'a str, Box<dyn Error>> {
+    let format = matches
+        .value_of("format")
+        .or(config.format.as_deref())
+        .unwrap_or(formats[0]);
+    if !formats.contains(&format) {
+        return Err(format!(
+            "Output format {} is unimplemented, expected one of {}",
+            format,
+            formats.join(", ")
+        )
+        .into());
+    }
+    Ok(format)
+}
+
+/// Loads the config file given via `--config`, or the one at the default
+/// location if it exists.
+fn load_config(matches: &ArgMatches) -> Result<Config, Box<dyn Error>> {
+    let path = match matches.value_of("config") {
+        Some(path) => PathBuf::from(path),
+        None => match Config::default_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Config::default()),
+        },
+    };
+    Config::load(&path)
+        .map_err(|e| format!("Failed to read config file {}, {}", path.display(), e).into())
+}
+
+/// Builds the list of instances to scan, from custom Onion URLs or an
+/// instances file if given, otherwise from the directory.
+async fn collect_instances(
+    matches: &ArgMatches,
+    config: &Config,
+    snapshot: Option<&Snapshot>,
+) -> Result<Vec<SDDirectoryInstance>, Box<dyn Error>> {
+    let mut instances = Vec::<SDDirectoryInstance>::new();
+    if let Some(onions) = matches.values_of("onion_url") {
+        info!("Scanning custom Onion URLs, skipping directory lookup");
+        for o in onions {
+            let i = SDDirectoryInstance::from_onion(o);
+            instances.push(i);
+        }
+    } else if let Some(path) = matches.value_of("instances_file") {
+        info!(
+            "Scanning sites listed in {}, skipping directory lookup",
+            path
+        );
+        instances = read_instances_file(path)
+            .map_err(|e| format!("Failed to read instances file {}, {}", path, e))?;
+    } else {
+        // TODO: Custom onions should be appended to, and by default
+        // directory entries are included (unless --directory=false)
+        let directory_urls: Vec<String> = match matches.values_of("directory_url") {
+            Some(urls) => urls.map(|u| u.to_owned()).collect(),
+            None => match &config.directory_url {
+                Some(urls) => urls.to_vec(),
+                None => vec![DIRECTORY_URL.to_owned()],
+            },
+        };
+        instances = get_securedrop_directories(&directory_urls, snapshot).await?;
+    }
+    // Locale filters can only be applied once the metadata is in.
+    let filter = instance_filter(matches, config)?;
+    instances.retain(|i| filter.admits_listing(i));
+    Ok(instances)
+}
+
+/// Prints an instance as a line of the JSON Lines output of a scan, as
+/// soon as it's scanned.
+fn print_instance_line(instance: &SDDirectoryInstance, anonymizer: Option<&Anonymizer>) {
+    match anonymizer {
+        Some(anonymizer) => {
+            let mut instance = instance.clone();
+            anonymizer.anonymize_instance(&mut instance);
+            println!("{}", serde_json::to_string(&instance).unwrap());
         }
-        counter += 1;
+        None => println!("{}", serde_json::to_string(instance).unwrap()),
     }
-    Ok(results)
 }
 
-/// Reads in a file containing JSON results from a previous scan,
-/// and inspects the metadata for languages to generate a report.
-async fn generate_l10n_report(input_file: &str) -> Result<String, Box<dyn Error>> {
-    let j = std::fs::read_to_string(input_file)?;
-    let instances: Vec<SDDirectoryInstance> = serde_json::from_str(&j)?;
-    let mut locales: BTreeMap<String, Vec<String>> = BTreeMap::new();
-    for i in instances {
-        // Metadata won't exist for down instances, so check first.
-        if let Some(m) = i.metadata {
-            for l in m.supported_languages {
-                locales
-                    .entry(l)
-                    .or_insert_with(Vec::new)
-                    .push(i.title.to_owned());
+/// Prints the line ending the JSON Lines output of a scan, holding the
+/// scan summary.
+fn print_summary_line(scan: &ScanSummary) {
+    println!("{{\"scan\":{}}}", serde_json::to_string(scan).unwrap());
+}
+
+/// The JSON output of `status`: the scan summary, then each entry.
+#[derive(Serialize)]
+struct StatusOutput<'a> {
+    scan: &'a ScanSummary,
+    instances: &'a [StatusEntry],
+    findings: &'a [Finding],
+}
+
+/// Scans the selected instances and prints the results, or writes them to
+/// `--output` and signs them with `--sign-key`, returning the exit code.
+async fn scan_command(matches: &ArgMatches, config: &Config) -> Result<i32, Box<dyn Error>> {
+    let format = output_format(matches, config, SCAN_FORMATS)?;
+    let output = ReportOutput::from_matches(matches, config)?;
+    let stream = format == "jsonl" && !matches.is_present("template") && output.path.is_none();
+    // Parsed first, so a mistyped assertion doesn't wait for the scan.
+    let fail_on = FailOn::from_matches(matches)?;
+    let results = run_scan(matches, config, stream).await?;
+    let full_instances = &results.instances;
+    let mut out = vec![];
+    if let Some(template) = matches.value_of("template") {
+        write!(
+            out,
+            "{}",
+            render_template(Path::new(template), full_instances, Utc::now())?
+        )?;
+    } else if format == "jsonl" {
+        // Unless going to a file, already printed as the scan went.
+        if !stream {
+            for i in full_instances {
+                writeln!(out, "{}", serde_json::to_string(i).unwrap())?;
             }
+            writeln!(
+                out,
+                "{{\"scan\":{}}}",
+                serde_json::to_string(&results.scan).unwrap()
+            )?;
         }
+    } else if format == "json" {
+        debug!("Will print results in JSON format");
+        writeln!(out, "{}", serde_json::to_string_pretty(&results).unwrap())?;
+    } else if format == "csv" {
+        write_csv(full_instances, &mut out)?;
+    } else if format == "html" {
+        write!(out, "{}", render_html(full_instances, Utc::now()))?;
+    } else if format == "markdown" {
+        write!(out, "{}", markdown::format_scan_results(full_instances))?;
+    } else if format == "ooni" {
+        write_ooni(&results, &mut out)?;
+    } else if format == "pp" {
+        for i in full_instances {
+            writeln!(out, "{:?}", i)?;
+        }
+    } else {
+        return Err(format!("Output format {} is unimplemented", format).into());
     }
-    let mut report = String::from("");
-    for (locale, sites) in locales {
-        report += &format!(
-            "{} ({}):\n  {}\n\n",
-            &locale,
-            &sites.len(),
-            sites.join("\n  ")
+    output.write(&out)?;
+    Ok(scan_exit_code(&fail_on, &results))
+}
+
+/// Scans the selected instances and prints the availability report,
+/// returning the exit code.
+async fn status_command(matches: &ArgMatches, config: &Config) -> Result<i32, Box<dyn Error>> {
+    let format = output_format(matches, config, REPORT_FORMATS)?;
+    let fail_on = FailOn::from_matches(matches)?;
+    let results = run_scan(matches, config, false).await?;
+    let entries = generate_status_report(&results.instances);
+    if format == "text" {
+        let color = use_color(matches);
+        print!("{}", interrupted_note(&results.scan));
+        println!("{}", format_status_report(&entries, color));
+        print!("{}", format_findings(&results.findings, color));
+    } else if format == "markdown" {
+        println!("{}", markdown::format_status_report(&entries));
+        print!("{}", markdown::format_findings(&results.findings));
+    } else if format == "json" {
+        let j = StatusOutput {
+            scan: &results.scan,
+            instances: &entries,
+            findings: &results.findings,
+        };
+        println!("{}", serde_json::to_string_pretty(&j).unwrap());
+    } else {
+        return Err(format!("Output format {} is unimplemented", format).into());
+    }
+    Ok(scan_exit_code(&fail_on, &results))
+}
+
+/// Scans the selected instances and prints every report on the scan, or
+/// writes it to `--output`, returning the exit code.
+async fn report_command(matches: &ArgMatches, config: &Config) -> Result<i32, Box<dyn Error>> {
+    let format = output_format(matches, config, REPORT_FORMATS)?;
+    let output = ReportOutput::from_matches(matches, config)?;
+    let fail_on = FailOn::from_matches(matches)?;
+    let results = run_scan(matches, config, false).await?;
+    let mut out = vec![];
+    write_full_report(
+        &mut out,
+        &results,
+        format,
+        use_color(matches),
+        &report_messages(matches, config)?,
+    )?;
+    output.write(&out)?;
+    Ok(scan_exit_code(&fail_on, &results))
+}
+
+/// Checks the landing pages and prints the report, returning the exit
+/// code.
+async fn landing_command(matches: &ArgMatches, config: &Config) -> Result<i32, Box<dyn Error>> {
+    let format = output_format(matches, config, TEXT_FORMATS)?;
+    let instances = match matches.values_of("landing_page_url") {
+        Some(urls) => urls
+            .map(|u| SDDirectoryInstance {
+                title: u.to_owned(),
+                landing_page_url: u.to_owned(),
+                ..SDDirectoryInstance::from_onion("")
+            })
+            .collect(),
+        None => collect_instances(matches, config, None).await?,
+    };
+    let mut landing = LandingOptions {
+        compare_clearnet: matches.is_present("compare_clearnet"),
+        ..LandingOptions::default()
+    };
+    if let Some(max) = matches.value_of("max_redirects") {
+        landing.max_redirects = max.parse()?;
+    }
+    if let Some(days) = matches.value_of("cert_expiry_days") {
+        landing.expiry_warning = chrono::Duration::days(days.parse()?);
+    } else if let Some(days) = config.cert_expiry_days {
+        landing.expiry_warning = chrono::Duration::days(days);
+    }
+    let checks = check_landing_pages(&instances, &scan_options(matches, config)?, &landing).await;
+    if format == "text" {
+        println!("{}", format_landing_report(&checks));
+    } else if format == "json" {
+        println!("{}", serde_json::to_string_pretty(&checks).unwrap());
+    } else {
+        return Err(format!("Output format {} is unimplemented", format).into());
+    }
+    // Failing a check is as serious as a high severity finding.
+    let unreachable = checks.iter().filter(|c| c.error.is_some()).count();
+    let findings = checks.iter().flat_map(|c| {
+        let failed = (c.error.is_none() && !c.passed()).then_some(Severity::High);
+        c.findings.iter().map(|f| f.severity).chain(failed)
+    });
+    Ok(FailOn::from_matches(matches)?.exit_code(unreachable, findings, 0))
+}
+
+/// Scans the selected instances for the dashboard, showing each result
+/// as it comes in. Unlike other scans, these aren't recorded.
+async fn scan_dashboard(
+    matches: &ArgMatches,
+    config: &Config,
+    dashboard: &std::sync::Mutex<Dashboard>,
+) -> Result<(), Box<dyn Error>> {
+    let options = scan_options(matches, config)?;
+    let filter = instance_filter(matches, config)?;
+    let instances = collect_instances(matches, config, None).await?;
+    dashboard.lock().unwrap().start_scan(instances.len());
+    let instances = populate_metadata(instances, &options, |i| {
+        if filter.admits_instance(i) {
+            dashboard.lock().unwrap().record(i);
+        }
+    })
+    .await?;
+    dashboard
+        .lock()
+        .unwrap()
+        .finish_scan(filter.apply(instances));
+    Ok(())
+}
+
+/// Scans the selected instances, recording the results in the history
+/// database if one was given. With `stream`, each result is printed as a
+/// line of JSON as soon as it comes in, followed by the scan summary.
+#[tracing::instrument(name = "scan", skip_all)]
+async fn run_scan(
+    matches: &ArgMatches,
+    config: &Config,
+    stream: bool,
+) -> Result<ScanResults, Box<dyn Error>> {
+    let mut options = scan_options(matches, config)?;
+    options.interrupt = Some(Interrupt::listen());
+    let filter = instance_filter(matches, config)?;
+    let anonymizer = anonymizer(matches, config)?;
+    if let Some(path) = matches.value_of("from_snapshot") {
+        // Replays are never recorded, so they can't skew the history.
+        let replayed = load_snapshot(Path::new(path))?;
+        let instances = filter.apply(replayed.instances);
+        let scan = ScanSummary::new(
+            replayed.scan.started_at,
+            replayed.scan.finished_at,
+            &instances,
         );
+        if stream {
+            for i in &instances {
+                print_instance_line(i, anonymizer.as_ref());
+            }
+            print_summary_line(&scan);
+        }
+        let mut results = ScanResults::new(scan, instances);
+        if let Some(anonymizer) = &anonymizer {
+            anonymizer.anonymize_results(&mut results);
+        }
+        return Ok(results);
+    }
+    let started_at = Utc::now();
+    if let Some(dir) = matches
+        .value_of("snapshot_dir")
+        .or(config.snapshot_dir.as_deref())
+    {
+        let compress =
+            matches.is_present("snapshot_compress") || config.snapshot_compress.unwrap_or(false);
+        options.snapshot = Some(Arc::new(Snapshot::create(
+            Path::new(dir),
+            started_at,
+            compress,
+        )?));
     }
-    Ok(report)
+    let checkpoint_path = matches
+        .value_of("checkpoint")
+        .or(config.checkpoint.as_deref());
+    let (checkpoint, completed) = match checkpoint_path {
+        Some(path) if matches.is_present("resume") => {
+            let (checkpoint, completed) = Checkpoint::resume(Path::new(path))?;
+            (Some(checkpoint), completed)
+        }
+        Some(path) => (Some(Checkpoint::create(Path::new(path))?), vec![]),
+        None if matches.is_present("resume") => {
+            return Err("--resume needs the checkpoint file, see --checkpoint".into())
+        }
+        None => (None, vec![]),
+    };
+    let instances = collect_instances(matches, config, options.snapshot.as_deref()).await?;
+    let listed = instances.len();
+    // The sites the checkpoint completed are taken from it rather than
+    // scanned again, as long as the directory still lists them.
+    let (done, instances): (Vec<_>, Vec<_>) = instances
+        .into_iter()
+        .partition(|i| completed.iter().any(|c| c.onion_address == i.onion_address));
+    let mut resumed: Vec<SDDirectoryInstance> = completed
+        .into_iter()
+        .filter(|c| done.iter().any(|i| i.onion_address == c.onion_address))
+        .collect();
+    let progress = if matches.is_present("quiet") {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(listed as u64)
+    };
+    progress.set_style(
+        ProgressStyle::default_bar()
+            .template("{bar:40} {pos}/{len} sites, {msg} (ETA {eta})")
+            .unwrap(),
+    );
+    progress.set_position(resumed.len() as u64);
+    if stream {
+        for i in resumed.iter().filter(|i| filter.admits_instance(i)) {
+            print_instance_line(i, anonymizer.as_ref());
+        }
+    }
+    let mut failures = resumed.iter().filter(|i| !i.available).count();
+    let mut first_response = None;
+    // Spans the wait for the first response, the nearest sdstatus can see
+    // to Tor's bootstrap.
+    let mut bootstrap = Some(tracing::info_span!("tor_bootstrap"));
+    let full_instances = populate_metadata(instances, &options, |i| {
+        if i.http_status.is_some() && first_response.is_none() {
+            first_response = Some(Utc::now());
+            bootstrap.take();
+        }
+        if !i.available {
+            failures += 1;
+            progress.set_message(format!("{} failed", failures));
+        }
+        progress.inc(1);
+        if stream && filter.admits_instance(i) {
+            progress.suspend(|| print_instance_line(i, anonymizer.as_ref()));
+        }
+        // A site the deadline cut short wasn't scanned, so a resumed scan
+        // tries it again.
+        if let Some(checkpoint) = checkpoint
+            .as_ref()
+            .filter(|_| i.failure_kind() != Some(FailureKind::DeadlineExceeded))
+        {
+            if let Err(e) = checkpoint.record(i) {
+                warn!(
+                    "Failed to record {} in the checkpoint, {}",
+                    i.onion_address, e
+                );
+            }
+        }
+    })
+    .await?;
+    progress.finish_and_clear();
+    if let Some(snapshot) = &options.snapshot {
+        snapshot.finish()?;
+    }
+    resumed.extend(full_instances);
+    let full_instances = resumed;
+    let unscanned = listed - full_instances.len();
+    let interrupted = options.interrupt.as_ref().is_some_and(|i| i.is_set());
+    if let (Some(checkpoint), Some(path)) = (checkpoint, checkpoint_path) {
+        if interrupted {
+            info!("Resume the scan from {} with --resume", path);
+        } else {
+            checkpoint.finish()?;
+        }
+    }
+    let full_instances = filter.apply(full_instances);
+    let mut scan = ScanSummary::new(started_at, Utc::now(), &full_instances);
+    scan.tor_bootstrap_ms = first_response.map(|t| (t - started_at).num_milliseconds() as u64);
+    scan.partial = interrupted;
+    scan.unscanned = unscanned;
+    if stream {
+        print_summary_line(&scan);
+    }
+    // Findings that compare against the history.
+    let mut history_findings = vec![];
+    if scan.partial {
+        // A partial scan would read as the missing sites going down.
+        warn!("Scan interrupted, its results are not recorded or sent anywhere");
+    } else if let Some(path) = matches.value_of("db").or(config.db.as_deref()) {
+        let mut history = db::open(path)?;
+        // Notifications fire on changes since the previous scan, so it
+        // must be loaded before this one is recorded.
+        let previous = match history.latest_scan_ids(1)?.first() {
+            Some(id) => Some((history.scan_started_at(*id)?, history.load_scan(*id)?)),
+            None => None,
+        };
+        let known = history.known_fingerprints()?;
+        let addresses = history.known_addresses()?;
+        history_findings = detect_key_rotations(&full_instances, &known);
+        history_findings.extend(detect_downgrades(
+            &full_instances,
+            &history.known_versions()?,
+        ));
+        let scan_id = history.record_scan(started_at, scan.finished_at, &full_instances)?;
+        info!("Recorded scan {} in history database at {}", scan_id, path);
+        if let (Some((previous_at, previous)), Some(notifications)) =
+            (previous, &config.notifications)
+        {
+            let mut events = detect_events(&previous, &full_instances, &known, started_at);
+            events.extend(detect_onion_changes(
+                &addresses,
+                &full_instances,
+                started_at,
+            ));
+            if let Some(hours) = notifications.outage_hours {
+                events.extend(detect_outages(
+                    history.as_ref(),
+                    &full_instances,
+                    previous_at,
+                    started_at,
+                    chrono::Duration::hours(hours),
+                )?);
+            }
+            send_notifications(notifications, &events).await;
+            if let Some(mastodon) = &notifications.mastodon {
+                if summary_due(previous_at, started_at) {
+                    let summary =
+                        WeeklySummary::new(history.as_ref(), &full_instances, started_at)?;
+                    let key = format!("sdstatus-{}", week_start(started_at).format("%Y-%m-%d"));
+                    if let Err(e) = post_status(mastodon, &summary.status(), &key).await {
+                        warn!(
+                            "Failed to post the weekly summary to {}, {}",
+                            mastodon.instance, e
+                        );
+                    }
+                }
+            }
+        }
+        if let Some(github) = &config.github {
+            let streaks = outage_streaks(history.as_ref(), &full_instances)?;
+            if let Err(e) = sync_issues(github, &full_instances, &streaks, started_at).await {
+                warn!("Failed to update outage issues in {}, {}", github.repo, e);
+            }
+        }
+    } else if config.notifications.is_some() || config.github.is_some() {
+        warn!("Notifications need a history database to detect changes, see --db");
+    }
+    let mut results = ScanResults::new(scan, full_instances);
+    results.findings.extend(history_findings);
+    results
+        .findings
+        .sort_by_key(|f| std::cmp::Reverse(f.severity));
+    if matches.is_present("email_summary") && !results.scan.partial {
+        match config.notifications.as_ref().and_then(|n| n.email.as_ref()) {
+            Some(email) => {
+                let entries = generate_status_report(&results.instances);
+                let up = entries.iter().filter(|e| e.available).count();
+                let subject = format!(
+                    "sdstatus: {} of {} SecureDrop instances available",
+                    up,
+                    entries.len()
+                );
+                // As with notifications, failing to mail doesn't fail the scan.
+                if let Err(e) =
+                    send_email(email, &subject, &format_status_report(&entries, false)).await
+                {
+                    warn!("Failed to mail the scan summary, {}", e);
+                }
+            }
+            None => warn!("No email settings in the config file, not mailing a summary"),
+        }
+    }
+    if let Some(publish) = config.publish.as_ref().filter(|_| !results.scan.partial) {
+        // As with notifications, failing to publish doesn't fail the scan.
+        if let Err(e) = publish_results(publish, &results).await {
+            warn!("Failed to publish results to {}, {}", publish.url, e);
+        }
+    }
+    #[cfg(feature = "otel")]
+    sdstatus::telemetry::record_scan(&results);
+    // Only what is printed or written out is anonymized: the email
+    // summary and published results go to those who run the directory.
+    if let Some(anonymizer) = &anonymizer {
+        anonymizer.anonymize_results(&mut results);
+    }
+    Ok(results)
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let env = Env::default().filter_or("RUST_LOG", "info,reqwest=info,hyper=info");
-    env_logger::init_from_env(env);
-
     let matches = App::new("sdstatus")
         .version(crate_version!())
         .about("Reports metadata about SecureDrop sites")
+        .arg(
+            Arg::new("config")
+                .about("Read settings from this TOML file [default: ~/.config/sdstatus/config.toml]")
+                .takes_value(true)
+                .global(true)
+                .long("config")
+                .short('c'),
+        )
+        .arg(
+            Arg::new("socks_proxy")
+                .about("Send requests through the Tor SOCKS proxy at this host:port; may be repeated to share sites between several Tor daemons [default: 127.0.0.1:9050]")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .global(true)
+                .long("socks-proxy"),
+        )
+        .arg(
+            Arg::new("log_file")
+                .about("Append log messages to this file instead of standard error")
+                .takes_value(true)
+                .global(true)
+                .long("log-file"),
+        )
+        .arg(
+            Arg::new("log_format")
+                .about("Write log messages as 'text' or 'json' lines [default: text]")
+                .takes_value(true)
+                .global(true)
+                .possible_values(&["text", "json"])
+                .long("log-format"),
+        )
+        .arg(
+            Arg::new("lang")
+                .about("Write report headings and labels in this language, e.g. de or pt-BR [default: en-US]")
+                .takes_value(true)
+                .global(true)
+                .long("lang"),
+        )
+        .arg(
+            Arg::new("color")
+                .about("When to color text output: 'auto' (on a terminal, unless NO_COLOR is set), 'always' or 'never'")
+                .takes_value(true)
+                .global(true)
+                .possible_values(&["auto", "always", "never"])
+                .long("color"),
+        )
+        .arg(
+            Arg::new("anonymize")
+                .about("Replace site titles, addresses and fingerprints in the output with salted hashes (see anonymize_salt in the config file)")
+                .global(true)
+                .long("anonymize"),
+        )
         .subcommand(
             App::new("scan")
                 .about("Retrieve metadata from SecureDrop sites")
+                .arg(format_arg(SCAN_FORMATS))
+                .args(scan_args())
+                .arg(fail_on_arg())
+                .arg(assert_arg())
+                .arg(watch_arg())
                 .arg(
-                    Arg::new("directory")
-                        .about("Read sites to scan from the securedrop.org directory")
-                        .default_value("true")
-                        .takes_value(false)
-                        .long("directory")
-                        .short('d'),
+                    Arg::new("template")
+                        .about("Render the results with this Tera template instead of --format")
+                        .takes_value(true)
+                        .long("template"),
                 )
+                .args(output_args()),
+        )
+        .subcommand(
+            App::new("status")
+                .about("Report which SecureDrop sites are reachable, and how quickly")
+                .arg(format_arg(REPORT_FORMATS))
+                .args(scan_args())
+                .arg(fail_on_arg())
+                .arg(assert_arg())
+                .arg(watch_arg()),
+        )
+        .subcommand(
+            App::new("report")
+                .about("Scan once and report on availability, versions, server OS, locales and findings")
+                .arg(format_arg(REPORT_FORMATS))
+                .args(scan_args())
+                .args(output_args())
+                .arg(fail_on_arg())
+                .arg(assert_arg())
+                .arg(watch_arg()),
+        )
+        .subcommand(
+            App::new("serve")
+                .about("Scan periodically, exposing the results as Prometheus metrics")
+                .args(scan_args())
                 .arg(
-                    Arg::new("format")
-                        .about("Specify output format: 'csv', 'json', or 'pp'")
-                        .default_value("json")
-                        .long("format")
-                        .short('f'),
+                    Arg::new("listen")
+                        .about("Address to serve /metrics on")
+                        .default_value("127.0.0.1:9732")
+                        .long("listen"),
                 )
                 .arg(
-                    Arg::new("onion_url")
-                        .about("Scan custom Onion URLs (skips directory)")
+                    Arg::new("interval")
+                        .about("Seconds to wait between scans")
+                        .default_value("3600")
+                        .long("interval"),
+                ),
+        )
+        .subcommand(
+            App::new("tui")
+                .about("Show a live dashboard of SecureDrop sites, rescanning in the background")
+                .args(scan_args())
+                .arg(
+                    Arg::new("interval")
+                        .about("Seconds to wait between scans")
+                        .default_value("300")
+                        .long("interval"),
+                ),
+        )
+        .subcommand(
+            App::new("daemon")
+                .about("Scan on a schedule, recording results and sending notifications")
+                .args(scan_args())
+                .arg(
+                    Arg::new("interval")
+                        .about("Seconds between the start of one scan and the next [default: 3600]")
+                        .takes_value(true)
+                        .long("interval"),
+                )
+                .arg(
+                    Arg::new("cron")
+                        .about("Scan on this cron schedule instead, e.g. '0 */6 * * *'")
+                        .takes_value(true)
+                        .conflicts_with("interval")
+                        .long("cron"),
+                )
+                .arg(
+                    Arg::new("jitter")
+                        .about("Delay each scan by a random number of seconds up to this [default: 0]")
+                        .takes_value(true)
+                        .long("jitter"),
+                )
+                .arg(
+                    Arg::new("listen")
+                        .about("Serve /healthz and /last-scan on this address, e.g. 127.0.0.1:9733")
+                        .takes_value(true)
+                        .long("listen"),
+                ),
+        )
+        .subcommand(
+            App::new("landing")
+                .about("Check that landing pages are served over HTTPS with a valid certificate")
+                .arg(format_arg(TEXT_FORMATS))
+                .arg(
+                    Arg::new("landing_page_url")
+                        .about("Check these landing page URLs (skips directory)")
                         .multiple(true),
+                )
+                .arg(
+                    Arg::new("compare_clearnet")
+                        .about("Also fetch each page without Tor, reporting sites that block or challenge Tor users")
+                        .long("compare-clearnet"),
+                )
+                .arg(
+                    Arg::new("max_redirects")
+                        .about("Follow at most this many redirects from each landing page [default: 10]")
+                        .takes_value(true)
+                        .long("max-redirects"),
+                )
+                .arg(
+                    Arg::new("cert_expiry_days")
+                        .about("Warn about certificates expiring within this many days [default: 30]")
+                        .takes_value(true)
+                        .long("cert-expiry-days"),
+                )
+                .arg(
+                    Arg::new("directory_url")
+                        .about("Read sites to check from this directory API; may be repeated to merge several [default: securedrop.org]")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .long("directory-url"),
+                )
+                .arg(
+                    Arg::new("timeout")
+                        .about("Seconds to wait for each request to a site [default: 30]")
+                        .takes_value(true)
+                        .long("timeout"),
+                )
+                .arg(
+                    Arg::new("request_delay")
+                        .about("Seconds to leave between consecutive requests to the same site [default: 1]")
+                        .takes_value(true)
+                        .long("request-delay"),
+                )
+                .arg(
+                    Arg::new("concurrency")
+                        .about("How many sites to check at once, from 1 to 64 [default: 8]")
+                        .takes_value(true)
+                        .long("concurrency")
+                        .short('j'),
+                )
+                .arg(
+                    Arg::new("no_isolate_circuits")
+                        .about("Let sites share Tor circuits, instead of checking each over circuits of its own")
+                        .long("no-isolate-circuits"),
+                )
+                .arg(fail_on_arg())
+                .arg(watch_arg())
+                .args(filter_args()),
+        )
+        .subcommand(
+            App::new("metadata")
+                .about("Fetch and print the metadata of a single SecureDrop site")
+                .arg(format_arg(METADATA_FORMATS))
+                .arg(
+                    Arg::new("onion_address")
+                        .about("The Onion URL of the site (skips directory)")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("raw")
+                        .about("Print the metadata as served, including fields sdstatus does not model")
+                        .long("raw"),
                 ),
         )
         .subcommand(
             App::new("l10n")
                 .about("Reports localization metrics from scanned metadata")
+                // Either mode's formats, checked against the one given.
+                .arg(format_arg(&["text", "markdown", "json", "csv"]))
+                .arg(
+                    Arg::new("input_file")
+                        .about("The JSON output of a previous 'scan', or a snapshot directory")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("coverage")
+                        .about("Compare the enabled locales to those SecureDrop ships")
+                        .long("coverage"),
+                )
+                .arg(
+                    Arg::new("sort")
+                        .about("Order locales by 'count' (most sites first) or 'locale', and sites within them by 'title' or 'onion'; may be repeated [default: locale]")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .possible_values(&["count", "locale", "title", "onion"])
+                        .long("sort"),
+                )
+                .arg(
+                    Arg::new("matrix")
+                        .about("Output a matrix of which sites enable which locales, as csv or json")
+                        .conflicts_with_all(&["coverage", "translations"])
+                        .long("matrix"),
+                )
+                .arg(
+                    Arg::new("translations")
+                        .about("Show how complete each locale's translation is on Weblate (needs [weblate] in the config file)")
+                        .conflicts_with("coverage")
+                        .long("translations"),
+                )
+                .arg(
+                    Arg::new("missing")
+                        .about("List the sites that do not enable the given locale instead")
+                        .takes_value(true)
+                        .conflicts_with_all(&["coverage", "matrix", "translations"])
+                        .long("missing"),
+                )
+                .args(output_args())
+                .args(filter_args()),
+        )
+        .subcommand(
+            App::new("versions")
+                .about("Reports which SecureDrop releases are running, from scanned metadata")
+                .arg(format_arg(REPORT_FORMATS))
+                .arg(
+                    Arg::new("input_file")
+                        .about("The JSON output of a previous 'scan', or a snapshot directory")
+                        .required(true),
+                )
+                .args(output_args())
+                .args(filter_args()),
+        )
+        .subcommand(
+            App::new("os")
+                .about("Reports which server OS releases are running, from scanned metadata")
+                .arg(format_arg(REPORT_FORMATS))
+                .arg(
+                    Arg::new("input_file")
+                        .about("The JSON output of a previous 'scan', or a snapshot directory")
+                        .required(true),
+                )
+                .args(output_args())
+                .args(filter_args()),
+        )
+        .subcommand(
+            App::new("tags")
+                .about("Reports which sites have each tag given in the config file")
+                .arg(format_arg(REPORT_FORMATS))
+                .arg(
+                    Arg::new("input_file")
+                        .about("The JSON output of a previous 'scan', or a snapshot directory")
+                        .required(true),
+                )
+                .args(output_args())
+                .args(filter_args()),
+        )
+        .subcommand(
+            App::new("outdated")
+                .about("Reports which sites are behind the latest SecureDrop release, from scanned metadata")
+                .arg(format_arg(REPORT_FORMATS))
+                .arg(
+                    Arg::new("input_file")
+                        .about("The JSON output of a previous 'scan', or a snapshot directory")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("latest_version")
+                        .about("Compare against this release instead of fetching the latest one, e.g. 2.12.1")
+                        .takes_value(true)
+                        .long("latest-version"),
+                )
+                .arg(
+                    Arg::new("releases_url")
+                        .about("Fetch SecureDrop releases from this GitHub API URL")
+                        .default_value(RELEASES_URL)
+                        .long("releases-url"),
+                )
+                .args(output_args())
+                .args(filter_args()),
+        )
+        .subcommand(
+            App::new("keys")
+                .about("Reports the algorithm and expiry of sites' journalist keys, from a scan with --verify-key")
+                .arg(format_arg(TEXT_FORMATS))
+                .arg(
+                    Arg::new("input_file")
+                        .about("The JSON output of a previous 'scan --verify-key', or a snapshot directory")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("warn_days")
+                        .about("Report keys expiring within this many days as expiring [default: 30]")
+                        .takes_value(true)
+                        .long("warn-days"),
+                )
+                .args(filter_args()),
+        )
+        .subcommand(
+            App::new("onion-names")
+                .about("Checks that sites' onion names map to their listed Onion addresses in SecureDrop's rulesets")
+                .arg(format_arg(TEXT_FORMATS))
+                .arg(
+                    Arg::new("input_file")
+                        .about("The JSON output of a previous 'scan', or a snapshot directory [default: read the directory]"),
+                )
+                .arg(
+                    Arg::new("directory_url")
+                        .about("Read sites from this directory API; may be repeated [default: securedrop.org]")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .conflicts_with("input_file")
+                        .long("directory-url"),
+                )
+                .arg(
+                    Arg::new("rulesets_url")
+                        .about("Fetch the onion name rulesets from this HTTPS Everywhere update channel")
+                        .default_value(RULESETS_URL)
+                        .long("rulesets-url"),
+                )
+                .args(filter_args()),
+        )
+        .subcommand(
+            App::new("show")
+                .about("Shows everything known about a single site, from a previous scan and the history database")
+                .arg(format_arg(TEXT_FORMATS))
+                .arg(
+                    Arg::new("onion_address")
+                        .about("The Onion address of the site")
+                        .required(true),
+                )
                 .arg(
                     Arg::new("input_file")
-                        .about("The JSON output of a previous 'scan'")
+                        .about("The JSON output of a previous 'scan', or a snapshot directory [default: the latest scan in the history database]"),
+                )
+                .arg(
+                    Arg::new("db")
+                        .about("The history database to read the site's past scans from")
+                        .takes_value(true)
+                        .long("db"),
+                )
+                .arg(
+                    Arg::new("window")
+                        .about("Report uptime over this window, e.g. 12h, 7d or 2w; may be repeated [default: 7d, 30d, 90d]")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .long("window"),
+                ),
+        )
+        .subcommand(
+            App::new("uptime")
+                .about("Reports how often each site was available, from the history database")
+                .arg(format_arg(REPORT_FORMATS))
+                .arg(
+                    Arg::new("db")
+                        .about("The history database to read scans from")
+                        .takes_value(true)
+                        .long("db"),
+                )
+                .arg(
+                    Arg::new("window")
+                        .about("Report uptime over this window, e.g. 12h, 7d or 2w; may be repeated [default: 7d, 30d, 90d]")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .long("window"),
+                )
+                .args(filter_args()),
+        )
+        .subcommand(
+            App::new("adoption")
+                .about("Reports how the sites' SecureDrop versions evolved, and how fast each release was adopted, from the history database")
+                .arg(format_arg(ADOPTION_FORMATS))
+                .arg(
+                    Arg::new("db")
+                        .about("The history database to read scans from")
+                        .takes_value(true)
+                        .long("db"),
+                )
+                .arg(
+                    Arg::new("window")
+                        .about("Report on this much of the history, e.g. 12w")
+                        .default_value("90d")
+                        .long("window"),
+                )
+                .arg(
+                    Arg::new("interval")
+                        .about("Count versions over intervals of this length, e.g. 1d or 1w")
+                        .default_value("1d")
+                        .long("interval"),
+                )
+                .args(filter_args()),
+        )
+        .subcommand(
+            App::new("chart")
+                .about("Draws an SVG chart of version adoption, uptime or locale support over time, from the history database")
+                .arg(
+                    Arg::new("chart")
+                        .about("What to chart")
+                        .possible_values(&["adoption", "uptime", "locales"])
                         .required(true),
+                )
+                .arg(
+                    Arg::new("db")
+                        .about("The history database to read scans from")
+                        .takes_value(true)
+                        .long("db"),
+                )
+                .arg(
+                    Arg::new("window")
+                        .about("Chart this much of the history, e.g. 12w")
+                        .default_value("90d")
+                        .long("window"),
+                )
+                .arg(
+                    Arg::new("interval")
+                        .about("Plot a point per interval of this length, e.g. 1d or 1w")
+                        .default_value("1d")
+                        .long("interval"),
+                )
+                .args(filter_args()),
+        )
+        .subcommand(
+            App::new("verify")
+                .about("Check the minisign signature of a report written with --output --sign-key")
+                .arg(
+                    Arg::new("file")
+                        .about("The signed report")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("public_key")
+                        .about("The minisign public key to check the signature against")
+                        .takes_value(true)
+                        .required(true)
+                        .long("public-key"),
+                )
+                .arg(
+                    Arg::new("signature")
+                        .about("The signature [default: <file>.minisig]")
+                        .takes_value(true)
+                        .long("signature"),
                 ),
         )
+        .subcommand(
+            App::new("diff")
+                .about("Reports changes between two scans")
+                .arg(format_arg(REPORT_FORMATS))
+                .arg(
+                    Arg::new("old")
+                        .about("The older scan: a JSON output file, or a scan id with --db"),
+                )
+                .arg(
+                    Arg::new("new")
+                        .about("The newer scan: a JSON output file, or a scan id with --db"),
+                )
+                .arg(
+                    Arg::new("db")
+                        .about("Compare scans stored in this history database (default: the latest two)")
+                        .takes_value(true)
+                        .long("db"),
+                )
+                .args(filter_args()),
+        )
         .get_matches();
+    // The config file can set up logging, so it's read before logging
+    // starts and load errors are reported on standard error.
+    let config = load_config(&matches)?;
+    let log_format = matches
+        .value_of("log_format")
+        .or(config.log_format.as_deref())
+        .unwrap_or("text");
+    let log_file = matches.value_of("log_file").or(config.log_file.as_deref());
+    // Log messages would garble the dashboard, so they are dropped unless
+    // they go to a file.
+    if matches.subcommand_name() != Some("tui") || log_file.is_some() {
+        init_logging(log_file, log_format == "json", config.telemetry.as_ref())?;
+    }
+
+    if let Some((name, matches)) = matches.subcommand() {
+        if matches.is_present("anonymize") && UNANONYMIZED_COMMANDS.contains(&name) {
+            return Err(format!("{} can't anonymize its output, see --anonymize", name).into());
+        }
+    }
 
     // Primary subcommand
-    if let Some(ref matches) = matches.subcommand_matches("scan") {
-        let mut instances = Vec::<SDDirectoryInstance>::new();
-        if let Some(onions) = matches.values_of("onion_url") {
-            info!("Scanning custom Onion URLs, skipping directory lookup");
-            for o in onions {
-                let i = SDDirectoryInstance::from_onion(o);
-                instances.push(i);
+    if let Some(matches) = matches.subcommand_matches("scan") {
+        watch(matches, || scan_command(matches, &config)).await?;
+    } else if let Some(matches) = matches.subcommand_matches("status") {
+        watch(matches, || status_command(matches, &config)).await?;
+    } else if let Some(matches) = matches.subcommand_matches("report") {
+        watch(matches, || report_command(matches, &config)).await?;
+    } else if let Some(matches) = matches.subcommand_matches("serve") {
+        let addr = matches.value_of("listen").unwrap().parse()?;
+        let interval = Duration::from_secs(matches.value_of("interval").unwrap().parse()?);
+        let metrics = Arc::new(RwLock::new(String::new()));
+        let scans = async {
+            loop {
+                // A failed scan (e.g. the directory being unreachable) keeps
+                // the previous metrics, rather than stopping the exporter.
+                match run_scan(matches, &config, false).await {
+                    Ok(results) => {
+                        *metrics.write().await =
+                            render_metrics(&results.instances, &results.scan, Utc::now());
+                    }
+                    Err(e) => error!("Scan failed, {}", e),
+                }
+                if !sleep_unless_interrupted(interval).await {
+                    break;
+                }
+            }
+        };
+        tokio::select! {
+            result = serve_metrics(addr, metrics.clone()) => result?,
+            _ = scans => {}
+        }
+    } else if let Some(matches) = matches.subcommand_matches("tui") {
+        let interval = Duration::from_secs(matches.value_of("interval").unwrap().parse()?);
+        let dashboard = Arc::new(std::sync::Mutex::new(Dashboard::default()));
+        let rescan = Arc::new(Notify::new());
+        let ui = tokio::task::spawn_blocking({
+            let dashboard = dashboard.clone();
+            let rescan = rescan.clone();
+            move || run_dashboard(dashboard, rescan)
+        });
+        let scans = async {
+            loop {
+                if let Err(e) = scan_dashboard(matches, &config, &dashboard).await {
+                    dashboard.lock().unwrap().fail_scan(e.to_string());
+                }
+                tokio::select! {
+                    _ = tokio::time::delay_for(interval) => {}
+                    _ = rescan.notified() => {}
+                }
+            }
+        };
+        tokio::select! {
+            result = ui => result??,
+            _ = scans => {}
+        }
+    } else if let Some(matches) = matches.subcommand_matches("daemon") {
+        let daemon = config.daemon.as_ref();
+        let cron = matches
+            .value_of("cron")
+            .or_else(|| daemon.and_then(|d| d.cron.as_deref()));
+        let schedule = match (matches.value_of("interval"), cron) {
+            (Some(interval), _) => Schedule::Interval(chrono::Duration::seconds(interval.parse()?)),
+            (None, Some(cron)) => Schedule::cron(cron)?,
+            (None, None) => Schedule::Interval(chrono::Duration::seconds(
+                daemon.and_then(|d| d.interval).unwrap_or(3600) as i64,
+            )),
+        };
+        if let Schedule::Interval(interval) = &schedule {
+            if *interval <= chrono::Duration::zero() {
+                return Err("The scan interval must be at least a second".into());
+            }
+        }
+        let jitter = match matches.value_of("jitter") {
+            Some(jitter) => jitter.parse()?,
+            None => daemon.and_then(|d| d.jitter).unwrap_or(0),
+        };
+        if matches.value_of("db").or(config.db.as_deref()).is_none() {
+            warn!("No history database given, see --db; scans will not be recorded");
+        }
+        let listen = match matches
+            .value_of("listen")
+            .or_else(|| daemon.and_then(|d| d.listen.as_deref()))
+        {
+            Some(addr) => Some(addr.parse::<SocketAddr>()?),
+            None => None,
+        };
+        let health = Arc::new(RwLock::new(ScanHealth::default()));
+        // Scans run one at a time in this loop, so they can never overlap.
+        let scans = async {
+            let mut last_started = None;
+            loop {
+                let now = Utc::now();
+                let next = add_jitter(
+                    schedule.next(last_started, now),
+                    chrono::Duration::seconds(jitter as i64),
+                );
+                health.write().await.next_scan_at = Some(next);
+                if next > now {
+                    info!("Next scan at {}", next.to_rfc3339());
+                    if let Ok(wait) = (next - now).to_std() {
+                        if !sleep_unless_interrupted(wait).await {
+                            break;
+                        }
+                    }
+                }
+                let started_at = Utc::now();
+                last_started = Some(started_at);
+                let result = run_scan(matches, &config, false).await;
+                let mut h = health.write().await;
+                h.started_at = Some(started_at);
+                h.finished_at = Some(Utc::now());
+                h.succeeded = Some(result.is_ok());
+                match result {
+                    Ok(results) => {
+                        info!(
+                            "Scan finished, {} of {} instances available",
+                            results.scan.succeeded, results.scan.instances
+                        );
+                        h.error = None;
+                        h.instances = Some(results.scan.instances);
+                        h.available = Some(results.scan.succeeded);
+                    }
+                    Err(e) => {
+                        error!("Scan failed, {}", e);
+                        h.error = Some(e.to_string());
+                    }
+                }
+                if Interrupt::listen().is_set() {
+                    break;
+                }
             }
+        };
+        match listen {
+            Some(addr) => tokio::select! {
+                result = serve_health(addr, health.clone()) => result?,
+                _ = scans => {}
+            },
+            None => scans.await,
+        }
+    } else if let Some(matches) = matches.subcommand_matches("landing") {
+        watch(matches, || landing_command(matches, &config)).await?;
+    } else if let Some(matches) = matches.subcommand_matches("metadata") {
+        let onion_address = matches.value_of("onion_address").unwrap();
+        let format = output_format(matches, &config, METADATA_FORMATS)?;
+        if let Err(e) = validate_v3(onion_address) {
+            error!("Malformed onion address {}: {}", onion_address, e);
+            std::process::exit(1);
+        }
+        let mut instance = SDDirectoryInstance::from_onion(onion_address);
+        if let Err(e) = instance
+            .get_metadata(&scan_options(matches, &config)?)
+            .await
+        {
+            error!(
+                "Failed to fetch metadata from {}, {}",
+                instance.onion_address, e
+            );
+            std::process::exit(EXIT_UNREACHABLE);
+        }
+        if matches.is_present("raw") {
+            match &instance.raw_metadata {
+                Some(raw) => println!("{}", serde_json::to_string_pretty(raw).unwrap()),
+                None => {
+                    return Err(
+                        format!("The metadata of {} is not JSON", instance.onion_address).into(),
+                    )
+                }
+            }
+        } else if let Some(m) = &instance.metadata {
+            print_metadata(m, format)?;
+        }
+    } else if let Some(matches) = matches.subcommand_matches("l10n") {
+        let input_file = matches.value_of("input_file").unwrap();
+        info!(
+            "Generating localization report from scan results at: {}",
+            input_file
+        );
+        let formats = if matches.is_present("matrix") {
+            MATRIX_FORMATS
         } else {
-            // TODO: Custom onions should be appended to, and by default
-            // directory entries are included (unless --directory=false)
-            info!("Fetching directory API at {}", DIRECTORY_URL);
-            instances = get_securedrop_directory().await?;
-        }
-        let format = matches.value_of("format").unwrap();
-        let full_instances = populate_metadata(instances).await?;
-        if format == "json" {
-            debug!("Will print results in JSON format");
-            let j = json!(full_instances);
-            println!("{}", serde_json::to_string_pretty(&j).unwrap());
-        } else if format == "pp" {
-            for i in full_instances {
-                println!("{:?}", i);
+            L10N_FORMATS
+        };
+        let format = output_format(matches, &config, formats)?;
+        let output = ReportOutput::from_matches(matches, &config)?;
+        let mut out = vec![];
+        let messages = report_messages(matches, &config)?;
+        let instances =
+            read_results(input_file).map_err(|e| format!("Failed to generate report, {}", e))?;
+        let instances = select_instances(matches, &config, instances)?;
+        let locales = generate_l10n_report(&instances);
+        if matches.is_present("matrix") {
+            let matrix = generate_locale_matrix(&instances);
+            if format == "csv" {
+                write_locale_matrix_csv(&matrix, &mut out)?;
+            } else if format == "json" {
+                writeln!(out, "{}", serde_json::to_string_pretty(&matrix).unwrap())?;
+            } else {
+                return Err(format!("Output format {} is unimplemented", format).into());
+            }
+        } else if let Some(missing) = matches.value_of("missing") {
+            let locale =
+                normalize_locale(missing).ok_or_else(|| format!("Invalid locale {}", missing))?;
+            let (_, site_order) = report_order(matches)?;
+            let sites = generate_missing_locale_report(&instances, &locale);
+            let mut groups = BTreeMap::new();
+            groups.insert(locale.to_owned(), sites);
+            let (_, sites) = sort_grouped_report(groups, GroupOrder::Name, site_order)
+                .pop()
+                .unwrap();
+            if format == "json" {
+                let j = json!({
+                    "locale": locale,
+                    "missing": sites,
+                    "unavailable": list_failures(&instances),
+                });
+                writeln!(out, "{}", serde_json::to_string_pretty(&j).unwrap())?;
+            } else {
+                let mut args = FluentArgs::new();
+                args.set("locale", display_locale(&locale));
+                let heading = messages.format("missing-locale", &args);
+                write_grouped_report(
+                    &mut out,
+                    "missing",
+                    &[(heading, sites)],
+                    &list_failures(&instances),
+                    format,
+                    &messages,
+                )?;
+            }
+        } else if matches.is_present("coverage") {
+            write_locale_coverage(
+                &mut out,
+                &generate_locale_coverage(&locales),
+                format,
+                &messages,
+            )?;
+        } else if matches.is_present("translations") {
+            let weblate = config
+                .weblate
+                .as_ref()
+                .ok_or("No [weblate] settings in the config file")?;
+            let entries = join_translations(&locales, &fetch_translations(weblate).await?);
+            if format == "json" {
+                writeln!(out, "{}", serde_json::to_string_pretty(&entries).unwrap())?;
+            } else if format == "text" {
+                write!(out, "{}", format_translations(&entries, &messages))?;
+            } else {
+                return Err(format!("Output format {} is unimplemented", format).into());
             }
         } else {
-            error!("Output format {} is unimplemented", format);
+            let (group_order, site_order) = report_order(matches)?;
+            let locales = sort_grouped_report(group_by_locale(&instances), group_order, site_order);
+            let invalid = list_invalid_locales(&instances);
+            if format == "json" {
+                let names: BTreeMap<_, _> = locales
+                    .iter()
+                    .filter_map(|(l, _)| Some((l.to_owned(), locale_name(l)?)))
+                    .collect();
+                let groups: BTreeMap<_, _> = locales.into_iter().collect();
+                let j = json!({
+                    "locales": groups,
+                    "names": names,
+                    "unavailable": list_failures(&instances),
+                    "invalid_locales": invalid,
+                });
+                writeln!(out, "{}", serde_json::to_string_pretty(&j).unwrap())?;
+            } else {
+                // Codes alone mean little to those not steeped in them.
+                let locales: Vec<_> = locales
+                    .into_iter()
+                    .map(|(l, sites)| (display_locale(&l), sites))
+                    .collect();
+                write_grouped_report(
+                    &mut out,
+                    "locales",
+                    &locales,
+                    &list_failures(&instances),
+                    format,
+                    &messages,
+                )?;
+                if format == "text" {
+                    write!(out, "{}", format_invalid_locales(&invalid, &messages))?;
+                }
+            }
         }
-    } else if let Some(ref matches) = matches.subcommand_matches("l10n") {
+        output.write(&out)?;
+    } else if let Some(matches) = matches.subcommand_matches("versions") {
         let input_file = matches.value_of("input_file").unwrap();
         info!(
-            "Generating localization report from scan results at: {}",
+            "Generating versions report from scan results at: {}",
+            input_file
+        );
+        let format = output_format(matches, &config, REPORT_FORMATS)?;
+        let output = ReportOutput::from_matches(matches, &config)?;
+        let mut out = vec![];
+        let instances =
+            read_results(input_file).map_err(|e| format!("Failed to generate report, {}", e))?;
+        let instances = select_instances(matches, &config, instances)?;
+        let versions = generate_versions_report(&instances);
+        let versions: Vec<_> = versions.into_iter().collect();
+        write_grouped_report(
+            &mut out,
+            "versions",
+            &versions,
+            &list_failures(&instances),
+            format,
+            &report_messages(matches, &config)?,
+        )?;
+        output.write(&out)?;
+    } else if let Some(matches) = matches.subcommand_matches("os") {
+        let input_file = matches.value_of("input_file").unwrap();
+        info!(
+            "Generating server OS report from scan results at: {}",
             input_file
         );
-        match generate_l10n_report(input_file).await {
-            Ok(r) => println!("{}", r),
-            Err(e) => {
-                error!("Failed to generated report, {}", e);
+        let format = output_format(matches, &config, REPORT_FORMATS)?;
+        let output = ReportOutput::from_matches(matches, &config)?;
+        let mut out = vec![];
+        let instances =
+            read_results(input_file).map_err(|e| format!("Failed to generate report, {}", e))?;
+        let instances = select_instances(matches, &config, instances)?;
+        let releases = generate_os_report(&instances);
+        write_os_report(
+            &mut out,
+            &releases,
+            &list_failures(&instances),
+            format,
+            &report_messages(matches, &config)?,
+        )?;
+        output.write(&out)?;
+    } else if let Some(matches) = matches.subcommand_matches("tags") {
+        let input_file = matches.value_of("input_file").unwrap();
+        let format = output_format(matches, &config, REPORT_FORMATS)?;
+        let output = ReportOutput::from_matches(matches, &config)?;
+        let mut out = vec![];
+        let filter = instance_filter(matches, &config)?;
+        if filter.tags.is_empty() {
+            warn!("No [tags] in the config file, so no site has a tag");
+        }
+        let mut instances = filter.apply(read_results(input_file)?);
+        let mut tags = filter.tags;
+        if let Some(anonymizer) = anonymizer(matches, &config)? {
+            anonymizer.anonymize_instances(&mut instances);
+            tags = anonymizer.anonymize_tags(&tags);
+        }
+        let tags: Vec<_> = generate_tags_report(&instances, &tags)
+            .into_iter()
+            .collect();
+        write_grouped_report(
+            &mut out,
+            "tags",
+            &tags,
+            &list_failures(&instances),
+            format,
+            &report_messages(matches, &config)?,
+        )?;
+        output.write(&out)?;
+    } else if let Some(matches) = matches.subcommand_matches("outdated") {
+        let input_file = matches.value_of("input_file").unwrap();
+        info!(
+            "Generating outdated version report from scan results at: {}",
+            input_file
+        );
+        let format = output_format(matches, &config, REPORT_FORMATS)?;
+        let output = ReportOutput::from_matches(matches, &config)?;
+        let mut out = vec![];
+        let instances = select_instances(matches, &config, read_results(input_file)?)?;
+        let releases = match matches
+            .value_of("latest_version")
+            .or(config.latest_version.as_deref())
+        {
+            Some(latest) => releases_up_to(latest)?,
+            None => match fetch_releases(matches.value_of("releases_url").unwrap()).await {
+                Ok(releases) if !releases.is_empty() => releases,
+                result => {
+                    if let Err(e) = result {
+                        warn!("Failed to fetch SecureDrop releases, {}", e);
+                    }
+                    warn!(
+                        "Comparing against the releases known to this build, up to {}",
+                        KNOWN_RELEASES[0]
+                    );
+                    KNOWN_RELEASES.iter().map(|r| r.to_string()).collect()
+                }
+            },
+        };
+        let report = generate_outdated_report(&instances, &releases);
+        let failures = list_failures(&instances);
+        let messages = &report_messages(matches, &config)?;
+        if format == "text" {
+            writeln!(
+                out,
+                "{}{}",
+                format_outdated_report(&report, use_color(matches)),
+                format_failures(&failures, messages)
+            )?;
+        } else if format == "markdown" {
+            writeln!(
+                out,
+                "{}{}",
+                markdown::format_outdated_report(&report),
+                markdown::format_failures(&failures, messages)
+            )?;
+        } else if format == "json" {
+            let j = json!({ "outdated": report, "unavailable": failures });
+            writeln!(out, "{}", serde_json::to_string_pretty(&j).unwrap())?;
+        } else {
+            return Err(format!("Output format {} is unimplemented", format).into());
+        }
+        output.write(&out)?;
+    } else if let Some(matches) = matches.subcommand_matches("keys") {
+        let input_file = matches.value_of("input_file").unwrap();
+        info!(
+            "Generating journalist key report from scan results at: {}",
+            input_file
+        );
+        let format = output_format(matches, &config, TEXT_FORMATS)?;
+        let instances = select_instances(matches, &config, read_results(input_file)?)?;
+        let warning = match matches.value_of("warn_days") {
+            Some(days) => chrono::Duration::days(days.parse()?),
+            None => chrono::Duration::days(EXPIRY_WARNING_DAYS),
+        };
+        let now = Utc::now();
+        let report = generate_key_report(&instances, warning, now);
+        if format == "text" {
+            println!("{}", format_key_report(&report, now, use_color(matches)));
+        } else if format == "json" {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        } else {
+            return Err(format!("Output format {} is unimplemented", format).into());
+        }
+    } else if let Some(matches) = matches.subcommand_matches("onion-names") {
+        let format = output_format(matches, &config, TEXT_FORMATS)?;
+        let instances = match matches.value_of("input_file") {
+            Some(input_file) => instance_filter(matches, &config)?.apply(read_results(input_file)?),
+            None => collect_instances(matches, &config, None).await?,
+        };
+        let names = fetch_onion_names(matches.value_of("rulesets_url").unwrap()).await?;
+        let report = verify_onion_names(&instances, &names);
+        if format == "text" {
+            println!("{}", format_onion_name_report(&report));
+        } else if format == "json" {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        } else {
+            return Err(format!("Output format {} is unimplemented", format).into());
+        }
+    } else if let Some(matches) = matches.subcommand_matches("show") {
+        let format = output_format(matches, &config, TEXT_FORMATS)?;
+        let onion = normalize_onion(matches.value_of("onion_address").unwrap()).to_lowercase();
+        let history = match matches.value_of("db").or(config.db.as_deref()) {
+            Some(path) => Some(db::open(path)?),
+            None => None,
+        };
+        let (scanned_at, instance) = match (matches.value_of("input_file"), &history) {
+            (Some(input_file), _) => (
+                None,
+                read_results(input_file)?
+                    .into_iter()
+                    .find(|i| normalize_onion(&i.onion_address).to_lowercase() == onion),
+            ),
+            (None, Some(history)) => match history.latest_result(&onion)? {
+                Some((scanned_at, instance)) => (Some(scanned_at), Some(instance)),
+                None => (None, None),
+            },
+            (None, None) => {
+                error!("A previous scan or a history database is required, via --db or the config file");
+                std::process::exit(1);
+            }
+        };
+        let instance = instance.ok_or_else(|| format!("No results for {}", onion))?;
+        let history = match &history {
+            Some(history) => {
+                let windows = uptime_windows(matches)?;
+                Some(instance_history(
+                    history.as_ref(),
+                    &instance.onion_address,
+                    &windows,
+                    Utc::now(),
+                )?)
+            }
+            None => None,
+        };
+        let mut detail = InstanceDetail::new(instance, scanned_at, history);
+        if let Some(anonymizer) = anonymizer(matches, &config)? {
+            anonymizer.anonymize_instance(&mut detail.instance);
+            anonymizer.anonymize_findings(&mut detail.findings);
+        }
+        if format == "text" {
+            print!("{}", format_instance_detail(&detail));
+        } else if format == "json" {
+            println!("{}", serde_json::to_string_pretty(&detail).unwrap());
+        } else {
+            return Err(format!("Output format {} is unimplemented", format).into());
+        }
+    } else if let Some(matches) = matches.subcommand_matches("uptime") {
+        let format = output_format(matches, &config, REPORT_FORMATS)?;
+        let path = match matches.value_of("db").or(config.db.as_deref()) {
+            Some(path) => path,
+            None => {
+                error!("A history database is required, via --db or the config file");
+                std::process::exit(1);
+            }
+        };
+        let windows = uptime_windows(matches)?;
+        let now = Utc::now();
+        let longest = windows
+            .iter()
+            .map(|(_, d)| *d)
+            .max()
+            .unwrap_or_else(chrono::Duration::zero);
+        let filter = instance_filter(matches, &config)?;
+        let mut outcomes = db::open(path)?.outcomes_since(now - longest)?;
+        // The history doesn't record locales, so locale filters are ignored.
+        outcomes.retain(|o| filter.admits(&o.title, &o.onion_address, None));
+        if let Some(anonymizer) = anonymizer(matches, &config)? {
+            anonymizer.anonymize_outcomes(&mut outcomes);
+        }
+        let entries = generate_uptime_report(&outcomes, &windows, now);
+        if format == "text" {
+            print!("{}", format_uptime_report(&entries));
+        } else if format == "markdown" {
+            print!("{}", markdown::format_uptime_report(&entries));
+        } else if format == "json" {
+            println!("{}", serde_json::to_string_pretty(&entries).unwrap());
+        } else {
+            return Err(format!("Output format {} is unimplemented", format).into());
+        }
+    } else if let Some(matches) = matches.subcommand_matches("adoption") {
+        let format = output_format(matches, &config, ADOPTION_FORMATS)?;
+        let path = match matches.value_of("db").or(config.db.as_deref()) {
+            Some(path) => path,
+            None => {
+                error!("A history database is required, via --db or the config file");
+                std::process::exit(1);
             }
+        };
+        let now = Utc::now();
+        let since = now - parse_window(matches.value_of("window").unwrap())?;
+        let interval = parse_window(matches.value_of("interval").unwrap())?;
+        let filter = instance_filter(matches, &config)?;
+        let mut outcomes = db::open(path)?.versions_since(since)?;
+        // As for uptime, locale filters are ignored.
+        outcomes.retain(|o| filter.admits(&o.title, &o.onion_address, None));
+        let report = generate_adoption_report(&outcomes, since, interval, now);
+        if format == "text" {
+            print!("{}", format_adoption_report(&report));
+        } else if format == "csv" {
+            write_adoption_csv(&report, std::io::stdout())?;
+        } else if format == "json" {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        } else {
+            return Err(format!("Output format {} is unimplemented", format).into());
+        }
+    } else if let Some(matches) = matches.subcommand_matches("chart") {
+        let path = match matches.value_of("db").or(config.db.as_deref()) {
+            Some(path) => path,
+            None => {
+                error!("A history database is required, via --db or the config file");
+                std::process::exit(1);
+            }
+        };
+        print!("{}", render_chart(matches, &config, path)?);
+    } else if let Some(matches) = matches.subcommand_matches("verify") {
+        let file = Path::new(matches.value_of("file").unwrap());
+        let signature = match matches.value_of("signature") {
+            Some(signature) => PathBuf::from(signature),
+            None => signature_path(file),
+        };
+        let public_key = Path::new(matches.value_of("public_key").unwrap());
+        let comment = verify_file(file, &signature, public_key)?;
+        println!("Signature verified, trusted comment: {}", comment);
+    } else if let Some(matches) = matches.subcommand_matches("diff") {
+        let format = output_format(matches, &config, REPORT_FORMATS)?;
+        let (old, new) = if let Some(path) = matches.value_of("db").or(config.db.as_deref()) {
+            let history = db::open(path)?;
+            let (old_id, new_id) = match (matches.value_of("old"), matches.value_of("new")) {
+                (Some(old), Some(new)) => (old.parse()?, new.parse()?),
+                _ => match history.latest_scan_ids(2)?.as_slice() {
+                    [new, old] => (*old, *new),
+                    _ => {
+                        error!("History database at {} has fewer than two scans", path);
+                        std::process::exit(1);
+                    }
+                },
+            };
+            info!("Comparing scans {} and {} from {}", old_id, new_id, path);
+            (history.load_scan(old_id)?, history.load_scan(new_id)?)
+        } else {
+            match (matches.value_of("old"), matches.value_of("new")) {
+                (Some(old), Some(new)) => {
+                    info!("Comparing scan results at {} and {}", old, new);
+                    (read_results(old)?, read_results(new)?)
+                }
+                _ => {
+                    error!("Two scan result files are required without --db");
+                    std::process::exit(1);
+                }
+            }
+        };
+        let diff = ScanDiff::between(
+            &select_instances(matches, &config, old)?,
+            &select_instances(matches, &config, new)?,
+        );
+        if format == "text" {
+            println!("{}", format_diff(&diff));
+        } else if format == "markdown" {
+            println!("{}", markdown::format_diff(&diff));
+        } else if format == "json" {
+            println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+        } else {
+            return Err(format!("Output format {} is unimplemented", format).into());
         }
     }
+    flush_telemetry();
     Ok(())
 }
@@ -0,0 +1,231 @@
+use crate::diff::ScanDiff;
+use crate::findings::{v2_summary, Finding};
+use crate::i18n::Messages;
+use crate::releases::OutdatedReport;
+use crate::report::{format_latency_summary, Failure, LocaleCoverage, StatusEntry};
+use crate::uptime::{format_uptime, UptimeEntry};
+use crate::SDDirectoryInstance;
+
+/// Escapes text for use inside a Markdown table cell.
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Renders a report grouping site titles by some key as a heading per
+/// group, each followed by a bulleted list of sites.
+pub fn format_grouped_report<'a>(
+    groups: impl IntoIterator<Item = (&'a String, &'a Vec<String>)>,
+) -> String {
+    let mut report = String::from("");
+    for (key, sites) in groups {
+        report += &format!("### {} ({})\n\n", key, sites.len());
+        for site in sites {
+            report += &format!("- {}\n", site);
+        }
+        report += "\n";
+    }
+    report
+}
+
+/// Renders the list of failed instances.
+pub fn format_failures(failures: &[Failure], messages: &Messages) -> String {
+    let mut report = format!("### {}\n\n", messages.count("unavailable", failures.len()));
+    for f in failures {
+        report += &format!(
+            "- {} (`{}`), {}: {}\n",
+            f.title, f.onion_address, f.kind, f.error
+        );
+    }
+    report
+}
+
+/// Renders the availability report as a table.
+pub fn format_status_report(entries: &[StatusEntry]) -> String {
+    let mut report = String::from(
+        "| Status | Title | Onion address | HTTP status | Latency (ms) | Failure | Error |\n\
+         | --- | --- | --- | --- | --- | --- | --- |\n",
+    );
+    for e in entries {
+        report += &format!(
+            "| {} | {} | `{}` | {} | {} | {} | {} |\n",
+            if e.available { "UP" } else { "DOWN" },
+            cell(&e.title),
+            e.onion_address,
+            e.http_status.map(|s| s.to_string()).unwrap_or_default(),
+            e.latency_ms.map(|l| l.to_string()).unwrap_or_default(),
+            e.failure.map(|f| f.to_string()).unwrap_or_default(),
+            cell(&match (e.error.as_deref(), e.key_matches) {
+                (Some(error), _) => error.to_owned(),
+                (None, Some(false)) => "Journalist key mismatch".to_owned(),
+                (None, _) => "".to_owned(),
+            })
+        );
+    }
+    let up = entries.iter().filter(|e| e.available).count();
+    report += &format!("\n{} of {} instances available\n", up, entries.len());
+    let summary = format_latency_summary(entries);
+    if !summary.is_empty() {
+        report += &format!("\n{}", summary.replace('\n', "  \n"));
+    }
+    report
+}
+
+/// Renders the results of a scan as a table of instances.
+pub fn format_scan_results(instances: &[SDDirectoryInstance]) -> String {
+    let mut report = String::from(
+        "| Title | Onion address | SecureDrop version | Server OS | Languages |\n\
+         | --- | --- | --- | --- | --- |\n",
+    );
+    for i in instances {
+        match &i.metadata {
+            Some(m) => {
+                report += &format!(
+                    "| {} | `{}` | {} | {} | {} |\n",
+                    cell(&i.title),
+                    i.onion_address,
+                    cell(&m.sd_version),
+                    cell(&m.server_os),
+                    cell(&m.supported_languages.join(", "))
+                )
+            }
+            None => {
+                report += &format!(
+                    "| {} | `{}` | unavailable | | |\n",
+                    cell(&i.title),
+                    i.onion_address
+                )
+            }
+        }
+    }
+    report
+}
+
+/// Renders the differences between two scans.
+pub fn format_diff(diff: &ScanDiff) -> String {
+    let mut report = String::from("");
+    for (heading, entries) in &[
+        ("Appeared", &diff.appeared),
+        ("Disappeared", &diff.disappeared),
+    ] {
+        report += &format!("### {} ({})\n\n", heading, entries.len());
+        for e in entries.iter() {
+            report += &format!("- {} (`{}`)\n", e.title, e.onion_address);
+        }
+        report += "\n";
+    }
+    for (heading, changes) in &[
+        ("Version changed", &diff.version_changes),
+        ("Server OS changed", &diff.os_changes),
+        ("GPG fingerprint rotated", &diff.fingerprint_changes),
+    ] {
+        report += &format!("### {} ({})\n\n", heading, changes.len());
+        for c in changes.iter() {
+            report += &format!(
+                "- {} (`{}`): `{}` → `{}`\n",
+                c.title, c.onion_address, c.old, c.new
+            );
+        }
+        report += "\n";
+    }
+    report
+}
+
+/// Renders the uptime report as a table, with a column per window.
+pub fn format_uptime_report(entries: &[UptimeEntry]) -> String {
+    let windows: Vec<&str> = match entries.first() {
+        Some(first) => first.windows.iter().map(|w| w.window.as_str()).collect(),
+        None => return String::from(""),
+    };
+    let mut report = format!(
+        "| Title | Onion address | {} |\n| --- | --- |{}\n",
+        windows.join(" | "),
+        " --- |".repeat(windows.len())
+    );
+    for e in entries {
+        let uptimes: Vec<String> = e.windows.iter().map(|w| format_uptime(w.uptime)).collect();
+        report += &format!(
+            "| {} | `{}` | {} |\n",
+            cell(&e.title),
+            e.onion_address,
+            uptimes.join(" | ")
+        );
+    }
+    report
+}
+
+/// Renders the outdated-version report as a heading per number of
+/// releases behind, each followed by a bulleted list of sites.
+pub fn format_outdated_report(report: &OutdatedReport) -> String {
+    let mut text = format!("Latest release: **{}**\n\n", report.latest);
+    for (n, sites) in &report.behind {
+        text += &format!(
+            "### {} release{} behind ({})\n\n",
+            n,
+            if *n == 1 { "" } else { "s" },
+            sites.len()
+        );
+        for site in sites {
+            text += &format!("- {}\n", site);
+        }
+        text += "\n";
+    }
+    if !report.unknown.is_empty() {
+        text += &format!("### Unknown version ({})\n\n", report.unknown.len());
+        for site in &report.unknown {
+            text += &format!("- {}\n", site);
+        }
+        text += "\n";
+    }
+    let behind: usize = report.behind.values().map(|sites| sites.len()).sum();
+    text += &format!(
+        "{} of {} available instances are behind the latest release\n\n",
+        behind,
+        behind + report.up_to_date.len() + report.unknown.len()
+    );
+    text
+}
+
+/// Renders the locale coverage report, with a table of shipped locales.
+pub fn format_locale_coverage(coverage: &LocaleCoverage, messages: &Messages) -> String {
+    let mut report = format!(
+        "| {} | {} |\n| --- | --- |\n",
+        messages.get("shipped-locale-column"),
+        messages.get("instances-column")
+    );
+    for (locale, n) in &coverage.shipped {
+        report += &format!("| `{}` | {} |\n", locale, n);
+    }
+    report += &format!(
+        "\n### {}\n\n",
+        messages.count("unused-locales", coverage.unused.len())
+    );
+    for locale in &coverage.unused {
+        report += &format!("- `{}`\n", locale);
+    }
+    report += &format!(
+        "\n### {}\n\n",
+        messages.count("unofficial-locales", coverage.unofficial.len())
+    );
+    for (locale, sites) in &coverage.unofficial {
+        report += &format!("- `{}` ({}): {}\n", locale, sites.len(), sites.join(", "));
+    }
+    report
+}
+
+/// Renders the findings about scanned instances, followed by a count of
+/// instances still advertising v2 addresses.
+pub fn format_findings(findings: &[Finding]) -> String {
+    let mut report = String::from("");
+    if !findings.is_empty() {
+        report += &format!("### Findings ({})\n\n", findings.len());
+        for f in findings {
+            report += &format!(
+                "- **{}** {} (`{}`): {}\n",
+                f.severity, f.title, f.onion_address, f.message
+            );
+        }
+        report += "\n";
+    }
+    report += &format!("{}\n", v2_summary(findings));
+    report
+}
@@ -0,0 +1,121 @@
+use crate::config::MastodonConfig;
+use crate::db::Storage;
+use crate::locale::instance_locales;
+use crate::SDDirectoryInstance;
+use chrono::{DateTime, Datelike, Duration, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+/// The longest status Mastodon accepts by default.
+const MAX_STATUS_CHARS: usize = 500;
+
+/// The start of the week `at` falls in: the Monday before, at midnight UTC.
+pub fn week_start(at: DateTime<Utc>) -> DateTime<Utc> {
+    let monday = at.naive_utc().date() - Duration::days(at.weekday().num_days_from_monday() as i64);
+    DateTime::from_utc(monday.and_hms(0, 0, 0), Utc)
+}
+
+/// Whether a scan at `scanned_at` should post the weekly summary: only the
+/// first scan of each week does, so the summary is posted once a week
+/// however often sites are scanned.
+pub fn summary_due(previous_at: DateTime<Utc>, scanned_at: DateTime<Utc>) -> bool {
+    previous_at < week_start(scanned_at)
+}
+
+/// The week's numbers: how many instances are up, which were first
+/// listed in the last week, and how many locales the available ones
+/// support between them.
+#[derive(Debug)]
+pub struct WeeklySummary {
+    pub available: usize,
+    pub total: usize,
+    /// The titles of instances first listed in the last week, or `None`
+    /// if the history doesn't go back a week.
+    pub new: Option<Vec<String>>,
+    pub locales: usize,
+}
+
+impl WeeklySummary {
+    /// Summarizes this scan's `instances`, against the history (which must
+    /// already include this scan) for new instances.
+    pub fn new(
+        history: &dyn Storage,
+        instances: &[SDDirectoryInstance],
+        scanned_at: DateTime<Utc>,
+    ) -> Result<WeeklySummary, Box<dyn Error>> {
+        let week_ago = scanned_at - Duration::weeks(1);
+        let mut first_listed: BTreeMap<String, DateTime<Utc>> = BTreeMap::new();
+        for l in history.listings()? {
+            first_listed.entry(l.onion_address).or_insert(l.scanned_at);
+        }
+        let new = if first_listed.values().any(|t| *t < week_ago) {
+            Some(
+                instances
+                    .iter()
+                    .filter(|i| {
+                        first_listed
+                            .get(&i.onion_address)
+                            .is_none_or(|t| *t >= week_ago)
+                    })
+                    .map(|i| i.title.to_owned())
+                    .collect(),
+            )
+        } else {
+            None
+        };
+        let locales: BTreeSet<String> = instances
+            .iter()
+            .filter(|i| i.available)
+            .flat_map(instance_locales)
+            .collect();
+        Ok(WeeklySummary {
+            available: instances.iter().filter(|i| i.available).count(),
+            total: instances.len(),
+            new,
+            locales: locales.len(),
+        })
+    }
+
+    /// The summary as a status, naming the new instances if they fit.
+    pub fn status(&self) -> String {
+        let mut status = format!(
+            "SecureDrop this week: {} of {} instances in the directory are up, offering their source interface in {} languages.",
+            self.available, self.total, self.locales
+        );
+        match self.new.as_deref() {
+            Some([]) | None => {}
+            Some(new) => {
+                let count = format!(" {} new this week.", new.len());
+                let named = format!(" New this week: {}.", new.join(", "));
+                let extra = if (status.clone() + &named).chars().count() <= MAX_STATUS_CHARS {
+                    named
+                } else {
+                    count
+                };
+                status += &extra;
+            }
+        }
+        status
+    }
+}
+
+/// Posts a status to the configured Mastodon account. The key keeps a
+/// retried request from posting twice.
+pub async fn post_status(
+    config: &MastodonConfig,
+    status: &str,
+    idempotency_key: &str,
+) -> Result<(), Box<dyn Error>> {
+    let url = format!("{}/api/v1/statuses", config.instance.trim_end_matches('/'));
+    let visibility = config.visibility.as_deref().unwrap_or("public");
+    reqwest::Client::new()
+        .post(&url)
+        .bearer_auth(&config.access_token)
+        .header("Idempotency-Key", idempotency_key)
+        .form(&[("status", status), ("visibility", visibility)])
+        .send()
+        .await?
+        .error_for_status()?;
+    info!("Posted the weekly summary to {}", config.instance);
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use crate::{SDDirectoryInstance, ScanSummary};
+use chrono::{DateTime, Utc};
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Escapes a value for use inside a Prometheus label.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Renders the results of a scan in the Prometheus text exposition format,
+/// with one series per instance for each gauge, followed by gauges about
+/// the scan as a whole.
+pub fn render_metrics(
+    instances: &[SDDirectoryInstance],
+    scan: &ScanSummary,
+    scraped_at: DateTime<Utc>,
+) -> String {
+    let mut up = String::from(
+        "# HELP sdstatus_up Whether the instance's metadata could be retrieved.\n\
+         # TYPE sdstatus_up gauge\n",
+    );
+    let mut info = String::from(
+        "# HELP sdstatus_version_info The SecureDrop release and server OS reported by the instance.\n\
+         # TYPE sdstatus_version_info gauge\n",
+    );
+    let mut latency = String::from(
+        "# HELP sdstatus_latency_seconds Time taken for the instance's metadata response to arrive.\n\
+         # TYPE sdstatus_latency_seconds gauge\n",
+    );
+    let mut last_scrape = String::from(
+        "# HELP sdstatus_last_scrape_timestamp_seconds When the instance was last scanned.\n\
+         # TYPE sdstatus_last_scrape_timestamp_seconds gauge\n",
+    );
+    for i in instances {
+        let labels = format!(
+            "onion_address=\"{}\",title=\"{}\"",
+            escape_label(&i.onion_address),
+            escape_label(&i.title)
+        );
+        up += &format!("sdstatus_up{{{}}} {}\n", labels, i.available as u8);
+        if let Some(m) = &i.metadata {
+            info += &format!(
+                "sdstatus_version_info{{{},sd_version=\"{}\",server_os=\"{}\"}} 1\n",
+                labels,
+                escape_label(&m.sd_version),
+                escape_label(&m.server_os)
+            );
+        }
+        if let Some(ms) = i.latency_ms {
+            latency += &format!(
+                "sdstatus_latency_seconds{{{}}} {}\n",
+                labels,
+                ms as f64 / 1000.0
+            );
+        }
+        last_scrape += &format!(
+            "sdstatus_last_scrape_timestamp_seconds{{{}}} {}\n",
+            labels,
+            scraped_at.timestamp()
+        );
+    }
+    up + &info + &latency + &last_scrape + &render_scan_metrics(scan)
+}
+
+/// Renders the gauges describing the scan itself.
+fn render_scan_metrics(scan: &ScanSummary) -> String {
+    let duration = (scan.finished_at - scan.started_at).num_milliseconds() as f64 / 1000.0;
+    let mut text = format!(
+        "# HELP sdstatus_scan_duration_seconds How long the last scan took.\n\
+         # TYPE sdstatus_scan_duration_seconds gauge\n\
+         sdstatus_scan_duration_seconds {}\n\
+         # HELP sdstatus_scan_instances Instances scanned, by outcome.\n\
+         # TYPE sdstatus_scan_instances gauge\n\
+         sdstatus_scan_instances{{outcome=\"succeeded\"}} {}\n\
+         sdstatus_scan_instances{{outcome=\"failed\"}} {}\n\
+         # HELP sdstatus_scan_retries Metadata fetches retried in the last scan.\n\
+         # TYPE sdstatus_scan_retries gauge\n\
+         sdstatus_scan_retries {}\n",
+        duration, scan.succeeded, scan.failed, scan.retries
+    );
+    if let Some(ms) = scan.tor_bootstrap_ms {
+        text += &format!(
+            "# HELP sdstatus_tor_bootstrap_seconds Time until the first instance responded in the last scan.\n\
+             # TYPE sdstatus_tor_bootstrap_seconds gauge\n\
+             sdstatus_tor_bootstrap_seconds {}\n",
+            ms as f64 / 1000.0
+        );
+    }
+    text
+}
+
+/// Serves the most recently rendered metrics at `/metrics` until the
+/// server fails.
+pub async fn serve_metrics(
+    addr: SocketAddr,
+    metrics: Arc<RwLock<String>>,
+) -> Result<(), hyper::Error> {
+    let make_svc = make_service_fn(move |_conn| {
+        let metrics = metrics.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let metrics = metrics.clone();
+                async move {
+                    let response = if req.uri().path() == "/metrics" {
+                        Response::builder()
+                            .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+                            .body(Body::from(metrics.read().await.clone()))
+                    } else {
+                        Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Body::empty())
+                    };
+                    Ok::<_, Infallible>(response.unwrap())
+                }
+            }))
+        }
+    });
+    info!("Serving metrics at http://{}/metrics", addr);
+    Server::bind(&addr).serve(make_svc).await
+}
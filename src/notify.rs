@@ -0,0 +1,349 @@
+use crate::config::{EmailConfig, MatrixConfig, NotificationsConfig, SlackConfig};
+use crate::db::{KnownAddress, KnownFingerprint, Storage};
+use crate::{normalize_onion, SDDirectoryInstance};
+use chrono::{DateTime, Duration, Utc};
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// The kind of state transition an event reports.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// The instance was available in the previous scan, but not this one.
+    Down,
+    /// The instance was unavailable in the previous scan, but is now back.
+    Up,
+    /// The instance reports a different SecureDrop release.
+    VersionChanged,
+    /// The instance advertises a different journalist key fingerprint
+    /// than it last did, even if that was before an outage.
+    KeyRotated,
+    /// The instance has now been unavailable for longer than the
+    /// configured number of hours.
+    Outage,
+    /// The directory lists the instance's title with another Onion
+    /// address than it last did.
+    OnionChanged,
+}
+
+/// A change in an instance's state between two consecutive scans.
+#[derive(Serialize, Debug, Clone)]
+pub struct Event {
+    pub kind: EventKind,
+    pub title: String,
+    pub onion_address: String,
+    /// The previous and new values, for version, key and Onion address
+    /// changes.
+    pub old: Option<String>,
+    pub new: Option<String>,
+    /// Why the instance could not be scanned, for `down` events.
+    pub error: Option<String>,
+    /// When the instance was first found unavailable, for `outage` events.
+    pub down_since: Option<DateTime<Utc>>,
+    pub scanned_at: DateTime<Utc>,
+}
+
+/// Compares a scan against the previous one, returning an event for each
+/// state transition. Instances missing from either scan are ignored, so
+/// that the first scan of an instance never fires. Key rotations are
+/// detected against `known`, the fingerprints each instance last
+/// advertised before this scan (see `Storage::known_fingerprints`).
+pub fn detect_events(
+    previous: &[SDDirectoryInstance],
+    current: &[SDDirectoryInstance],
+    known: &BTreeMap<String, KnownFingerprint>,
+    scanned_at: DateTime<Utc>,
+) -> Vec<Event> {
+    let previous: BTreeMap<&str, &SDDirectoryInstance> = previous
+        .iter()
+        .map(|i| (i.onion_address.as_str(), i))
+        .collect();
+    let mut events = vec![];
+    for i in current {
+        let event = |kind, old: Option<&str>, new: Option<&str>| Event {
+            kind,
+            title: i.title.to_owned(),
+            onion_address: i.onion_address.to_owned(),
+            old: old.map(|o| o.to_owned()),
+            new: new.map(|n| n.to_owned()),
+            error: i.error.to_owned(),
+            down_since: None,
+            scanned_at,
+        };
+        if let (Some(m), Some(k)) = (&i.metadata, known.get(&i.onion_address)) {
+            if k.rotated_to(&m.gpg_fpr) {
+                events.push(event(
+                    EventKind::KeyRotated,
+                    Some(&k.fingerprint),
+                    Some(&m.gpg_fpr),
+                ));
+            }
+        }
+        let p = match previous.get(i.onion_address.as_str()) {
+            Some(p) => p,
+            None => continue,
+        };
+        if p.available && !i.available {
+            events.push(event(EventKind::Down, None, None));
+        } else if !p.available && i.available {
+            events.push(event(EventKind::Up, None, None));
+        }
+        if let (Some(pm), Some(m)) = (&p.metadata, &i.metadata) {
+            if pm.sd_version != m.sd_version {
+                events.push(event(
+                    EventKind::VersionChanged,
+                    Some(&pm.sd_version),
+                    Some(&m.sd_version),
+                ));
+            }
+        }
+    }
+    events
+}
+
+/// Returns an `outage` event for each instance whose unavailability
+/// crossed `threshold` between the previous scan (at `previous_scan_at`)
+/// and this one, so each outage is only reported once. This scan must
+/// already be recorded in `history`.
+pub fn detect_outages(
+    history: &dyn Storage,
+    current: &[SDDirectoryInstance],
+    previous_scan_at: DateTime<Utc>,
+    scanned_at: DateTime<Utc>,
+    threshold: Duration,
+) -> Result<Vec<Event>, Box<dyn Error>> {
+    let mut events = vec![];
+    for i in current.iter().filter(|i| !i.available) {
+        if let Some(down_since) = history.down_since(&i.onion_address)? {
+            let crossed_at = down_since + threshold;
+            if crossed_at > previous_scan_at && crossed_at <= scanned_at {
+                events.push(Event {
+                    kind: EventKind::Outage,
+                    title: i.title.to_owned(),
+                    onion_address: i.onion_address.to_owned(),
+                    old: None,
+                    new: None,
+                    error: i.error.to_owned(),
+                    down_since: Some(down_since),
+                    scanned_at,
+                });
+            }
+        }
+    }
+    Ok(events)
+}
+
+/// Returns an `onion_changed` event for each instance listed under a title
+/// that was last listed with another Onion address, per `known` (see
+/// `Storage::known_addresses`). Titles listed more than once in this scan
+/// are ignored, as they are likely distinct sites.
+pub fn detect_onion_changes(
+    known: &BTreeMap<String, KnownAddress>,
+    current: &[SDDirectoryInstance],
+    scanned_at: DateTime<Utc>,
+) -> Vec<Event> {
+    let mut listings: BTreeMap<&str, usize> = BTreeMap::new();
+    for i in current {
+        *listings.entry(&i.title).or_default() += 1;
+    }
+    let mut events = vec![];
+    for i in current.iter().filter(|i| listings[i.title.as_str()] == 1) {
+        let k = match known.get(&i.title) {
+            Some(k) => k,
+            None => continue,
+        };
+        let old = normalize_onion(&k.onion_address).to_lowercase();
+        if old != normalize_onion(&i.onion_address).to_lowercase() {
+            events.push(Event {
+                kind: EventKind::OnionChanged,
+                title: i.title.to_owned(),
+                onion_address: i.onion_address.to_owned(),
+                old: Some(k.onion_address.to_owned()),
+                new: Some(i.onion_address.to_owned()),
+                error: None,
+                down_since: None,
+                scanned_at,
+            });
+        }
+    }
+    events
+}
+
+/// The message sent to chat channels when no template is configured.
+pub const DEFAULT_MESSAGE: &str = "{% if kind == \"down\" %}{{ title }} ({{ onion_address }}) is down: {{ error }}\
+{% elif kind == \"up\" %}{{ title }} ({{ onion_address }}) is back up\
+{% elif kind == \"version_changed\" %}{{ title }} ({{ onion_address }}) changed from SecureDrop {{ old }} to {{ new }}\
+{% elif kind == \"key_rotated\" %}{{ title }} ({{ onion_address }}) rotated its GPG key from {{ old }} to {{ new }}\
+{% elif kind == \"onion_changed\" %}{{ title }} changed its Onion address from {{ old }} to {{ new }}\
+{% else %}{{ title }} ({{ onion_address }}) has been down since {{ down_since }}: {{ error }}{% endif %}";
+
+/// Renders the chat message for an event with a Tera template, which can
+/// use any of the event's fields.
+pub fn render_message(template: &str, event: &Event) -> Result<String, tera::Error> {
+    let context = tera::Context::from_serialize(event)?;
+    tera::Tera::one_off(template, &context, false)
+}
+
+/// Sends each event to every configured notification channel. Failing to
+/// notify is logged, but never fails the scan that produced the events.
+pub async fn send_notifications(config: &NotificationsConfig, events: &[Event]) {
+    if events.is_empty() {
+        return;
+    }
+    let client = reqwest::Client::new();
+    for url in &config.webhooks {
+        for event in events {
+            debug!(
+                "Posting {:?} event for {} to {}",
+                event.kind, event.title, url
+            );
+            let result = client
+                .post(url)
+                .json(event)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            if let Err(e) = result {
+                warn!("Failed to notify webhook {}, {}", url, e);
+            }
+        }
+    }
+    if config.slack.is_some() || config.matrix.is_some() {
+        let template = config.message.as_deref().unwrap_or(DEFAULT_MESSAGE);
+        for (n, event) in events.iter().enumerate() {
+            let message = match render_message(template, event) {
+                Ok(message) => message,
+                Err(e) => {
+                    warn!("Failed to render notification message, {}", e);
+                    continue;
+                }
+            };
+            if let Some(slack) = &config.slack {
+                if let Err(e) = send_slack(&client, slack, &message).await {
+                    warn!("Failed to notify Slack, {}", e);
+                }
+            }
+            if let Some(matrix) = &config.matrix {
+                // Transaction ids let the homeserver drop retried duplicates.
+                let txn_id = format!("sdstatus-{}-{}", event.scanned_at.timestamp_nanos(), n);
+                if let Err(e) = send_matrix(&client, matrix, &message, &txn_id).await {
+                    warn!("Failed to notify Matrix room {}, {}", matrix.room_id, e);
+                }
+            }
+        }
+    }
+    if let Some(email) = &config.email {
+        // Only critical events are mailed, to keep the list's volume low.
+        let critical: Vec<&Event> = events
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e.kind,
+                    EventKind::Outage | EventKind::KeyRotated | EventKind::OnionChanged
+                )
+            })
+            .collect();
+        if !critical.is_empty() {
+            let template = config.message.as_deref().unwrap_or(DEFAULT_MESSAGE);
+            let mut body = String::from("");
+            for event in &critical {
+                match render_message(template, event) {
+                    Ok(message) => body += &format!("{}\n", message),
+                    Err(e) => warn!("Failed to render notification message, {}", e),
+                }
+            }
+            let subject = format!("sdstatus: {} critical alerts", critical.len());
+            if let Err(e) = send_email(email, &subject, &body).await {
+                warn!("Failed to send email alert, {}", e);
+            }
+        }
+    }
+    info!("Sent notifications for {} events", events.len());
+}
+
+/// Mails a plain-text message to the configured recipients. Sending
+/// blocks, so it runs off the async executor.
+pub async fn send_email(
+    email: &EmailConfig,
+    subject: &str,
+    body: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut message = Message::builder()
+        .from(email.from.parse()?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN);
+    for to in &email.to {
+        message = message.to(to.parse()?);
+    }
+    let message = message.body(body.to_owned())?;
+
+    let mut transport = match email.tls.as_deref().unwrap_or("starttls") {
+        "starttls" => SmtpTransport::starttls_relay(&email.smtp_server)?,
+        "tls" => SmtpTransport::relay(&email.smtp_server)?,
+        "none" => SmtpTransport::builder_dangerous(&email.smtp_server),
+        other => return Err(format!("Unknown email tls mode {}", other).into()),
+    };
+    if let Some(port) = email.smtp_port {
+        transport = transport.port(port);
+    }
+    if let (Some(username), Some(password)) = (&email.username, &email.password) {
+        transport =
+            transport.credentials(Credentials::new(username.to_owned(), password.to_owned()));
+    }
+    let transport = transport.build();
+    tokio::task::spawn_blocking(move || transport.send(&message).map_err(|e| e.to_string()))
+        .await??;
+    debug!("Mailed {:?} to {}", subject, email.to.join(", "));
+    Ok(())
+}
+
+/// Posts a message to a Slack incoming webhook.
+async fn send_slack(
+    client: &reqwest::Client,
+    slack: &SlackConfig,
+    message: &str,
+) -> Result<(), reqwest::Error> {
+    client
+        .post(&slack.webhook_url)
+        .json(&json!({ "text": message }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Sends a text message to a Matrix room.
+async fn send_matrix(
+    client: &reqwest::Client,
+    matrix: &MatrixConfig,
+    message: &str,
+    txn_id: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut url = reqwest::Url::parse(&matrix.homeserver)?;
+    url.path_segments_mut()
+        .map_err(|_| format!("Invalid homeserver URL {}", matrix.homeserver))?
+        .pop_if_empty()
+        .extend(&[
+            "_matrix",
+            "client",
+            "v3",
+            "rooms",
+            &matrix.room_id,
+            "send",
+            "m.room.message",
+            txn_id,
+        ]);
+    client
+        .put(url)
+        .bearer_auth(&matrix.access_token)
+        .json(&json!({ "msgtype": "m.text", "body": message }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use custom_error::custom_error;
+use sha3::{Digest, Sha3_256};
+
+custom_error! {pub OnionError
+    WrongLength{len: usize} = "expected 56 characters, found {len}",
+    InvalidBase32 = "not valid base32",
+    UnsupportedVersion{version: u8} = "unsupported onion service version {version}",
+    BadChecksum = "checksum does not match",
+}
+
+const BASE32_ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// Decodes unpadded, lowercase RFC 4648 base32.
+fn decode_base32(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = BASE32_ALPHABET.iter().position(|&a| a == c)? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(bytes)
+}
+
+/// Checks that an Onion address (with or without scheme, path and
+/// `.onion` suffix) is a well-formed v3 address: 56 base32 characters
+/// encoding the service's public key, a checksum and the version byte,
+/// per the Tor rendezvous specification.
+pub fn validate_v3(address: &str) -> Result<(), OnionError> {
+    let address = crate::normalize_onion(address).to_lowercase();
+    let label = address.trim_end_matches(".onion");
+    // Subdomains of the service are allowed, e.g. `www.<address>.onion`.
+    let label = label.rsplit('.').next().unwrap_or(label);
+    if label.len() != 56 {
+        return Err(OnionError::WrongLength { len: label.len() });
+    }
+    let decoded = decode_base32(label).ok_or(OnionError::InvalidBase32)?;
+    let (pubkey, rest) = decoded.split_at(32);
+    let (checksum, version) = (&rest[..2], rest[2]);
+    if version != 3 {
+        return Err(OnionError::UnsupportedVersion { version });
+    }
+    let mut hasher = Sha3_256::new();
+    hasher.update(b".onion checksum");
+    hasher.update(pubkey);
+    hasher.update([version]);
+    if hasher.finalize()[..2] != *checksum {
+        return Err(OnionError::BadChecksum);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "currentaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaal37qd";
+
+    #[test]
+    fn decodes_base32() {
+        assert_eq!(decode_base32("mzxw6ytb").unwrap(), b"fooba");
+        assert_eq!(decode_base32("").unwrap(), b"");
+        assert!(decode_base32("mzxw6yt1").is_none());
+    }
+
+    #[test]
+    fn accepts_v3_addresses_however_written() {
+        assert!(validate_v3(ADDRESS).is_ok());
+        assert!(validate_v3(&format!("{}.onion", ADDRESS)).is_ok());
+        assert!(validate_v3(&format!("http://{}.onion/metadata", ADDRESS)).is_ok());
+        assert!(validate_v3(&format!("www.{}.onion", ADDRESS.to_uppercase())).is_ok());
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        assert!(matches!(
+            validate_v3("expyuzz4wqqyqhjn.onion"),
+            Err(OnionError::WrongLength { len: 16 })
+        ));
+        let invalid = ADDRESS.replace('c', "1");
+        assert!(matches!(
+            validate_v3(&invalid),
+            Err(OnionError::InvalidBase32)
+        ));
+        // The last character holds the low bits of the version byte.
+        let version_4 = format!("{}e", &ADDRESS[..55]);
+        assert!(matches!(
+            validate_v3(&version_4),
+            Err(OnionError::UnsupportedVersion { version: 4 })
+        ));
+        let typo = ADDRESS.replacen('a', "b", 1);
+        assert!(matches!(validate_v3(&typo), Err(OnionError::BadChecksum)));
+    }
+}
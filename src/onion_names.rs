@@ -0,0 +1,155 @@
+use crate::{normalize_onion, SDDirectoryInstance};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+
+/// Where SecureDrop publishes the HTTPS Everywhere update channel mapping
+/// onion names such as `example.securedrop.tor.onion` to Onion addresses,
+/// as used by Tor Browser.
+pub const RULESETS_URL: &str = "https://securedrop.org/https-everywhere/";
+
+#[derive(Deserialize, Debug)]
+struct Rulesets {
+    rulesets: Vec<Ruleset>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Ruleset {
+    #[serde(default)]
+    target: Vec<String>,
+    #[serde(default)]
+    rule: Vec<Rule>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Rule {
+    to: String,
+}
+
+/// Fetches the rulesets from the update channel at `url`: the latest
+/// timestamp, then the gzipped rulesets published at it. Returns the Onion
+/// address each onion name maps to.
+///
+/// The channel's signature isn't checked, as the mapping is only compared
+/// against the directory, never used to reach a site.
+pub async fn fetch_onion_names(url: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let url = url.trim_end_matches('/');
+    info!("Fetching onion name rulesets from {}", url);
+    let timestamp = reqwest::get(&format!("{}/latest-rulesets-timestamp", url))
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let compressed = reqwest::get(&format!("{}/default.rulesets.{}.gz", url, timestamp.trim()))
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let mut json = vec![];
+    GzDecoder::new(&compressed[..]).read_to_end(&mut json)?;
+    parse_rulesets(&json)
+}
+
+/// Reads the Onion address each onion name maps to from rulesets in the
+/// HTTPS Everywhere format.
+pub fn parse_rulesets(json: &[u8]) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let rulesets: Rulesets = serde_json::from_slice(json)?;
+    let mut names = HashMap::new();
+    for ruleset in rulesets.rulesets {
+        if let Some(rule) = ruleset.rule.first() {
+            for target in ruleset.target {
+                names.insert(target.to_lowercase(), normalize_onion(&rule.to));
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// An instance whose onion name doesn't lead to its listed address.
+#[derive(Serialize, Debug)]
+pub struct OnionNameIssue {
+    pub title: String,
+    pub onion_name: String,
+    pub onion_address: String,
+    /// Where the rulesets send the onion name, if anywhere.
+    pub maps_to: Option<String>,
+}
+
+/// Which instances' onion names map to their listed addresses.
+#[derive(Serialize, Debug)]
+pub struct OnionNameReport {
+    /// Sites, as `title (onion name)`, whose alias is correct.
+    pub verified: Vec<String>,
+    /// Aliases mapping to another address than the one listed.
+    pub stale: Vec<OnionNameIssue>,
+    /// Aliases missing from the rulesets.
+    pub broken: Vec<OnionNameIssue>,
+}
+
+/// Checks each instance with an onion name against the rulesets.
+pub fn verify_onion_names(
+    instances: &[SDDirectoryInstance],
+    names: &HashMap<String, String>,
+) -> OnionNameReport {
+    let mut report = OnionNameReport {
+        verified: vec![],
+        stale: vec![],
+        broken: vec![],
+    };
+    for i in instances {
+        let name = match i.onion_name.as_deref().map(str::trim) {
+            Some(name) if !name.is_empty() => name,
+            _ => continue,
+        };
+        let listed = normalize_onion(&i.onion_address).to_lowercase();
+        let maps_to = names.get(&name.to_lowercase());
+        let issue = OnionNameIssue {
+            title: i.title.to_owned(),
+            onion_name: name.to_owned(),
+            onion_address: i.onion_address.to_owned(),
+            maps_to: maps_to.cloned(),
+        };
+        match maps_to {
+            Some(address) if address.to_lowercase() == listed => {
+                report.verified.push(format!("{} ({})", i.title, name))
+            }
+            Some(_) => report.stale.push(issue),
+            None => report.broken.push(issue),
+        }
+    }
+    report
+}
+
+/// Renders the onion name report as human-readable text.
+pub fn format_onion_name_report(report: &OnionNameReport) -> String {
+    let mut text = String::from("");
+    if !report.stale.is_empty() {
+        text += &format!("Stale onion names ({}):\n", report.stale.len());
+        for s in &report.stale {
+            text += &format!(
+                "  {}: {} maps to {}, not {}\n",
+                s.title,
+                s.onion_name,
+                s.maps_to.as_deref().unwrap_or(""),
+                s.onion_address
+            );
+        }
+        text += "\n";
+    }
+    if !report.broken.is_empty() {
+        text += &format!("Broken onion names ({}):\n", report.broken.len());
+        for b in &report.broken {
+            text += &format!("  {}: {} is not in the rulesets\n", b.title, b.onion_name);
+        }
+        text += "\n";
+    }
+    let total = report.verified.len() + report.stale.len() + report.broken.len();
+    text += &format!(
+        "{} of {} onion names map to the listed address\n",
+        report.verified.len(),
+        total
+    );
+    text
+}
@@ -0,0 +1,118 @@
+use crate::failure::FailureKind;
+use crate::{SDDirectoryInstance, ScanResults};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::error::Error;
+
+/// The version of OONI's base data format the measurements follow.
+const DATA_FORMAT_VERSION: &str = "0.2.0";
+
+/// The name the measurements are filed under, as OONI names its tests.
+pub const TEST_NAME: &str = "securedrop_reachability";
+
+/// The version of the test keys below, bumped when they change.
+const TEST_VERSION: &str = "0.1.0";
+
+/// OONI's placeholders for a probe's network and country, which sdstatus
+/// doesn't look up, so as not to reveal where it runs.
+const UNKNOWN_ASN: &str = "AS0";
+const UNKNOWN_CC: &str = "ZZ";
+
+/// OONI's timestamp format, in UTC.
+fn ooni_time(t: DateTime<Utc>) -> String {
+    t.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// What was measured of one instance, in the `test_keys` of its
+/// measurement.
+#[derive(Serialize, Debug)]
+pub struct TestKeys {
+    pub title: String,
+    pub onion_address: String,
+    pub landing_page_url: String,
+    /// Whether the metadata endpoint responded with SecureDrop metadata.
+    pub accessible: bool,
+    /// Why it didn't, as a `failure` kind (e.g. `timeout`), or null, as
+    /// OONI reports failures.
+    pub failure: Option<FailureKind>,
+    pub http_status: Option<u16>,
+    pub latency_ms: Option<u64>,
+    pub sd_version: Option<String>,
+    pub attempts: u32,
+}
+
+/// One instance's result as an OONI measurement.
+#[derive(Serialize, Debug)]
+pub struct Measurement {
+    pub annotations: serde_json::Value,
+    pub data_format_version: &'static str,
+    /// The Onion address measured.
+    pub input: String,
+    pub measurement_start_time: String,
+    pub probe_asn: &'static str,
+    pub probe_cc: &'static str,
+    pub probe_ip: &'static str,
+    pub report_id: String,
+    pub software_name: &'static str,
+    pub software_version: &'static str,
+    pub test_keys: TestKeys,
+    pub test_name: &'static str,
+    /// Seconds taken to fetch the metadata.
+    pub test_runtime: f64,
+    pub test_start_time: String,
+    pub test_version: &'static str,
+}
+
+impl Measurement {
+    fn new(instance: &SDDirectoryInstance, report_id: &str, started_at: DateTime<Utc>) -> Self {
+        Measurement {
+            annotations: serde_json::json!({ "engine_name": "sdstatus" }),
+            data_format_version: DATA_FORMAT_VERSION,
+            input: instance.onion_address.to_owned(),
+            measurement_start_time: ooni_time(started_at),
+            probe_asn: UNKNOWN_ASN,
+            probe_cc: UNKNOWN_CC,
+            probe_ip: "127.0.0.1",
+            report_id: report_id.to_owned(),
+            software_name: "sdstatus",
+            software_version: env!("CARGO_PKG_VERSION"),
+            test_keys: TestKeys {
+                title: instance.title.to_owned(),
+                onion_address: instance.onion_address.to_owned(),
+                landing_page_url: instance.landing_page_url.to_owned(),
+                accessible: instance.available,
+                failure: instance.failure_kind(),
+                http_status: instance.http_status,
+                latency_ms: instance.latency_ms,
+                sd_version: instance.metadata.as_ref().map(|m| m.sd_version.to_owned()),
+                attempts: instance.attempts,
+            },
+            test_name: TEST_NAME,
+            test_runtime: instance.duration_ms.unwrap_or(0) as f64 / 1000.0,
+            test_start_time: ooni_time(started_at),
+            test_version: TEST_VERSION,
+        }
+    }
+}
+
+/// Writes the results of a scan as an OONI report: one measurement per
+/// instance, one per line, all sharing a report id made from the scan's
+/// start.
+pub fn write_ooni<W: std::io::Write>(
+    results: &ScanResults,
+    mut writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let started_at = results.scan.started_at;
+    let report_id = format!(
+        "{}_{}_{}_{}_n1_sdstatus",
+        started_at.format("%Y%m%dT%H%M%SZ"),
+        TEST_NAME.replace('_', ""),
+        UNKNOWN_CC,
+        UNKNOWN_ASN.trim_start_matches("AS")
+    );
+    for i in &results.instances {
+        let measurement = Measurement::new(i, &report_id, started_at);
+        writeln!(writer, "{}", serde_json::to_string(&measurement)?)?;
+    }
+    Ok(())
+}
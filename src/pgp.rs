@@ -0,0 +1,436 @@
+use chrono::{DateTime, TimeZone, Utc};
+use custom_error::custom_error;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+custom_error! {pub PgpError
+    InvalidArmor = "Not an ASCII-armored OpenPGP public key",
+    Truncated = "OpenPGP data is truncated",
+    UnsupportedPacket{tag: u8} = "Unsupported OpenPGP packet encoding (tag {tag})",
+    UnsupportedVersion{version: u8} = "Unsupported OpenPGP key version {version}",
+    NoPublicKey = "No public key packet found",
+}
+
+/// Decodes an ASCII-armored OpenPGP public key block into binary packets.
+pub fn dearmor(armored: &str) -> Result<Vec<u8>, PgpError> {
+    let mut lines = armored
+        .lines()
+        .map(|l| l.trim())
+        .skip_while(|l| *l != "-----BEGIN PGP PUBLIC KEY BLOCK-----");
+    if lines.next().is_none() {
+        return Err(PgpError::InvalidArmor);
+    }
+    // Armor headers (e.g. "Comment: ...") end at a blank line, which some
+    // keys leave out. Base64 has no colons, so headers can't be mistaken
+    // for the body.
+    let mut lines = lines.skip_while(|l| l.contains(':'));
+    let mut body = String::new();
+    for line in &mut lines {
+        if line.starts_with("-----END") {
+            break;
+        }
+        // The CRC24 checksum line is optional, and not verified here.
+        if line.is_empty() || line.starts_with('=') {
+            continue;
+        }
+        body += line;
+    }
+    base64::decode(&body).map_err(|_| PgpError::InvalidArmor)
+}
+
+/// A single OpenPGP packet: its tag and body.
+pub struct Packet<'a> {
+    pub tag: u8,
+    pub body: &'a [u8],
+}
+
+/// Splits binary OpenPGP data into packets, in either the old or the new
+/// packet format.
+pub fn packets(mut data: &[u8]) -> Result<Vec<Packet<'_>>, PgpError> {
+    let mut packets = vec![];
+    while !data.is_empty() {
+        let header = data[0];
+        if header & 0x80 == 0 {
+            return Err(PgpError::UnsupportedPacket { tag: header });
+        }
+        let (tag, header_len, body_len) = if header & 0x40 != 0 {
+            let tag = header & 0x3f;
+            match data.get(1) {
+                Some(&o) if o < 192 => (tag, 2, o as usize),
+                Some(&o) if o < 224 => {
+                    let o2 = *data.get(2).ok_or(PgpError::Truncated)?;
+                    (tag, 3, ((o as usize - 192) << 8) + o2 as usize + 192)
+                }
+                Some(255) => (tag, 6, be_len(data.get(2..6).ok_or(PgpError::Truncated)?)),
+                // Partial body lengths are not used for key material.
+                Some(_) => return Err(PgpError::UnsupportedPacket { tag }),
+                None => return Err(PgpError::Truncated),
+            }
+        } else {
+            let tag = (header >> 2) & 0x0f;
+            match header & 0x03 {
+                0 => (tag, 2, *data.get(1).ok_or(PgpError::Truncated)? as usize),
+                1 => (tag, 3, be_len(data.get(1..3).ok_or(PgpError::Truncated)?)),
+                2 => (tag, 5, be_len(data.get(1..5).ok_or(PgpError::Truncated)?)),
+                _ => (tag, 1, data.len() - 1),
+            }
+        };
+        let body = data
+            .get(header_len..header_len + body_len)
+            .ok_or(PgpError::Truncated)?;
+        packets.push(Packet { tag, body });
+        data = &data[header_len + body_len..];
+    }
+    Ok(packets)
+}
+
+fn be_len(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |acc, b| (acc << 8) | *b as usize)
+}
+
+/// Computes the fingerprint of a version 4 public key packet body, as an
+/// uppercase hex string like the `gpg_fpr` metadata field.
+pub fn fingerprint(key_body: &[u8]) -> Result<String, PgpError> {
+    match key_body.first() {
+        Some(4) => {}
+        Some(&version) => return Err(PgpError::UnsupportedVersion { version }),
+        None => return Err(PgpError::Truncated),
+    }
+    let mut hasher = Sha1::new();
+    hasher.update([0x99]);
+    hasher.update((key_body.len() as u16).to_be_bytes());
+    hasher.update(key_body);
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect())
+}
+
+/// Returns the fingerprint of the primary key in an armored public key.
+pub fn primary_fingerprint(armored: &str) -> Result<String, PgpError> {
+    let data = dearmor(armored)?;
+    let packets = packets(&data)?;
+    // The primary key is tag 6; subkeys (tag 14) follow it.
+    let key = packets
+        .iter()
+        .find(|p| p.tag == 6)
+        .ok_or(PgpError::NoPublicKey)?;
+    fingerprint(key.body)
+}
+
+/// One key of an OpenPGP certificate, the primary key or a subkey.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct KeyDetails {
+    pub fingerprint: String,
+    /// The public key algorithm, e.g. `rsa` or `ed25519`.
+    pub algorithm: String,
+    /// The key size, for RSA, DSA and Elgamal keys.
+    pub bits: Option<u32>,
+    pub created: DateTime<Utc>,
+    /// When the key expires, according to its latest self-signature, if
+    /// it expires at all.
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl KeyDetails {
+    /// The algorithm and, where it has one, the size, e.g. `rsa4096`.
+    pub fn describe(&self) -> String {
+        match self.bits {
+            Some(bits) => format!("{}{}", self.algorithm, bits),
+            None => self.algorithm.to_owned(),
+        }
+    }
+}
+
+/// The keys of a journalist key certificate.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct KeyHealth {
+    pub primary: KeyDetails,
+    pub subkeys: Vec<KeyDetails>,
+}
+
+impl KeyHealth {
+    /// When the certificate stops being usable: when the primary key
+    /// expires, or the last of its subkeys (which submissions are
+    /// encrypted to) does, whichever is sooner.
+    pub fn expires(&self) -> Option<DateTime<Utc>> {
+        let subkeys = if self.subkeys.iter().any(|k| k.expires.is_none()) {
+            None
+        } else {
+            self.subkeys.iter().filter_map(|k| k.expires).max()
+        };
+        match (self.primary.expires, subkeys) {
+            (Some(primary), Some(subkeys)) => Some(primary.min(subkeys)),
+            (primary, subkeys) => primary.or(subkeys),
+        }
+    }
+}
+
+/// Names the public key algorithm of a key packet body, with its size
+/// where there is one: the bit count of the first MPI for RSA, DSA and
+/// Elgamal, the curve for elliptic curve keys.
+fn algorithm(body: &[u8]) -> (String, Option<u32>) {
+    let id = body.get(5).copied().unwrap_or(0);
+    let bits = body
+        .get(6..8)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]) as u32);
+    let curve = || {
+        let len = *body.get(6)? as usize;
+        let oid = body.get(7..7 + len)?;
+        Some(match oid {
+            [0x2B, 0x06, 0x01, 0x04, 0x01, 0xDA, 0x47, 0x0F, 0x01] => "ed25519",
+            [0x2B, 0x06, 0x01, 0x04, 0x01, 0x97, 0x55, 0x01, 0x05, 0x01] => "cv25519",
+            [0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07] => "nistp256",
+            [0x2B, 0x81, 0x04, 0x00, 0x22] => "nistp384",
+            [0x2B, 0x81, 0x04, 0x00, 0x23] => "nistp521",
+            _ => return None,
+        })
+    };
+    match id {
+        1..=3 => ("rsa".to_owned(), bits),
+        16 => ("elgamal".to_owned(), bits),
+        17 => ("dsa".to_owned(), bits),
+        18 | 19 | 22 => {
+            let kind = match id {
+                18 => "ecdh",
+                19 => "ecdsa",
+                _ => "eddsa",
+            };
+            (curve().unwrap_or(kind).to_owned(), None)
+        }
+        25 => ("x25519".to_owned(), None),
+        27 => ("ed25519".to_owned(), None),
+        _ => (format!("algorithm {}", id), None),
+    }
+}
+
+/// Reads a version 4 key packet body.
+fn key_details(body: &[u8]) -> Result<KeyDetails, PgpError> {
+    let fingerprint = fingerprint(body)?;
+    let created = body.get(1..5).ok_or(PgpError::Truncated)?;
+    let created = u32::from_be_bytes([created[0], created[1], created[2], created[3]]);
+    let (algorithm, bits) = algorithm(body);
+    Ok(KeyDetails {
+        fingerprint,
+        algorithm,
+        bits,
+        created: Utc.timestamp(created as i64, 0),
+        expires: None,
+    })
+}
+
+/// What a signature says about the key it is on, as far as expiry goes.
+struct Signature {
+    kind: u8,
+    /// When the signature was made, in seconds since the epoch.
+    created: u32,
+    /// How long after its creation the key expires, in seconds.
+    key_expires: Option<u32>,
+    /// The key ID of the key that made the signature, as hex, if given.
+    issuer: Option<String>,
+}
+
+/// Reads the subpackets of a signature, calling `read` with the type and
+/// data of each. Returns `None` if they are malformed.
+fn subpackets(mut data: &[u8], mut read: impl FnMut(u8, &[u8])) -> Option<()> {
+    while !data.is_empty() {
+        let (header_len, len) = match data[0] {
+            o if o < 192 => (1, o as usize),
+            o if o < 255 => (2, ((o as usize - 192) << 8) + *data.get(1)? as usize + 192),
+            _ => (5, be_len(data.get(1..5)?)),
+        };
+        let packet = data.get(header_len..header_len + len)?;
+        read(*packet.first()? & 0x7f, &packet[1..]);
+        data = &data[header_len + len..];
+    }
+    Some(())
+}
+
+/// Reads a version 4 signature packet body. Returns `None` for other
+/// versions, or if it is malformed.
+fn signature(body: &[u8]) -> Option<Signature> {
+    if *body.first()? != 4 {
+        return None;
+    }
+    let mut signature = Signature {
+        kind: *body.get(1)?,
+        created: 0,
+        key_expires: None,
+        issuer: None,
+    };
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    let time = |v: &[u8]| {
+        v.get(..4)
+            .map(|v| u32::from_be_bytes([v[0], v[1], v[2], v[3]]))
+    };
+    let hashed_len = u16::from_be_bytes([*body.get(4)?, *body.get(5)?]) as usize;
+    let hashed = body.get(6..6 + hashed_len)?;
+    let rest = &body[6 + hashed_len..];
+    let unhashed_len = u16::from_be_bytes([*rest.first()?, *rest.get(1)?]) as usize;
+    let unhashed = rest.get(2..2 + unhashed_len)?;
+    subpackets(hashed, |kind, data| match kind {
+        2 => signature.created = time(data).unwrap_or(0),
+        9 => signature.key_expires = time(data),
+        // An issuer fingerprint: a version, then the fingerprint.
+        33 if data.len() == 21 => signature.issuer = Some(hex(&data[13..])),
+        16 if data.len() == 8 => signature.issuer = Some(hex(data)),
+        _ => {}
+    })?;
+    // The issuer is commonly left unhashed, as it can't be forged anyway.
+    subpackets(unhashed, |kind, data| match kind {
+        33 if data.len() == 21 && signature.issuer.is_none() => {
+            signature.issuer = Some(hex(&data[13..]))
+        }
+        16 if data.len() == 8 && signature.issuer.is_none() => signature.issuer = Some(hex(data)),
+        _ => {}
+    })?;
+    Some(signature)
+}
+
+/// Reads the algorithm, creation and expiry dates of the primary key and
+/// each subkey in an armored public key. Expiry dates are taken from the
+/// latest self-signature of each key (user ID certifications and direct
+/// signatures for the primary key, binding signatures for subkeys). The
+/// signatures themselves aren't verified.
+pub fn key_health(armored: &str) -> Result<KeyHealth, PgpError> {
+    let data = dearmor(armored)?;
+    let mut primary: Option<(KeyDetails, u32)> = None;
+    let mut subkeys: Vec<(KeyDetails, u32)> = vec![];
+    for p in packets(&data)? {
+        match p.tag {
+            6 if primary.is_none() => primary = Some((key_details(p.body)?, 0)),
+            14 if primary.is_some() => subkeys.push((key_details(p.body)?, 0)),
+            2 => {
+                let signature = match signature(p.body) {
+                    Some(signature) => signature,
+                    None => continue,
+                };
+                // Only the primary key's own signatures count, not
+                // certifications by others.
+                let issued_by_primary = match (&signature.issuer, &primary) {
+                    (Some(issuer), Some((key, _))) => key.fingerprint.ends_with(issuer.as_str()),
+                    _ => true,
+                };
+                let key = match (signature.kind, subkeys.last_mut()) {
+                    _ if !issued_by_primary => None,
+                    (0x10..=0x13 | 0x1f, _) => primary.as_mut(),
+                    (0x18, Some(subkey)) => Some(subkey),
+                    _ => None,
+                };
+                if let Some((key, latest)) = key {
+                    if signature.created >= *latest {
+                        *latest = signature.created;
+                        key.expires = signature
+                            .key_expires
+                            .filter(|e| *e > 0)
+                            .map(|e| key.created + chrono::Duration::seconds(e as i64));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let (primary, _) = primary.ok_or(PgpError::NoPublicKey)?;
+    Ok(KeyHealth {
+        primary,
+        subkeys: subkeys.into_iter().map(|(k, _)| k).collect(),
+    })
+}
+
+/// Normalizes a fingerprint for comparison, ignoring case and spacing.
+pub fn normalize_fingerprint(fpr: &str) -> String {
+    fpr.chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An ed25519 key made on 2024-01-01 that expires on 2025-12-31, with a
+    /// cv25519 subkey that expires on 2024-12-31.
+    const KEY: &str = include_str!("../tests/fixtures/journalist.asc");
+    const FINGERPRINT: &str = "A5F6A198EDAA9AD012E3DB1AC3F129E5DC3AC454";
+
+    fn armor(data: &[u8]) -> String {
+        format!(
+            "-----BEGIN PGP PUBLIC KEY BLOCK-----\n\n{}\n-----END PGP PUBLIC KEY BLOCK-----\n",
+            base64::encode(data)
+        )
+    }
+
+    #[test]
+    fn reads_the_primary_fingerprint() {
+        assert_eq!(primary_fingerprint(KEY).unwrap(), FINGERPRINT);
+    }
+
+    #[test]
+    fn reads_key_health() {
+        let health = key_health(KEY).unwrap();
+        assert_eq!(health.primary.fingerprint, FINGERPRINT);
+        assert_eq!(health.primary.describe(), "ed25519");
+        assert_eq!(health.primary.created, Utc.ymd(2024, 1, 1).and_hms(0, 0, 0));
+        assert_eq!(
+            health.primary.expires,
+            Some(Utc.ymd(2025, 12, 31).and_hms(0, 0, 0))
+        );
+        assert_eq!(health.subkeys.len(), 1);
+        assert_eq!(health.subkeys[0].describe(), "cv25519");
+        assert_eq!(
+            health.subkeys[0].fingerprint,
+            "CFE124504D226F12934D310767BDF9187A90C05C"
+        );
+        // The subkey expires first, and with it the certificate.
+        assert_eq!(
+            health.expires(),
+            Some(Utc.ymd(2024, 12, 31).and_hms(0, 0, 0))
+        );
+    }
+
+    #[test]
+    fn reads_armor_with_or_without_headers() {
+        let bare = KEY.replacen("-----\n\n", "-----\n", 1);
+        assert_eq!(primary_fingerprint(&bare).unwrap(), FINGERPRINT);
+        let headers = KEY.replacen("-----\n\n", "-----\nComment: Journalist key\n\n", 1);
+        assert_eq!(primary_fingerprint(&headers).unwrap(), FINGERPRINT);
+        assert!(matches!(
+            primary_fingerprint("<html>Not found</html>"),
+            Err(PgpError::InvalidArmor)
+        ));
+    }
+
+    #[test]
+    fn rejects_empty_and_truncated_keys() {
+        assert!(matches!(fingerprint(&[]), Err(PgpError::Truncated)));
+        assert!(matches!(
+            primary_fingerprint(&armor(&[])),
+            Err(PgpError::NoPublicKey)
+        ));
+        let data = dearmor(KEY).unwrap();
+        assert!(matches!(packets(&data[..10]), Err(PgpError::Truncated)));
+        // However it is cut short, the key is an error, never a panic.
+        for len in 0..data.len() {
+            let armored = armor(&data[..len]);
+            let _ = primary_fingerprint(&armored);
+            let _ = key_health(&armored);
+        }
+    }
+
+    #[test]
+    fn rejects_v5_keys() {
+        let mut data = dearmor(KEY).unwrap();
+        // The first packet's header is two bytes, then the key version.
+        data[2] = 5;
+        assert!(matches!(
+            key_health(&armor(&data)),
+            Err(PgpError::UnsupportedVersion { version: 5 })
+        ));
+        assert!(matches!(
+            primary_fingerprint(&armor(&data)),
+            Err(PgpError::UnsupportedVersion { version: 5 })
+        ));
+    }
+}
@@ -0,0 +1,346 @@
+use crate::db::{
+    LatestValues, Listing, LocaleOutcome, Outcome, Storage, TimedResult, VersionOutcome,
+};
+use crate::{SDDirectoryInstance, SDMetadata};
+use ::postgres::{Client, Row};
+use chrono::{DateTime, Utc};
+use postgres_native_tls::MakeTlsConnector;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+// Schema migrations, applied in order, as for SQLite (see `db::MIGRATIONS`):
+// the two lists must be kept in step. Timestamps are kept as RFC 3339 text,
+// as in SQLite, so both backends order and compare them alike.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE scans (
+        id BIGSERIAL PRIMARY KEY,
+        started_at TEXT NOT NULL,
+        finished_at TEXT NOT NULL
+    );
+    CREATE TABLE results (
+        id BIGSERIAL PRIMARY KEY,
+        scan_id BIGINT NOT NULL REFERENCES scans(id),
+        onion_address TEXT NOT NULL,
+        title TEXT NOT NULL,
+        landing_page_url TEXT NOT NULL,
+        available BOOLEAN NOT NULL,
+        http_status INTEGER,
+        latency_ms BIGINT,
+        error TEXT,
+        metadata TEXT
+    );
+    CREATE INDEX results_onion_address ON results(onion_address);",
+    "ALTER TABLE results ADD COLUMN duration_ms BIGINT;",
+];
+
+/// PostgresHistory stores the results of each scan in a PostgreSQL
+/// database, with the same schema as `SqliteHistory`, so several
+/// deployments and dashboards can share it.
+pub struct PostgresHistory {
+    // Queries need the client mutably, while `Storage` reads through
+    // shared references.
+    client: RefCell<Client>,
+}
+
+impl PostgresHistory {
+    /// Connects to the database at `url`, e.g.
+    /// `postgres://sdstatus@db.example.org/sdstatus`, bringing its schema up
+    /// to date. TLS is used as the connection string's `sslmode` asks.
+    pub fn connect(url: &str) -> Result<PostgresHistory, Box<dyn Error>> {
+        let tls = MakeTlsConnector::new(native_tls::TlsConnector::new()?);
+        let mut client = Client::connect(url, tls)?;
+        migrate(&mut client)?;
+        Ok(PostgresHistory {
+            client: RefCell::new(client),
+        })
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        params: &[&(dyn ::postgres::types::ToSql + Sync)],
+    ) -> Result<Vec<Row>, ::postgres::Error> {
+        self.client.borrow_mut().query(query, params)
+    }
+}
+
+fn parse_time(time: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    Ok(DateTime::parse_from_rfc3339(time)?.with_timezone(&Utc))
+}
+
+/// Reads the columns of a result row, in the order `load_scan` selects
+/// them, into an instance.
+fn instance_from_row(row: &Row) -> Result<SDDirectoryInstance, Box<dyn Error>> {
+    let metadata: Option<String> = row.get(8);
+    Ok(SDDirectoryInstance {
+        metadata: match metadata {
+            Some(m) => Some(serde_json::from_str::<SDMetadata>(&m)?),
+            None => None,
+        },
+        onion_name: None,
+        onion_address: row.get(0),
+        title: row.get(1),
+        landing_page_url: row.get(2),
+        available: row.get(3),
+        http_status: row.get::<_, Option<i32>>(4).map(|s| s as u16),
+        latency_ms: row.get::<_, Option<i64>>(5).map(|l| l as u64),
+        duration_ms: row.get::<_, Option<i64>>(6).map(|d| d as u64),
+        error: row.get(7),
+        failure: None,
+        attempts: 0,
+        key_fingerprint: None,
+        key_matches: None,
+        journalist_key: None,
+        schema_drift: vec![],
+        raw_metadata: None,
+        samples: None,
+    })
+}
+
+impl Storage for PostgresHistory {
+    fn record_scan(
+        &mut self,
+        started_at: DateTime<Utc>,
+        finished_at: DateTime<Utc>,
+        instances: &[SDDirectoryInstance],
+    ) -> Result<i64, Box<dyn Error>> {
+        let mut client = self.client.borrow_mut();
+        let mut tx = client.transaction()?;
+        let scan_id: i64 = tx
+            .query_one(
+                "INSERT INTO scans (started_at, finished_at) VALUES ($1, $2) RETURNING id",
+                &[&started_at.to_rfc3339(), &finished_at.to_rfc3339()],
+            )?
+            .get(0);
+        for i in instances {
+            let metadata = match &i.metadata {
+                Some(m) => Some(serde_json::to_string(m)?),
+                None => None,
+            };
+            tx.execute(
+                "INSERT INTO results (scan_id, onion_address, title, landing_page_url,
+                     available, http_status, latency_ms, duration_ms, error, metadata)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+                &[
+                    &scan_id,
+                    &i.onion_address,
+                    &i.title,
+                    &i.landing_page_url,
+                    &i.available,
+                    &i.http_status.map(i32::from),
+                    &i.latency_ms.map(|l| l as i64),
+                    &i.duration_ms.map(|d| d as i64),
+                    &i.error,
+                    &metadata,
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(scan_id)
+    }
+
+    fn latest_scan_ids(&self, limit: usize) -> Result<Vec<i64>, Box<dyn Error>> {
+        let rows = self.query(
+            "SELECT id FROM scans ORDER BY started_at DESC, id DESC LIMIT $1",
+            &[&(limit as i64)],
+        )?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    fn scan_started_at(&self, scan_id: i64) -> Result<DateTime<Utc>, Box<dyn Error>> {
+        let started_at: String = self
+            .client
+            .borrow_mut()
+            .query_one("SELECT started_at FROM scans WHERE id = $1", &[&scan_id])?
+            .get(0);
+        Ok(parse_time(&started_at)?)
+    }
+
+    fn down_since(&self, onion_address: &str) -> Result<Option<DateTime<Utc>>, Box<dyn Error>> {
+        let started_at: Option<String> = self
+            .client
+            .borrow_mut()
+            .query_one(
+                "SELECT MIN(s.started_at) FROM results r JOIN scans s ON s.id = r.scan_id
+                 WHERE r.onion_address = $1 AND NOT r.available AND s.started_at > COALESCE(
+                     (SELECT MAX(s2.started_at) FROM results r2 JOIN scans s2 ON s2.id = r2.scan_id
+                      WHERE r2.onion_address = $1 AND r2.available), '')",
+                &[&onion_address],
+            )?
+            .get(0);
+        Ok(started_at.as_deref().map(parse_time).transpose()?)
+    }
+
+    fn outcomes_since(&self, since: DateTime<Utc>) -> Result<Vec<Outcome>, Box<dyn Error>> {
+        let rows = self.query(
+            "SELECT r.onion_address, r.title, s.started_at, r.available
+             FROM results r JOIN scans s ON s.id = r.scan_id
+             WHERE s.started_at >= $1 ORDER BY s.started_at, r.id",
+            &[&since.to_rfc3339()],
+        )?;
+        let mut outcomes = vec![];
+        for row in rows {
+            outcomes.push(Outcome {
+                onion_address: row.get(0),
+                title: row.get(1),
+                scanned_at: parse_time(row.get(2))?,
+                available: row.get(3),
+            });
+        }
+        Ok(outcomes)
+    }
+
+    fn versions_since(&self, since: DateTime<Utc>) -> Result<Vec<VersionOutcome>, Box<dyn Error>> {
+        let rows = self.query(
+            "SELECT r.onion_address, r.title, s.started_at, r.metadata::jsonb ->> 'sd_version'
+             FROM results r JOIN scans s ON s.id = r.scan_id
+             WHERE s.started_at >= $1 AND r.available AND r.metadata IS NOT NULL
+             ORDER BY s.started_at, r.id",
+            &[&since.to_rfc3339()],
+        )?;
+        let mut outcomes = vec![];
+        for row in rows {
+            let sd_version: Option<String> = row.get(3);
+            if let Some(sd_version) = sd_version.filter(|v| !v.is_empty()) {
+                outcomes.push(VersionOutcome {
+                    onion_address: row.get(0),
+                    title: row.get(1),
+                    scanned_at: parse_time(row.get(2))?,
+                    sd_version,
+                });
+            }
+        }
+        Ok(outcomes)
+    }
+
+    fn locales_since(&self, since: DateTime<Utc>) -> Result<Vec<LocaleOutcome>, Box<dyn Error>> {
+        let rows = self.query(
+            "SELECT r.onion_address, r.title, s.started_at,
+                    (r.metadata::jsonb -> 'supported_languages')::text
+             FROM results r JOIN scans s ON s.id = r.scan_id
+             WHERE s.started_at >= $1 AND r.available AND r.metadata IS NOT NULL
+             ORDER BY s.started_at, r.id",
+            &[&since.to_rfc3339()],
+        )?;
+        let mut outcomes = vec![];
+        for row in rows {
+            let locales: Option<String> = row.get(3);
+            outcomes.push(LocaleOutcome {
+                onion_address: row.get(0),
+                title: row.get(1),
+                scanned_at: parse_time(row.get(2))?,
+                locales: match locales {
+                    Some(locales) => serde_json::from_str(&locales)?,
+                    None => vec![],
+                },
+            });
+        }
+        Ok(outcomes)
+    }
+
+    fn latest_metadata_values(&self, field: &str) -> Result<LatestValues, Box<dyn Error>> {
+        let rows = self.query(
+            "SELECT onion_address, started_at, value FROM (
+                 SELECT r.onion_address, s.started_at, r.id, r.metadata::jsonb ->> $1 AS value
+                 FROM results r JOIN scans s ON s.id = r.scan_id
+             ) v
+             WHERE value IS NOT NULL AND value != ''
+             ORDER BY started_at, id",
+            &[&field],
+        )?;
+        let mut latest = BTreeMap::new();
+        for row in rows {
+            // Later scans overwrite earlier ones.
+            latest.insert(row.get(0), (row.get(2), parse_time(row.get(1))?));
+        }
+        Ok(latest)
+    }
+
+    fn listings(&self) -> Result<Vec<Listing>, Box<dyn Error>> {
+        let rows = self.query(
+            "SELECT r.title, r.onion_address, s.id, s.started_at
+             FROM results r JOIN scans s ON s.id = r.scan_id
+             WHERE r.title != ''
+             ORDER BY s.started_at, r.id",
+            &[],
+        )?;
+        let mut listings = vec![];
+        for row in rows {
+            listings.push(Listing {
+                title: row.get(0),
+                onion_address: row.get(1),
+                scan_id: row.get(2),
+                scanned_at: parse_time(row.get(3))?,
+            });
+        }
+        Ok(listings)
+    }
+
+    fn load_scan(&self, scan_id: i64) -> Result<Vec<SDDirectoryInstance>, Box<dyn Error>> {
+        let rows = self.query(
+            "SELECT onion_address, title, landing_page_url, available, http_status,
+                    latency_ms, duration_ms, error, metadata
+             FROM results WHERE scan_id = $1 ORDER BY id",
+            &[&scan_id],
+        )?;
+        rows.iter().map(instance_from_row).collect()
+    }
+
+    fn latest_result(&self, onion_address: &str) -> Result<Option<TimedResult>, Box<dyn Error>> {
+        let rows = self.query(
+            "SELECT r.onion_address, r.title, r.landing_page_url, r.available, r.http_status,
+                    r.latency_ms, r.duration_ms, r.error, r.metadata, s.started_at
+             FROM results r JOIN scans s ON s.id = r.scan_id
+             WHERE r.onion_address = $1 ORDER BY s.started_at DESC, r.id DESC LIMIT 1",
+            &[&onion_address],
+        )?;
+        match rows.first() {
+            Some(row) => Ok(Some((parse_time(row.get(9))?, instance_from_row(row)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn last_available(&self, onion_address: &str) -> Result<Option<DateTime<Utc>>, Box<dyn Error>> {
+        let started_at: Option<String> = self
+            .client
+            .borrow_mut()
+            .query_one(
+                "SELECT MAX(s.started_at) FROM results r JOIN scans s ON s.id = r.scan_id
+                 WHERE r.onion_address = $1 AND r.available",
+                &[&onion_address],
+            )?
+            .get(0);
+        Ok(started_at.as_deref().map(parse_time).transpose()?)
+    }
+}
+
+/// Applies any migrations the database has not seen yet. PostgreSQL has no
+/// `user_version` pragma, so the number applied is kept in a table.
+fn migrate(client: &mut Client) -> Result<(), Box<dyn Error>> {
+    // Checked first, as IF NOT EXISTS would log a notice on every run.
+    let exists: bool = client
+        .query_one("SELECT to_regclass('schema_version') IS NOT NULL", &[])?
+        .get(0);
+    if !exists {
+        client.batch_execute("CREATE TABLE schema_version (version INTEGER NOT NULL)")?;
+    }
+    let mut tx = client.transaction()?;
+    // Lock the table, so daemons starting together don't both migrate.
+    tx.batch_execute("LOCK TABLE schema_version IN EXCLUSIVE MODE")?;
+    let version: Option<i32> = tx
+        .query_one("SELECT MAX(version) FROM schema_version", &[])?
+        .get(0);
+    let version = version.unwrap_or(0) as usize;
+    for (n, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        debug!("Applying database migration {}", n + 1);
+        tx.batch_execute(migration)?;
+        tx.execute("DELETE FROM schema_version", &[])?;
+        tx.execute(
+            "INSERT INTO schema_version (version) VALUES ($1)",
+            &[&((n + 1) as i32)],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use crate::failure::FailureKind;
+use custom_error::custom_error;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+custom_error! {pub PreflightError
+    Io{source: std::io::Error} = "pre-flight check failed: {source}",
+    Timeout = "pre-flight check timed out",
+    Proxy{message: String} = "pre-flight check failed: {message}",
+    Rejected{code: u8} = @{ format!("{} (SOCKS reply {:#04x})", reply_message(*code), code) },
+}
+
+/// What a SOCKS5 reply code means, including Tor's extended errors for
+/// Onion services (proposal 304).
+fn reply_message(code: u8) -> &'static str {
+    match code {
+        0x01 => "general SOCKS server failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        0xf0 => "onion service descriptor not found",
+        0xf1 => "onion service descriptor is invalid",
+        0xf2 => "onion service introduction failed",
+        0xf3 => "onion service rendezvous failed",
+        0xf4 => "onion service client authorization missing",
+        0xf5 => "onion service client authorization incorrect",
+        0xf6 => "onion address is invalid",
+        0xf7 => "onion service introduction timed out",
+        _ => "unknown SOCKS reply",
+    }
+}
+
+impl PreflightError {
+    pub fn failure_kind(&self) -> FailureKind {
+        match self {
+            PreflightError::Io { .. } | PreflightError::Proxy { .. } => {
+                FailureKind::TorProxyUnreachable
+            }
+            PreflightError::Timeout => FailureKind::Timeout,
+            PreflightError::Rejected { code } => match code {
+                0xf0 | 0xf1 => FailureKind::DescriptorNotFound,
+                0x05 => FailureKind::ConnectionRefused,
+                0x06 | 0xf7 => FailureKind::Timeout,
+                0xf6 => FailureKind::MalformedAddress,
+                0x01..=0x04 | 0xf2 | 0xf3 => FailureKind::TorCircuit,
+                _ => FailureKind::Other,
+            },
+        }
+    }
+}
+
+/// Asks the Tor SOCKS proxy at `proxy` (a `socks5h://host:port` URL) to
+/// connect to port 80 of an Onion service, without sending a request. This
+/// tells a service that is offline, because Tor can't find its descriptor,
+/// apart from one whose web server is broken. Tor only reports the reason
+/// a connection failed if the `SocksPort` has the `ExtendedErrors` flag.
+///
+/// `credentials` should be those the scan's requests use, so that Tor can
+/// reuse the circuit it builds for them.
+pub async fn check_onion(
+    proxy: &str,
+    host: &str,
+    credentials: Option<(&str, &str)>,
+    timeout: Duration,
+) -> Result<(), PreflightError> {
+    open_stream(proxy, host, 80, credentials, timeout)
+        .await
+        .map(|_| ())
+}
+
+/// Opens a connection to `port` of `host` through the Tor SOCKS proxy at
+/// `proxy`, as `check_onion` does, for the caller to talk over.
+pub async fn open_stream(
+    proxy: &str,
+    host: &str,
+    port: u16,
+    credentials: Option<(&str, &str)>,
+    timeout: Duration,
+) -> Result<TcpStream, PreflightError> {
+    match tokio::time::timeout(timeout, connect(proxy, host, port, credentials)).await {
+        Ok(result) => result,
+        Err(_) => Err(PreflightError::Timeout),
+    }
+}
+
+async fn connect(
+    proxy: &str,
+    host: &str,
+    port: u16,
+    credentials: Option<(&str, &str)>,
+) -> Result<TcpStream, PreflightError> {
+    let addr = proxy.split_once("://").map_or(proxy, |(_, rest)| rest);
+    let addr = addr.rsplit_once('@').map_or(addr, |(_, addr)| addr);
+    let mut stream = TcpStream::connect(addr.trim_end_matches('/')).await?;
+
+    let method = if credentials.is_some() { 0x02 } else { 0x00 };
+    stream.write_all(&[0x05, 0x01, method]).await?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice != [0x05, method] {
+        return Err(PreflightError::Proxy {
+            message: "SOCKS proxy refused the authentication method".to_owned(),
+        });
+    }
+    if let Some((username, password)) = credentials {
+        let mut auth = vec![0x01, username.len() as u8];
+        auth.extend(username.as_bytes());
+        auth.push(password.len() as u8);
+        auth.extend(password.as_bytes());
+        stream.write_all(&auth).await?;
+        let mut status = [0u8; 2];
+        stream.read_exact(&mut status).await?;
+        if status[1] != 0x00 {
+            return Err(PreflightError::Proxy {
+                message: "SOCKS proxy rejected the credentials".to_owned(),
+            });
+        }
+    }
+
+    let mut request = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+    request.extend(host.as_bytes());
+    request.extend(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0x00 {
+        return Err(PreflightError::Rejected { code: reply[1] });
+    }
+    // Skip the address the proxy bound, whose length depends on its type.
+    let skip = match reply[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        _ => 0,
+    };
+    let mut bound = vec![0u8; skip + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(stream)
+}
@@ -0,0 +1,83 @@
+use crate::config::PublishConfig;
+use crate::failure::FailureKind;
+use crate::ScanResults;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::error::Error;
+
+/// One instance's result, as published to the directory.
+#[derive(Serialize, Debug)]
+pub struct PublishedResult {
+    pub onion_address: String,
+    pub title: String,
+    pub landing_page_url: String,
+    pub available: bool,
+    /// The SecureDrop version the instance reported, if it responded.
+    pub sd_version: Option<String>,
+    pub latency_ms: Option<u64>,
+    /// Why the instance could not be scanned, if it couldn't.
+    pub failure: Option<FailureKind>,
+}
+
+/// The results of a scan, as published to the directory, so it can show
+/// how fresh each site's availability is.
+#[derive(Serialize, Debug)]
+pub struct Publication {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub results: Vec<PublishedResult>,
+}
+
+impl Publication {
+    /// The parts of a scan's results fit for the public directory. Error
+    /// messages, which may include internal details, are left out.
+    pub fn new(results: &ScanResults) -> Publication {
+        Publication {
+            started_at: results.scan.started_at,
+            finished_at: results.scan.finished_at,
+            results: results
+                .instances
+                .iter()
+                .map(|i| PublishedResult {
+                    onion_address: i.onion_address.to_owned(),
+                    title: i.title.to_owned(),
+                    landing_page_url: i.landing_page_url.to_owned(),
+                    available: i.available,
+                    sd_version: i.metadata.as_ref().map(|m| m.sd_version.to_owned()),
+                    latency_ms: i.latency_ms,
+                    failure: i.failure_kind(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// POSTs a scan's results to the configured endpoint, authenticating with
+/// its token as the securedrop.org API expects.
+pub async fn publish_results(
+    config: &PublishConfig,
+    results: &ScanResults,
+) -> Result<(), Box<dyn Error>> {
+    let publication = Publication::new(results);
+    debug!(
+        "Publishing {} results to {}",
+        publication.results.len(),
+        config.url
+    );
+    reqwest::Client::new()
+        .post(&config.url)
+        .header(
+            reqwest::header::AUTHORIZATION,
+            format!("Token {}", config.token),
+        )
+        .json(&publication)
+        .send()
+        .await?
+        .error_for_status()?;
+    info!(
+        "Published {} results to {}",
+        publication.results.len(),
+        config.url
+    );
+    Ok(())
+}
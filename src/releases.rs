@@ -0,0 +1,147 @@
+use crate::color::{paint, Color};
+use crate::SDDirectoryInstance;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// The GitHub API listing SecureDrop's releases.
+pub const RELEASES_URL: &str = "https://api.github.com/repos/freedomofpress/securedrop/releases";
+
+/// SecureDrop releases as of this version of sdstatus, newest first. Used
+/// when the releases can't be fetched, and to count releases behind a
+/// version pinned with `--latest-version`.
+pub const KNOWN_RELEASES: &[&str] = &[
+    "2.12.1", "2.12.0", "2.11.1", "2.11.0", "2.10.0", "2.9.0", "2.8.0", "2.7.0", "2.6.1", "2.6.0",
+    "2.5.2", "2.5.1", "2.5.0", "2.4.2", "2.4.1", "2.4.0", "2.3.2", "2.3.1", "2.3.0", "2.2.1",
+    "2.2.0", "2.1.0", "2.0.2", "2.0.1", "2.0.0", "1.8.2", "1.8.1", "1.8.0", "1.7.1", "1.7.0",
+    "1.6.0", "1.5.0", "1.4.1", "1.4.0", "1.3.0", "1.2.2", "1.2.1", "1.2.0", "1.1.0", "1.0.0",
+];
+
+#[derive(Deserialize, Debug)]
+struct Release {
+    tag_name: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+}
+
+/// Parses a version such as `2.5.1` into its numeric components, so
+/// versions compare numerically rather than as strings.
+pub fn parse_version(version: &str) -> Option<Vec<u64>> {
+    version
+        .trim()
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect()
+}
+
+/// Fetches the versions of SecureDrop's published releases from the GitHub
+/// releases API at `url`, newest first. Drafts, pre-releases and tags that
+/// aren't plain version numbers are left out.
+pub async fn fetch_releases(url: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    info!("Fetching SecureDrop releases from {}", url);
+    let releases: Vec<Release> = reqwest::Client::new()
+        .get(url)
+        .query(&[("per_page", "100")])
+        // GitHub rejects API requests without a User-Agent.
+        .header(reqwest::header::USER_AGENT, "sdstatus")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let mut versions: Vec<(Vec<u64>, String)> = releases
+        .into_iter()
+        .filter(|r| !r.draft && !r.prerelease)
+        .filter_map(|r| parse_version(&r.tag_name).map(|v| (v, r.tag_name)))
+        .collect();
+    versions.sort_by(|a, b| b.0.cmp(&a.0));
+    Ok(versions.into_iter().map(|(_, tag)| tag).collect())
+}
+
+/// Lists the releases up to and including `latest`, newest first, from
+/// the known releases, so the report can be built without fetching them.
+pub fn releases_up_to(latest: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let version =
+        parse_version(latest).ok_or_else(|| format!("Invalid release version {}", latest))?;
+    let mut releases = vec![latest.trim().to_owned()];
+    releases.extend(
+        KNOWN_RELEASES
+            .iter()
+            .filter(|r| parse_version(r).is_some_and(|r| r < version))
+            .map(|r| r.to_string()),
+    );
+    Ok(releases)
+}
+
+/// How far behind the latest release the available instances are.
+#[derive(Serialize, Debug)]
+pub struct OutdatedReport {
+    pub latest: String,
+    /// Sites, as `title (version)`, keyed by how many releases behind they
+    /// are.
+    pub behind: BTreeMap<usize, Vec<String>>,
+    pub up_to_date: Vec<String>,
+    /// Sites reporting a version that couldn't be parsed.
+    pub unknown: Vec<String>,
+}
+
+/// Compares each available instance's `sd_version` against `releases`
+/// (newest first), counting how many releases are newer than the one it
+/// runs.
+pub fn generate_outdated_report(
+    instances: &[SDDirectoryInstance],
+    releases: &[String],
+) -> OutdatedReport {
+    let parsed: Vec<Vec<u64>> = releases.iter().filter_map(|r| parse_version(r)).collect();
+    let mut report = OutdatedReport {
+        latest: releases.first().cloned().unwrap_or_default(),
+        behind: BTreeMap::new(),
+        up_to_date: vec![],
+        unknown: vec![],
+    };
+    for i in instances {
+        if let Some(m) = &i.metadata {
+            let site = format!("{} ({})", i.title, m.sd_version);
+            match parse_version(&m.sd_version) {
+                Some(version) => match parsed.iter().filter(|r| **r > version).count() {
+                    0 => report.up_to_date.push(site),
+                    n => report.behind.entry(n).or_default().push(site),
+                },
+                None => report.unknown.push(site),
+            }
+        }
+    }
+    report
+}
+
+/// Renders the outdated-version report as human-readable text. With
+/// `color`, the groups behind the latest release are headed in yellow.
+pub fn format_outdated_report(report: &OutdatedReport, color: bool) -> String {
+    let mut text = format!("Latest release: {}\n\n", report.latest);
+    for (n, sites) in &report.behind {
+        let heading = format!("{} release{} behind", n, if *n == 1 { "" } else { "s" });
+        text += &format!(
+            "{} ({}):\n  {}\n\n",
+            paint(&heading, Color::Yellow, color),
+            sites.len(),
+            sites.join("\n  ")
+        );
+    }
+    if !report.unknown.is_empty() {
+        text += &format!(
+            "Unknown version ({}):\n  {}\n\n",
+            report.unknown.len(),
+            report.unknown.join("\n  ")
+        );
+    }
+    let behind: usize = report.behind.values().map(|sites| sites.len()).sum();
+    text += &format!(
+        "{} of {} available instances are behind the latest release\n\n",
+        behind,
+        behind + report.up_to_date.len() + report.unknown.len()
+    );
+    text
+}
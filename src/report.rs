@@ -0,0 +1,595 @@
+use crate::color::{paint, Color};
+use crate::failure::FailureKind;
+use crate::i18n::Messages;
+use crate::locale::instance_locales;
+use crate::{LatencySamples, SDDirectoryInstance};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+// Ubuntu releases no longer supported for SecureDrop servers, as reported
+// in the `server_os` metadata field.
+pub const DEPRECATED_SERVER_OS: &[&str] = &["14.04", "16.04", "20.04"];
+
+// The locales SecureDrop ships translations for, including the source
+// locale, as BCP 47 tags (see `locale::normalize_locale`).
+pub const SHIPPED_LOCALES: &[&str] = &[
+    "ar", "ca", "cs", "de-DE", "el", "en-US", "es-ES", "fr-FR", "hi", "is", "it-IT", "nb-NO", "nl",
+    "pt-BR", "pt-PT", "ro", "ru", "sk", "sv", "tr", "zh-Hans", "zh-Hant",
+];
+
+/// Inspects the metadata of scanned instances for languages, and maps
+/// each locale to the titles of the sites supporting it. Locales are
+/// normalized to BCP 47 tags, so `pt_BR` and `pt-BR` are one; values that
+/// aren't locales are left out (see `locale::list_invalid_locales`).
+pub fn generate_l10n_report(instances: &[SDDirectoryInstance]) -> BTreeMap<String, Vec<String>> {
+    group_by_locale(instances)
+        .into_iter()
+        .map(|(l, sites)| (l, sites.iter().map(|i| i.title.to_owned()).collect()))
+        .collect()
+}
+
+/// Groups the instances by the locales they enable, as the localization
+/// report does, keeping the instances themselves so they can be sorted
+/// (see `sort_grouped_report`).
+pub fn group_by_locale(
+    instances: &[SDDirectoryInstance],
+) -> BTreeMap<String, Vec<&SDDirectoryInstance>> {
+    let mut locales: BTreeMap<String, Vec<&SDDirectoryInstance>> = BTreeMap::new();
+    for i in instances {
+        // Down instances have no metadata, and so no locales.
+        for l in instance_locales(i) {
+            locales.entry(l).or_default().push(i);
+        }
+    }
+    locales
+}
+
+/// Lists the available sites that don't enable `locale`, a normalized
+/// locale: the inverse of the localization report. Down sites are left
+/// out, as their locales aren't known.
+pub fn generate_missing_locale_report<'a>(
+    instances: &'a [SDDirectoryInstance],
+    locale: &str,
+) -> Vec<&'a SDDirectoryInstance> {
+    instances
+        .iter()
+        .filter(|i| i.metadata.is_some())
+        .filter(|i| !instance_locales(i).iter().any(|l| l == locale))
+        .collect()
+}
+
+/// Groups the titles of available instances by the SecureDrop release
+/// they report running.
+pub fn generate_versions_report(
+    instances: &[SDDirectoryInstance],
+) -> BTreeMap<String, Vec<String>> {
+    let mut versions: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for i in instances {
+        if let Some(m) = &i.metadata {
+            versions
+                .entry(m.sd_version.to_owned())
+                .or_default()
+                .push(i.title.to_owned());
+        }
+    }
+    versions
+}
+
+/// Groups the titles of instances by the tags given to their Onion
+/// addresses (see `Config::tags`), whether or not they were reachable.
+/// Instances without tags are left out.
+pub fn generate_tags_report(
+    instances: &[SDDirectoryInstance],
+    tags: &BTreeMap<String, Vec<String>>,
+) -> BTreeMap<String, Vec<String>> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for i in instances {
+        for tag in tags.get(&i.onion_address).into_iter().flatten() {
+            groups
+                .entry(tag.to_owned())
+                .or_default()
+                .push(i.title.to_owned());
+        }
+    }
+    groups
+}
+
+/// Groups the titles of available instances by the Ubuntu release their
+/// servers report running.
+pub fn generate_os_report(instances: &[SDDirectoryInstance]) -> BTreeMap<String, Vec<String>> {
+    let mut releases: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for i in instances {
+        if let Some(m) = &i.metadata {
+            releases
+                .entry(m.server_os.to_owned())
+                .or_default()
+                .push(i.title.to_owned());
+        }
+    }
+    releases
+}
+
+/// How the locales enabled across instances line up with those SecureDrop
+/// ships.
+#[derive(Serialize, Debug)]
+pub struct LocaleCoverage {
+    /// Shipped locales, with the number of instances enabling each.
+    pub shipped: BTreeMap<String, usize>,
+    /// Shipped locales no instance has enabled.
+    pub unused: Vec<String>,
+    /// Enabled locales that SecureDrop doesn't ship, with the titles of the
+    /// sites enabling them.
+    pub unofficial: BTreeMap<String, Vec<String>>,
+}
+
+/// Compares the locales in a localization report (see
+/// `generate_l10n_report`) to those SecureDrop ships.
+pub fn generate_locale_coverage(locales: &BTreeMap<String, Vec<String>>) -> LocaleCoverage {
+    let shipped: BTreeMap<String, usize> = SHIPPED_LOCALES
+        .iter()
+        .map(|l| {
+            (
+                l.to_string(),
+                locales.get(*l).map_or(0, |sites| sites.len()),
+            )
+        })
+        .collect();
+    LocaleCoverage {
+        unused: shipped
+            .iter()
+            .filter(|(_, n)| **n == 0)
+            .map(|(l, _)| l.to_owned())
+            .collect(),
+        unofficial: locales
+            .iter()
+            .filter(|(l, _)| !SHIPPED_LOCALES.contains(&l.as_str()))
+            .map(|(l, sites)| (l.to_owned(), sites.to_vec()))
+            .collect(),
+        shipped,
+    }
+}
+
+/// Renders the locale coverage report as human-readable text.
+pub fn format_locale_coverage(coverage: &LocaleCoverage, messages: &Messages) -> String {
+    let mut report = format!("{}\n", messages.get("shipped-locales"));
+    for (locale, n) in &coverage.shipped {
+        report += &format!("  {:<8} {}\n", locale, n);
+    }
+    report += &format!(
+        "\n{}:\n  {}\n\n",
+        messages.count("unused-locales", coverage.unused.len()),
+        coverage.unused.join("\n  ")
+    );
+    report += &format!(
+        "{}:\n",
+        messages.count("unofficial-locales", coverage.unofficial.len())
+    );
+    for (locale, sites) in &coverage.unofficial {
+        report += &format!("  {} ({}): {}\n", locale, sites.len(), sites.join(", "));
+    }
+    report
+}
+
+/// How to order the groups of a grouped report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupOrder {
+    /// Alphabetically by the key, e.g. the locale.
+    Name,
+    /// By the number of sites in the group, largest first.
+    Count,
+}
+
+/// How to order the sites within each group of a grouped report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SiteOrder {
+    Title,
+    Onion,
+}
+
+/// Orders a grouped report of instances, returning the sites' titles.
+/// Without a site order, sites are left in the order they were scanned
+/// in.
+pub fn sort_grouped_report(
+    groups: BTreeMap<String, Vec<&SDDirectoryInstance>>,
+    group_order: GroupOrder,
+    site_order: Option<SiteOrder>,
+) -> Vec<(String, Vec<String>)> {
+    let mut groups: Vec<(String, Vec<&SDDirectoryInstance>)> = groups.into_iter().collect();
+    for (_, sites) in &mut groups {
+        match site_order {
+            Some(SiteOrder::Title) => sites.sort_by(|a, b| a.title.cmp(&b.title)),
+            Some(SiteOrder::Onion) => sites.sort_by(|a, b| a.onion_address.cmp(&b.onion_address)),
+            None => {}
+        }
+    }
+    if group_order == GroupOrder::Count {
+        // The sort is stable, so groups of equal size stay alphabetical.
+        groups.sort_by_key(|(_, sites)| std::cmp::Reverse(sites.len()));
+    }
+    groups
+        .into_iter()
+        .map(|(key, sites)| (key, sites.iter().map(|i| i.title.to_owned()).collect()))
+        .collect()
+}
+
+/// The availability of a single instance, as observed during a scan.
+#[derive(Serialize, Debug)]
+pub struct StatusEntry {
+    pub title: String,
+    pub onion_address: String,
+    pub available: bool,
+    pub http_status: Option<u16>,
+    pub latency_ms: Option<u64>,
+    pub duration_ms: Option<u64>,
+    pub attempts: u32,
+    pub error: Option<String>,
+    pub failure: Option<FailureKind>,
+    /// Whether the published journalist key matches the metadata, if it
+    /// was checked.
+    pub key_matches: Option<bool>,
+    pub samples: Option<LatencySamples>,
+}
+
+/// An instance whose metadata could not be retrieved during a scan.
+#[derive(Serialize, Debug)]
+pub struct Failure {
+    pub title: String,
+    pub onion_address: String,
+    pub kind: FailureKind,
+    pub error: String,
+}
+
+/// Lists the instances that failed during a scan, so reports can show
+/// them separately from those built on retrieved metadata.
+pub fn list_failures(instances: &[SDDirectoryInstance]) -> Vec<Failure> {
+    instances
+        .iter()
+        .filter(|i| !i.available)
+        .map(|i| Failure {
+            title: i.title.to_owned(),
+            onion_address: i.onion_address.to_owned(),
+            kind: i.failure_kind().unwrap_or(FailureKind::Other),
+            error: i
+                .error
+                .to_owned()
+                .unwrap_or_else(|| "Metadata not available".to_owned()),
+        })
+        .collect()
+}
+
+/// Renders the list of failed instances as human-readable text.
+pub fn format_failures(failures: &[Failure], messages: &Messages) -> String {
+    let mut report = format!("{}:\n", messages.count("unavailable", failures.len()));
+    for f in failures {
+        report += &format!(
+            "  {} ({}) [{}]: {}\n",
+            f.title, f.onion_address, f.kind, f.error
+        );
+    }
+    report
+}
+
+/// Counts failed instances by why they failed, most common first.
+pub fn count_failure_kinds<'a>(
+    kinds: impl IntoIterator<Item = &'a FailureKind>,
+) -> Vec<(FailureKind, usize)> {
+    let mut counts: BTreeMap<FailureKind, usize> = BTreeMap::new();
+    for kind in kinds {
+        *counts.entry(*kind).or_default() += 1;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+    counts
+}
+
+/// Renders failure counts as e.g. `3 timeout, 1 Tor circuit failure`.
+pub fn format_failure_kinds(counts: &[(FailureKind, usize)]) -> String {
+    counts
+        .iter()
+        .map(|(kind, n)| format!("{} {}", n, kind))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The spread of a set of timings, in milliseconds.
+#[derive(Serialize, Debug)]
+pub struct LatencySummary {
+    pub min: u64,
+    pub median: u64,
+    pub p95: u64,
+}
+
+/// Summarizes timings by their minimum, median and 95th percentile (by
+/// the nearest-rank method), or None if there are none.
+pub fn summarize_latency(mut values: Vec<u64>) -> Option<LatencySummary> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let rank = |p: f64| values[((p * values.len() as f64).ceil() as usize).max(1) - 1];
+    Some(LatencySummary {
+        min: values[0],
+        median: rank(0.5),
+        p95: rank(0.95),
+    })
+}
+
+/// Renders the time to first byte and total duration of the responses
+/// in a status report, one line each.
+pub fn format_latency_summary(entries: &[StatusEntry]) -> String {
+    let ttfb = entries.iter().filter_map(|e| e.latency_ms).collect();
+    let duration = entries.iter().filter_map(|e| e.duration_ms).collect();
+    let mut report = String::from("");
+    for (name, values) in [("Time to first byte", ttfb), ("Total duration", duration)] {
+        if let Some(s) = summarize_latency(values) {
+            report += &format!(
+                "{}: min {} ms, median {} ms, p95 {} ms\n",
+                name, s.min, s.median, s.p95
+            );
+        }
+    }
+    report
+}
+
+/// Summarizes which scanned instances were reachable, in the order given.
+pub fn generate_status_report(instances: &[SDDirectoryInstance]) -> Vec<StatusEntry> {
+    instances
+        .iter()
+        .map(|i| StatusEntry {
+            title: i.title.to_owned(),
+            onion_address: i.onion_address.to_owned(),
+            available: i.available,
+            http_status: i.http_status,
+            latency_ms: i.latency_ms,
+            duration_ms: i.duration_ms,
+            attempts: i.attempts,
+            error: i.error.to_owned(),
+            failure: i.failure_kind(),
+            key_matches: i.key_matches,
+            samples: i.samples.clone(),
+        })
+        .collect()
+}
+
+/// Renders the availability report as human-readable text, one line per
+/// instance followed by a count of reachable instances. Titles are padded
+/// so onion addresses line up, and with `color`, statuses are colored.
+pub fn format_status_report(entries: &[StatusEntry], color: bool) -> String {
+    let mut report = String::from("");
+    let width = entries
+        .iter()
+        .map(|e| e.title.chars().count())
+        .max()
+        .unwrap_or(0);
+    for e in entries {
+        let status = e
+            .http_status
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "-".to_owned());
+        let latency = e
+            .latency_ms
+            .map(|l| format!("{} ms", l))
+            .unwrap_or_else(|| "-".to_owned());
+        let (available, available_color) = if e.available {
+            ("UP  ", Color::Green)
+        } else {
+            ("DOWN", Color::Red)
+        };
+        report += &format!(
+            "{}  {:>3}  {:>8}  {:<width$}  {}",
+            paint(available, available_color, color),
+            status,
+            latency,
+            e.title,
+            e.onion_address,
+            width = width
+        );
+        if let Some(failure) = &e.failure {
+            report += &format!(" [{}]", failure);
+        }
+        if let Some(error) = &e.error {
+            report += &format!(": {}", error);
+        }
+        if e.key_matches == Some(false) {
+            report += &paint(" [journalist key mismatch]", Color::Red, color);
+        }
+        if let Some(s) = &e.samples {
+            report += &format!(" ({} of {} fetches", s.succeeded, s.fetches);
+            if let (Some(median), Some(p95)) = (s.median_ms, s.p95_ms) {
+                report += &format!(", median {} ms, p95 {} ms", median, p95);
+            }
+            report += ")";
+        }
+        report += "\n";
+    }
+    let up = entries.iter().filter(|e| e.available).count();
+    report += &format!("\n{} of {} instances available\n", up, entries.len());
+    let failures = count_failure_kinds(entries.iter().filter_map(|e| e.failure.as_ref()));
+    if !failures.is_empty() {
+        report += &format!("Failures: {}\n", format_failure_kinds(&failures));
+    }
+    report += &format_latency_summary(entries);
+    report
+}
+
+/// Renders a report grouping site titles by some key (locale, version, etc.)
+/// as human-readable text.
+pub fn format_grouped_report<'a>(
+    groups: impl IntoIterator<Item = (&'a String, &'a Vec<String>)>,
+) -> String {
+    let mut report = String::from("");
+    for (key, sites) in groups {
+        report += &format!("{} ({}):\n  {}\n\n", &key, &sites.len(), sites.join("\n  "));
+    }
+    report
+}
+
+/// Writes one CSV row per instance, leaving the metadata columns empty
+/// for instances that were unavailable.
+pub fn write_csv<W: std::io::Write>(
+    instances: &[SDDirectoryInstance],
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record([
+        "title",
+        "onion_address",
+        "sd_version",
+        "server_os",
+        "gpg_fpr",
+        "supported_languages",
+    ])?;
+    for i in instances {
+        match &i.metadata {
+            Some(m) => wtr.write_record([
+                &i.title,
+                &i.onion_address,
+                &m.sd_version,
+                &m.server_os,
+                &m.gpg_fpr,
+                &m.supported_languages.join(" "),
+            ])?,
+            None => wtr.write_record([&i.title, &i.onion_address, "", "", "", ""])?,
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Which locales each available instance enables.
+#[derive(Serialize, Debug)]
+pub struct LocaleMatrix {
+    /// Every locale enabled on any instance, alphabetically.
+    pub locales: Vec<String>,
+    pub instances: Vec<LocaleMatrixRow>,
+}
+
+/// An instance's row of the locale matrix.
+#[derive(Serialize, Debug)]
+pub struct LocaleMatrixRow {
+    pub title: String,
+    pub onion_address: String,
+    pub locales: BTreeMap<String, bool>,
+}
+
+/// Builds the instance × locale matrix from the metadata of scanned
+/// instances. Instances without metadata are left out.
+pub fn generate_locale_matrix(instances: &[SDDirectoryInstance]) -> LocaleMatrix {
+    let locales: Vec<String> = generate_l10n_report(instances).into_keys().collect();
+    let rows = instances
+        .iter()
+        .filter_map(|i| {
+            i.metadata.as_ref()?;
+            let enabled = instance_locales(i);
+            Some(LocaleMatrixRow {
+                title: i.title.to_owned(),
+                onion_address: i.onion_address.to_owned(),
+                locales: locales
+                    .iter()
+                    .map(|l| (l.to_owned(), enabled.contains(l)))
+                    .collect(),
+            })
+        })
+        .collect();
+    LocaleMatrix {
+        locales,
+        instances: rows,
+    }
+}
+
+/// Writes the locale matrix as CSV, with a column per locale holding
+/// `true` or `false`.
+pub fn write_locale_matrix_csv<W: std::io::Write>(
+    matrix: &LocaleMatrix,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    let mut header = vec!["title", "onion_address"];
+    header.extend(matrix.locales.iter().map(|l| l.as_str()));
+    wtr.write_record(&header)?;
+    for row in &matrix.instances {
+        let mut record = vec![row.title.to_owned(), row.onion_address.to_owned()];
+        record.extend(row.locales.values().map(|enabled| enabled.to_string()));
+        wtr.write_record(&record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SDMetadata;
+
+    fn instance(title: &str, onion: &str, languages: &[&str]) -> SDDirectoryInstance {
+        SDDirectoryInstance {
+            title: title.to_owned(),
+            metadata: Some(SDMetadata {
+                supported_languages: languages.iter().map(|l| l.to_string()).collect(),
+                ..SDMetadata::default()
+            }),
+            ..SDDirectoryInstance::from_onion(onion)
+        }
+    }
+
+    #[test]
+    fn sorts_sites_by_their_own_onion() {
+        // Two sites share a title, and the one enabling de-DE sorts last.
+        let instances = vec![
+            instance("Tribune", "aaaa.onion", &["en_US"]),
+            instance("Herald", "mmmm.onion", &["de_DE", "en_US"]),
+            instance("Tribune", "zzzz.onion", &["de_DE"]),
+            instance("", "bbbb.onion", &["de_DE"]),
+        ];
+        let sorted = sort_grouped_report(
+            group_by_locale(&instances),
+            GroupOrder::Name,
+            Some(SiteOrder::Onion),
+        );
+        assert_eq!(
+            sorted,
+            vec![
+                (
+                    "de-DE".to_owned(),
+                    vec!["".to_owned(), "Herald".to_owned(), "Tribune".to_owned()]
+                ),
+                (
+                    "en-US".to_owned(),
+                    vec!["Tribune".to_owned(), "Herald".to_owned()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn sorts_groups_and_sites() {
+        let instances = vec![
+            instance("Tribune", "aaaa.onion", &["en_US"]),
+            instance("Herald", "mmmm.onion", &["de_DE", "en_US"]),
+        ];
+        let sorted = sort_grouped_report(
+            group_by_locale(&instances),
+            GroupOrder::Count,
+            Some(SiteOrder::Title),
+        );
+        let keys: Vec<_> = sorted
+            .iter()
+            .map(|(k, sites)| (k.as_str(), sites.clone()))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("en-US", vec!["Herald".to_owned(), "Tribune".to_owned()]),
+                ("de-DE", vec!["Herald".to_owned()]),
+            ]
+        );
+        // Without a site order, sites stay in scan order.
+        let missing = generate_missing_locale_report(&instances, "de-DE");
+        let groups = BTreeMap::from([("de-DE".to_owned(), missing)]);
+        assert_eq!(
+            sort_grouped_report(groups, GroupOrder::Name, None),
+            vec![("de-DE".to_owned(), vec!["Tribune".to_owned()])]
+        );
+    }
+}
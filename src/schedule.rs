@@ -0,0 +1,182 @@
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::str::FromStr;
+
+/// When the daemon runs scans: every so often, or on a cron schedule.
+pub enum Schedule {
+    Interval(Duration),
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    /// Parses a cron expression. The usual five fields (minute, hour, day
+    /// of month, month, day of week) are accepted, with days of the week
+    /// numbered as in crontab, as well as the six or seven field form with
+    /// seconds (and years) of the `cron` crate, which numbers them 1 (Sunday)
+    /// to 7 (Saturday).
+    pub fn cron(expression: &str) -> Result<Schedule, Box<dyn Error>> {
+        let expression = expression.trim();
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let expression = if fields.len() == 5 {
+            let days = crontab_days(fields[4])
+                .map_err(|e| format!("Invalid cron expression {}, {}", expression, e))?;
+            format!("0 {} {}", fields[..4].join(" "), days)
+        } else {
+            expression.to_owned()
+        };
+        let schedule = cron::Schedule::from_str(&expression)
+            .map_err(|e| format!("Invalid cron expression {}, {}", expression, e))?;
+        Ok(Schedule::Cron(Box::new(schedule)))
+    }
+
+    /// The time of the next scan, given when the last one started (if any)
+    /// and the current time. Runs that were missed because the previous
+    /// scan overran are skipped rather than run back to back, so scans
+    /// never overlap or pile up.
+    pub fn next(&self, last_started: Option<DateTime<Utc>>, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Schedule::Interval(interval) => {
+                let mut next = match last_started {
+                    Some(last) => last + *interval,
+                    None => return now,
+                };
+                if next < now {
+                    warn!("Scan overran the interval, skipping missed runs");
+                    while next < now {
+                        next += *interval;
+                    }
+                }
+                next
+            }
+            Schedule::Cron(schedule) => {
+                if let Some(last) = last_started {
+                    if schedule
+                        .after(&last)
+                        .next()
+                        .is_some_and(|missed| missed < now)
+                    {
+                        warn!("Scan overran its schedule, skipping missed runs");
+                    }
+                }
+                schedule.after(&now).next().unwrap_or(now)
+            }
+        }
+    }
+}
+
+/// Translates a crontab day of week field, where 0 and 7 are Sunday and
+/// 1 to 6 Monday to Saturday, to the numbering of the `cron` crate. Ranges
+/// and steps are spelled out as lists, as a range ending on Sunday (7)
+/// wraps around in the crate's numbering. Days given by name don't change.
+fn crontab_days(field: &str) -> Result<String, String> {
+    let mut items = Vec::new();
+    let mut days = BTreeSet::new();
+    for item in field.split(',') {
+        if item == "*" || item == "?" || item.chars().any(|c| c.is_ascii_alphabetic()) {
+            items.push(item.to_owned());
+            continue;
+        }
+        let invalid = || format!("invalid day of week {}", item);
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse().map_err(|_| invalid())?),
+            None => (item, 1),
+        };
+        let parse = |day: &str| match day.parse::<u32>() {
+            Ok(day) if day <= 7 => Ok(day),
+            _ => Err(invalid()),
+        };
+        let (first, last) = match range.split_once('-') {
+            Some((first, last)) => (parse(first)?, parse(last)?),
+            None if range == "*" => (0, 6),
+            None if item.contains('/') => (parse(range)?, 6),
+            None => (parse(range)?, parse(range)?),
+        };
+        if step == 0 || first > last {
+            return Err(invalid());
+        }
+        days.extend((first..=last).step_by(step).map(|day| day % 7 + 1));
+    }
+    items.extend(days.iter().map(|day| day.to_string()));
+    Ok(items.join(","))
+}
+
+/// Delays a scheduled time by a random amount of up to `jitter`, so that
+/// many daemons on the same schedule don't all scan at once.
+pub fn add_jitter(time: DateTime<Utc>, jitter: Duration) -> DateTime<Utc> {
+    let max = jitter.num_milliseconds();
+    if max <= 0 {
+        return time;
+    }
+    time + Duration::milliseconds(rand::thread_rng().gen_range(0..=max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, TimeZone, Weekday};
+
+    /// The weekdays of the next runs of a cron schedule, from a Wednesday.
+    fn weekdays(expression: &str, runs: usize) -> Vec<Weekday> {
+        let mut now = Utc.ymd(2026, 10, 14).and_hms(0, 0, 0);
+        let schedule = Schedule::cron(expression).unwrap();
+        (0..runs)
+            .map(|_| {
+                now = schedule.next(None, now) + Duration::seconds(1);
+                now.weekday()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn crontab_days_of_week() {
+        assert_eq!(weekdays("0 9 * * 1", 1), [Weekday::Mon]);
+        assert_eq!(weekdays("0 9 * * 0", 1), [Weekday::Sun]);
+        assert_eq!(weekdays("0 9 * * 7", 1), [Weekday::Sun]);
+        assert_eq!(weekdays("0 9 * * 6", 1), [Weekday::Sat]);
+        assert_eq!(
+            weekdays("0 9 * * 1-5", 5),
+            [
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+                Weekday::Mon,
+                Weekday::Tue
+            ]
+        );
+        assert_eq!(
+            weekdays("0 9 * * 5-7", 3),
+            [Weekday::Fri, Weekday::Sat, Weekday::Sun]
+        );
+        assert_eq!(
+            weekdays("0 9 * * */3", 3),
+            [Weekday::Wed, Weekday::Sat, Weekday::Sun]
+        );
+        assert_eq!(weekdays("0 9 * * Mon,3", 2), [Weekday::Wed, Weekday::Mon]);
+    }
+
+    #[test]
+    fn crontab_day_translation() {
+        assert_eq!(crontab_days("*").unwrap(), "*");
+        assert_eq!(crontab_days("0").unwrap(), "1");
+        assert_eq!(crontab_days("0,7").unwrap(), "1");
+        assert_eq!(crontab_days("1-5").unwrap(), "2,3,4,5,6");
+        assert_eq!(crontab_days("1-7/2").unwrap(), "1,2,4,6");
+        assert_eq!(crontab_days("MON-FRI").unwrap(), "MON-FRI");
+        assert!(crontab_days("8").is_err());
+        assert!(crontab_days("5-1").is_err());
+        assert!(crontab_days("*/0").is_err());
+    }
+
+    #[test]
+    fn seconds_field_uses_the_crate_numbering() {
+        assert_eq!(weekdays("0 0 9 * * 1", 1), [Weekday::Sun]);
+    }
+
+    #[test]
+    fn invalid_cron_expressions() {
+        assert!(Schedule::cron("0 9 * * 8").is_err());
+        assert!(Schedule::cron("0 9 * *").is_err());
+    }
+}
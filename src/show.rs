@@ -0,0 +1,182 @@
+use crate::db::{Outcome, Storage};
+use crate::findings::{detect_findings, Finding};
+use crate::uptime::{format_uptime, generate_uptime_report, WindowUptime};
+use crate::SDDirectoryInstance;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::error::Error;
+
+/// How many of an instance's latest scans are listed.
+const RECENT_SCANS: usize = 10;
+
+/// Everything known about a single instance.
+#[derive(Serialize, Debug)]
+pub struct InstanceDetail {
+    pub instance: SDDirectoryInstance,
+    /// When the scan the instance's result comes from began, if known.
+    pub scanned_at: Option<DateTime<Utc>>,
+    pub findings: Vec<Finding>,
+    pub history: Option<InstanceHistory>,
+}
+
+impl InstanceDetail {
+    pub fn new(
+        instance: SDDirectoryInstance,
+        scanned_at: Option<DateTime<Utc>>,
+        history: Option<InstanceHistory>,
+    ) -> InstanceDetail {
+        InstanceDetail {
+            findings: detect_findings(std::slice::from_ref(&instance)),
+            instance,
+            scanned_at,
+            history,
+        }
+    }
+}
+
+/// What the history database records about an instance.
+#[derive(Serialize, Debug)]
+pub struct InstanceHistory {
+    pub last_available: Option<DateTime<Utc>>,
+    /// When the instance went down, if it is down.
+    pub down_since: Option<DateTime<Utc>>,
+    pub uptime: Vec<WindowUptime>,
+    /// The outcomes of the latest scans, newest first.
+    pub recent: Vec<RecentScan>,
+}
+
+/// Whether an instance was available in one scan.
+#[derive(Serialize, Debug)]
+pub struct RecentScan {
+    pub scanned_at: DateTime<Utc>,
+    pub available: bool,
+}
+
+/// Gathers what the history database records about the instance at
+/// `onion_address`: when it was last seen up, its uptime over each of
+/// `windows` ending at `now`, and its latest scans.
+pub fn instance_history(
+    history: &dyn Storage,
+    onion_address: &str,
+    windows: &[(String, Duration)],
+    now: DateTime<Utc>,
+) -> Result<InstanceHistory, Box<dyn Error>> {
+    let longest = windows
+        .iter()
+        .map(|(_, length)| *length)
+        .max()
+        .unwrap_or_else(Duration::zero);
+    let mut outcomes: Vec<Outcome> = history
+        .outcomes_since(now - longest)?
+        .into_iter()
+        .filter(|o| o.onion_address == onion_address)
+        .collect();
+    let uptime = generate_uptime_report(&outcomes, windows, now)
+        .pop()
+        .map(|e| e.windows)
+        .unwrap_or_default();
+    outcomes.reverse();
+    Ok(InstanceHistory {
+        last_available: history.last_available(onion_address)?,
+        down_since: history.down_since(onion_address)?,
+        uptime,
+        recent: outcomes
+            .iter()
+            .take(RECENT_SCANS)
+            .map(|o| RecentScan {
+                scanned_at: o.scanned_at,
+                available: o.available,
+            })
+            .collect(),
+    })
+}
+
+/// Formats a time to the minute, or `never` for no time.
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| "never".to_owned())
+}
+
+/// Renders everything known about an instance as human-readable text.
+pub fn format_instance_detail(detail: &InstanceDetail) -> String {
+    let i = &detail.instance;
+    let mut report = format!("{} ({})\n", i.title, i.onion_address);
+    if !i.landing_page_url.is_empty() {
+        report += &format!("Landing page: {}\n", i.landing_page_url);
+    }
+    if let Some(name) = &i.onion_name {
+        report += &format!("Onion name: {}\n", name);
+    }
+    report += "\n";
+    if let Some(scanned_at) = detail.scanned_at {
+        report += &format!("Scanned: {}\n", format_time(Some(scanned_at)));
+    }
+    report += &format!(
+        "Status: {}",
+        if i.available {
+            "available"
+        } else {
+            "unavailable"
+        }
+    );
+    if let Some(status) = i.http_status {
+        report += &format!(", HTTP {}", status);
+    }
+    if let Some(latency) = i.latency_ms {
+        report += &format!(", {} ms", latency);
+    }
+    report += "\n";
+    if let Some(error) = &i.error {
+        match i.failure_kind() {
+            Some(kind) => report += &format!("Error: [{}] {}\n", kind, error),
+            None => report += &format!("Error: {}\n", error),
+        }
+    }
+    if let Some(m) = &i.metadata {
+        report += &format!("\nSecureDrop version: {}\n", m.sd_version);
+        report += &format!("Server OS: {}\n", m.server_os);
+        report += &format!("Journalist key: {}", m.gpg_fpr);
+        match i.key_matches {
+            Some(true) => report += " (verified)",
+            Some(false) => report += " (does not match the published key)",
+            None => {}
+        }
+        report += "\n";
+        report += &format!("Source Interface: {}\n", m.v3_source_url);
+        if let Some(v2) = m.v2_source_url.as_deref().filter(|v2| !v2.is_empty()) {
+            report += &format!("v2 Source Interface: {}\n", v2);
+        }
+        report += &format!("Languages: {}\n", m.supported_languages.join(", "));
+    }
+    if let Some(h) = &detail.history {
+        report += &format!("\nLast available: {}\n", format_time(h.last_available));
+        if let Some(down_since) = h.down_since {
+            report += &format!("Down since: {}\n", format_time(Some(down_since)));
+        }
+        let uptime: Vec<_> = h
+            .uptime
+            .iter()
+            .map(|w| format!("{} {}", w.window, format_uptime(w.uptime)))
+            .collect();
+        if !uptime.is_empty() {
+            report += &format!("Uptime: {}\n", uptime.join(", "));
+        }
+        if !h.recent.is_empty() {
+            report += "Recent scans:\n";
+            for r in &h.recent {
+                report += &format!(
+                    "  {}  {}\n",
+                    format_time(Some(r.scanned_at)),
+                    if r.available { "UP" } else { "DOWN" }
+                );
+            }
+        }
+    }
+    if !detail.findings.is_empty() {
+        report += &format!("\nFindings ({}):\n", detail.findings.len());
+        for f in &detail.findings {
+            report += &format!("  [{}] {}\n", f.severity, f.message);
+        }
+    }
+    report
+}
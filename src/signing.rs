@@ -0,0 +1,135 @@
+use chrono::Utc;
+use minisign::{PublicKey, SecretKey, SignatureBox};
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Where the signature of a report is kept: next to it, with `.minisig`
+/// appended to its name, as minisign itself does.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".minisig");
+    PathBuf::from(name)
+}
+
+/// Signs the file at `path` with the minisign secret key at `key_path`,
+/// decrypting it with `password` (none for an unencrypted key), and
+/// writes the signature to `signature_path(path)`. The trusted comment
+/// holds when the file was signed and its name, as with `minisign -S`,
+/// so `minisign -V` can check the signature just as `verify_file` does.
+pub fn sign_file(
+    path: &Path,
+    key_path: &Path,
+    password: Option<&str>,
+) -> Result<PathBuf, Box<dyn Error>> {
+    // An empty password reads the key without prompting for one.
+    let key = SecretKey::from_file(key_path, Some(password.unwrap_or_default().to_owned()))
+        .map_err(|e| format!("Failed to read signing key {}, {}", key_path.display(), e))?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let comment = format!("timestamp:{}\tfile:{}", Utc::now().timestamp(), name);
+    let signature = minisign::sign(None, &key, File::open(path)?, Some(&comment), None)?;
+    let signature_path = signature_path(path);
+    std::fs::write(&signature_path, signature.to_string())
+        .map_err(|e| format!("Failed to write {}, {}", signature_path.display(), e))?;
+    Ok(signature_path)
+}
+
+/// Checks the signature at `signature_path` of the file at `path` against
+/// the minisign public key at `key_path`, returning its trusted comment.
+/// The file must have the name the comment gives it, or a signature of
+/// one report could pass for that of another, renamed in its place.
+pub fn verify_file(
+    path: &Path,
+    signature_path: &Path,
+    key_path: &Path,
+) -> Result<String, Box<dyn Error>> {
+    let key = PublicKey::from_file(key_path)
+        .map_err(|e| format!("Failed to read public key {}, {}", key_path.display(), e))?;
+    let signature = SignatureBox::from_file(signature_path).map_err(|e| {
+        format!(
+            "Failed to read signature {}, {}",
+            signature_path.display(),
+            e
+        )
+    })?;
+    minisign::verify(&key, &signature, File::open(path)?, true, false, false)
+        .map_err(|e| format!("Signature of {} is invalid, {}", path.display(), e))?;
+    let comment = signature.trusted_comment()?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    match signed_name(&comment) {
+        Some(signed) if signed == name => Ok(comment),
+        Some(signed) => Err(format!(
+            "Signature of {} is for another file, {}",
+            path.display(),
+            signed
+        )
+        .into()),
+        None => Err(format!(
+            "Signature of {} does not name the file it is for",
+            path.display()
+        )
+        .into()),
+    }
+}
+
+/// The name of the signed file, from a trusted comment as written by
+/// `sign_file` or `minisign -S`: tab-separated fields, one `file:<name>`.
+fn signed_name(comment: &str) -> Option<&str> {
+    comment
+        .split('\t')
+        .find_map(|field| field.strip_prefix("file:"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minisign::KeyPair;
+
+    /// A directory of its own for each test, as they run in parallel.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sdstatus-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn reads_signed_names() {
+        assert_eq!(
+            signed_name("timestamp:1760400000\tfile:status.html"),
+            Some("status.html")
+        );
+        assert_eq!(
+            signed_name("timestamp:1760400000\tfile:status.html\thashed"),
+            Some("status.html")
+        );
+        assert_eq!(signed_name("timestamp:1760400000"), None);
+    }
+
+    #[test]
+    fn signatures_verify_only_for_the_signed_file() {
+        let dir = scratch_dir("signing");
+        // An empty password leaves the key unencrypted.
+        let keys = KeyPair::generate_encrypted_keypair(Some(String::new())).unwrap();
+        let (secret, public) = (dir.join("sdstatus.key"), dir.join("sdstatus.pub"));
+        std::fs::write(&secret, keys.sk.to_box(None).unwrap().to_string()).unwrap();
+        std::fs::write(&public, keys.pk.to_box().unwrap().to_string()).unwrap();
+
+        let report = dir.join("status.html");
+        std::fs::write(&report, "<html>").unwrap();
+        let signature = sign_file(&report, &secret, None).unwrap();
+        assert_eq!(signature, dir.join("status.html.minisig"));
+        let comment = verify_file(&report, &signature, &public).unwrap();
+        assert!(comment.ends_with("\tfile:status.html"), "{}", comment);
+
+        std::fs::write(&report, "<html>altered").unwrap();
+        assert!(verify_file(&report, &signature, &public).is_err());
+
+        // The same contents under another name, with the signature copied.
+        let renamed = dir.join("other.html");
+        std::fs::write(&renamed, "<html>").unwrap();
+        let error = verify_file(&renamed, &signature, &public).unwrap_err();
+        assert!(error.to_string().contains("another file"), "{}", error);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,312 @@
+use crate::failure::FailureKind;
+use crate::{dedupe_instances, SDDirectoryInstance, SDMetadata, ScanResults, ScanSummary};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The name of the file listing a snapshot's contents.
+pub const MANIFEST: &str = "manifest.json";
+
+/// What a raw response in a snapshot is.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseKind {
+    Directory,
+    Metadata,
+}
+
+/// A raw response saved in a snapshot.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotEntry {
+    pub kind: ResponseKind,
+    pub url: String,
+    /// The instance the response came from, for metadata.
+    pub onion_address: Option<String>,
+    pub fetched_at: DateTime<Utc>,
+    pub http_status: Option<u16>,
+    /// The file holding the body, relative to the snapshot, if there was
+    /// one.
+    pub file: Option<String>,
+    /// Why no response was received, if none was.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// The manifest of a snapshot, written once the scan is done.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Manifest {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub responses: Vec<SnapshotEntry>,
+}
+
+/// Archives the raw responses of a scan, for reproducing its reports
+/// later. Each scan gets its own directory, named after when it started,
+/// holding the body of every response and a manifest of when each was
+/// fetched.
+#[derive(Debug)]
+pub struct Snapshot {
+    dir: PathBuf,
+    compress: bool,
+    manifest: Mutex<Manifest>,
+}
+
+impl Snapshot {
+    /// Creates the snapshot directory for a scan starting at `started_at`
+    /// under `root`. With `compress`, bodies are gzipped.
+    pub fn create(
+        root: &Path,
+        started_at: DateTime<Utc>,
+        compress: bool,
+    ) -> Result<Snapshot, Box<dyn Error>> {
+        let dir = root.join(started_at.format("%Y%m%dT%H%M%SZ").to_string());
+        std::fs::create_dir_all(dir.join("metadata"))?;
+        info!("Saving raw responses to {}", dir.display());
+        Ok(Snapshot {
+            dir,
+            compress,
+            manifest: Mutex::new(Manifest {
+                started_at,
+                finished_at: None,
+                responses: vec![],
+            }),
+        })
+    }
+
+    /// Saves the body of the response from a directory API.
+    pub fn save_directory(&self, url: &str, http_status: u16, body: &[u8]) {
+        let n = self
+            .manifest
+            .lock()
+            .unwrap()
+            .responses
+            .iter()
+            .filter(|r| r.kind == ResponseKind::Directory)
+            .count();
+        let name = format!("directory-{}.json", n);
+        self.save(
+            ResponseKind::Directory,
+            url,
+            None,
+            Some(http_status),
+            &name,
+            body,
+        );
+    }
+
+    /// Saves the body of the metadata response from an instance.
+    pub fn save_metadata(&self, onion_address: &str, url: &str, http_status: u16, body: &[u8]) {
+        let name = format!("metadata/{}.json", onion_address.replace('/', "_"));
+        self.save(
+            ResponseKind::Metadata,
+            url,
+            Some(onion_address),
+            Some(http_status),
+            &name,
+            body,
+        );
+    }
+
+    /// Records that no metadata response was received from an instance.
+    pub fn save_failure(&self, onion_address: &str, url: &str, error: &str) {
+        self.record(SnapshotEntry {
+            kind: ResponseKind::Metadata,
+            url: url.to_owned(),
+            onion_address: Some(onion_address.to_owned()),
+            fetched_at: Utc::now(),
+            http_status: None,
+            file: None,
+            error: Some(error.to_owned()),
+        });
+    }
+
+    /// Adds an entry to the manifest. A retried fetch replaces the
+    /// earlier one.
+    fn record(&self, entry: SnapshotEntry) {
+        let mut manifest = self.manifest.lock().unwrap();
+        manifest
+            .responses
+            .retain(|r| r.kind != entry.kind || r.url != entry.url);
+        manifest.responses.push(entry);
+    }
+
+    /// Writes a body and records it in the manifest. Failing to archive a
+    /// response is logged, but never fails the scan.
+    fn save(
+        &self,
+        kind: ResponseKind,
+        url: &str,
+        onion_address: Option<&str>,
+        http_status: Option<u16>,
+        name: &str,
+        body: &[u8],
+    ) {
+        let file = if self.compress {
+            format!("{}.gz", name)
+        } else {
+            name.to_owned()
+        };
+        if let Err(e) = self.write(&file, body) {
+            warn!("Failed to save {} to snapshot, {}", url, e);
+            return;
+        }
+        self.record(SnapshotEntry {
+            kind,
+            url: url.to_owned(),
+            onion_address: onion_address.map(|o| o.to_owned()),
+            fetched_at: Utc::now(),
+            http_status,
+            file: Some(file),
+            error: None,
+        });
+    }
+
+    fn write(&self, file: &str, body: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut out = File::create(self.dir.join(file))?;
+        if self.compress {
+            let mut encoder = GzEncoder::new(out, Compression::default());
+            encoder.write_all(body)?;
+            encoder.finish()?;
+        } else {
+            out.write_all(body)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the manifest, once every response has been saved.
+    pub fn finish(&self) -> Result<(), Box<dyn Error>> {
+        let mut manifest = self.manifest.lock().unwrap();
+        manifest.finished_at = Some(Utc::now());
+        let out = File::create(self.dir.join(MANIFEST))?;
+        serde_json::to_writer_pretty(out, &*manifest)?;
+        Ok(())
+    }
+}
+
+/// Reads a body saved in the snapshot at `dir`, decompressing it if need
+/// be.
+pub(crate) fn read_body(dir: &Path, file: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut body = vec![];
+    let mut f = File::open(dir.join(file))?;
+    if file.ends_with(".gz") {
+        GzDecoder::new(f).read_to_end(&mut body)?;
+    } else {
+        f.read_to_end(&mut body)?;
+    }
+    Ok(body)
+}
+
+/// Reads the manifest of the snapshot at `path` (its directory, or the
+/// manifest itself), returning it with the snapshot's directory.
+pub fn load_manifest(path: &Path) -> Result<(PathBuf, Manifest), Box<dyn Error>> {
+    let (dir, manifest_path) = if path.is_dir() {
+        (path.to_path_buf(), path.join(MANIFEST))
+    } else {
+        (
+            path.parent()
+                .unwrap_or_else(|| Path::new("."))
+                .to_path_buf(),
+            path.to_path_buf(),
+        )
+    };
+    let manifest: Manifest = serde_json::from_reader(
+        File::open(&manifest_path)
+            .map_err(|e| format!("Failed to open {}, {}", manifest_path.display(), e))?,
+    )?;
+    Ok((dir, manifest))
+}
+
+/// Rebuilds a scan's results from the snapshot at `path` (its directory,
+/// or its manifest), as if the scan were run again against the same
+/// responses. Nothing is fetched. The summary gives the times of the
+/// original scan.
+pub fn load_snapshot(path: &Path) -> Result<ScanResults, Box<dyn Error>> {
+    let (dir, manifest) = load_manifest(path)?;
+    info!(
+        "Replaying scan started at {} from {}",
+        manifest.started_at.to_rfc3339(),
+        dir.display()
+    );
+
+    let mut instances = vec![];
+    let mut metadata: HashMap<&str, &SnapshotEntry> = HashMap::new();
+    for entry in &manifest.responses {
+        match entry.kind {
+            ResponseKind::Directory => {
+                if let Some(file) = &entry.file {
+                    let listed: Vec<SDDirectoryInstance> =
+                        serde_json::from_slice(&read_body(&dir, file)?)?;
+                    instances.extend(listed);
+                }
+            }
+            ResponseKind::Metadata => {
+                if let Some(onion) = &entry.onion_address {
+                    metadata.insert(onion, entry);
+                }
+            }
+        }
+    }
+    // Scans of custom Onion URLs don't fetch a directory.
+    if !manifest
+        .responses
+        .iter()
+        .any(|r| r.kind == ResponseKind::Directory)
+    {
+        instances = metadata
+            .keys()
+            .map(|o| SDDirectoryInstance::from_onion(o))
+            .collect();
+        instances.sort_by(|a, b| a.onion_address.cmp(&b.onion_address));
+    }
+    let mut instances = dedupe_instances(instances);
+
+    for i in &mut instances {
+        i.attempts = 1;
+        let entry = match metadata.get(i.onion_address.as_str()) {
+            Some(entry) => entry,
+            None => {
+                i.attempts = 0;
+                i.error = Some("Not in snapshot".to_owned());
+                i.failure = Some(FailureKind::Other);
+                continue;
+            }
+        };
+        i.http_status = entry.http_status;
+        i.error = entry.error.to_owned();
+        i.failure = i.error.as_deref().map(FailureKind::classify);
+        if let Some(file) = &entry.file {
+            // As in a live scan, error statuses fail whatever the body.
+            if let Some(failure) = entry.http_status.and_then(FailureKind::from_status) {
+                i.error = Some(format!("HTTP status {}", entry.http_status.unwrap()));
+                i.failure = Some(failure);
+                continue;
+            }
+            let body = read_body(&dir, file)?;
+            i.raw_metadata = serde_json::from_slice(&body).ok();
+            match SDMetadata::parse(&body) {
+                Ok((m, drift)) => {
+                    i.metadata = Some(m);
+                    i.schema_drift = drift;
+                    i.available = true;
+                }
+                Err(e) => {
+                    i.error = Some(format!("error decoding response body: {}", e));
+                    i.failure = Some(FailureKind::from_parse_error(&e));
+                }
+            }
+        }
+    }
+    let finished_at = manifest.finished_at.unwrap_or(manifest.started_at);
+    Ok(ScanResults::new(
+        ScanSummary::new(manifest.started_at, finished_at, &instances),
+        instances,
+    ))
+}
@@ -0,0 +1,139 @@
+use crate::config::TelemetryConfig;
+use crate::ScanResults;
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::error::Error;
+use std::sync::OnceLock;
+use tracing_subscriber::{Layer, Registry};
+
+/// The service name deployments are reported under, unless configured.
+const DEFAULT_SERVICE_NAME: &str = "sdstatus";
+
+/// The providers exporting telemetry, kept to flush them on exit.
+struct Providers {
+    tracer: SdkTracerProvider,
+    meter: SdkMeterProvider,
+}
+
+static PROVIDERS: OnceLock<Providers> = OnceLock::new();
+
+/// Sets up exporting traces and metrics over OTLP (HTTP with protobuf) to
+/// the configured collector, returning the layer that turns tracing spans
+/// into OpenTelemetry ones. Without an endpoint, the exporters follow the
+/// usual `OTEL_EXPORTER_OTLP_*` variables, or default to a local collector.
+/// Spans and metrics are exported in batches, from threads of their own.
+pub fn init_telemetry(
+    config: &TelemetryConfig,
+) -> Result<Box<dyn Layer<Registry> + Send + Sync>, Box<dyn Error>> {
+    let resource = Resource::builder()
+        .with_service_name(
+            config
+                .service_name
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_owned()),
+        )
+        .build();
+    let endpoint = config
+        .otlp_endpoint
+        .as_deref()
+        .map(|e| e.trim_end_matches('/'));
+
+    let mut spans = SpanExporter::builder().with_http();
+    let mut metrics = MetricExporter::builder().with_http();
+    if let Some(endpoint) = endpoint {
+        spans = spans.with_endpoint(format!("{}/v1/traces", endpoint));
+        metrics = metrics.with_endpoint(format!("{}/v1/metrics", endpoint));
+    }
+    let tracer = SdkTracerProvider::builder()
+        .with_batch_exporter(spans.build()?)
+        .with_resource(resource.clone())
+        .build();
+    let meter = SdkMeterProvider::builder()
+        .with_reader(PeriodicReader::builder(metrics.build()?).build())
+        .with_resource(resource)
+        .build();
+
+    let layer = tracing_opentelemetry::layer().with_tracer(tracer.tracer("sdstatus"));
+    PROVIDERS
+        .set(Providers { tracer, meter })
+        .map_err(|_| "Telemetry is already set up")?;
+    Ok(Box::new(layer))
+}
+
+/// Records the results of a scan as metrics, mirroring the Prometheus
+/// exporter's gauges.
+pub fn record_scan(results: &ScanResults) {
+    let meter = match PROVIDERS.get() {
+        Some(providers) => providers.meter.meter("sdstatus"),
+        None => return,
+    };
+    let up = meter
+        .u64_gauge("sdstatus.up")
+        .with_description("Whether the instance's metadata could be retrieved.")
+        .build();
+    let latency = meter
+        .f64_gauge("sdstatus.latency")
+        .with_description("Time taken for the instance's metadata response to arrive.")
+        .with_unit("s")
+        .build();
+    for i in &results.instances {
+        let attributes = [
+            KeyValue::new("onion_address", i.onion_address.to_owned()),
+            KeyValue::new("title", i.title.to_owned()),
+        ];
+        up.record(i.available as u64, &attributes);
+        if let Some(ms) = i.latency_ms {
+            latency.record(ms as f64 / 1000.0, &attributes);
+        }
+    }
+
+    let scan = &results.scan;
+    meter
+        .f64_gauge("sdstatus.scan.duration")
+        .with_description("How long the last scan took.")
+        .with_unit("s")
+        .build()
+        .record(
+            (scan.finished_at - scan.started_at).num_milliseconds() as f64 / 1000.0,
+            &[],
+        );
+    let instances = meter
+        .u64_gauge("sdstatus.scan.instances")
+        .with_description("Instances scanned, by outcome.")
+        .build();
+    instances.record(
+        scan.succeeded as u64,
+        &[KeyValue::new("outcome", "succeeded")],
+    );
+    instances.record(scan.failed as u64, &[KeyValue::new("outcome", "failed")]);
+    meter
+        .u64_gauge("sdstatus.scan.retries")
+        .with_description("Metadata fetches retried in the last scan.")
+        .build()
+        .record(scan.retries as u64, &[]);
+    if let Some(ms) = scan.tor_bootstrap_ms {
+        meter
+            .f64_gauge("sdstatus.tor_bootstrap")
+            .with_description("Time until the first instance responded in the last scan.")
+            .with_unit("s")
+            .build()
+            .record(ms as f64 / 1000.0, &[]);
+    }
+}
+
+/// Exports any telemetry not yet sent, before sdstatus exits.
+pub fn shutdown_telemetry() {
+    if let Some(providers) = PROVIDERS.get() {
+        if let Err(e) = providers.tracer.shutdown() {
+            warn!("Failed to export traces, {}", e);
+        }
+        if let Err(e) = providers.meter.shutdown() {
+            warn!("Failed to export metrics, {}", e);
+        }
+    }
+}
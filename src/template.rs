@@ -0,0 +1,41 @@
+use crate::report::{
+    generate_l10n_report, generate_os_report, generate_versions_report, list_failures,
+};
+use crate::SDDirectoryInstance;
+use chrono::{DateTime, Utc};
+use std::error::Error;
+use std::path::Path;
+use tera::{Context, Tera};
+
+/// Renders the results of a scan with a user-supplied Tera template. The
+/// template can use:
+///
+/// - `instances`: every scanned instance, as in the JSON output of `scan`
+/// - `locales`, `versions`, `server_os`: site titles grouped by locale,
+///   SecureDrop release and server OS, as in the corresponding reports
+/// - `failures`: the instances that could not be scanned
+/// - `generated_at`: when the report was generated, in RFC 3339 format
+///
+/// Output is HTML-escaped if the template's file name ends in `.html`.
+pub fn render_template(
+    path: &Path,
+    instances: &[SDDirectoryInstance],
+    generated_at: DateTime<Utc>,
+) -> Result<String, Box<dyn Error>> {
+    let contents = std::fs::read_to_string(path)?;
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().trim_end_matches(".tera").to_owned())
+        .unwrap_or_default();
+    let mut tera = Tera::default();
+    tera.add_raw_template(&name, &contents)?;
+
+    let mut context = Context::new();
+    context.insert("instances", instances);
+    context.insert("locales", &generate_l10n_report(instances));
+    context.insert("versions", &generate_versions_report(instances));
+    context.insert("server_os", &generate_os_report(instances));
+    context.insert("failures", &list_failures(instances));
+    context.insert("generated_at", &generated_at.to_rfc3339());
+    Ok(tera.render(&name, &context)?)
+}
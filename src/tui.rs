@@ -0,0 +1,184 @@
+use crate::show::{format_instance_detail, InstanceDetail};
+use crate::{normalize_onion, SDDirectoryInstance};
+use chrono::{DateTime, Utc};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// What the dashboard shows: the latest result for each instance, and
+/// how far along the scan in progress is.
+#[derive(Debug, Default)]
+pub struct Dashboard {
+    pub instances: Vec<SDDirectoryInstance>,
+    /// How many instances the scan in progress has scanned, out of how
+    /// many, if one is in progress.
+    pub progress: Option<(usize, usize)>,
+    pub last_scan: Option<DateTime<Utc>>,
+    /// Why the last scan failed, if it did.
+    pub error: Option<String>,
+}
+
+impl Dashboard {
+    /// Notes that a scan of `total` instances has started. The results of
+    /// the previous scan are shown until they are replaced.
+    pub fn start_scan(&mut self, total: usize) {
+        self.progress = Some((0, total));
+        self.error = None;
+    }
+
+    /// Shows the result of scanning an instance as soon as it comes in.
+    pub fn record(&mut self, instance: &SDDirectoryInstance) {
+        if let Some((scanned, _)) = &mut self.progress {
+            *scanned += 1;
+        }
+        let onion = normalize_onion(&instance.onion_address);
+        match self
+            .instances
+            .iter_mut()
+            .find(|i| normalize_onion(&i.onion_address) == onion)
+        {
+            Some(i) => *i = instance.clone(),
+            None => self.instances.push(instance.clone()),
+        }
+    }
+
+    /// Replaces the results with those of the completed scan, dropping
+    /// instances it no longer includes.
+    pub fn finish_scan(&mut self, instances: Vec<SDDirectoryInstance>) {
+        self.instances = instances;
+        self.progress = None;
+        self.last_scan = Some(Utc::now());
+    }
+
+    /// Notes that a scan failed, e.g. as the directory was unreachable.
+    pub fn fail_scan(&mut self, error: String) {
+        self.progress = None;
+        self.error = Some(error);
+    }
+}
+
+/// Runs the dashboard in the terminal until the user quits, redrawing it
+/// as `dashboard` is updated by scans in the background. Pressing `r`
+/// notifies `rescan`. This blocks, so should run on its own thread.
+pub fn run_dashboard(dashboard: Arc<Mutex<Dashboard>>, rescan: Arc<Notify>) -> std::io::Result<()> {
+    let mut terminal = ratatui::try_init()?;
+    let result = event_loop(&mut terminal, &dashboard, &rescan);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    dashboard: &Mutex<Dashboard>,
+    rescan: &Notify,
+) -> std::io::Result<()> {
+    let mut selected = TableState::default().with_selected(0);
+    loop {
+        terminal.draw(|frame| draw(frame, &dashboard.lock().unwrap(), &mut selected))?;
+        // Poll rather than block, so results coming in are drawn promptly.
+        if !event::poll(Duration::from_millis(250))? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('r') => rescan.notify(),
+                KeyCode::Down | KeyCode::Char('j') => selected.select_next(),
+                KeyCode::Up | KeyCode::Char('k') => selected.select_previous(),
+                KeyCode::Home | KeyCode::Char('g') => selected.select_first(),
+                KeyCode::End | KeyCode::Char('G') => selected.select_last(),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The line above the table: the scan's progress or outcome, and the keys.
+fn status_line(dashboard: &Dashboard) -> String {
+    let scan = match (&dashboard.progress, &dashboard.error, &dashboard.last_scan) {
+        (Some((scanned, total)), _, _) => format!("Scanning, {} of {} sites", scanned, total),
+        (None, Some(error), _) => format!("Scan failed, {}", error),
+        (None, None, Some(last_scan)) => {
+            let up = dashboard.instances.iter().filter(|i| i.available).count();
+            format!(
+                "{} of {} sites available at {}",
+                up,
+                dashboard.instances.len(),
+                last_scan.format("%H:%M UTC")
+            )
+        }
+        (None, None, None) => "Waiting for the first scan".to_owned(),
+    };
+    format!("{}  |  q quit, r rescan, ↑↓ select", scan)
+}
+
+fn draw(frame: &mut Frame, dashboard: &Dashboard, selected: &mut TableState) {
+    let [header, body] =
+        Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(frame.area());
+    let [list, detail] =
+        Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(body);
+    frame.render_widget(Line::from(status_line(dashboard)), header);
+
+    let rows = dashboard.instances.iter().map(|i| {
+        let (status, color) = if i.available {
+            ("UP", Color::Green)
+        } else {
+            ("DOWN", Color::Red)
+        };
+        Row::new(vec![
+            Cell::from(status).style(Style::default().fg(color)),
+            Cell::from(if i.title.is_empty() {
+                i.onion_address.as_str()
+            } else {
+                i.title.as_str()
+            }),
+            Cell::from(
+                i.metadata
+                    .as_ref()
+                    .map(|m| m.sd_version.as_str())
+                    .unwrap_or("-"),
+            ),
+            Cell::from(
+                i.latency_ms
+                    .map(|l| format!("{} ms", l))
+                    .unwrap_or_else(|| "-".to_owned()),
+            ),
+            Cell::from(i.failure_kind().map(|k| k.to_string()).unwrap_or_default()),
+        ])
+    });
+    let widths = [
+        Constraint::Length(4),
+        Constraint::Fill(1),
+        Constraint::Length(8),
+        Constraint::Length(9),
+        Constraint::Length(20),
+    ];
+    let table = Table::new(rows, widths)
+        .header(
+            Row::new(vec!["", "Site", "Version", "Latency", "Failure"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().borders(Borders::ALL).title("Sites"))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(table, list, selected);
+
+    let text = match selected.selected().and_then(|n| dashboard.instances.get(n)) {
+        Some(i) => format_instance_detail(&InstanceDetail::new(i.clone(), None, None)),
+        None => String::from(""),
+    };
+    frame.render_widget(
+        Paragraph::new(text)
+            .wrap(Wrap { trim: false })
+            .block(Block::default().borders(Borders::ALL).title("Details")),
+        detail,
+    );
+}
@@ -0,0 +1,118 @@
+use crate::db::Outcome;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// The windows uptime is reported over when none are given.
+pub const DEFAULT_WINDOWS: &[&str] = &["7d", "30d", "90d"];
+
+/// Parses a window such as `7d`, `12h` or `2w` into a duration.
+pub fn parse_window(window: &str) -> Result<Duration, Box<dyn Error>> {
+    let window = window.trim();
+    let (count, unit) = window.split_at(window.len().saturating_sub(1));
+    let count: i64 = count
+        .parse()
+        .map_err(|_| format!("Invalid uptime window {}", window))?;
+    match unit {
+        "h" => Ok(Duration::hours(count)),
+        "d" => Ok(Duration::days(count)),
+        "w" => Ok(Duration::weeks(count)),
+        _ => Err(format!(
+            "Invalid uptime window {}, expected e.g. 12h, 7d or 2w",
+            window
+        )
+        .into()),
+    }
+}
+
+/// How often an instance was available over one window.
+#[derive(Serialize, Debug)]
+pub struct WindowUptime {
+    pub window: String,
+    /// The number of scans of the instance in the window.
+    pub scans: u32,
+    /// The percentage of those scans in which it was available, if it was
+    /// scanned at all.
+    pub uptime: Option<f64>,
+}
+
+/// An instance's uptime over each of the requested windows.
+#[derive(Serialize, Debug)]
+pub struct UptimeEntry {
+    pub title: String,
+    pub onion_address: String,
+    pub windows: Vec<WindowUptime>,
+}
+
+/// Computes every instance's uptime over each window ending at `now`, from
+/// the history of scan outcomes. Instances are listed by title, under the
+/// title they had in their latest scan.
+pub fn generate_uptime_report(
+    outcomes: &[Outcome],
+    windows: &[(String, Duration)],
+    now: DateTime<Utc>,
+) -> Vec<UptimeEntry> {
+    let mut by_onion: BTreeMap<&str, Vec<&Outcome>> = BTreeMap::new();
+    for o in outcomes {
+        by_onion.entry(&o.onion_address).or_default().push(o);
+    }
+    let mut entries: Vec<UptimeEntry> = by_onion
+        .into_iter()
+        .map(|(onion_address, outcomes)| UptimeEntry {
+            title: outcomes
+                .iter()
+                .max_by_key(|o| o.scanned_at)
+                .map(|o| o.title.to_owned())
+                .unwrap_or_default(),
+            onion_address: onion_address.to_owned(),
+            windows: windows
+                .iter()
+                .map(|(name, length)| {
+                    let in_window: Vec<_> = outcomes
+                        .iter()
+                        .filter(|o| o.scanned_at >= now - *length)
+                        .collect();
+                    let up = in_window.iter().filter(|o| o.available).count();
+                    WindowUptime {
+                        window: name.to_owned(),
+                        scans: in_window.len() as u32,
+                        uptime: if in_window.is_empty() {
+                            None
+                        } else {
+                            Some(100.0 * up as f64 / in_window.len() as f64)
+                        },
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+    entries.sort_by(|a, b| a.title.cmp(&b.title));
+    entries
+}
+
+/// Formats an uptime percentage, or `-` for a window without scans.
+pub fn format_uptime(uptime: Option<f64>) -> String {
+    uptime
+        .map(|u| format!("{:.1}%", u))
+        .unwrap_or_else(|| "-".to_owned())
+}
+
+/// Renders the uptime report as human-readable text, with a column per
+/// window.
+pub fn format_uptime_report(entries: &[UptimeEntry]) -> String {
+    let mut report = String::from("");
+    if let Some(first) = entries.first() {
+        for w in &first.windows {
+            report += &format!("{:>7}  ", w.window);
+        }
+        report += "Site\n";
+    }
+    for e in entries {
+        for w in &e.windows {
+            report += &format!("{:>7}  ", format_uptime(w.uptime));
+        }
+        report += &format!("{} ({})\n", e.title, e.onion_address);
+    }
+    report
+}
@@ -0,0 +1,121 @@
+use crate::config::WeblateConfig;
+use crate::i18n::Messages;
+use fluent_bundle::FluentArgs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// The Weblate instance SecureDrop is translated on.
+pub const WEBLATE_URL: &str = "https://weblate.securedrop.org";
+
+/// A page of the Weblate translations API.
+#[derive(Deserialize, Debug)]
+struct TranslationsPage {
+    next: Option<String>,
+    results: Vec<Translation>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Translation {
+    language_code: String,
+    translated_percent: f64,
+}
+
+/// Fetches how much of the SecureDrop component is translated into each
+/// language, as a percentage keyed by Weblate language code.
+pub async fn fetch_translations(
+    config: &WeblateConfig,
+) -> Result<BTreeMap<String, f64>, Box<dyn Error>> {
+    let mut url = Some(format!(
+        "{}/api/components/{}/{}/translations/",
+        config
+            .url
+            .as_deref()
+            .unwrap_or(WEBLATE_URL)
+            .trim_end_matches('/'),
+        config.project.as_deref().unwrap_or("securedrop"),
+        config.component.as_deref().unwrap_or("securedrop"),
+    ));
+    let client = reqwest::Client::new();
+    let mut translations = BTreeMap::new();
+    while let Some(page_url) = url {
+        debug!("Fetching Weblate translations from {}", page_url);
+        let page: TranslationsPage = client
+            .get(&page_url)
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!("Token {}", config.token),
+            )
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        for t in page.results {
+            translations.insert(t.language_code, t.translated_percent);
+        }
+        url = page.next;
+    }
+    Ok(translations)
+}
+
+/// How complete a locale's translation is, and how many sites enable it.
+#[derive(Serialize, Debug)]
+pub struct LocaleTranslation {
+    pub locale: String,
+    pub instances: usize,
+    /// Percent translated, if Weblate has the locale.
+    pub translated_percent: Option<f64>,
+}
+
+/// Looks up a locale such as `de-DE` in Weblate's translations, which may
+/// name it `de_DE`, `de-DE` or just `de`.
+fn lookup(translations: &BTreeMap<String, f64>, locale: &str) -> Option<f64> {
+    let language = locale.split(['_', '-']).next().unwrap_or(locale);
+    [
+        locale.to_owned(),
+        locale.replace('-', "_"),
+        locale.replace('_', "-"),
+        language.to_owned(),
+    ]
+    .iter()
+    .find_map(|code| translations.get(code).copied())
+}
+
+/// Joins a localization report (see `generate_l10n_report`) with the
+/// translation percentages from Weblate.
+pub fn join_translations(
+    locales: &BTreeMap<String, Vec<String>>,
+    translations: &BTreeMap<String, f64>,
+) -> Vec<LocaleTranslation> {
+    locales
+        .iter()
+        .map(|(locale, sites)| LocaleTranslation {
+            locale: locale.to_owned(),
+            instances: sites.len(),
+            translated_percent: lookup(translations, locale),
+        })
+        .collect()
+}
+
+/// Renders the joined report as human-readable text.
+pub fn format_translations(entries: &[LocaleTranslation], messages: &Messages) -> String {
+    let mut report = String::from("");
+    for e in entries {
+        let percent = match e.translated_percent {
+            Some(p) => {
+                let mut args = FluentArgs::new();
+                args.set("percent", format!("{:.1}", p));
+                messages.format("translated", &args)
+            }
+            None => messages.get("not-on-weblate"),
+        };
+        report += &format!(
+            "{}: {}, {}\n",
+            e.locale,
+            percent,
+            messages.count("enabled-on", e.instances)
+        );
+    }
+    report
+}
@@ -0,0 +1,128 @@
+//! A stand-in for the Tor network: local HTTP servers serving fixture
+//! responses, reached through an `HttpClient` that maps Onion addresses to
+//! them, so scans run end to end without Tor.
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use sdstatus::http::{HttpClient, HttpError, ReqwestClient, ResponseFuture};
+use sdstatus::ScanOptions;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The directory fixture files are read from.
+pub fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+pub fn fixture(name: &str) -> Vec<u8> {
+    std::fs::read(fixture_path(name)).unwrap()
+}
+
+/// A local HTTP server answering each path with a canned status and body,
+/// and anything else with a 404.
+pub struct FixtureServer {
+    pub addr: SocketAddr,
+}
+
+impl FixtureServer {
+    /// Starts a server on a free port, running until the test ends.
+    pub fn start(routes: Vec<(&str, u16, Vec<u8>)>) -> FixtureServer {
+        let routes: Arc<HashMap<String, (u16, Vec<u8>)>> = Arc::new(
+            routes
+                .into_iter()
+                .map(|(path, status, body)| (path.to_owned(), (status, body)))
+                .collect(),
+        );
+        let make_svc = make_service_fn(move |_conn| {
+            let routes = routes.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let (status, body) = routes
+                        .get(req.uri().path())
+                        .cloned()
+                        .unwrap_or((404, vec![]));
+                    let response = Response::builder()
+                        .status(status)
+                        .body(Body::from(body))
+                        .unwrap();
+                    async move { Ok::<_, Infallible>(response) }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        FixtureServer { addr }
+    }
+
+    /// A server for an Onion service whose `/metadata` is the fixture
+    /// `metadata/<name>.json`.
+    pub fn metadata(name: &str) -> FixtureServer {
+        let body = fixture(&format!("metadata/{}.json", name));
+        FixtureServer::start(vec![("/metadata", 200, body)])
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+}
+
+/// Routes requests for Onion addresses to the fixture servers standing in
+/// for them. Addresses without a server fail as Tor fails for an Onion
+/// service that is offline.
+#[derive(Debug)]
+pub struct OnionNetwork {
+    services: HashMap<String, SocketAddr>,
+    client: ReqwestClient,
+}
+
+impl OnionNetwork {
+    pub fn new() -> OnionNetwork {
+        OnionNetwork {
+            services: HashMap::new(),
+            client: ReqwestClient::new(reqwest::Client::new()),
+        }
+    }
+
+    /// Serves the Onion service at `onion_address` from `server`.
+    pub fn serve(&mut self, onion_address: &str, server: &FixtureServer) -> &mut OnionNetwork {
+        self.services.insert(onion_address.to_owned(), server.addr);
+        self
+    }
+
+    /// Scan options fetching through this network, without the delays
+    /// meant to keep real Onion services from being hammered.
+    pub fn scan_options(self) -> ScanOptions {
+        ScanOptions {
+            retries: 0,
+            retry_backoff: Duration::from_millis(0),
+            request_delay: Duration::from_millis(0),
+            client: Some(Arc::new(self)),
+            ..ScanOptions::default()
+        }
+    }
+}
+
+impl HttpClient for OnionNetwork {
+    fn get<'a>(&'a self, url: &'a str) -> ResponseFuture<'a> {
+        Box::pin(async move {
+            let rest = url.trim_start_matches("http://");
+            let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+            match self.services.get(host) {
+                Some(addr) => self.client.get(&format!("http://{}{}", addr, path)).await,
+                None => Err(HttpError::Recorded {
+                    message:
+                        "socks connect error: onion service descriptor not found (SOCKS reply 0xf0)"
+                            .to_owned(),
+                }),
+            }
+        })
+    }
+}
@@ -0,0 +1,199 @@
+//! Runs the whole pipeline against fixture servers: the directory is
+//! fetched, each instance's metadata is fetched through a fake Onion
+//! network, and the results are aggregated into reports.
+
+mod common;
+
+use chrono::Utc;
+use common::{fixture, FixtureServer, OnionNetwork};
+use sdstatus::assertion::Assertion;
+use sdstatus::db::{SqliteHistory, Storage};
+use sdstatus::failure::FailureKind;
+use sdstatus::findings::FindingKind;
+use sdstatus::report::{
+    format_status_report, generate_l10n_report, generate_status_report, generate_versions_report,
+    list_failures,
+};
+use sdstatus::{
+    get_securedrop_directories, populate_metadata, SDDirectoryInstance, ScanMode, ScanOptions,
+    ScanResults, ScanSummary,
+};
+
+const CURRENT: &str = "currentaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaal37qd.onion";
+const OUTDATED: &str = "outdatedaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaobbqd.onion";
+const BROKEN: &str = "brokenaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaabhmad.onion";
+const NOT_SECUREDROP: &str = "notsdaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaallzyd.onion";
+
+/// Serves the fixture directory and scans it. Of its five instances, two
+/// serve metadata, one answers with a server error, one isn't SecureDrop,
+/// and one is offline. The instances are sorted by title, since they are
+/// otherwise in the order their scans finished.
+async fn scan_fixtures() -> ScanResults {
+    let directory =
+        FixtureServer::start(vec![("/api/v1/directory/", 200, fixture("directory.json"))]);
+    let current = FixtureServer::metadata("current");
+    let outdated = FixtureServer::metadata("outdated");
+    let not_securedrop = FixtureServer::metadata("notsd");
+    let broken = FixtureServer::start(vec![("/metadata", 500, b"Internal Server Error".to_vec())]);
+    let mut network = OnionNetwork::new();
+    network
+        .serve(CURRENT, &current)
+        .serve(OUTDATED, &outdated)
+        .serve(BROKEN, &broken)
+        .serve(NOT_SECUREDROP, &not_securedrop);
+
+    let started_at = Utc::now();
+    let instances = get_securedrop_directories(&[directory.url("/api/v1/directory/")], None)
+        .await
+        .unwrap();
+    let mut instances = populate_metadata(instances, &network.scan_options(), |_| {})
+        .await
+        .unwrap();
+    instances.sort_by(|a, b| a.title.cmp(&b.title));
+    ScanResults::new(
+        ScanSummary::new(started_at, Utc::now(), &instances),
+        instances,
+    )
+}
+
+fn by_title<'a>(results: &'a ScanResults, title: &str) -> &'a SDDirectoryInstance {
+    results.instances.iter().find(|i| i.title == title).unwrap()
+}
+
+#[tokio::test]
+async fn scan_classifies_each_instance() {
+    let results = scan_fixtures().await;
+    assert_eq!(results.instances.len(), 5);
+
+    let current = by_title(&results, "Current Times");
+    assert!(current.available);
+    assert_eq!(current.http_status, Some(200));
+    assert_eq!(current.metadata.as_ref().unwrap().sd_version, "2.6.0");
+
+    let failures: Vec<(&str, Option<FailureKind>)> = results
+        .instances
+        .iter()
+        .filter(|i| !i.available)
+        .map(|i| (i.title.as_str(), i.failure_kind()))
+        .collect();
+    assert_eq!(
+        failures,
+        vec![
+            ("Broken Herald", Some(FailureKind::HttpServerError)),
+            ("Not SecureDrop", Some(FailureKind::SchemaMismatch)),
+            ("Offline Tribune", Some(FailureKind::DescriptorNotFound)),
+        ]
+    );
+
+    assert_eq!(results.scan.instances, 5);
+    assert_eq!(results.scan.succeeded, 2);
+    assert_eq!(results.scan.failed, 3);
+    assert_eq!(results.scan.retries, 0);
+}
+
+#[tokio::test]
+async fn reports_aggregate_the_scan() {
+    let results = scan_fixtures().await;
+
+    let versions = generate_versions_report(&results.instances);
+    assert_eq!(versions["2.6.0"], vec!["Current Times"]);
+    assert_eq!(versions["2.5.1"], vec!["Outdated Gazette"]);
+    assert_eq!(versions.len(), 2);
+
+    let locales = generate_l10n_report(&results.instances);
+    assert_eq!(locales["en-US"], vec!["Current Times", "Outdated Gazette"]);
+    assert_eq!(locales["es-ES"], vec!["Outdated Gazette"]);
+
+    let report = format_status_report(&generate_status_report(&results.instances), false);
+    assert!(report.contains(CURRENT));
+    assert!(report.contains("2 of 5 instances available"), "{}", report);
+
+    assert!(results
+        .findings
+        .iter()
+        .any(|f| f.kind == FindingKind::V2Onion && f.title == "Outdated Gazette"));
+}
+
+#[tokio::test]
+async fn history_keeps_the_scan() {
+    let results = scan_fixtures().await;
+    let mut history = SqliteHistory::open(":memory:").unwrap();
+    let id = history
+        .record_scan(
+            results.scan.started_at,
+            results.scan.finished_at,
+            &results.instances,
+        )
+        .unwrap();
+
+    assert_eq!(history.latest_scan_ids(1).unwrap(), vec![id]);
+    let loaded = history.load_scan(id).unwrap();
+    assert_eq!(loaded.len(), 5);
+    let available: Vec<&str> = loaded
+        .iter()
+        .filter(|i| i.available)
+        .map(|i| i.title.as_str())
+        .collect();
+    assert_eq!(available, vec!["Current Times", "Outdated Gazette"]);
+    assert!(history.down_since(BROKEN).unwrap().is_some());
+    assert!(history.down_since(CURRENT).unwrap().is_none());
+}
+
+#[tokio::test]
+async fn request_budget_stops_retries() {
+    let broken = FixtureServer::start(vec![("/metadata", 500, b"Internal Server Error".to_vec())]);
+    let mut network = OnionNetwork::new();
+    network.serve(BROKEN, &broken);
+    let options = ScanOptions {
+        retries: 3,
+        request_budget: Some(2),
+        ..network.scan_options()
+    };
+
+    let mut instance = SDDirectoryInstance::from_onion(BROKEN);
+    assert!(instance.get_metadata(&options).await.is_err());
+    assert_eq!(instance.attempts, 2);
+
+    let options = ScanOptions {
+        request_budget: Some(0),
+        ..options
+    };
+    let mut instance = SDDirectoryInstance::from_onion(BROKEN);
+    assert!(instance.get_metadata(&options).await.is_err());
+    assert_eq!(instance.attempts, 0);
+}
+
+#[tokio::test]
+async fn ping_scans_count_responding_sites_as_up() {
+    let current = FixtureServer::start(vec![("/", 200, b"<html>".to_vec())]);
+    let broken = FixtureServer::start(vec![("/", 500, b"Internal Server Error".to_vec())]);
+    let mut network = OnionNetwork::new();
+    network.serve(CURRENT, &current).serve(BROKEN, &broken);
+    let options = ScanOptions {
+        mode: ScanMode::Ping,
+        ..network.scan_options()
+    };
+    let instances = [CURRENT, BROKEN, OUTDATED]
+        .iter()
+        .map(|onion| SDDirectoryInstance::from_onion(onion))
+        .collect();
+    let instances = populate_metadata(instances, &options, |_| {})
+        .await
+        .unwrap();
+
+    // Pings fetch no metadata, so only failed pings count as failures.
+    assert!(instances.iter().all(|i| i.metadata.is_none()));
+    let mut failed: Vec<String> = list_failures(&instances)
+        .into_iter()
+        .map(|f| f.onion_address)
+        .collect();
+    failed.sort();
+    assert_eq!(failed, vec![BROKEN, OUTDATED]);
+
+    for holds in ["up=1", "down=2", "locale:en_US=0"] {
+        let assertion: Assertion = holds.parse().unwrap();
+        assert_eq!(assertion.check(&instances), Ok(()), "{}", holds);
+    }
+    let version: Assertion = "version>=2.6".parse().unwrap();
+    assert!(version.check(&instances).is_err());
+}